@@ -139,6 +139,14 @@ pub enum Ty {
     },
 }
 
+/// Where a node starts in the source, from the range of its first token. Lines and
+/// columns are counted from 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub line: u32,
+    pub column: u32,
+}
+
 ///
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Statement {
@@ -149,33 +157,43 @@ pub enum Statement {
         condition: Box<Expression>,
         on_true: Box<Statement>,
         on_false: Option<Box<Statement>>,
+        location: Location,
     },
     For {
         value: Box<Expression>,
         range: Box<Expression>,
         body: Box<Statement>,
+        location: Location,
     },
     While {
         condition: Box<Expression>,
         body: Box<Statement>,
+        location: Location,
     },
     Return {
         expr: Option<Box<Expression>>,
+        location: Location,
+    },
+    Break {
+        location: Location,
+    },
+    Continue {
+        location: Location,
     },
-    Break,
-    Continue,
 
     // Assignments
     /// a = b
     Assign {
         dst: Box<Expression>,
         src: Box<Expression>,
+        location: Location,
     },
     /// a += b
     CompoundAssign {
         dst: Box<Expression>,
         op: AssignOp,
         src: Box<Expression>,
+        location: Location,
     },
 
     // Declarations
@@ -183,12 +201,14 @@ pub enum Statement {
         name: String,
         ty: Box<Ty>,
         body: Box<Statement>,
+        location: Location,
     },
     Struct {},
     Let {
         name: String,
         ty: Ty,
         expr: Box<Expression>,
+        location: Location,
     },
 }
 
@@ -1,10 +1,23 @@
 use std::collections::HashMap;
 
 use crate::{
-    ast::{BinaryOp, Expression, FunctionParam, Statement, Ty},
+    ast::{BinaryOp, Expression, FunctionParam, Location, Statement, Ty},
     parser::Module,
 };
-use ir::function_builder::FunctionBuilder;
+use ir::{
+    debug_location::{DebugLocation, SourceFile},
+    function_builder::FunctionBuilder,
+};
+
+impl Location {
+    fn to_ir(self, file: SourceFile) -> DebugLocation {
+        DebugLocation {
+            file,
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
 
 impl Ty {
     fn to_ir(&self) -> ir::ty::TypeKind {
@@ -158,6 +171,8 @@ pub struct Emitter {
     context: ir::context::Context,
     variables: Variables,
     types: Types,
+    /// File the module is compiled from, if its locations are to be kept.
+    source_file: Option<SourceFile>,
 }
 
 impl Emitter {
@@ -166,6 +181,7 @@ impl Emitter {
             context: ir::context::Context::new(),
             variables: Variables::default(),
             types: Types::default(),
+            source_file: None,
         };
 
         ret.types
@@ -177,14 +193,23 @@ impl Emitter {
         ret
     }
 
+    /// Names the file the module is compiled from. Only then are instructions given
+    /// the source location they are built for.
+    pub fn set_source_file(&mut self, name: &str) {
+        self.source_file = Some(self.context.add_source_file(name));
+    }
+
     ///
     pub fn emit(&mut self, module: &Module) {
         self.variables.enter_scope();
         for decl in module.declarations.iter() {
             match decl {
-                Statement::Function { name, ty, body } => {
-                    self.emit_function(name, ty, body)
-                }
+                Statement::Function {
+                    name,
+                    ty,
+                    body,
+                    location,
+                } => self.emit_function(name, ty, body, *location),
                 Statement::Struct {} => todo!(),
                 _ => panic!(),
             }
@@ -198,13 +223,31 @@ impl Emitter {
     }
 
     ///
-    fn emit_function(&mut self, name: &str, ty: &Ty, body: &Statement) {
-        self.variables.enter_scope();
+    fn emit_function(
+        &mut self,
+        name: &str,
+        ty: &Ty,
+        body: &Statement,
+        location: Location,
+    ) {
         let function = self.emit_function_type(name, ty);
         let Ty::Function { ret, params } = ty else {
             panic!()
         };
+        if let Some(file) = self.source_file {
+            self.context.set_debug_info(
+                function,
+                ir::debug_location::FunctionDebugInfo {
+                    location: location.to_ir(file),
+                    parameter_names: params
+                        .iter()
+                        .map(|param| param.name.clone())
+                        .collect(),
+                },
+            );
+        }
 
+        // Registered in the enclosing scope, so later functions can call it.
         self.variables.insert(
             name,
             Symbol::Function {
@@ -213,10 +256,12 @@ impl Emitter {
                 params: params.to_vec(),
             },
         );
+        self.variables.enter_scope();
 
         let mut builder = self.context.builder(function);
         let prologue = builder.create_label("prologue");
         builder.set_insert_point(prologue);
+        builder.set_location(self.source_file.map(|file| location.to_ir(file)));
 
         for (i, param) in params.iter().enumerate() {
             let value = builder.parameter(i);
@@ -259,6 +304,13 @@ impl Emitter {
             .create_function(name, Some(return_type), &parameter_types)
     }
 
+    /// Gives the instructions built from now on `location`, in the file of the
+    /// function. Nothing changes when the function keeps no locations.
+    fn set_location(builder: &mut FunctionBuilder<'_>, location: Location) {
+        let current = builder.location();
+        builder.set_location(current.map(|current| location.to_ir(current.file)));
+    }
+
     ///
     fn emit_block(
         variables: &mut Variables,
@@ -278,19 +330,30 @@ impl Emitter {
                 condition,
                 on_true,
                 on_false,
-            } => Self::emit_if(
-                variables,
-                types,
-                builder,
-                condition,
-                on_true,
-                on_false.as_deref(),
-            ),
-            Statement::For { value, range, body } => {
-                Self::emit_for(variables, types, builder, value, range, body)
+                location,
+            } => {
+                Self::set_location(builder, *location);
+                Self::emit_if(
+                    variables,
+                    types,
+                    builder,
+                    condition,
+                    on_true,
+                    on_false.as_deref(),
+                )
+            }
+            Statement::For {
+                value,
+                range,
+                body,
+                location,
+            } => {
+                Self::set_location(builder, *location);
+                Self::emit_for(variables, types, builder, value, range, body, *location)
             }
-            Statement::While { condition, body } => todo!(),
-            Statement::Return { expr } => {
+            Statement::While { .. } => todo!(),
+            Statement::Return { expr, location } => {
+                Self::set_location(builder, *location);
                 if let Some(expr) = expr {
                     let value =
                         Self::emit_expression(variables, types, builder, expr).unwrap();
@@ -300,16 +363,23 @@ impl Emitter {
                     builder.ret(None);
                 }
             }
-            Statement::Break => todo!(),
-            Statement::Continue => todo!(),
-            Statement::Assign { dst, src } => {
+            Statement::Break { .. } => todo!(),
+            Statement::Continue { .. } => todo!(),
+            Statement::Assign { dst, src, location } => {
+                Self::set_location(builder, *location);
                 let dst = Self::emit_expression(variables, types, builder, dst).unwrap();
                 let src = Self::emit_expression(variables, types, builder, src).unwrap();
 
                 let src_value = src.extract(builder);
                 builder.store(dst.value(), src_value);
             }
-            Statement::CompoundAssign { dst, op, src } => {
+            Statement::CompoundAssign {
+                dst,
+                op,
+                src,
+                location,
+            } => {
+                Self::set_location(builder, *location);
                 let dst = Self::emit_expression(variables, types, builder, dst).unwrap();
                 let src = Self::emit_expression(variables, types, builder, src).unwrap();
 
@@ -331,7 +401,13 @@ impl Emitter {
 
                 builder.store(dst.value(), value);
             }
-            Statement::Let { name, ty, expr } => {
+            Statement::Let {
+                name,
+                ty,
+                expr,
+                location,
+            } => {
+                Self::set_location(builder, *location);
                 let ty =
                     types.get_or_create(ty.clone(), || builder.create_type(ty.to_ir()));
 
@@ -376,7 +452,9 @@ impl Emitter {
         {
             builder.set_insert_point(on_true_label);
             Self::emit_block(variables, types, builder, on_true);
-            builder.branch(end_label);
+            if !builder.is_terminated() {
+                builder.branch(end_label);
+            }
         }
 
         {
@@ -384,7 +462,9 @@ impl Emitter {
             if let Some(on_false) = on_false {
                 Self::emit_block(variables, types, builder, on_false);
             }
-            builder.branch(end_label);
+            if !builder.is_terminated() {
+                builder.branch(end_label);
+            }
         }
 
         builder.set_insert_point(end_label);
@@ -400,6 +480,7 @@ impl Emitter {
         value: &Expression,
         range: &Expression,
         body: &Statement,
+        location: Location,
     ) {
         variables.enter_scope();
 
@@ -447,6 +528,8 @@ impl Emitter {
             builder.set_insert_point(loop_continue);
             Self::emit_block(variables, types, builder, body);
 
+            // Stepping belongs to the loop header, not the last statement of the body.
+            Self::set_location(builder, location);
             let one = builder
                 .alloc_constant(ir::constant::ConstantValue::Integer { ty, value: 1 });
             let vv = value.extract(builder);
@@ -513,7 +596,24 @@ impl Emitter {
                 Some(CodegenValue::RValue { value })
             }
             Expression::Call { func, args } => {
-                todo!();
+                let Expression::Identifier { name } = func.as_ref() else {
+                    panic!("expected a function name")
+                };
+                let Some(Symbol::Function { ir: function, .. }) = variables.get(name)
+                else {
+                    panic!("`{name}` is not a function")
+                };
+                let function = *function;
+
+                let mut arguments = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let arg =
+                        Self::emit_expression(variables, types, builder, arg).unwrap();
+                    arguments.push(arg.extract(builder));
+                }
+
+                let value = builder.call(function, &arguments).unwrap();
+                Some(CodegenValue::RValue { value })
             }
             Expression::Identifier { name } => {
                 let sym = variables.get(name).unwrap();
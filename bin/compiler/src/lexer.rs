@@ -229,13 +229,20 @@ where
 pub struct Lexer<'a> {
     source: &'a str,
     tokens: Vec<TokenData>,
+    /// Offset of the start of every line.
+    line_starts: Vec<u32>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+
         Self {
             source,
             tokens: Vec::new(),
+            line_starts,
         }
     }
     ///
@@ -483,6 +490,19 @@ impl<'a> Lexer<'a> {
         None
     }
 
+    /// Line and column of the start of `token`, both counted from 1. Columns count
+    /// characters.
+    pub fn get_location(&self, token: Token) -> (u32, u32) {
+        let start = self.get_token_data(token).range.0;
+        let line = self
+            .line_starts
+            .partition_point(|&line_start| line_start <= start);
+        let line_start = self.line_starts[line - 1] as usize;
+        let column = self.source[line_start..start as usize].chars().count();
+
+        (line as u32, column as u32 + 1)
+    }
+
     ///
     pub fn get_token_kind(&self, token: Token) -> TokenKind {
         let token_data = self.get_token_data(token);
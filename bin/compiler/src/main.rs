@@ -11,7 +11,7 @@ use ir::{
 use crate::{lexer::Lexer, parser::Parser};
 
 fn main() {
-    let source = "
+    let sample = "
         fn test(a: u32, b: u32) -> u32 {
             let temp: u32 = a / 2;
 
@@ -28,12 +28,23 @@ fn main() {
             return a;
         }
     ";
-    let mut lexer = Lexer::new(source);
+    // Compiles the file given as the first argument, or the sample above.
+    let path = std::env::args().nth(1);
+    let source = match &path {
+        Some(path) => {
+            std::fs::read_to_string(path).expect("failed to read the source file")
+        }
+        None => sample.to_string(),
+    };
+    let mut lexer = Lexer::new(&source);
     let tokens = lexer.lex();
     let mut parser = Parser::new(&lexer, tokens);
     let module = parser.parse();
     println!("{module:#?}");
     let mut emitter = emitter::Emitter::new();
+    if let Some(path) = &path {
+        emitter.set_source_file(path);
+    }
     emitter.emit(module);
 
     // for token in tokens {
@@ -18,6 +18,7 @@ where
 
     ///
     pub(super) fn parse_asignment(&mut self) -> Statement {
+        let location = self.location();
         let dst = Self::parse_nested(self.lexer, &mut self.tokens).unwrap();
 
         let token = self.peek_token().unwrap();
@@ -31,6 +32,7 @@ where
                 dst: Box::new(dst),
                 op,
                 src: Box::new(src),
+                location,
             }
         };
 
@@ -41,6 +43,7 @@ where
                 Statement::Assign {
                     dst: Box::new(dst),
                     src: Box::new(src),
+                    location,
                 }
             }
             TokenKind::AmpEqual => make_compound_assign(dst, AssignOp::And),
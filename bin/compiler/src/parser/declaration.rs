@@ -22,6 +22,7 @@ where
 
     ///
     fn parse_fn(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::Fn));
         let name = {
             self.lexer
@@ -43,6 +44,7 @@ where
             name,
             ty: Box::new(ty),
             body: Box::new(body),
+            location,
         }
     }
 
@@ -97,6 +99,7 @@ where
 
     ///
     fn parse_let(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::Let));
         let name = {
             let token = self.eat_token();
@@ -112,6 +115,7 @@ where
             name,
             ty,
             expr: Box::new(expr),
+            location,
         }
     }
 }
@@ -334,15 +334,20 @@ where
 
                     let mut args = Vec::new();
                     loop {
+                        let token = tokens.peek_token().unwrap();
+                        if lexer.get_token_kind(token) == TokenKind::ParenClose {
+                            tokens.eat_token();
+                            break;
+                        }
+
                         args.push(Self::parse_expression_impl(lexer, tokens).unwrap());
 
-                        if let Some(token) = tokens.peek_token() {
-                            let kind = lexer.get_token_kind(token);
-                            if kind == TokenKind::Comma {
-                                continue;
-                            }
+                        let token = tokens.eat_token();
+                        match lexer.get_token_kind(token) {
+                            TokenKind::Comma => continue,
+                            TokenKind::ParenClose => break,
+                            kind => panic!("unexpected token: {kind:?}"),
                         }
-                        break;
                     }
 
                     lhs = Expression::Call {
@@ -5,7 +5,7 @@ mod statement;
 mod ty;
 
 use crate::{
-    ast::Statement,
+    ast::{Location, Statement},
     lexer::{Lexer, Token, TokenKind, Tokens},
 };
 
@@ -65,4 +65,11 @@ where
     fn peek_token(&mut self) -> Option<Token> {
         self.tokens.peek_token()
     }
+
+    /// Location of the next token, where the node about to be parsed starts.
+    fn location(&mut self) -> Location {
+        let (line, column) = self.lexer.get_location(self.peek_token().unwrap());
+
+        Location { line, column }
+    }
 }
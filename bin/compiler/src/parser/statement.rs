@@ -28,6 +28,7 @@ where
 
     ///
     fn parse_for(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::For));
         let ident = self.parse_identifier();
         self.eat_expect(TokenKind::Keyword(Keyword::In));
@@ -38,11 +39,13 @@ where
             value: Box::new(ident),
             range: Box::new(range),
             body: Box::new(body),
+            location,
         }
     }
 
     ///
     fn parse_while(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::While));
         let condition = self.parse_expression().unwrap();
         let body = self.parse_block();
@@ -50,11 +53,13 @@ where
         Statement::While {
             condition: Box::new(condition),
             body: Box::new(body),
+            location,
         }
     }
 
     ///
     fn parse_if(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::If));
         let condition = self.parse_expression().unwrap();
         let on_true = self.parse_block();
@@ -77,38 +82,46 @@ where
             condition: Box::new(condition),
             on_true: Box::new(on_true),
             on_false: on_false.map(Box::new),
+            location,
         }
     }
 
     ///
     fn parse_break(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::Break));
         self.eat_expect(TokenKind::Semicolon);
 
-        Statement::Break
+        Statement::Break { location }
     }
 
     ///
     fn parse_continue(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::Continue));
         self.eat_expect(TokenKind::Semicolon);
 
-        Statement::Continue
+        Statement::Continue { location }
     }
 
     ///
     fn parse_return(&mut self) -> Statement {
+        let location = self.location();
         self.eat_expect(TokenKind::Keyword(Keyword::Return));
         let token = self.peek_token().unwrap();
         match self.lexer.get_token_kind(token) {
             TokenKind::Semicolon => {
                 self.eat_token();
-                Statement::Return { expr: None }
+                Statement::Return {
+                    expr: None,
+                    location,
+                }
             }
             _ => {
                 let expr = self.parse_expression().unwrap();
                 let stmt = Statement::Return {
                     expr: Some(Box::new(expr)),
+                    location,
                 };
 
                 self.eat_expect(TokenKind::Semicolon);
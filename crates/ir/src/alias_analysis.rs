@@ -0,0 +1,320 @@
+use crate::{
+    function::FunctionData,
+    instruction::Instruction,
+    pointer_analysis::PointerAnalysis,
+    ty::{TypeKind, Types},
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Number of addresses a pointer may be tracked with before it is given up on, e.g.
+/// for a chain of `select`.
+const MAX_ADDRESSES: usize = 4;
+
+/// How the memory two pointers point to relates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Alias {
+    /// The pointers never point to overlapping memory.
+    No,
+    /// The pointers may point to overlapping memory.
+    May,
+    /// The pointers always point to the same address, with the same type.
+    Must,
+}
+
+/// An index applied by `GetElementPtr`, `None` if it is not a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    /// Elements of the memory a pointer points to.
+    Element(Option<i64>),
+    /// A field of the struct a pointer points to.
+    Field(Option<i64>),
+}
+
+/// A pointer described by the pointer it is derived from, and the indices applied
+/// to it by `GetElementPtr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Address {
+    base: Value,
+    /// Element offsets following another element offset are summed into it, while
+    /// any other index starts a new one. A pointer cast loses the whole path.
+    path: Option<Vec<Index>>,
+}
+
+impl Address {
+    /// The address `index` elements or fields further.
+    fn offset(&self, index: Option<i64>, same_type: bool) -> Self {
+        let path = self.path.as_ref().map(|path| {
+            let mut path = path.clone();
+            match path.last_mut() {
+                Some(Index::Element(last)) if same_type => {
+                    *last = last.zip(index).map(|(a, b)| a.wrapping_add(b));
+                }
+                _ if same_type => path.push(Index::Element(index)),
+                _ => path.push(Index::Field(index)),
+            }
+            path
+        });
+
+        Address {
+            base: self.base,
+            path,
+        }
+    }
+}
+
+/// Answers whether two pointers may point to the same memory.
+///
+/// Every pointer is traced back to its base: a `stack_alloc`, a parameter, or an
+/// unknown pointer, e.g. loaded from memory or returned by a call. Pointers with the
+/// same base are told apart by their constant `GetElementPtr` indices:
+/// ```text
+/// let v1: *u32 = stack_alloc.u32 4
+/// let v2: *u32 = get_element_ptr.*u32 v1, 1_u32    // must alias v3
+/// let v3: *u32 = get_element_ptr.*u32 v1, 1_u32    // doesn't alias v1
+/// let v4: *u32 = get_element_ptr.*u32 v1, v0       // may alias v1, v2 and v3
+/// ```
+/// Fields are told apart by their index, not their offset, so an element offset from
+/// a pointer to a field may alias any other pointer into the same struct.
+///
+/// Different stack slots never alias, and a stack slot that does not escape (see
+/// [`PointerAnalysis`]) never aliases a pointer with another base. Parameters may
+/// alias each other, and any unknown pointer, but never a stack slot of the function.
+#[derive(Debug)]
+pub(crate) struct AliasAnalysis {
+    /// Possible addresses of every pointer.
+    addresses: HashMap<Value, Vec<Address>>,
+    /// The type every pointer points to.
+    pointees: HashMap<Value, TypeKind>,
+    /// Bases created by `stack_alloc`.
+    stack_slots: HashSet<Value>,
+    /// Stack slots whose address, or an address derived from it, escapes.
+    escaped_slots: HashSet<Value>,
+    parameters: HashSet<Value>,
+}
+
+impl AliasAnalysis {
+    /// Traces every pointer of `pointer_analysis` back to its bases.
+    pub fn new(
+        types: &Types,
+        function: &FunctionData,
+        pointer_analysis: &PointerAnalysis,
+    ) -> Self {
+        let stack_slots: HashSet<Value> = pointer_analysis
+            .creators
+            .iter()
+            .filter(|(_, location)| {
+                matches!(
+                    function.instruction(location),
+                    Instruction::StackAlloc { .. }
+                )
+            })
+            .map(|(value, _)| *value)
+            .collect();
+        let escaped_slots = pointer_analysis
+            .escaped_pointers
+            .iter()
+            .map(|pointer| pointer_analysis.pointer_origins[pointer])
+            .chain(pointer_analysis.escaped_pointers.iter().copied())
+            .filter(|origin| stack_slots.contains(origin))
+            .collect();
+
+        let mut addresses: HashMap<Value, Vec<Address>> = HashMap::new();
+        let mut pointees = HashMap::new();
+        for value in function.topological_sort() {
+            if !pointer_analysis.pointers.contains(&value) {
+                continue;
+            }
+            if let Some(pointee) = types.strip_pointer(function.values().get(value).ty())
+            {
+                pointees.insert(value, types.get(pointee).type_kind().clone());
+            }
+
+            let creator = pointer_analysis
+                .creators
+                .get(&value)
+                .map(|location| function.instruction(location));
+            let derived = match creator {
+                Some(Instruction::GetElementPtr { ptr, index, .. }) => {
+                    let same_type = types.get(function.values().get(*ptr).ty())
+                        == types.get(function.values().get(value).ty());
+                    let index = constant_index(types, function, *index);
+                    addresses.get(ptr).map(|addresses| {
+                        addresses
+                            .iter()
+                            .map(|address| address.offset(index, same_type))
+                            .collect()
+                    })
+                }
+                Some(Instruction::Select {
+                    on_true, on_false, ..
+                }) => match (addresses.get(on_true), addresses.get(on_false)) {
+                    (Some(a), Some(b)) => {
+                        let mut union = a.clone();
+                        for address in b.iter() {
+                            if !union.contains(address) {
+                                union.push(address.clone());
+                            }
+                        }
+                        (union.len() <= MAX_ADDRESSES).then_some(union)
+                    }
+                    _ => None,
+                },
+                Some(Instruction::Cast { value: source, .. }) => {
+                    addresses.get(source).map(|addresses| {
+                        addresses
+                            .iter()
+                            .map(|address| Address {
+                                base: address.base,
+                                path: None,
+                            })
+                            .collect()
+                    })
+                }
+                _ => None,
+            };
+
+            let base = Address {
+                base: value,
+                path: Some(vec![Index::Element(Some(0))]),
+            };
+            addresses.insert(value, derived.unwrap_or_else(|| vec![base]));
+        }
+
+        AliasAnalysis {
+            addresses,
+            pointees,
+            stack_slots,
+            escaped_slots,
+            parameters: function.parameters().iter().copied().collect(),
+        }
+    }
+
+    /// How the memory pointed to by `a` and `b` relates.
+    pub fn alias(&self, a: Value, b: Value) -> Alias {
+        if a == b {
+            return Alias::Must;
+        }
+        let same_type = self.pointees.get(&a) == self.pointees.get(&b);
+        let (Some(a), Some(b)) = (self.addresses.get(&a), self.addresses.get(&b)) else {
+            return Alias::May;
+        };
+
+        let mut results = a
+            .iter()
+            .flat_map(|a| b.iter().map(move |b| self.address_alias(a, b, same_type)));
+        let first = results.next().unwrap_or(Alias::May);
+        results.fold(
+            first,
+            |result, other| {
+                if result == other {
+                    result
+                } else {
+                    Alias::May
+                }
+            },
+        )
+    }
+
+    /// Returns `true` if `a` and `b` may point into the same object, at any offset.
+    /// An access through one may reach the other with pointer arithmetic.
+    pub fn same_object(&self, a: Value, b: Value) -> bool {
+        let (Some(a), Some(b)) = (self.addresses.get(&a), self.addresses.get(&b)) else {
+            return true;
+        };
+
+        let whole = |address: &Address| Address {
+            base: address.base,
+            path: None,
+        };
+        a.iter().any(|a| {
+            b.iter()
+                .any(|b| self.address_alias(&whole(a), &whole(b), false) != Alias::No)
+        })
+    }
+
+    /// Returns `true` if `ptr` only points into stack slots that don't escape, so no
+    /// access through a pointer with another base can touch its memory. Calls may
+    /// still access it through their arguments.
+    pub fn is_local(&self, ptr: Value) -> bool {
+        self.addresses.get(&ptr).is_some_and(|addresses| {
+            addresses.iter().all(|address| {
+                self.stack_slots.contains(&address.base)
+                    && !self.escaped_slots.contains(&address.base)
+            })
+        })
+    }
+
+    /// How `a` and `b` relate, given whether the pointers have the same type.
+    fn address_alias(&self, a: &Address, b: &Address, same_type: bool) -> Alias {
+        if a.base == b.base {
+            let (Some(a), Some(b)) = (&a.path, &b.path) else {
+                return Alias::May;
+            };
+
+            // An element offset from a pointer to a field may reach into the fields
+            // around it, so only the very same address is known.
+            let from_field = |path: &[Index]| {
+                path[1..]
+                    .iter()
+                    .any(|index| matches!(index, Index::Element(_)))
+            };
+            if from_field(a) || from_field(b) {
+                let constant = a.iter().all(|index| {
+                    !matches!(index, Index::Element(None) | Index::Field(None))
+                });
+                return if a == b && constant && same_type {
+                    Alias::Must
+                } else {
+                    Alias::May
+                };
+            }
+
+            for (a, b) in a.iter().zip(b.iter()) {
+                match (a, b) {
+                    (Index::Element(Some(a)), Index::Element(Some(b)))
+                    | (Index::Field(Some(a)), Index::Field(Some(b)))
+                        if a == b => {}
+                    // Different elements, or different fields of a struct.
+                    (Index::Element(Some(_)), Index::Element(Some(_)))
+                    | (Index::Field(Some(_)), Index::Field(Some(_))) => return Alias::No,
+                    _ => return Alias::May,
+                }
+            }
+
+            // A pointer to a whole element overlaps the pointers to its fields.
+            return if a.len() == b.len() && same_type {
+                Alias::Must
+            } else {
+                Alias::May
+            };
+        }
+
+        let is_slot = |base: &Value| self.stack_slots.contains(base);
+        let is_local = |base: &Value| is_slot(base) && !self.escaped_slots.contains(base);
+        let is_parameter = |base: &Value| self.parameters.contains(base);
+
+        let distinct = (is_slot(&a.base) && is_slot(&b.base))
+            || is_local(&a.base)
+            || is_local(&b.base)
+            || (is_slot(&a.base) && is_parameter(&b.base))
+            || (is_parameter(&a.base) && is_slot(&b.base));
+        if distinct {
+            Alias::No
+        } else {
+            Alias::May
+        }
+    }
+}
+
+/// The value of a constant `GetElementPtr` index, sign extended.
+fn constant_index(types: &Types, function: &FunctionData, index: Value) -> Option<i64> {
+    let value = function.constant(index)?.integer()?;
+    let (num_bits, is_signed) = types.integer(function.values().get(index).ty())?;
+    if is_signed && num_bits < 64 {
+        let shift = 64 - num_bits;
+        Some(((value << shift) as i64) >> shift)
+    } else {
+        Some(value as i64)
+    }
+}
@@ -0,0 +1,593 @@
+use crate::{
+    backend::bytecode::{FunctionCode, IntegerKind, Op, Program, Register},
+    instruction::{BinaryOp, IntCompareOp, UnaryOp},
+};
+use std::fmt;
+
+/// Start of every encoded program.
+const MAGIC: &[u8; 4] = b"IRBC";
+/// Bumped on every change of the format.
+const VERSION: u8 = 1;
+
+/// Operations of `Op::Binary`, by their opcode.
+const BINARY_OPS: [BinaryOp; 13] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Mod,
+    BinaryOp::Div,
+    BinaryOp::Shr,
+    BinaryOp::Shl,
+    BinaryOp::Sar,
+    BinaryOp::And,
+    BinaryOp::Or,
+    BinaryOp::Xor,
+    BinaryOp::BitAnd,
+    BinaryOp::BitOr,
+];
+/// Predicates of `Op::Compare`, by their opcode. Every one has an unsigned opcode,
+/// followed by the signed one.
+const PREDICATES: [IntCompareOp; 6] = [
+    IntCompareOp::Equal,
+    IntCompareOp::NotEqual,
+    IntCompareOp::GreaterThan,
+    IntCompareOp::GreaterThanOrEqual,
+    IntCompareOp::LessThan,
+    IntCompareOp::LessThanOrEqual,
+];
+
+const BINARY: u8 = 0x00;
+const NEG: u8 = 0x10;
+const NOT: u8 = 0x11;
+const COMPARE: u8 = 0x20;
+const CAST: u8 = 0x30;
+const SELECT: u8 = 0x31;
+const ADDRESS: u8 = 0x32;
+const OFFSET: u8 = 0x33;
+const FRAME_ADDRESS: u8 = 0x34;
+const LOAD: u8 = 0x35;
+const STORE: u8 = 0x36;
+const JUMP: u8 = 0x40;
+const BRANCH: u8 = 0x41;
+const CALL: u8 = 0x42;
+const TAIL_CALL: u8 = 0x43;
+const RETURN: u8 = 0x44;
+const RETURN_VALUE: u8 = 0x45;
+
+/// Error decoding a [`Program`] from bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes don't start with the magic number and version of the format.
+    InvalidHeader,
+    /// The bytes end in the middle of the program.
+    UnexpectedEnd,
+    /// Malformed content at byte `offset`, e.g. an unknown opcode or a register out of
+    /// the frame.
+    Invalid { offset: usize, reason: &'static str },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidHeader => write!(f, "not a bytecode program"),
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of bytecode"),
+            DecodeError::Invalid { offset, reason } => {
+                write!(f, "invalid bytecode at {offset}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Program {
+    /// Serializes the program to a compact binary form, read back by
+    /// [`Program::decode`].
+    ///
+    /// Every instruction is an opcode byte followed by its operands. Integers are
+    /// LEB128 variable length, and constants zigzag encoded first so small negative
+    /// numbers stay short.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: Vec::new() };
+        encoder.bytes.extend_from_slice(MAGIC);
+        encoder.bytes.push(VERSION);
+        encoder.varint(self.functions.len() as u64);
+        for function in self.functions.iter() {
+            encoder.function(function);
+        }
+        encoder.bytes
+    }
+
+    /// Reads a program serialized by [`Program::encode`].
+    ///
+    /// The program is checked to be safe to run, e.g. branch targets and registers are
+    /// in bounds and calls pass the right number of arguments.
+    pub fn decode(bytes: &[u8]) -> Result<Program, DecodeError> {
+        if bytes.len() < MAGIC.len() + 1
+            || &bytes[..MAGIC.len()] != MAGIC
+            || bytes[MAGIC.len()] != VERSION
+        {
+            return Err(DecodeError::InvalidHeader);
+        }
+
+        let mut decoder = Decoder {
+            bytes,
+            position: MAGIC.len() + 1,
+        };
+        let count = decoder.varint()? as usize;
+        let mut functions = Vec::new();
+        // Offset of every call, to report the ones with wrong arguments.
+        let mut calls = Vec::new();
+        for _ in 0..count {
+            functions.push(decoder.function(&mut calls)?);
+        }
+        if decoder.position != bytes.len() {
+            return decoder.invalid("trailing bytes");
+        }
+
+        for (offset, function, arguments) in calls {
+            match functions.get(function as usize) {
+                Some(callee) if callee.parameters.len() == arguments => {}
+                Some(_) => {
+                    return Err(DecodeError::Invalid {
+                        offset,
+                        reason: "wrong number of arguments",
+                    })
+                }
+                None => {
+                    return Err(DecodeError::Invalid {
+                        offset,
+                        reason: "unknown function",
+                    })
+                }
+            }
+        }
+
+        Ok(Program { functions })
+    }
+}
+
+/// Writes the binary form of a program.
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn function(&mut self, function: &FunctionCode) {
+        self.varint(function.name.len() as u64);
+        self.bytes.extend_from_slice(function.name.as_bytes());
+        self.varint(function.parameters.len() as u64);
+        for kind in function.parameters.iter() {
+            self.kind(*kind);
+        }
+        self.varint(u64::from(function.num_registers));
+        self.varint(function.constants.len() as u64);
+        for (register, value) in function.constants.iter() {
+            self.varint(u64::from(*register));
+            let value = *value as i64;
+            self.varint(((value << 1) ^ (value >> 63)) as u64);
+        }
+        self.varint(function.frame_size);
+        self.varint(function.code.len() as u64);
+        for op in function.code.iter() {
+            self.op(op);
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::Binary {
+                op,
+                kind,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let index = BINARY_OPS.iter().position(|other| other == op).unwrap();
+                self.bytes.push(BINARY + index as u8);
+                self.kind(*kind);
+                self.registers(&[*dst, *lhs, *rhs]);
+            }
+            Op::Unary { op, kind, dst, src } => {
+                self.bytes.push(match op {
+                    UnaryOp::Neg => NEG,
+                    UnaryOp::Not => NOT,
+                });
+                self.kind(*kind);
+                self.registers(&[*dst, *src]);
+            }
+            Op::Compare {
+                pred,
+                is_signed,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let index = PREDICATES.iter().position(|other| other == pred).unwrap();
+                self.bytes
+                    .push(COMPARE + 2 * index as u8 + u8::from(*is_signed));
+                self.registers(&[*dst, *lhs, *rhs]);
+            }
+            Op::Cast { from, to, dst, src } => {
+                self.bytes.push(CAST);
+                self.kind(*from);
+                self.kind(*to);
+                self.registers(&[*dst, *src]);
+            }
+            Op::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.bytes.push(SELECT);
+                self.registers(&[*dst, *condition, *on_true, *on_false]);
+            }
+            Op::Address {
+                dst,
+                base,
+                index,
+                scale,
+            } => {
+                self.bytes.push(ADDRESS);
+                self.registers(&[*dst, *base, *index]);
+                self.varint(*scale);
+            }
+            Op::Offset { dst, base, offset } => {
+                self.bytes.push(OFFSET);
+                self.registers(&[*dst, *base]);
+                self.varint(*offset);
+            }
+            Op::FrameAddress { dst, offset } => {
+                self.bytes.push(FRAME_ADDRESS);
+                self.registers(&[*dst]);
+                self.varint(*offset);
+            }
+            Op::Load { kind, dst, address } => {
+                self.bytes.push(LOAD);
+                self.kind(*kind);
+                self.registers(&[*dst, *address]);
+            }
+            Op::Store { size, address, src } => {
+                self.bytes.push(STORE);
+                self.bytes.push(*size);
+                self.registers(&[*address, *src]);
+            }
+            Op::Jump { target } => {
+                self.bytes.push(JUMP);
+                self.varint(u64::from(*target));
+            }
+            Op::Branch {
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.bytes.push(BRANCH);
+                self.registers(&[*condition]);
+                self.varint(u64::from(*on_true));
+                self.varint(u64::from(*on_false));
+            }
+            Op::Call {
+                function,
+                dst,
+                arguments,
+                tail,
+            } => {
+                self.bytes.push(if *tail { TAIL_CALL } else { CALL });
+                self.varint(u64::from(*function));
+                // 0 when the result is not used.
+                self.varint(dst.map_or(0, |dst| u64::from(dst) + 1));
+                self.varint(arguments.len() as u64);
+                self.registers(arguments);
+            }
+            Op::Return { src: None } => self.bytes.push(RETURN),
+            Op::Return { src: Some(src) } => {
+                self.bytes.push(RETURN_VALUE);
+                self.registers(&[*src]);
+            }
+        }
+    }
+
+    fn kind(&mut self, kind: IntegerKind) {
+        self.bytes
+            .push(kind.num_bits | u8::from(kind.is_signed) << 7);
+    }
+
+    fn registers(&mut self, registers: &[Register]) {
+        for register in registers {
+            self.varint(u64::from(*register));
+        }
+    }
+
+    /// Writes `value` in unsigned LEB128.
+    fn varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+}
+
+/// Reads the binary form of a program.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+/// Limits of the operands of the function being decoded.
+struct Bounds {
+    num_registers: u32,
+    num_instructions: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads a function, adding its calls as `(offset, function, argument count)` to
+    /// `calls` to be checked once all functions are known.
+    fn function(
+        &mut self,
+        calls: &mut Vec<(usize, u32, usize)>,
+    ) -> Result<FunctionCode, DecodeError> {
+        let length = self.varint()? as usize;
+        let start = self.position;
+        let name = self.take(length)?;
+        let name =
+            String::from_utf8(name.to_vec()).map_err(|_| DecodeError::Invalid {
+                offset: start,
+                reason: "function name is not UTF-8",
+            })?;
+
+        let count = self.varint()? as usize;
+        let parameters = (0..count)
+            .map(|_| self.kind())
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_registers = self.u32()?;
+        if parameters.len() > num_registers as usize {
+            return self.invalid("parameters don't fit in the registers");
+        }
+        let mut bounds = Bounds {
+            num_registers,
+            num_instructions: 0,
+        };
+
+        let count = self.varint()? as usize;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let register = self.register(&bounds)?;
+            let value = self.varint()?;
+            constants.push((register, (value >> 1) ^ (value & 1).wrapping_neg()));
+        }
+
+        let frame_size = self.varint()?;
+        bounds.num_instructions = self.varint()? as usize;
+        let mut code = Vec::new();
+        for _ in 0..bounds.num_instructions {
+            let offset = self.position;
+            let op = self.op(&bounds)?;
+            if let Op::Call {
+                function,
+                arguments,
+                ..
+            } = &op
+            {
+                calls.push((offset, *function, arguments.len()));
+            }
+            code.push(op);
+        }
+
+        // Running off the end of the code is not possible.
+        let terminated = matches!(
+            code.last(),
+            Some(
+                Op::Jump { .. }
+                    | Op::Branch { .. }
+                    | Op::Return { .. }
+                    | Op::Call { tail: true, .. }
+            )
+        );
+        if !terminated {
+            return self.invalid("code doesn't end with a branch or return");
+        }
+
+        Ok(FunctionCode {
+            name,
+            parameters,
+            num_registers,
+            constants,
+            frame_size,
+            code,
+        })
+    }
+
+    fn op(&mut self, bounds: &Bounds) -> Result<Op, DecodeError> {
+        let offset = self.position;
+        let opcode = self.u8()?;
+        let op = match opcode {
+            _ if (BINARY..BINARY + BINARY_OPS.len() as u8).contains(&opcode) => {
+                Op::Binary {
+                    op: BINARY_OPS[usize::from(opcode - BINARY)],
+                    kind: self.kind()?,
+                    dst: self.register(bounds)?,
+                    lhs: self.register(bounds)?,
+                    rhs: self.register(bounds)?,
+                }
+            }
+            NEG | NOT => Op::Unary {
+                op: if opcode == NEG {
+                    UnaryOp::Neg
+                } else {
+                    UnaryOp::Not
+                },
+                kind: self.kind()?,
+                dst: self.register(bounds)?,
+                src: self.register(bounds)?,
+            },
+            _ if (COMPARE..COMPARE + 2 * PREDICATES.len() as u8).contains(&opcode) => {
+                let index = opcode - COMPARE;
+                Op::Compare {
+                    pred: PREDICATES[usize::from(index / 2)],
+                    is_signed: index % 2 == 1,
+                    dst: self.register(bounds)?,
+                    lhs: self.register(bounds)?,
+                    rhs: self.register(bounds)?,
+                }
+            }
+            CAST => Op::Cast {
+                from: self.kind()?,
+                to: self.kind()?,
+                dst: self.register(bounds)?,
+                src: self.register(bounds)?,
+            },
+            SELECT => Op::Select {
+                dst: self.register(bounds)?,
+                condition: self.register(bounds)?,
+                on_true: self.register(bounds)?,
+                on_false: self.register(bounds)?,
+            },
+            ADDRESS => Op::Address {
+                dst: self.register(bounds)?,
+                base: self.register(bounds)?,
+                index: self.register(bounds)?,
+                scale: self.varint()?,
+            },
+            OFFSET => Op::Offset {
+                dst: self.register(bounds)?,
+                base: self.register(bounds)?,
+                offset: self.varint()?,
+            },
+            FRAME_ADDRESS => Op::FrameAddress {
+                dst: self.register(bounds)?,
+                offset: self.varint()?,
+            },
+            LOAD => Op::Load {
+                kind: self.kind()?,
+                dst: self.register(bounds)?,
+                address: self.register(bounds)?,
+            },
+            STORE => {
+                let size = self.u8()?;
+                if !matches!(size, 1 | 2 | 4 | 8) {
+                    return self.invalid("invalid store size");
+                }
+                Op::Store {
+                    size,
+                    address: self.register(bounds)?,
+                    src: self.register(bounds)?,
+                }
+            }
+            JUMP => Op::Jump {
+                target: self.target(bounds)?,
+            },
+            BRANCH => Op::Branch {
+                condition: self.register(bounds)?,
+                on_true: self.target(bounds)?,
+                on_false: self.target(bounds)?,
+            },
+            CALL | TAIL_CALL => {
+                let function = self.u32()?;
+                let dst = match self.u32()? {
+                    0 => None,
+                    register => Some(register - 1),
+                };
+                if dst.is_some_and(|dst| dst >= bounds.num_registers) {
+                    return self.invalid("register out of the frame");
+                }
+                let count = self.varint()? as usize;
+                let arguments = (0..count)
+                    .map(|_| self.register(bounds))
+                    .collect::<Result<_, _>>()?;
+                Op::Call {
+                    function,
+                    dst,
+                    arguments,
+                    tail: opcode == TAIL_CALL,
+                }
+            }
+            RETURN => Op::Return { src: None },
+            RETURN_VALUE => Op::Return {
+                src: Some(self.register(bounds)?),
+            },
+            _ => {
+                return Err(DecodeError::Invalid {
+                    offset,
+                    reason: "unknown opcode",
+                })
+            }
+        };
+
+        Ok(op)
+    }
+
+    fn kind(&mut self) -> Result<IntegerKind, DecodeError> {
+        let byte = self.u8()?;
+        let num_bits = byte & 0x7f;
+        if !(1..=64).contains(&num_bits) {
+            return self.invalid("invalid integer width");
+        }
+        Ok(IntegerKind {
+            num_bits,
+            is_signed: byte & 0x80 != 0,
+        })
+    }
+
+    fn register(&mut self, bounds: &Bounds) -> Result<Register, DecodeError> {
+        let register = self.u32()?;
+        if register >= bounds.num_registers {
+            return self.invalid("register out of the frame");
+        }
+        Ok(register)
+    }
+
+    fn target(&mut self, bounds: &Bounds) -> Result<u32, DecodeError> {
+        let target = self.u32()?;
+        if target as usize >= bounds.num_instructions {
+            return self.invalid("branch target out of the code");
+        }
+        Ok(target)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let value = self.varint()?;
+        u32::try_from(value).or_else(|_| self.invalid("integer too large"))
+    }
+
+    /// Reads an unsigned LEB128 integer.
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        self.invalid("integer too large")
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Error for the content before the current position.
+    fn invalid<T>(&self, reason: &'static str) -> Result<T, DecodeError> {
+        Err(DecodeError::Invalid {
+            offset: self.position,
+            reason,
+        })
+    }
+}
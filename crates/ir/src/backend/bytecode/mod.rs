@@ -0,0 +1,473 @@
+//! Register based bytecode, run by a virtual machine without generating native code.
+//!
+//! Every value of a function gets a virtual register of its frame, holding 64 bits.
+//! Integers are kept extended to 64 bits according to the signedness of their type,
+//! and every instruction producing a narrower integer brings its result back to that
+//! form. Constants are registers too, set when the frame is created. Modules with
+//! float or struct values, or integers wider than 64 bits, are rejected.
+//!
+//! Memory is a single byte array. `stack_alloc` memory lives in the frame of the
+//! function, and pointers are offsets into the array.
+
+mod encoding;
+mod vm;
+
+pub use encoding::DecodeError;
+pub use vm::{StackFrame, Trap, TrapKind};
+
+use crate::{
+    backend::{
+        align_of, align_up, block_order, check_types, field_offset, size_of, Unsupported,
+    },
+    constant::ConstantValue,
+    function::{FunctionData, Functions},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    label::Label,
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use std::collections::HashMap;
+
+/// Index of a register in the frame of a function.
+pub(crate) type Register = u32;
+
+/// Width and signedness of the integer held in a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IntegerKind {
+    pub num_bits: u8,
+    pub is_signed: bool,
+}
+
+impl IntegerKind {
+    /// Pointers and other values using all the bits of a register.
+    pub const WORD: IntegerKind = IntegerKind {
+        num_bits: 64,
+        is_signed: false,
+    };
+
+    /// The kind of the registers holding values of type `ty`.
+    fn of(types: &Types, ty: Type) -> Self {
+        match types.get(ty).type_kind() {
+            TypeKind::Integer {
+                num_bits,
+                is_signed,
+            } => IntegerKind {
+                num_bits: *num_bits as u8,
+                is_signed: *is_signed,
+            },
+            TypeKind::Pointer { .. } => IntegerKind::WORD,
+            TypeKind::Float { .. } | TypeKind::Struct { .. } => {
+                unreachable!("rejected by `check_types`")
+            }
+        }
+    }
+
+    /// Extends the low `num_bits` of `value` to 64 bits, with copies of the sign bit
+    /// for signed kinds and zeros otherwise.
+    pub fn canonicalize(self, value: u64) -> u64 {
+        let shift = 64 - u32::from(self.num_bits);
+        if self.is_signed {
+            (((value << shift) as i64) >> shift) as u64
+        } else {
+            (value << shift) >> shift
+        }
+    }
+
+    /// Bytes taken in memory.
+    pub fn size(self) -> usize {
+        ((usize::from(self.num_bits) + 7) / 8).next_power_of_two()
+    }
+}
+
+/// A bytecode instruction. Branch targets are indices of instructions in the code of
+/// the function, and called functions indices in the [`Program`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Op {
+    /// `dst = lhs op rhs`, signed operations picked by `kind`.
+    Binary {
+        op: BinaryOp,
+        kind: IntegerKind,
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    Unary {
+        op: UnaryOp,
+        kind: IntegerKind,
+        dst: Register,
+        src: Register,
+    },
+    /// `dst = lhs pred rhs`, as 0 or 1.
+    Compare {
+        pred: IntCompareOp,
+        is_signed: bool,
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Reads `src` as an integer of kind `from`, and converts it to `to`.
+    Cast {
+        from: IntegerKind,
+        to: IntegerKind,
+        dst: Register,
+        src: Register,
+    },
+    Select {
+        dst: Register,
+        condition: Register,
+        on_true: Register,
+        on_false: Register,
+    },
+    /// `dst = base + index * scale`.
+    Address {
+        dst: Register,
+        base: Register,
+        index: Register,
+        scale: u64,
+    },
+    /// `dst = base + offset`.
+    Offset {
+        dst: Register,
+        base: Register,
+        offset: u64,
+    },
+    /// Address of the `stack_alloc` memory at `offset` in the frame.
+    FrameAddress {
+        dst: Register,
+        offset: u64,
+    },
+    Load {
+        kind: IntegerKind,
+        dst: Register,
+        address: Register,
+    },
+    /// Writes the low `size` bytes of `src`.
+    Store {
+        size: u8,
+        address: Register,
+        src: Register,
+    },
+    Jump {
+        target: u32,
+    },
+    Branch {
+        condition: Register,
+        on_true: u32,
+        on_false: u32,
+    },
+    /// Calls `function` in a new frame. A tail call replaces the frame of the caller,
+    /// returning right away to its caller.
+    Call {
+        function: u32,
+        dst: Option<Register>,
+        arguments: Box<[Register]>,
+        tail: bool,
+    },
+    Return {
+        src: Option<Register>,
+    },
+}
+
+/// Bytecode of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FunctionCode {
+    pub name: String,
+    /// Kinds of the parameters, passed in the first registers.
+    pub parameters: Vec<IntegerKind>,
+    pub num_registers: u32,
+    /// Registers holding constants, with their value.
+    pub constants: Vec<(Register, u64)>,
+    /// Bytes of `stack_alloc` memory.
+    pub frame_size: u64,
+    pub code: Vec<Op>,
+}
+
+/// A module compiled to bytecode, ready to be run or [encoded](Program::encode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    functions: Vec<FunctionCode>,
+}
+
+impl Program {
+    /// Compiles every function of the module, if all its values are pointers or
+    /// integers of at most 64 bits.
+    pub(crate) fn new(types: &Types, functions: &Functions) -> Result<Self, Unsupported> {
+        check_types(types, functions, |ty| match types.get(ty).type_kind() {
+            TypeKind::Integer { num_bits, .. } => *num_bits <= 64,
+            TypeKind::Pointer { .. } => true,
+            TypeKind::Float { .. } | TypeKind::Struct { .. } => false,
+        })?;
+
+        let indices: HashMap<u32, u32> = functions
+            .iter()
+            .enumerate()
+            .map(|(index, (id, _))| (id, index as u32))
+            .collect();
+        let functions = functions
+            .iter()
+            .map(|(_, function)| {
+                FunctionCompiler::new(types, &indices, function).compile()
+            })
+            .collect();
+
+        Ok(Program { functions })
+    }
+
+    /// Index of the function called `name`.
+    fn function(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+}
+
+/// Translates a single function to bytecode.
+struct FunctionCompiler<'a> {
+    types: &'a Types,
+    /// Index in the program of every function, by handle.
+    indices: &'a HashMap<u32, u32>,
+    function: &'a FunctionData,
+    registers: HashMap<Value, Register>,
+    constants: Vec<(Register, u64)>,
+    /// Offset in the frame of the memory of every `stack_alloc`.
+    allocations: HashMap<Value, u64>,
+    frame_size: u64,
+    code: Vec<Op>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(
+        types: &'a Types,
+        indices: &'a HashMap<u32, u32>,
+        function: &'a FunctionData,
+    ) -> Self {
+        let mut compiler = FunctionCompiler {
+            types,
+            indices,
+            function,
+            registers: HashMap::new(),
+            constants: Vec::new(),
+            allocations: HashMap::new(),
+            frame_size: 0,
+            code: Vec::new(),
+        };
+        for parameter in function.parameters() {
+            compiler.register(*parameter);
+        }
+        compiler
+    }
+
+    fn compile(mut self) -> FunctionCode {
+        let order = block_order(self.function);
+        let mut positions = HashMap::new();
+        for (i, label) in order.iter().enumerate() {
+            positions.insert(*label, self.code.len() as u32);
+            for instr in self.function.labels().get(*label).instructions.iter() {
+                self.compile_instruction(instr, order.get(i + 1).copied());
+            }
+        }
+
+        // Branches were emitted with the ids of their target labels.
+        let position = |target: &mut u32| *target = positions[&Label(*target)];
+        for op in self.code.iter_mut() {
+            match op {
+                Op::Jump { target } => position(target),
+                Op::Branch {
+                    on_true, on_false, ..
+                } => {
+                    position(on_true);
+                    position(on_false);
+                }
+                _ => {}
+            }
+        }
+
+        let definition = self.function.definition();
+        FunctionCode {
+            name: definition.name.clone(),
+            parameters: definition
+                .parameter_types
+                .iter()
+                .map(|ty| IntegerKind::of(self.types, *ty))
+                .collect(),
+            num_registers: self.registers.len() as u32,
+            constants: self.constants,
+            frame_size: self.frame_size,
+            code: self.code,
+        }
+    }
+
+    /// Compiles `instr`, with `next` the block laid out right after the current one.
+    fn compile_instruction(&mut self, instr: &Instruction, next: Option<Label>) {
+        let op = match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => Op::Binary {
+                op: *op,
+                kind: self.kind(*dst),
+                dst: self.register(*dst),
+                lhs: self.register(*lhs),
+                rhs: self.register(*rhs),
+            },
+            Instruction::ArithmeticUnary { dst, op, value } => Op::Unary {
+                op: *op,
+                kind: self.kind(*dst),
+                dst: self.register(*dst),
+                src: self.register(*value),
+            },
+            Instruction::Branch { target } => {
+                if Some(*target) == next {
+                    return;
+                }
+                Op::Jump { target: target.0 }
+            }
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => Op::Branch {
+                condition: self.register(*condition),
+                on_true: on_true.0,
+                on_false: on_false.0,
+            },
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                tail,
+            } => Op::Call {
+                function: self.indices[&function.0],
+                dst: dst.map(|dst| self.register(dst)),
+                arguments: arguments
+                    .iter()
+                    .map(|argument| self.register(*argument))
+                    .collect(),
+                tail: *tail,
+            },
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                let from = match cast_op {
+                    CastOp::ZeroExtend | CastOp::SignExtend => IntegerKind {
+                        num_bits: self.kind(*value).num_bits,
+                        is_signed: *cast_op == CastOp::SignExtend,
+                    },
+                    CastOp::Truncate | CastOp::BitCast => IntegerKind::WORD,
+                };
+                Op::Cast {
+                    from,
+                    to: self.kind(*dst),
+                    dst: self.register(*dst),
+                    src: self.register(*value),
+                }
+            }
+            Instruction::GetElementPtr { dst, ptr, index } => {
+                let ptr_type = self.ty(*ptr);
+                let pointee = self.types.strip_pointer(ptr_type).unwrap();
+                let same_type = self.types.get(ptr_type) == self.types.get(self.ty(*dst));
+                if !same_type && self.types.is_struct(pointee) {
+                    let field = self
+                        .function
+                        .constant(*index)
+                        .and_then(|c| c.integer())
+                        .expect("struct field index must be a constant");
+                    Op::Offset {
+                        dst: self.register(*dst),
+                        base: self.register(*ptr),
+                        offset: field_offset(self.types, pointee, field as usize),
+                    }
+                } else {
+                    Op::Address {
+                        dst: self.register(*dst),
+                        base: self.register(*ptr),
+                        index: self.register(*index),
+                        scale: size_of(self.types, pointee),
+                    }
+                }
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => Op::Compare {
+                pred: *pred,
+                is_signed: self.kind(*lhs).is_signed,
+                dst: self.register(*dst),
+                lhs: self.register(*lhs),
+                rhs: self.register(*rhs),
+            },
+            Instruction::Load { dst, ptr } => Op::Load {
+                kind: self.kind(*dst),
+                dst: self.register(*dst),
+                address: self.register(*ptr),
+            },
+            Instruction::Return { value } => Op::Return {
+                src: value.map(|value| self.register(value)),
+            },
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => Op::Select {
+                dst: self.register(*dst),
+                condition: self.register(*condition),
+                on_true: self.register(*on_true),
+                on_false: self.register(*on_false),
+            },
+            Instruction::StackAlloc { dst, ty, size } => {
+                let offset = match self.allocations.get(dst) {
+                    Some(offset) => *offset,
+                    None => {
+                        let offset = align_up(self.frame_size, align_of(self.types, *ty));
+                        self.frame_size =
+                            offset + size_of(self.types, *ty) * *size as u64;
+                        self.allocations.insert(*dst, offset);
+                        offset
+                    }
+                };
+                Op::FrameAddress {
+                    dst: self.register(*dst),
+                    offset,
+                }
+            }
+            Instruction::Store { ptr, value } => Op::Store {
+                size: self.kind(*value).size() as u8,
+                address: self.register(*ptr),
+                src: self.register(*value),
+            },
+            Instruction::Nop => return,
+        };
+
+        self.code.push(op);
+    }
+
+    /// The register of `value`, allocated on first use.
+    fn register(&mut self, value: Value) -> Register {
+        if let Some(register) = self.registers.get(&value) {
+            return *register;
+        }
+
+        let register = self.registers.len() as Register;
+        self.registers.insert(value, register);
+        if let Some(constant) = self.function.constant(value) {
+            let bits = match constant {
+                ConstantValue::Integer { value: bits, .. } => bits,
+                ConstantValue::Float { .. } => unreachable!("rejected by `check_types`"),
+            };
+            self.constants
+                .push((register, self.kind(value).canonicalize(bits)));
+        }
+        register
+    }
+
+    fn kind(&self, value: Value) -> IntegerKind {
+        IntegerKind::of(self.types, self.ty(value))
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    backend::bytecode::{IntegerKind, Op, Program, Register},
+    instruction::{BinaryOp, IntCompareOp, UnaryOp},
+};
+use std::fmt;
+
+/// Memory below this address is never handed out, so null pointers trap.
+const NULL_GUARD: usize = 16;
+/// Frames are aligned to this, the largest alignment of any type.
+const FRAME_ALIGNMENT: usize = 16;
+/// Bytes of memory all frames may use together.
+const MAX_MEMORY: usize = 64 << 20;
+/// Frames that may be live at the same time.
+const MAX_FRAMES: usize = 1 << 16;
+
+/// Why the execution of a program stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    /// Division or remainder by zero.
+    DivisionByZero,
+    /// Signed division of the smallest 64 bit integer by -1.
+    IntegerOverflow,
+    /// Access to memory outside of the frames of the running functions.
+    OutOfBounds { address: u64, size: u64 },
+    /// Too many nested calls, or too much `stack_alloc` memory.
+    StackOverflow,
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapKind::DivisionByZero => write!(f, "division by zero"),
+            TrapKind::IntegerOverflow => write!(f, "integer overflow"),
+            TrapKind::OutOfBounds { address, size } => {
+                write!(f, "out of bounds access of {size} bytes at {address:#x}")
+            }
+            TrapKind::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
+
+/// A function running when a [`Trap`] happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: String,
+    /// Index of the instruction executing in the bytecode of the function.
+    pub instruction: usize,
+}
+
+/// Error stopping the execution of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// Functions running at the time, innermost first.
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        for frame in self.backtrace.iter() {
+            write!(f, "\n  at {}+{}", frame.function, frame.instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Trap {}
+
+impl Program {
+    /// Calls the function `name` with `arguments`, one per parameter, and returns its
+    /// result extended to 64 bits according to its signedness.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no function `name`, or the number of arguments is wrong.
+    pub fn run(&self, name: &str, arguments: &[u64]) -> Result<Option<u64>, Trap> {
+        let function = self
+            .function(name)
+            .unwrap_or_else(|| panic!("no function named `{name}`"));
+        let parameters = &self.functions[function].parameters;
+        assert_eq!(
+            arguments.len(),
+            parameters.len(),
+            "wrong number of arguments"
+        );
+
+        let mut vm = Vm {
+            program: self,
+            registers: Vec::new(),
+            memory: vec![0; NULL_GUARD],
+            frames: Vec::new(),
+        };
+        let arguments = arguments
+            .iter()
+            .zip(parameters.iter())
+            .map(|(argument, kind)| kind.canonicalize(*argument))
+            .collect::<Vec<_>>();
+        vm.push_frame(function, &arguments, None)
+            .map_err(|kind| vm.trap(kind))?;
+        vm.execute().map_err(|kind| vm.trap(kind))
+    }
+}
+
+/// A call in progress.
+#[derive(Debug)]
+struct Frame {
+    function: usize,
+    /// Index of the next instruction, or of the one executing when the frame is not
+    /// the innermost.
+    pc: usize,
+    /// Start of the registers of the frame in [`Vm::registers`].
+    registers: usize,
+    /// Start of the `stack_alloc` memory of the frame in [`Vm::memory`].
+    memory: usize,
+    /// Register of the caller receiving the result.
+    dst: Option<Register>,
+}
+
+/// State of a running program.
+struct Vm<'a> {
+    program: &'a Program,
+    /// Registers of all frames, the innermost last.
+    registers: Vec<u64>,
+    memory: Vec<u8>,
+    frames: Vec<Frame>,
+}
+
+impl Vm<'_> {
+    /// Runs until the outermost frame returns.
+    fn execute(&mut self) -> Result<Option<u64>, TrapKind> {
+        let program = self.program;
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let base = frame.registers;
+            let op = &program.functions[frame.function].code[frame.pc];
+            frame.pc += 1;
+
+            match op {
+                Op::Binary {
+                    op,
+                    kind,
+                    dst,
+                    lhs,
+                    rhs,
+                } => {
+                    let (a, b) = (self.get(base, *lhs), self.get(base, *rhs));
+                    let result = binary(*op, *kind, a, b)?;
+                    self.set(base, *dst, kind.canonicalize(result));
+                }
+                Op::Unary { op, kind, dst, src } => {
+                    let a = self.get(base, *src);
+                    let result = match op {
+                        UnaryOp::Neg => a.wrapping_neg(),
+                        UnaryOp::Not => !a,
+                    };
+                    self.set(base, *dst, kind.canonicalize(result));
+                }
+                Op::Compare {
+                    pred,
+                    is_signed,
+                    dst,
+                    lhs,
+                    rhs,
+                } => {
+                    let (a, b) = (self.get(base, *lhs), self.get(base, *rhs));
+                    let ordering = if *is_signed {
+                        (a as i64).cmp(&(b as i64))
+                    } else {
+                        a.cmp(&b)
+                    };
+                    let result = match pred {
+                        IntCompareOp::Equal => ordering.is_eq(),
+                        IntCompareOp::NotEqual => ordering.is_ne(),
+                        IntCompareOp::GreaterThan => ordering.is_gt(),
+                        IntCompareOp::GreaterThanOrEqual => ordering.is_ge(),
+                        IntCompareOp::LessThan => ordering.is_lt(),
+                        IntCompareOp::LessThanOrEqual => ordering.is_le(),
+                    };
+                    self.set(base, *dst, u64::from(result));
+                }
+                Op::Cast { from, to, dst, src } => {
+                    let a = self.get(base, *src);
+                    self.set(base, *dst, to.canonicalize(from.canonicalize(a)));
+                }
+                Op::Select {
+                    dst,
+                    condition,
+                    on_true,
+                    on_false,
+                } => {
+                    let src = if self.get(base, *condition) != 0 {
+                        on_true
+                    } else {
+                        on_false
+                    };
+                    self.set(base, *dst, self.get(base, *src));
+                }
+                Op::Address {
+                    dst,
+                    base: pointer,
+                    index,
+                    scale,
+                } => {
+                    let offset = self.get(base, *index).wrapping_mul(*scale);
+                    let address = self.get(base, *pointer).wrapping_add(offset);
+                    self.set(base, *dst, address);
+                }
+                Op::Offset {
+                    dst,
+                    base: pointer,
+                    offset,
+                } => {
+                    let address = self.get(base, *pointer).wrapping_add(*offset);
+                    self.set(base, *dst, address);
+                }
+                Op::FrameAddress { dst, offset } => {
+                    let memory = self.frames.last().unwrap().memory as u64;
+                    self.set(base, *dst, memory + offset);
+                }
+                Op::Load { kind, dst, address } => {
+                    let address = self.get(base, *address);
+                    let bytes = self.memory(address, kind.size())?;
+                    let mut value = [0; 8];
+                    value[..bytes.len()].copy_from_slice(bytes);
+                    self.set(base, *dst, kind.canonicalize(u64::from_le_bytes(value)));
+                }
+                Op::Store { size, address, src } => {
+                    let value = self.get(base, *src).to_le_bytes();
+                    let address = self.get(base, *address);
+                    let size = usize::from(*size);
+                    self.memory(address, size)?.copy_from_slice(&value[..size]);
+                }
+                Op::Jump { target } => {
+                    self.frames.last_mut().unwrap().pc = *target as usize;
+                }
+                Op::Branch {
+                    condition,
+                    on_true,
+                    on_false,
+                } => {
+                    let target = if self.get(base, *condition) != 0 {
+                        on_true
+                    } else {
+                        on_false
+                    };
+                    self.frames.last_mut().unwrap().pc = *target as usize;
+                }
+                Op::Call {
+                    function,
+                    dst,
+                    arguments,
+                    tail,
+                } => {
+                    let arguments: Vec<u64> = arguments
+                        .iter()
+                        .map(|argument| self.get(base, *argument))
+                        .collect();
+                    let dst = if *tail {
+                        // The callee returns straight to the caller of this frame.
+                        let frame = self.pop_frame();
+                        frame.dst
+                    } else {
+                        *dst
+                    };
+                    self.push_frame(*function as usize, &arguments, dst)?;
+                }
+                Op::Return { src } => {
+                    let value = src.map(|src| self.get(base, src));
+                    let frame = self.pop_frame();
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    if let (Some(dst), Some(value)) = (frame.dst, value) {
+                        let base = self.frames.last().unwrap().registers;
+                        self.set(base, dst, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Enters `function`, returning to `dst` in the current frame.
+    fn push_frame(
+        &mut self,
+        function: usize,
+        arguments: &[u64],
+        dst: Option<Register>,
+    ) -> Result<(), TrapKind> {
+        let code = &self.program.functions[function];
+        let memory =
+            (self.memory.len() + FRAME_ALIGNMENT - 1) / FRAME_ALIGNMENT * FRAME_ALIGNMENT;
+        let end = memory.saturating_add(code.frame_size as usize);
+        if self.frames.len() == MAX_FRAMES || end > MAX_MEMORY {
+            return Err(TrapKind::StackOverflow);
+        }
+        self.memory.resize(end, 0);
+
+        let registers = self.registers.len();
+        self.registers
+            .resize(registers + code.num_registers as usize, 0);
+        self.registers[registers..registers + arguments.len()].copy_from_slice(arguments);
+        for (register, value) in code.constants.iter() {
+            self.registers[registers + *register as usize] = *value;
+        }
+
+        self.frames.push(Frame {
+            function,
+            pc: 0,
+            registers,
+            memory,
+            dst,
+        });
+        Ok(())
+    }
+
+    /// Leaves the innermost frame, releasing its registers and memory.
+    fn pop_frame(&mut self) -> Frame {
+        let frame = self.frames.pop().unwrap();
+        self.registers.truncate(frame.registers);
+        self.memory.truncate(frame.memory);
+        frame
+    }
+
+    fn get(&self, base: usize, register: Register) -> u64 {
+        self.registers[base + register as usize]
+    }
+
+    fn set(&mut self, base: usize, register: Register, value: u64) {
+        self.registers[base + register as usize] = value;
+    }
+
+    /// The `size` bytes of memory at `address`.
+    fn memory(&mut self, address: u64, size: usize) -> Result<&mut [u8], TrapKind> {
+        let out_of_bounds = TrapKind::OutOfBounds {
+            address,
+            size: size as u64,
+        };
+        let start = usize::try_from(address).map_err(|_| out_of_bounds)?;
+        if start < NULL_GUARD || start.saturating_add(size) > self.memory.len() {
+            return Err(out_of_bounds);
+        }
+        Ok(&mut self.memory[start..start + size])
+    }
+
+    /// The [`Trap`] for `kind` happening in the current state.
+    fn trap(&self, kind: TrapKind) -> Trap {
+        let backtrace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| StackFrame {
+                function: self.program.functions[frame.function].name.clone(),
+                // The pc has moved past the instruction being executed.
+                instruction: frame.pc.saturating_sub(1),
+            })
+            .collect();
+        Trap { kind, backtrace }
+    }
+}
+
+/// `a op b` on registers of kind `kind`, before bringing the result back to `kind`.
+fn binary(op: BinaryOp, kind: IntegerKind, a: u64, b: u64) -> Result<u64, TrapKind> {
+    let shift = (b & 63) as u32;
+    let result = match op {
+        BinaryOp::Add => a.wrapping_add(b),
+        BinaryOp::Sub => a.wrapping_sub(b),
+        BinaryOp::Mul => a.wrapping_mul(b),
+        BinaryOp::Div | BinaryOp::Mod if b == 0 => return Err(TrapKind::DivisionByZero),
+        BinaryOp::Div if kind.is_signed => (a as i64)
+            .checked_div(b as i64)
+            .ok_or(TrapKind::IntegerOverflow)?
+            as u64,
+        BinaryOp::Div => a / b,
+        BinaryOp::Mod if kind.is_signed => (a as i64)
+            .checked_rem(b as i64)
+            .ok_or(TrapKind::IntegerOverflow)?
+            as u64,
+        BinaryOp::Mod => a % b,
+        BinaryOp::Shl => a << shift,
+        // Shifting right brings in the bits above the width, which have to be zeros
+        // or copies of the sign bit whatever the signedness of the kind.
+        BinaryOp::Shr => {
+            let unsigned = IntegerKind {
+                is_signed: false,
+                ..kind
+            };
+            unsigned.canonicalize(a) >> shift
+        }
+        BinaryOp::Sar => {
+            let signed = IntegerKind {
+                is_signed: true,
+                ..kind
+            };
+            ((signed.canonicalize(a) as i64) >> shift) as u64
+        }
+        BinaryOp::And | BinaryOp::BitAnd => a & b,
+        BinaryOp::Or | BinaryOp::BitOr => a | b,
+        BinaryOp::Xor => a ^ b,
+    };
+
+    Ok(result)
+}
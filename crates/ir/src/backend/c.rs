@@ -0,0 +1,451 @@
+use crate::{
+    backend::{block_order, check_types, Unsupported},
+    constant::ConstantValue,
+    function::{FunctionData, Functions, Linkage},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use std::fmt::Write;
+
+/// Translates every function of the module to a standalone C99 translation unit.
+///
+/// Each value becomes a local variable declared at the top of its function, each
+/// block a label jumped to with `goto`, and each `stack_alloc` a local array.
+/// Integer arithmetic is done on `uint64_t` and converted back to the type of the
+/// result, so overflow wraps as in the IR instead of being undefined. Floats are
+/// `float` and `double`, with `fmod` from `<math.h>` for remainders.
+///
+/// Integers of 1, 8, 16, 32 and 64 bits and floats of 32 and 64 bits are supported.
+/// ```text
+/// fn add(a: u32, b: u32) -> u32        uint32_t add(uint32_t v0, uint32_t v1) {
+///                                          uint32_t v2;
+/// let v2: u32 = add v0, v1             L0:
+/// ret v2                                   v2 = (uint32_t)((uint64_t)v0 + (uint64_t)v1);
+///                                          return v2;
+///                                      }
+/// ```
+pub(crate) fn emit(types: &Types, functions: &Functions) -> Result<String, Unsupported> {
+    check_types(types, functions, |ty| is_supported(types, ty))?;
+
+    let mut out = String::new();
+    writeln!(out, "#include <math.h>").unwrap();
+    writeln!(out, "#include <stdint.h>").unwrap();
+    writeln!(out, "#include <string.h>\n").unwrap();
+
+    // Structs no value uses may have fields C cannot hold, and are not needed.
+    let structs: Vec<u32> = types
+        .iter()
+        .filter(|(id, data)| {
+            matches!(data.type_kind(), TypeKind::Struct { .. })
+                && is_supported(types, Type(*id))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    for id in structs.iter() {
+        writeln!(out, "struct t{id};").unwrap();
+    }
+    // Fields are created before the structs containing them, so definitions in handle
+    // order are complete where they are used.
+    for id in structs.iter() {
+        let TypeKind::Struct { types: fields } = types.get(Type(*id)).type_kind() else {
+            unreachable!();
+        };
+        writeln!(out, "struct t{id} {{").unwrap();
+        for (i, field) in fields.iter().enumerate() {
+            writeln!(out, "    {} f{i};", c_type(types, *field)).unwrap();
+        }
+        writeln!(out, "}};").unwrap();
+    }
+    if !structs.is_empty() {
+        writeln!(out).unwrap();
+    }
+
+    for (_, function) in functions.iter() {
+        writeln!(out, "{};", signature(types, function)).unwrap();
+    }
+
+    for (_, function) in functions.iter() {
+        writeln!(out).unwrap();
+        let mut emitter = FunctionEmitter {
+            types,
+            functions,
+            function,
+            out: &mut out,
+        };
+        emitter.emit();
+    }
+
+    Ok(out)
+}
+
+/// Returns `true` if `ty`, and every type it holds or points to, has a C type.
+fn is_supported(types: &Types, ty: Type) -> bool {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } => matches!(num_bits, 1 | 8 | 16 | 32 | 64),
+        TypeKind::Float { num_bits } => matches!(num_bits, 32 | 64),
+        TypeKind::Struct { types: fields } => {
+            fields.iter().all(|field| is_supported(types, *field))
+        }
+        TypeKind::Pointer { ty } => is_supported(types, *ty),
+    }
+}
+
+/// The C type holding values of type `ty`. `u1` is held in a `uint8_t` as 0 or 1.
+fn c_type(types: &Types, ty: Type) -> String {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer {
+            num_bits,
+            is_signed,
+        } => {
+            let bits = match num_bits {
+                1 | 8 => 8,
+                16 | 32 | 64 => *num_bits,
+                _ => unreachable!("rejected by `is_supported`"),
+            };
+            let prefix = if *is_signed { "" } else { "u" };
+            format!("{prefix}int{bits}_t")
+        }
+        TypeKind::Float { num_bits: 32 } => "float".to_string(),
+        TypeKind::Float { num_bits: 64 } => "double".to_string(),
+        TypeKind::Float { .. } => unreachable!("rejected by `is_supported`"),
+        TypeKind::Struct { .. } => format!("struct t{}", ty.id()),
+        TypeKind::Pointer { ty } => format!("{}*", c_type(types, *ty)),
+    }
+}
+
+/// The C declaration of `function`, without the trailing `;` or body.
+fn signature(types: &Types, function: &FunctionData) -> String {
+    let definition = function.definition();
+    let storage = match definition.linkage {
+        Linkage::External => "",
+        Linkage::Internal => "static ",
+    };
+    let return_type = definition
+        .return_type
+        .map_or("void".to_string(), |ty| c_type(types, ty));
+    let parameters = if function.parameters().is_empty() {
+        "void".to_string()
+    } else {
+        function
+            .parameters()
+            .iter()
+            .map(|parameter| {
+                let ty = function.values().get(*parameter).ty();
+                format!("{} v{}", c_type(types, ty), parameter.id())
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!("{storage}{return_type} {}({parameters})", definition.name)
+}
+
+/// Emits the definition of a single function.
+struct FunctionEmitter<'a> {
+    types: &'a Types,
+    functions: &'a Functions,
+    function: &'a FunctionData,
+    out: &'a mut String,
+}
+
+impl FunctionEmitter<'_> {
+    fn emit(&mut self) {
+        writeln!(self.out, "{} {{", signature(self.types, self.function)).unwrap();
+
+        let mut created: Vec<Value> = self
+            .function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .filter_map(|instr| instr.creates())
+            .collect();
+        created.sort();
+        for value in created {
+            let ty = c_type(self.types, self.ty(value));
+            writeln!(self.out, "    {ty} v{};", value.id()).unwrap();
+        }
+
+        let mut allocations: Vec<(Value, Type, usize)> = self
+            .function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .filter_map(|instr| match instr {
+                Instruction::StackAlloc { dst, ty, size } => Some((*dst, *ty, *size)),
+                _ => None,
+            })
+            .collect();
+        allocations.sort_by_key(|(dst, _, _)| *dst);
+        for (dst, ty, size) in allocations {
+            let ty = c_type(self.types, ty);
+            writeln!(self.out, "    {ty} a{}[{size}];", dst.id()).unwrap();
+        }
+
+        for label in block_order(self.function) {
+            writeln!(self.out, "L{}:", label.id()).unwrap();
+            for instr in self.function.labels().get(label).instructions.iter() {
+                if let Some(statement) = self.statement(instr) {
+                    writeln!(self.out, "    {statement}").unwrap();
+                }
+            }
+        }
+
+        writeln!(self.out, "}}").unwrap();
+    }
+
+    /// The C statement performing `instr`.
+    fn statement(&self, instr: &Instruction) -> Option<String> {
+        let statement = match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs }
+                if self.is_float(*dst) =>
+            {
+                let (a, b) = (self.value(*lhs), self.value(*rhs));
+                let expr = match op {
+                    BinaryOp::Add => format!("{a} + {b}"),
+                    BinaryOp::Sub => format!("{a} - {b}"),
+                    BinaryOp::Mul => format!("{a} * {b}"),
+                    BinaryOp::Div => format!("{a} / {b}"),
+                    BinaryOp::Mod if self.num_bits(*dst) == 32 => {
+                        format!("fmodf({a}, {b})")
+                    }
+                    BinaryOp::Mod => format!("fmod({a}, {b})"),
+                    op => panic!("{op:?} is not defined on floats"),
+                };
+                self.assign(*dst, &expr)
+            }
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => {
+                let (a, b) = (self.value(*lhs), self.value(*rhs));
+                let wide =
+                    |operator: &str| format!("(uint64_t){a} {operator} (uint64_t){b}");
+                let shift = format!("((uint64_t){b} & 63)");
+                let expr = match op {
+                    BinaryOp::Add => wide("+"),
+                    BinaryOp::Sub => wide("-"),
+                    BinaryOp::Mul => wide("*"),
+                    // Operands of the same type, promoted to at least `int`.
+                    BinaryOp::Div => format!("{a} / {b}"),
+                    BinaryOp::Mod => format!("{a} % {b}"),
+                    BinaryOp::Shl => format!("(uint64_t){a} << {shift}"),
+                    BinaryOp::Shr => {
+                        let unsigned = self.integer_type(*lhs, false);
+                        format!("(uint64_t)({unsigned}){a} >> {shift}")
+                    }
+                    BinaryOp::Sar => {
+                        let signed = self.integer_type(*lhs, true);
+                        format!("(int64_t)({signed}){a} >> {shift}")
+                    }
+                    BinaryOp::And | BinaryOp::BitAnd => wide("&"),
+                    BinaryOp::Or | BinaryOp::BitOr => wide("|"),
+                    BinaryOp::Xor => wide("^"),
+                };
+                self.assign(*dst, &expr)
+            }
+            Instruction::ArithmeticUnary { dst, op, value } => {
+                let a = self.value(*value);
+                let expr = match op {
+                    UnaryOp::Neg if self.is_float(*dst) => format!("-{a}"),
+                    UnaryOp::Neg => format!("0 - (uint64_t){a}"),
+                    UnaryOp::Not => format!("~(uint64_t){a}"),
+                };
+                self.assign(*dst, &expr)
+            }
+            Instruction::Branch { target } => format!("goto L{};", target.id()),
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => format!(
+                "if ({}) goto L{}; else goto L{};",
+                self.value(*condition),
+                on_true.id(),
+                on_false.id()
+            ),
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                ..
+            } => {
+                let name = &self.functions.get(*function).definition().name;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(*argument))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match dst {
+                    Some(dst) => format!("v{} = {name}({arguments});", dst.id()),
+                    None => format!("{name}({arguments});"),
+                }
+            }
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                let a = self.value(*value);
+                let expr = match cast_op {
+                    // Reinterprets the bits, through a copy of the operand.
+                    CastOp::BitCast if self.is_float(*value) != self.is_float(*dst) => {
+                        let ty = c_type(self.types, self.ty(*value));
+                        let dst = dst.id();
+                        return Some(format!(
+                            "memcpy(&v{dst}, &({ty}){{{a}}}, sizeof v{dst});"
+                        ));
+                    }
+                    // Converts between `float` and `double`.
+                    _ if self.is_float(*value) => a,
+                    CastOp::SignExtend if self.num_bits(*value) == 1 => {
+                        format!("0 - (uint64_t){a}")
+                    }
+                    CastOp::SignExtend => {
+                        format!("({}){a}", self.integer_type(*value, true))
+                    }
+                    CastOp::ZeroExtend => {
+                        format!("({}){a}", self.integer_type(*value, false))
+                    }
+                    CastOp::Truncate | CastOp::BitCast => a,
+                };
+                self.assign(*dst, &expr)
+            }
+            Instruction::GetElementPtr { dst, ptr, index } => {
+                let ptr_type = self.ty(*ptr);
+                let pointee = self.types.strip_pointer(ptr_type).unwrap();
+                let same_type = self.types.get(ptr_type) == self.types.get(self.ty(*dst));
+                let (p, i) = (self.value(*ptr), self.value(*index));
+                if !same_type && self.types.is_struct(pointee) {
+                    let field = self
+                        .function
+                        .constant(*index)
+                        .and_then(|c| c.integer())
+                        .expect("struct field index must be a constant");
+                    format!("v{} = &{p}->f{field};", dst.id())
+                } else {
+                    let index_type = self.integer_type(*index, self.is_signed(*index));
+                    format!("v{} = {p} + ({index_type}){i};", dst.id())
+                }
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let operator = match pred {
+                    IntCompareOp::Equal => "==",
+                    IntCompareOp::NotEqual => "!=",
+                    IntCompareOp::GreaterThan => ">",
+                    IntCompareOp::GreaterThanOrEqual => ">=",
+                    IntCompareOp::LessThan => "<",
+                    IntCompareOp::LessThanOrEqual => "<=",
+                };
+                let (a, b) = (self.value(*lhs), self.value(*rhs));
+                format!("v{} = {a} {operator} {b};", dst.id())
+            }
+            Instruction::Load { dst, ptr } => {
+                format!("v{} = *{};", dst.id(), self.value(*ptr))
+            }
+            Instruction::Return { value } => match value {
+                Some(value) => format!("return {};", self.value(*value)),
+                None => "return;".to_string(),
+            },
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => format!(
+                "v{} = {} ? {} : {};",
+                dst.id(),
+                self.value(*condition),
+                self.value(*on_true),
+                self.value(*on_false)
+            ),
+            Instruction::StackAlloc { dst, .. } => {
+                format!("v{} = a{};", dst.id(), dst.id())
+            }
+            Instruction::Store { ptr, value } => {
+                format!("*{} = {};", self.value(*ptr), self.value(*value))
+            }
+            Instruction::Nop => return None,
+        };
+
+        Some(statement)
+    }
+
+    /// Assigns `expr` to `dst`, converted to its type. `u1` results keep their low bit.
+    fn assign(&self, dst: Value, expr: &str) -> String {
+        let ty = c_type(self.types, self.ty(dst));
+        if self.num_bits(dst) == 1 {
+            format!("v{} = ({ty})(({expr}) & 1);", dst.id())
+        } else {
+            format!("v{} = ({ty})({expr});", dst.id())
+        }
+    }
+
+    /// The expression reading `value`, a variable or a literal.
+    fn value(&self, value: Value) -> String {
+        let Some(constant) = self.function.constant(value) else {
+            return format!("v{}", value.id());
+        };
+
+        let ty = c_type(self.types, self.ty(value));
+        match constant {
+            ConstantValue::Integer { value, .. } if self.is_signed_value(value) => {
+                format!("(({ty}){})", value as i64)
+            }
+            ConstantValue::Integer { value, .. } => format!("(({ty})UINT64_C({value}))"),
+            ConstantValue::Float { value, .. } if value.is_nan() => {
+                format!("(({ty})NAN)")
+            }
+            ConstantValue::Float { value, .. } if value.is_infinite() => {
+                let sign = if value < 0.0 { "-" } else { "" };
+                format!("(({ty}){sign}INFINITY)")
+            }
+            ConstantValue::Float { value, .. } => format!("(({ty}){value:?})"),
+        }
+    }
+
+    /// Returns `true` if the bits of a constant are a small negative number, printed
+    /// as such for readability.
+    fn is_signed_value(&self, value: u64) -> bool {
+        (value as i64) < 0 && (value as i64) > i64::from(i32::MIN)
+    }
+
+    /// The integer type with the width of `value`, signed or not.
+    fn integer_type(&self, value: Value, is_signed: bool) -> String {
+        let bits = match self.num_bits(value) {
+            1 => 8,
+            num_bits => num_bits,
+        };
+        let prefix = if is_signed { "" } else { "u" };
+        format!("{prefix}int{bits}_t")
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+
+    fn num_bits(&self, value: Value) -> u32 {
+        match self.types.get(self.ty(value)).type_kind() {
+            TypeKind::Integer { num_bits, .. } | TypeKind::Float { num_bits } => {
+                *num_bits
+            }
+            _ => 64,
+        }
+    }
+
+    fn is_float(&self, value: Value) -> bool {
+        matches!(
+            self.types.get(self.ty(value)).type_kind(),
+            TypeKind::Float { .. }
+        )
+    }
+
+    fn is_signed(&self, value: Value) -> bool {
+        self.types
+            .integer(self.ty(value))
+            .is_some_and(|(_, is_signed)| is_signed)
+    }
+}
@@ -0,0 +1,543 @@
+//! DWARF 4 debug information for the native backends: a line table mapping code to
+//! source locations, and a compile unit describing the functions and their parameters.
+//!
+//! Sections are built as bytes with [`Fixup`]s where addresses and sizes go, filled in
+//! by whoever lays out the code, with relocations or assembler expressions.
+
+use crate::{
+    debug_location::{DebugLocation, SourceFile},
+    function::{Function, Functions},
+    ty::{Type, TypeKind, Types},
+};
+use std::collections::HashMap;
+
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_POINTER_TYPE: u8 = 0x0F;
+const DW_TAG_SUBPROGRAM: u8 = 0x2E;
+const DW_TAG_FORMAL_PARAMETER: u8 = 0x05;
+
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_BYTE_SIZE: u8 = 0x0B;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LANGUAGE: u8 = 0x13;
+const DW_AT_ENCODING: u8 = 0x3E;
+const DW_AT_EXTERNAL: u8 = 0x3F;
+const DW_AT_FRAME_BASE: u8 = 0x40;
+const DW_AT_DECL_FILE: u8 = 0x3A;
+const DW_AT_DECL_LINE: u8 = 0x3B;
+const DW_AT_TYPE: u8 = 0x49;
+
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA1: u8 = 0x0B;
+const DW_FORM_DATA2: u8 = 0x05;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_DATA8: u8 = 0x07;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_FLAG: u8 = 0x0C;
+const DW_FORM_UDATA: u8 = 0x0F;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+
+const DW_ATE_BOOLEAN: u8 = 0x02;
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_ATE_UNSIGNED: u8 = 0x08;
+
+/// The source language isn't one DWARF knows, and C types and expressions are the
+/// closest for debuggers.
+const DW_LANG_C99: u16 = 0x0C;
+
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_FBREG: u8 = 0x91;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_FILE: u8 = 0x04;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Number of operands of the standard line opcodes, from `DW_LNS_copy` on.
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Abbreviation codes, in the order of [`abbreviations`].
+const COMPILE_UNIT: u8 = 1;
+const BASE_TYPE: u8 = 2;
+const POINTER_TYPE: u8 = 3;
+const SUBPROGRAM: u8 = 4;
+const VOID_SUBPROGRAM: u8 = 5;
+const FORMAL_PARAMETER: u8 = 6;
+
+/// A value only known once the code is laid out, to be written at an offset of a
+/// [`DebugSection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fixup {
+    /// 64 bit address of the start of `.text`.
+    TextStart,
+    /// 64 bit size of `.text`.
+    TextSize,
+    /// 64 bit address of a function.
+    FunctionStart(Function),
+    /// 64 bit size of the code of a function.
+    FunctionSize(Function),
+    /// 32 bit offset of the line table of the unit in `.debug_line`.
+    LineTable,
+    /// 32 bit offset of the abbreviations of the unit in `.debug_abbrev`.
+    Abbreviations,
+}
+
+impl Fixup {
+    /// Size in bytes of the value.
+    pub fn size(self) -> usize {
+        match self {
+            Fixup::LineTable | Fixup::Abbreviations => 4,
+            _ => 8,
+        }
+    }
+}
+
+/// Contents of a debug section, with zeros where the fixups go.
+#[derive(Default)]
+pub(crate) struct DebugSection {
+    pub data: Vec<u8>,
+    pub fixups: Vec<(usize, Fixup)>,
+}
+
+impl DebugSection {
+    fn fixup(&mut self, fixup: Fixup) {
+        self.fixups.push((self.data.len(), fixup));
+        self.data.resize(self.data.len() + fixup.size(), 0);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.data.extend(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn uleb(&mut self, value: u64) {
+        uleb(&mut self.data, value);
+    }
+
+    fn sleb(&mut self, value: i64) {
+        sleb(&mut self.data, value);
+    }
+
+    /// Writes the length of the unit starting at `start`, where a 32 bit placeholder
+    /// was left.
+    fn patch_length(&mut self, start: usize) {
+        let length = (self.data.len() - start - 4) as u32;
+        self.data[start..start + 4].copy_from_slice(&length.to_le_bytes());
+    }
+}
+
+/// Where a variable is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VariableLocation {
+    /// A register, by its DWARF number.
+    Register(u16),
+    /// Memory at an offset from the frame base of the function.
+    Frame(i64),
+}
+
+/// What the debug information says about a function, besides its
+/// [`FunctionDebugInfo`](crate::debug_location::FunctionDebugInfo).
+pub(crate) struct DebugFunction {
+    pub function: Function,
+    /// Register the frame base is kept in, by its DWARF number.
+    pub frame_base: u16,
+    /// Where every parameter is once the prologue has run, if anywhere. The location
+    /// only holds the parameter while it is live.
+    pub parameters: Vec<Option<VariableLocation>>,
+}
+
+/// Code, tag, whether entries have children, and the attributes with their form.
+type Abbreviation = (u8, u8, bool, &'static [(u8, u8)]);
+
+/// The `.debug_abbrev` section, describing the entries of [`debug_info`].
+pub(crate) fn abbreviations() -> Vec<u8> {
+    let abbreviations: [Abbreviation; 6] = [
+        (
+            COMPILE_UNIT,
+            DW_TAG_COMPILE_UNIT,
+            true,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_LANGUAGE, DW_FORM_DATA2),
+                (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA8),
+            ],
+        ),
+        (
+            BASE_TYPE,
+            DW_TAG_BASE_TYPE,
+            false,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_ENCODING, DW_FORM_DATA1),
+                (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+            ],
+        ),
+        (
+            POINTER_TYPE,
+            DW_TAG_POINTER_TYPE,
+            false,
+            &[(DW_AT_BYTE_SIZE, DW_FORM_DATA1)],
+        ),
+        (
+            SUBPROGRAM,
+            DW_TAG_SUBPROGRAM,
+            true,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_DECL_FILE, DW_FORM_UDATA),
+                (DW_AT_DECL_LINE, DW_FORM_DATA4),
+                (DW_AT_EXTERNAL, DW_FORM_FLAG),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA8),
+                (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+                (DW_AT_TYPE, DW_FORM_REF4),
+            ],
+        ),
+        (
+            VOID_SUBPROGRAM,
+            DW_TAG_SUBPROGRAM,
+            true,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_DECL_FILE, DW_FORM_UDATA),
+                (DW_AT_DECL_LINE, DW_FORM_DATA4),
+                (DW_AT_EXTERNAL, DW_FORM_FLAG),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA8),
+                (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+            ],
+        ),
+        (
+            FORMAL_PARAMETER,
+            DW_TAG_FORMAL_PARAMETER,
+            false,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_TYPE, DW_FORM_REF4),
+                (DW_AT_LOCATION, DW_FORM_EXPRLOC),
+            ],
+        ),
+    ];
+
+    let mut out = Vec::new();
+    for (code, tag, has_children, attributes) in abbreviations {
+        out.extend([code, tag, has_children as u8]);
+        for (attribute, form) in attributes {
+            out.extend([*attribute, *form]);
+        }
+        out.extend([0, 0]);
+    }
+    out.push(0);
+    out
+}
+
+/// The `.debug_info` section: a compile unit named after the first of the
+/// `source_files`, with a subprogram for every function with [`FunctionDebugInfo`],
+/// holding its parameters.
+///
+/// Integer, float and pointer types are described, pointers without what they point
+/// to. Parameters of other types are left out.
+///
+/// [`FunctionDebugInfo`]: crate::debug_location::FunctionDebugInfo
+pub(crate) fn debug_info(
+    types: &Types,
+    functions: &Functions,
+    source_files: &[String],
+    debug_functions: &[DebugFunction],
+) -> DebugSection {
+    let mut section = DebugSection::default();
+    section.u32(0);
+    section.u16(4);
+    section.fixup(Fixup::Abbreviations);
+    // Size of an address.
+    section.u8(8);
+
+    section.uleb(COMPILE_UNIT as u64);
+    section.string(&source_files[0]);
+    section.u16(DW_LANG_C99);
+    section.fixup(Fixup::LineTable);
+    section.fixup(Fixup::TextStart);
+    section.fixup(Fixup::TextSize);
+
+    // Every type used by a described function, before the functions referring to them.
+    let mut type_offsets = HashMap::new();
+    let described = debug_functions.iter().filter_map(|debug_function| {
+        let definition = functions.get(debug_function.function).definition();
+        definition.debug_info.as_ref()?;
+        Some((debug_function, definition))
+    });
+    for (_, definition) in described.clone() {
+        let used = definition
+            .return_type
+            .iter()
+            .chain(&definition.parameter_types);
+        for ty in used {
+            let Some(description) = TypeDescription::new(types, *ty) else {
+                continue;
+            };
+            type_offsets.entry(description).or_insert_with(|| {
+                let offset = section.data.len() as u32;
+                description.write(&mut section);
+                offset
+            });
+        }
+    }
+    let type_offset = |ty: Type| {
+        TypeDescription::new(types, ty).map(|description| type_offsets[&description])
+    };
+
+    for (debug_function, definition) in described {
+        let debug_info = definition.debug_info.as_ref().unwrap();
+        let return_type = definition.return_type.and_then(type_offset);
+        let code = match return_type {
+            Some(_) => SUBPROGRAM,
+            None => VOID_SUBPROGRAM,
+        };
+        section.uleb(code as u64);
+        section.string(&definition.name);
+        section.uleb(file_number(debug_info.location.file));
+        section.u32(debug_info.location.line);
+        section.u8((definition.linkage == crate::function::Linkage::External) as u8);
+        section.fixup(Fixup::FunctionStart(debug_function.function));
+        section.fixup(Fixup::FunctionSize(debug_function.function));
+        let mut frame_base = Vec::new();
+        register_location(&mut frame_base, debug_function.frame_base);
+        section.uleb(frame_base.len() as u64);
+        section.data.extend(frame_base);
+        if let Some(offset) = return_type {
+            section.u32(offset);
+        }
+
+        let parameters = debug_info
+            .parameter_names
+            .iter()
+            .zip(&definition.parameter_types)
+            .zip(&debug_function.parameters);
+        for ((name, ty), location) in parameters {
+            let Some(ty) = type_offset(*ty) else {
+                continue;
+            };
+            section.uleb(FORMAL_PARAMETER as u64);
+            section.string(name);
+            section.u32(ty);
+            // An empty expression for a parameter kept nowhere, as it is never used.
+            let mut expression = Vec::new();
+            match location {
+                Some(VariableLocation::Register(register)) => {
+                    register_location(&mut expression, *register)
+                }
+                Some(VariableLocation::Frame(offset)) => {
+                    expression.push(DW_OP_FBREG);
+                    sleb(&mut expression, *offset);
+                }
+                None => {}
+            }
+            section.uleb(expression.len() as u64);
+            section.data.extend(expression);
+        }
+        // End of the parameters.
+        section.u8(0);
+    }
+    // End of the compile unit.
+    section.u8(0);
+
+    section.patch_length(0);
+    section
+}
+
+/// Code of a function, with the locations of its instructions.
+pub(crate) struct LineSequence {
+    pub function: Function,
+    /// Offsets in the code where a location starts, in order.
+    pub rows: Vec<(usize, DebugLocation)>,
+    /// Size of the code.
+    pub size: usize,
+}
+
+/// Number of a file in the line table, which counts from 1.
+pub(crate) fn file_number(file: SourceFile) -> u64 {
+    file.id() as u64 + 1
+}
+
+/// The `.debug_line` section, with a sequence for every function.
+pub(crate) fn line_table(
+    source_files: &[String],
+    sequences: &[LineSequence],
+) -> DebugSection {
+    let mut section = DebugSection::default();
+    section.u32(0);
+    section.u16(4);
+    let header_length = section.data.len();
+    section.u32(0);
+    // Minimum instruction length, maximum operations per instruction, `is_stmt` by
+    // default, line base, line range and opcode base. Only standard opcodes are used,
+    // so the special opcodes don't matter.
+    section.data.extend([1, 1, 1, (-5i8) as u8, 14]);
+    section.u8(STANDARD_OPCODE_LENGTHS.len() as u8 + 1);
+    section.data.extend(STANDARD_OPCODE_LENGTHS);
+    // No include directories, and the source files relative to the compilation
+    // directory, with no modification time nor size.
+    section.u8(0);
+    for source_file in source_files {
+        section.string(source_file);
+        section.data.extend([0, 0, 0]);
+    }
+    section.u8(0);
+    let length = (section.data.len() - header_length - 4) as u32;
+    section.data[header_length..header_length + 4].copy_from_slice(&length.to_le_bytes());
+
+    for sequence in sequences
+        .iter()
+        .filter(|sequence| !sequence.rows.is_empty())
+    {
+        section.data.extend([0, 9, DW_LNE_SET_ADDRESS]);
+        section.fixup(Fixup::FunctionStart(sequence.function));
+
+        let (mut address, mut file, mut line, mut column) = (0, 1, 1, 0);
+        for (offset, location) in sequence.rows.iter() {
+            if *offset != address {
+                section.u8(DW_LNS_ADVANCE_PC);
+                section.uleb((offset - address) as u64);
+                address = *offset;
+            }
+            if file_number(location.file) != file {
+                section.u8(DW_LNS_SET_FILE);
+                section.uleb(file_number(location.file));
+                file = file_number(location.file);
+            }
+            if location.line != line {
+                section.u8(DW_LNS_ADVANCE_LINE);
+                section.sleb(location.line as i64 - line as i64);
+                line = location.line;
+            }
+            if location.column != column {
+                section.u8(DW_LNS_SET_COLUMN);
+                section.uleb(location.column as u64);
+                column = location.column;
+            }
+            section.u8(DW_LNS_COPY);
+        }
+
+        section.u8(DW_LNS_ADVANCE_PC);
+        section.uleb((sequence.size - address) as u64);
+        section.data.extend([0, 1, DW_LNE_END_SEQUENCE]);
+    }
+
+    section.patch_length(0);
+    section
+}
+
+/// How a type is described, shared by the types described the same way.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum TypeDescription {
+    Integer { num_bits: u32, is_signed: bool },
+    Float { num_bits: u32 },
+    Pointer,
+}
+
+impl TypeDescription {
+    fn new(types: &Types, ty: Type) -> Option<Self> {
+        match *types.get(ty).type_kind() {
+            TypeKind::Integer {
+                num_bits,
+                is_signed,
+            } => Some(TypeDescription::Integer {
+                num_bits,
+                is_signed,
+            }),
+            TypeKind::Float { num_bits } => Some(TypeDescription::Float { num_bits }),
+            TypeKind::Pointer { .. } => Some(TypeDescription::Pointer),
+            TypeKind::Struct { .. } => None,
+        }
+    }
+
+    fn write(self, section: &mut DebugSection) {
+        let (name, encoding, num_bits) = match self {
+            TypeDescription::Integer { num_bits: 1, .. } => {
+                ("bool".to_string(), DW_ATE_BOOLEAN, 8)
+            }
+            TypeDescription::Integer {
+                num_bits,
+                is_signed,
+            } => {
+                let (prefix, encoding) = match is_signed {
+                    true => ('i', DW_ATE_SIGNED),
+                    false => ('u', DW_ATE_UNSIGNED),
+                };
+                (format!("{prefix}{num_bits}"), encoding, num_bits)
+            }
+            TypeDescription::Float { num_bits } => {
+                (format!("f{num_bits}"), DW_ATE_FLOAT, num_bits)
+            }
+            TypeDescription::Pointer => {
+                section.uleb(POINTER_TYPE as u64);
+                section.u8(8);
+                return;
+            }
+        };
+        section.uleb(BASE_TYPE as u64);
+        section.string(&name);
+        section.u8(encoding);
+        section.u8(((num_bits + 7) / 8).next_power_of_two() as u8);
+    }
+}
+
+/// Expression of the location of a value kept in `register`.
+fn register_location(out: &mut Vec<u8>, register: u16) {
+    if register < 32 {
+        out.push(DW_OP_REG0 + register as u8);
+    } else {
+        out.push(DW_OP_REGX);
+        uleb(out, register as u64);
+    }
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
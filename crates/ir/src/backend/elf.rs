@@ -0,0 +1,328 @@
+//! Writer of ELF64 relocatable object files, for the system linker.
+
+use super::align_up;
+
+/// `e_machine` of x86-64 objects.
+pub(crate) const EM_X86_64: u16 = 62;
+
+/// Absolute 64 bit address.
+pub(crate) const R_X86_64_64: u32 = 1;
+/// Displacement to a function through the PLT, relative to the place.
+pub(crate) const R_X86_64_PLT32: u32 = 4;
+/// Absolute 32 bit address, zero extended.
+pub(crate) const R_X86_64_32: u32 = 10;
+
+const ET_REL: u16 = 1;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+const HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const RELOCATION_SIZE: usize = 24;
+
+/// A section with contents, and the relocations applied to them.
+pub(crate) struct Section {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub alignment: u64,
+    pub is_executable: bool,
+    /// Whether the section is loaded in memory, as opposed to e.g. notes.
+    pub is_allocated: bool,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Section {
+    /// An empty section holding no data, e.g. `.note.GNU-stack`.
+    pub fn note(name: &str) -> Self {
+        Section {
+            name: name.to_string(),
+            data: Vec::new(),
+            alignment: 1,
+            is_executable: false,
+            is_allocated: false,
+            relocations: Vec::new(),
+        }
+    }
+}
+
+/// What a [`Symbol`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymbolKind {
+    Function,
+    /// The start of its section, for relocations between sections. Has no name.
+    Section,
+}
+
+/// A function or section defined in the object.
+pub(crate) struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Index of the section in [`ObjectFile::sections`].
+    pub section: usize,
+    pub offset: u64,
+    pub size: u64,
+    pub is_global: bool,
+}
+
+impl Symbol {
+    /// The local symbol of the section at `index` in [`ObjectFile::sections`].
+    pub fn section(index: usize) -> Self {
+        Symbol {
+            name: String::new(),
+            kind: SymbolKind::Section,
+            section: index,
+            offset: 0,
+            size: 0,
+            is_global: false,
+        }
+    }
+}
+
+/// A value to patch at `offset` of a section, computed by the linker from the address
+/// of a symbol.
+pub(crate) struct Relocation {
+    pub offset: u64,
+    /// Index of the symbol in [`ObjectFile::symbols`].
+    pub symbol: usize,
+    pub kind: u32,
+    pub addend: i64,
+}
+
+/// An ELF64 little endian relocatable object file.
+pub(crate) struct ObjectFile {
+    pub machine: u16,
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl ObjectFile {
+    /// The bytes of the object file.
+    ///
+    /// The contents of the sections come right after the header, followed by their
+    /// relocation tables, the symbol table, the string tables and the section headers.
+    /// Sections keep their order, after the null section.
+    pub fn write(&self) -> Vec<u8> {
+        // Local symbols have to come before global ones, after the null symbol.
+        let mut order: Vec<usize> = (0..self.symbols.len()).collect();
+        order.sort_by_key(|&symbol| self.symbols[symbol].is_global);
+        let mut symbol_index = vec![0; self.symbols.len()];
+        for (i, symbol) in order.iter().enumerate() {
+            symbol_index[*symbol] = i + 1;
+        }
+        let first_global = 1 + self.symbols.iter().filter(|s| !s.is_global).count();
+
+        let mut strings = StringTable::default();
+        let mut symbol_table = vec![0; SYMBOL_SIZE];
+        for symbol in order.iter().map(|&symbol| &self.symbols[symbol]) {
+            let binding = if symbol.is_global {
+                STB_GLOBAL
+            } else {
+                STB_LOCAL
+            };
+            let (name, kind) = match symbol.kind {
+                SymbolKind::Function => (strings.add(&symbol.name), STT_FUNC),
+                SymbolKind::Section => (0, STT_SECTION),
+            };
+            symbol_table.extend(name.to_le_bytes());
+            symbol_table.push(binding << 4 | kind);
+            symbol_table.push(0);
+            symbol_table.extend((symbol.section as u16 + 1).to_le_bytes());
+            symbol_table.extend(symbol.offset.to_le_bytes());
+            symbol_table.extend(symbol.size.to_le_bytes());
+        }
+
+        let mut out = vec![0; HEADER_SIZE];
+        let mut headers = vec![0; SECTION_HEADER_SIZE];
+        let mut section_names = StringTable::default();
+
+        let relocated: Vec<usize> = (0..self.sections.len())
+            .filter(|&section| !self.sections[section].relocations.is_empty())
+            .collect();
+        let symbol_table_index = 1 + self.sections.len() + relocated.len();
+
+        for section in self.sections.iter() {
+            align(&mut out, section.alignment);
+            let mut flags = 0;
+            if section.is_allocated {
+                flags |= SHF_ALLOC;
+            }
+            if section.is_executable {
+                flags |= SHF_EXECINSTR;
+            }
+            SectionHeader {
+                name: section_names.add(&section.name),
+                kind: SHT_PROGBITS,
+                flags,
+                offset: out.len(),
+                size: section.data.len(),
+                link: 0,
+                info: 0,
+                alignment: section.alignment,
+                entry_size: 0,
+            }
+            .write(&mut headers);
+            out.extend(&section.data);
+        }
+
+        for &index in relocated.iter() {
+            let section = &self.sections[index];
+            align(&mut out, 8);
+            let offset = out.len();
+            for relocation in section.relocations.iter() {
+                let symbol = symbol_index[relocation.symbol] as u64;
+                out.extend(relocation.offset.to_le_bytes());
+                out.extend((symbol << 32 | relocation.kind as u64).to_le_bytes());
+                out.extend(relocation.addend.to_le_bytes());
+            }
+            SectionHeader {
+                name: section_names.add(&format!(".rela{}", section.name)),
+                kind: SHT_RELA,
+                flags: SHF_INFO_LINK,
+                offset,
+                size: section.relocations.len() * RELOCATION_SIZE,
+                link: symbol_table_index as u32,
+                info: index as u32 + 1,
+                alignment: 8,
+                entry_size: RELOCATION_SIZE as u64,
+            }
+            .write(&mut headers);
+        }
+
+        align(&mut out, 8);
+        SectionHeader {
+            name: section_names.add(".symtab"),
+            kind: SHT_SYMTAB,
+            flags: 0,
+            offset: out.len(),
+            size: symbol_table.len(),
+            link: symbol_table_index as u32 + 1,
+            info: first_global as u32,
+            alignment: 8,
+            entry_size: SYMBOL_SIZE as u64,
+        }
+        .write(&mut headers);
+        out.extend(&symbol_table);
+
+        SectionHeader {
+            name: section_names.add(".strtab"),
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: out.len(),
+            size: strings.data.len(),
+            link: 0,
+            info: 0,
+            alignment: 1,
+            entry_size: 0,
+        }
+        .write(&mut headers);
+        out.extend(&strings.data);
+
+        let name = section_names.add(".shstrtab");
+        SectionHeader {
+            name,
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: out.len(),
+            size: section_names.data.len(),
+            link: 0,
+            info: 0,
+            alignment: 1,
+            entry_size: 0,
+        }
+        .write(&mut headers);
+        out.extend(&section_names.data);
+
+        align(&mut out, 8);
+        let section_headers_offset = out.len();
+        let num_sections = headers.len() / SECTION_HEADER_SIZE;
+        out.extend(&headers);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        // Magic, 64 bit, little endian, version 1, System V ABI.
+        header.extend([0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+        header.extend([0; 8]);
+        header.extend(ET_REL.to_le_bytes());
+        header.extend(self.machine.to_le_bytes());
+        header.extend(1u32.to_le_bytes());
+        // No entry point and no program headers.
+        header.extend(0u64.to_le_bytes());
+        header.extend(0u64.to_le_bytes());
+        header.extend((section_headers_offset as u64).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend((HEADER_SIZE as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend((SECTION_HEADER_SIZE as u16).to_le_bytes());
+        header.extend((num_sections as u16).to_le_bytes());
+        header.extend((num_sections as u16 - 1).to_le_bytes());
+        out[..HEADER_SIZE].copy_from_slice(&header);
+
+        out
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    alignment: u64,
+    entry_size: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.name.to_le_bytes());
+        out.extend(self.kind.to_le_bytes());
+        out.extend(self.flags.to_le_bytes());
+        // Relocatable objects aren't loaded at any address.
+        out.extend(0u64.to_le_bytes());
+        out.extend((self.offset as u64).to_le_bytes());
+        out.extend((self.size as u64).to_le_bytes());
+        out.extend(self.link.to_le_bytes());
+        out.extend(self.info.to_le_bytes());
+        out.extend(self.alignment.to_le_bytes());
+        out.extend(self.entry_size.to_le_bytes());
+    }
+}
+
+/// Null terminated strings, referred to by their offset.
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl Default for StringTable {
+    /// A table starting with the empty string, as required by ELF.
+    fn default() -> Self {
+        StringTable { data: vec![0] }
+    }
+}
+
+impl StringTable {
+    fn add(&mut self, string: &str) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data.extend(string.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+fn align(out: &mut Vec<u8>, alignment: u64) {
+    out.resize(align_up(out.len() as u64, alignment) as usize, 0);
+}
@@ -0,0 +1,427 @@
+use crate::{
+    backend::{block_order, check_types, Unsupported},
+    constant::ConstantValue,
+    function::{FunctionData, Functions, Linkage},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use std::fmt::Write;
+
+/// Prints every function of the module as a textual LLVM IR module, accepted by
+/// `llvm-as`.
+///
+/// Blocks become basic blocks named after their label, and values keep their number.
+/// The IR has no signedness on LLVM integer types, so it is carried by the
+/// instructions instead, e.g. `udiv` or `icmp slt`. Pointers are typed, and struct
+/// types are printed as literal structs, compared structurally like in the IR.
+/// Floats are `float` and `double`, other widths are not supported.
+/// ```text
+/// fn add(a: u32, b: u32) -> u32        define i32 @add(i32 %v0, i32 %v1) {
+///                                      L0:
+/// let v2: u32 = add v0, v1               %v2 = add i32 %v0, %v1
+/// ret v2                                 ret i32 %v2
+///                                      }
+/// ```
+pub(crate) fn emit(types: &Types, functions: &Functions) -> Result<String, Unsupported> {
+    check_types(types, functions, |ty| is_supported(types, ty))?;
+
+    let mut out = String::new();
+    for (_, function) in functions.iter() {
+        let mut emitter = FunctionEmitter {
+            types,
+            functions,
+            function,
+            out: &mut out,
+        };
+        emitter.emit();
+    }
+
+    Ok(out)
+}
+
+/// Returns `true` if `ty`, and every type it holds or points to, has an LLVM type.
+fn is_supported(types: &Types, ty: Type) -> bool {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { .. } => true,
+        TypeKind::Float { num_bits } => matches!(num_bits, 32 | 64),
+        TypeKind::Struct { types: fields } => {
+            fields.iter().all(|field| is_supported(types, *field))
+        }
+        TypeKind::Pointer { ty } => is_supported(types, *ty),
+    }
+}
+
+/// The LLVM type of values of type `ty`.
+fn llvm_type(types: &Types, ty: Type) -> String {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } => format!("i{num_bits}"),
+        TypeKind::Float { num_bits: 32 } => "float".to_string(),
+        TypeKind::Float { num_bits: 64 } => "double".to_string(),
+        TypeKind::Float { .. } => unreachable!("rejected by `is_supported`"),
+        TypeKind::Struct { types: fields } => {
+            let fields = fields
+                .iter()
+                .map(|field| llvm_type(types, *field))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{{ {fields} }}")
+        }
+        TypeKind::Pointer { ty } => format!("{}*", llvm_type(types, *ty)),
+    }
+}
+
+/// Emits the definition of a single function.
+struct FunctionEmitter<'a> {
+    types: &'a Types,
+    functions: &'a Functions,
+    function: &'a FunctionData,
+    out: &'a mut String,
+}
+
+impl FunctionEmitter<'_> {
+    fn emit(&mut self) {
+        let definition = self.function.definition();
+        let linkage = match definition.linkage {
+            Linkage::External => "",
+            Linkage::Internal => "internal ",
+        };
+        let return_type = definition
+            .return_type
+            .map_or("void".to_string(), |ty| llvm_type(self.types, ty));
+        let parameters = self
+            .function
+            .parameters()
+            .iter()
+            .map(|parameter| self.typed(*parameter))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            self.out,
+            "define {linkage}{return_type} @{}({parameters}) {{",
+            definition.name
+        )
+        .unwrap();
+
+        // The entry block of an LLVM function can't be branched to.
+        let entry = self.function.labels().entry();
+        let order = block_order(self.function);
+        let entry_is_target = order
+            .iter()
+            .any(|label| self.function.labels().targets(*label).contains(&entry));
+        if entry_is_target {
+            writeln!(self.out, "entry:").unwrap();
+            writeln!(self.out, "  br label %L{}", entry.id()).unwrap();
+        }
+
+        for label in order {
+            writeln!(self.out, "L{}:", label.id()).unwrap();
+            for instr in self.function.labels().get(label).instructions.iter() {
+                if let Some(instruction) = self.instruction(instr) {
+                    writeln!(self.out, "  {instruction}").unwrap();
+                }
+            }
+        }
+
+        writeln!(self.out, "}}\n").unwrap();
+    }
+
+    /// The LLVM instruction performing `instr`.
+    fn instruction(&self, instr: &Instruction) -> Option<String> {
+        let instruction = match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs }
+                if self.is_float(*dst) =>
+            {
+                let op = match op {
+                    BinaryOp::Add => "fadd",
+                    BinaryOp::Sub => "fsub",
+                    BinaryOp::Mul => "fmul",
+                    BinaryOp::Div => "fdiv",
+                    BinaryOp::Mod => "frem",
+                    op => panic!("{op:?} is not defined on floats"),
+                };
+                format!(
+                    "%v{} = {op} {}, {}",
+                    dst.id(),
+                    self.typed(*lhs),
+                    self.value(*rhs)
+                )
+            }
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => {
+                let is_signed = self.is_signed(*dst);
+                let op = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Sub => "sub",
+                    BinaryOp::Mul => "mul",
+                    BinaryOp::Div if is_signed => "sdiv",
+                    BinaryOp::Div => "udiv",
+                    BinaryOp::Mod if is_signed => "srem",
+                    BinaryOp::Mod => "urem",
+                    BinaryOp::Shl => "shl",
+                    BinaryOp::Shr => "lshr",
+                    BinaryOp::Sar => "ashr",
+                    BinaryOp::And | BinaryOp::BitAnd => "and",
+                    BinaryOp::Or | BinaryOp::BitOr => "or",
+                    BinaryOp::Xor => "xor",
+                };
+                format!(
+                    "%v{} = {op} {}, {}",
+                    dst.id(),
+                    self.typed(*lhs),
+                    self.value(*rhs)
+                )
+            }
+            Instruction::ArithmeticUnary { dst, op, value } => {
+                let ty = self.llvm_type(*value);
+                let a = self.value(*value);
+                match op {
+                    UnaryOp::Neg if self.is_float(*dst) => {
+                        format!("%v{} = fneg {ty} {a}", dst.id())
+                    }
+                    UnaryOp::Neg => format!("%v{} = sub {ty} 0, {a}", dst.id()),
+                    UnaryOp::Not => format!("%v{} = xor {ty} {a}, -1", dst.id()),
+                }
+            }
+            Instruction::Branch { target } => format!("br label %L{}", target.id()),
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => format!(
+                "br {}, label %L{}, label %L{}",
+                self.typed(*condition),
+                on_true.id(),
+                on_false.id()
+            ),
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                tail,
+            } => {
+                let definition = self.functions.get(*function).definition();
+                let return_type = definition
+                    .return_type
+                    .map_or("void".to_string(), |ty| llvm_type(self.types, ty));
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.typed(*argument))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let tail = if *tail { "tail " } else { "" };
+                let call =
+                    format!("{tail}call {return_type} @{}({arguments})", definition.name);
+                match dst {
+                    Some(dst) => format!("%v{} = {call}", dst.id()),
+                    None => call,
+                }
+            }
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                let op = self.cast_op(*cast_op, self.ty(*value), self.ty(*dst));
+                format!(
+                    "%v{} = {op} {} to {}",
+                    dst.id(),
+                    self.typed(*value),
+                    self.llvm_type(*dst)
+                )
+            }
+            Instruction::GetElementPtr { dst, ptr, index } => {
+                let ptr_type = self.ty(*ptr);
+                let pointee = self.types.strip_pointer(ptr_type).unwrap();
+                let same_type = self.types.get(ptr_type) == self.types.get(self.ty(*dst));
+                let indices = if !same_type && self.types.is_struct(pointee) {
+                    let field = self
+                        .function
+                        .constant(*index)
+                        .and_then(|c| c.integer())
+                        .expect("struct field index must be a constant");
+                    format!("i32 0, i32 {field}")
+                } else {
+                    self.typed(*index)
+                };
+                format!(
+                    "%v{} = getelementptr {}, {}, {indices}",
+                    dst.id(),
+                    llvm_type(self.types, pointee),
+                    self.typed(*ptr)
+                )
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } if self.is_float(*lhs) => {
+                // Ordered, false when an operand is NaN, except for `une`.
+                let pred = match pred {
+                    IntCompareOp::Equal => "oeq",
+                    IntCompareOp::NotEqual => "une",
+                    IntCompareOp::GreaterThan => "ogt",
+                    IntCompareOp::GreaterThanOrEqual => "oge",
+                    IntCompareOp::LessThan => "olt",
+                    IntCompareOp::LessThanOrEqual => "ole",
+                };
+                format!(
+                    "%v{} = fcmp {pred} {}, {}",
+                    dst.id(),
+                    self.typed(*lhs),
+                    self.value(*rhs)
+                )
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let sign = if self.is_signed(*lhs) { "s" } else { "u" };
+                let pred = match pred {
+                    IntCompareOp::Equal => "eq".to_string(),
+                    IntCompareOp::NotEqual => "ne".to_string(),
+                    IntCompareOp::GreaterThan => format!("{sign}gt"),
+                    IntCompareOp::GreaterThanOrEqual => format!("{sign}ge"),
+                    IntCompareOp::LessThan => format!("{sign}lt"),
+                    IntCompareOp::LessThanOrEqual => format!("{sign}le"),
+                };
+                format!(
+                    "%v{} = icmp {pred} {}, {}",
+                    dst.id(),
+                    self.typed(*lhs),
+                    self.value(*rhs)
+                )
+            }
+            Instruction::Load { dst, ptr } => format!(
+                "%v{} = load {}, {}",
+                dst.id(),
+                self.llvm_type(*dst),
+                self.typed(*ptr)
+            ),
+            Instruction::Return { value } => match value {
+                Some(value) => format!("ret {}", self.typed(*value)),
+                None => "ret void".to_string(),
+            },
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => format!(
+                "%v{} = select {}, {}, {}",
+                dst.id(),
+                self.typed(*condition),
+                self.typed(*on_true),
+                self.typed(*on_false)
+            ),
+            Instruction::StackAlloc { dst, ty, size } => format!(
+                "%v{} = alloca {}, i64 {size}",
+                dst.id(),
+                llvm_type(self.types, *ty)
+            ),
+            Instruction::Store { ptr, value } => {
+                format!("store {}, {}", self.typed(*value), self.typed(*ptr))
+            }
+            Instruction::Nop => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// The LLVM cast converting from `from` to `to`. LLVM picks the operation from the
+    /// widths, so a cast between types of the same width is a `bitcast`. Other casts
+    /// from or to floats convert the value.
+    fn cast_op(&self, cast_op: CastOp, from: Type, to: Type) -> &'static str {
+        use TypeKind::{Float, Integer, Pointer};
+        let from = self.types.get(from).type_kind();
+        let to = self.types.get(to).type_kind();
+        match (from, to) {
+            (Integer { num_bits: a, .. }, Integer { num_bits: b, .. }) if a > b => {
+                "trunc"
+            }
+            (Integer { num_bits: a, .. }, Integer { num_bits: b, .. }) if a < b => {
+                match cast_op {
+                    CastOp::SignExtend => "sext",
+                    _ => "zext",
+                }
+            }
+            (Integer { .. }, Pointer { .. }) => "inttoptr",
+            (Pointer { .. }, Integer { .. }) => "ptrtoint",
+            _ if cast_op == CastOp::BitCast => "bitcast",
+            (Float { num_bits: a }, Float { num_bits: b }) if a > b => "fptrunc",
+            (Float { num_bits: a }, Float { num_bits: b }) if a < b => "fpext",
+            (
+                Float { .. },
+                Integer {
+                    is_signed: true, ..
+                },
+            ) => "fptosi",
+            (Float { .. }, Integer { .. }) => "fptoui",
+            (
+                Integer {
+                    is_signed: true, ..
+                },
+                Float { .. },
+            ) => "sitofp",
+            (Integer { .. }, Float { .. }) => "uitofp",
+            _ => "bitcast",
+        }
+    }
+
+    /// `value` preceded by its type, as most operands are printed.
+    fn typed(&self, value: Value) -> String {
+        format!("{} {}", self.llvm_type(value), self.value(value))
+    }
+
+    /// The name of `value`, or its literal for constants.
+    fn value(&self, value: Value) -> String {
+        let Some(constant) = self.function.constant(value) else {
+            return format!("%v{}", value.id());
+        };
+
+        match constant {
+            ConstantValue::Integer { value: bits, .. } => {
+                let num_bits = self.types.integer(self.ty(value)).map_or(64, |(n, _)| n);
+                match num_bits {
+                    1 => (bits & 1 == 1).to_string(),
+                    // Printed signed, the way `llvm-dis` does.
+                    _ => {
+                        let shift = 64 - num_bits;
+                        (((bits << shift) as i64) >> shift).to_string()
+                    }
+                }
+            }
+            // Hexadecimal is exact, a decimal `float` literal has to be representable.
+            ConstantValue::Float { value: float, .. } => {
+                match self.llvm_type(value).as_str() {
+                    "float" => format!("0x{:016X}", f64::from(float as f32).to_bits()),
+                    _ => format!("0x{:016X}", float.to_bits()),
+                }
+            }
+        }
+    }
+
+    fn llvm_type(&self, value: Value) -> String {
+        llvm_type(self.types, self.ty(value))
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+
+    fn is_float(&self, value: Value) -> bool {
+        matches!(
+            self.types.get(self.ty(value)).type_kind(),
+            TypeKind::Float { .. }
+        )
+    }
+
+    fn is_signed(&self, value: Value) -> bool {
+        self.types
+            .integer(self.ty(value))
+            .is_some_and(|(_, is_signed)| is_signed)
+    }
+}
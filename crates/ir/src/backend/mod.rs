@@ -0,0 +1,158 @@
+//! Code generation from the IR.
+
+pub mod bytecode;
+pub(crate) mod c;
+pub(crate) mod dwarf;
+pub(crate) mod elf;
+pub(crate) mod llvm;
+pub(crate) mod register_allocation;
+pub mod riscv64;
+pub(crate) mod selection;
+pub(crate) mod wasm;
+pub mod x86_64;
+
+use crate::{
+    function::{FunctionData, Functions},
+    label::Label,
+    ty::{Type, TypeKind, Types},
+};
+
+/// Error returned by a backend for a module using values it cannot generate code for,
+/// like floats in a backend without floating point support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    /// Name of the function using the values.
+    pub function: String,
+    /// The values which are not supported, e.g. `f32 values`.
+    pub what: String,
+}
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} are not supported, in `{}`", self.what, self.function)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+impl From<Unsupported> for std::io::Error {
+    fn from(error: Unsupported) -> Self {
+        std::io::Error::new(std::io::ErrorKind::Unsupported, error)
+    }
+}
+
+/// Checks that every value of the module has a type accepted by `is_supported`, and
+/// that every type reachable from them through pointers and fields fits in memory,
+/// with integers and floats of at most 64 bits.
+pub(crate) fn check_types(
+    types: &Types,
+    functions: &Functions,
+    is_supported: impl Fn(Type) -> bool,
+) -> Result<(), Unsupported> {
+    for (_, function) in functions.iter() {
+        let unsupported = |ty: Type| Unsupported {
+            function: function.definition().name.clone(),
+            what: describe(types.get(ty).type_kind()),
+        };
+
+        for (_, value) in function.values().iter() {
+            if !is_supported(value.ty()) {
+                return Err(unsupported(value.ty()));
+            }
+            if let Some(ty) = unsized_type(types, value.ty()) {
+                return Err(unsupported(ty));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The first integer or float type wider than 64 bits reachable from `ty`.
+fn unsized_type(types: &Types, ty: Type) -> Option<Type> {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } | TypeKind::Float { num_bits } => {
+            (*num_bits > 64).then_some(ty)
+        }
+        TypeKind::Pointer { ty } => unsized_type(types, *ty),
+        TypeKind::Struct { types: fields } => {
+            fields.iter().find_map(|field| unsized_type(types, *field))
+        }
+    }
+}
+
+/// Description of the values of a type for [`Unsupported`], e.g. `u128 values`.
+fn describe(ty: &TypeKind) -> String {
+    match ty {
+        TypeKind::Integer {
+            num_bits,
+            is_signed: true,
+        } => format!("i{num_bits} values"),
+        TypeKind::Integer {
+            num_bits,
+            is_signed: false,
+        } => format!("u{num_bits} values"),
+        TypeKind::Float { num_bits } => format!("f{num_bits} values"),
+        TypeKind::Struct { .. } => "struct values".to_string(),
+        TypeKind::Pointer { .. } => "pointer values".to_string(),
+    }
+}
+
+/// Rounds `offset` up to a multiple of `alignment`.
+pub(crate) fn align_up(offset: u64, alignment: u64) -> u64 {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// Size in bytes of a value of type `ty` in memory. Integers are rounded up to the
+/// next power of two bytes, so `u1` takes one byte.
+pub(crate) fn size_of(types: &Types, ty: Type) -> u64 {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } | TypeKind::Float { num_bits } => {
+            match num_bits {
+                0..=8 => 1,
+                9..=16 => 2,
+                17..=32 => 4,
+                33..=64 => 8,
+                _ => unreachable!("{num_bits} bit values are rejected by `check_types`"),
+            }
+        }
+        TypeKind::Pointer { .. } => 8,
+        TypeKind::Struct { types: fields } => {
+            let end = fields.last().map_or(0, |last| {
+                field_offset(types, ty, fields.len() - 1) + size_of(types, *last)
+            });
+            align_up(end, align_of(types, ty))
+        }
+    }
+}
+
+/// Alignment in bytes of a value of type `ty` in memory.
+pub(crate) fn align_of(types: &Types, ty: Type) -> u64 {
+    match types.get(ty).type_kind() {
+        TypeKind::Struct { types: fields } => fields
+            .iter()
+            .map(|field| align_of(types, *field))
+            .max()
+            .unwrap_or(1),
+        _ => size_of(types, ty),
+    }
+}
+
+/// Offset in bytes of field `index` of the struct type `ty`.
+pub(crate) fn field_offset(types: &Types, ty: Type, index: usize) -> u64 {
+    let TypeKind::Struct { types: fields } = types.get(ty).type_kind() else {
+        panic!("{ty:?} is not a struct");
+    };
+
+    let mut offset: u64 = 0;
+    for field in fields[..index].iter() {
+        offset = align_up(offset, align_of(types, *field)) + size_of(types, *field);
+    }
+    align_up(offset, align_of(types, fields[index]))
+}
+
+/// Blocks reachable from the entry, entry first, in the order they are laid out.
+pub(crate) fn block_order(function: &FunctionData) -> Vec<Label> {
+    let mut order = Vec::new();
+    function.labels().cfg().bfs(|label| order.push(label));
+    order
+}
@@ -0,0 +1,274 @@
+use crate::{
+    backend::block_order,
+    function::FunctionData,
+    instruction::{CastOp, Instruction},
+    ty::{Type, Types},
+    value::Value,
+};
+use std::collections::HashMap;
+
+/// A machine register, by its index in the register table of the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct PhysicalRegister(pub u8);
+
+/// A register values may be allocated to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AllocatableRegister {
+    pub register: PhysicalRegister,
+    /// Preserved by calls, so it can hold values live across them. The function has
+    /// to save and restore it when it uses it.
+    pub callee_saved: bool,
+}
+
+/// Registers interchangeable for some kind of values, e.g. the general purpose ones.
+#[derive(Debug)]
+pub(crate) struct RegisterClass {
+    /// Registers handed out to values of the class, in order of preference.
+    pub registers: &'static [AllocatableRegister],
+}
+
+/// Description of the registers of a target the allocator works with.
+///
+/// Registers used by the target to lower instructions, e.g. for operands or
+/// arguments, should not be part of any class.
+#[derive(Debug)]
+pub(crate) struct RegisterDescription {
+    pub classes: &'static [RegisterClass],
+    /// Index in `classes` of the class holding values of a type, or `None` for types
+    /// always kept in memory.
+    pub class_of: fn(&Types, Type) -> Option<usize>,
+}
+
+/// Where a value lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Assignment {
+    Register(PhysicalRegister),
+    /// In a stack slot of the frame.
+    Spilled,
+}
+
+/// Positions where a value is live. Instructions are numbered from 1 in
+/// [`block_order`], 0 standing for the function entry where the parameters are
+/// created. The value is live after `start`, and up to and including `end`.
+#[derive(Debug, Clone, Copy)]
+struct LiveInterval {
+    value: Value,
+    start: usize,
+    end: usize,
+    class: usize,
+}
+
+/// Result of the register allocation of a function.
+#[derive(Debug)]
+pub(crate) struct RegisterAllocation {
+    /// Where every value that is not a constant lives.
+    pub assignments: HashMap<Value, Assignment>,
+    /// Callee saved registers given to some value, which the function must preserve.
+    pub used_callee_saved: Vec<PhysicalRegister>,
+}
+
+impl RegisterAllocation {
+    /// Allocates registers to the values of `function` with linear scan.
+    ///
+    /// Each value is given the interval from its creation to its last use, through the
+    /// [`LivenessAnalysis`](crate::liveness_analysis::LivenessAnalysis) of the blocks,
+    /// and intervals are handed registers in order of their start. When none is free,
+    /// the interval ending last is spilled. Intervals containing a call are only given
+    /// callee saved registers.
+    ///
+    /// A `bitcast` whose operand dies at the cast is given the register of the operand
+    /// when possible, so the copy can be left out.
+    pub fn new(
+        types: &Types,
+        function: &FunctionData,
+        description: &RegisterDescription,
+    ) -> Self {
+        let (intervals, calls, copies) =
+            Self::build_intervals(types, function, description);
+
+        let mut assignments = HashMap::new();
+        let mut used_callee_saved = Vec::new();
+        // Intervals holding a register, with the register.
+        let mut active: Vec<(LiveInterval, AllocatableRegister)> = Vec::new();
+
+        for interval in intervals.iter() {
+            active.retain(|(other, _)| other.end > interval.start);
+
+            // A call strictly inside the interval clobbers the caller saved registers.
+            let crosses_call = calls
+                .iter()
+                .any(|call| interval.start < *call && *call < interval.end);
+            let allowed =
+                |register: &AllocatableRegister| !crosses_call || register.callee_saved;
+            let is_free = |register: &AllocatableRegister| {
+                !active
+                    .iter()
+                    .any(|(_, other)| other.register == register.register)
+            };
+
+            let registers = description.classes[interval.class].registers;
+            let hint = copies
+                .get(&interval.value)
+                .and_then(|source| match assignments.get(source) {
+                    Some(Assignment::Register(register)) => Some(*register),
+                    _ => None,
+                })
+                .and_then(|hint| registers.iter().find(|r| r.register == hint));
+            let free = hint
+                .filter(|register| allowed(register) && is_free(register))
+                .or_else(|| {
+                    registers
+                        .iter()
+                        .find(|register| allowed(register) && is_free(register))
+                });
+
+            let register = match free {
+                Some(register) => Some(*register),
+                None => {
+                    // Take the register of the interval living the longest, if it
+                    // lives longer than this one.
+                    let victim = active
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (other, register))| {
+                            other.class == interval.class && allowed(register)
+                        })
+                        .max_by_key(|(_, (other, _))| other.end)
+                        .filter(|(_, (other, _))| other.end > interval.end)
+                        .map(|(i, _)| i);
+                    victim.map(|i| {
+                        let (spilled, register) = active.remove(i);
+                        assignments.insert(spilled.value, Assignment::Spilled);
+                        register
+                    })
+                }
+            };
+
+            match register {
+                Some(register) => {
+                    if register.callee_saved
+                        && !used_callee_saved.contains(&register.register)
+                    {
+                        used_callee_saved.push(register.register);
+                    }
+                    assignments
+                        .insert(interval.value, Assignment::Register(register.register));
+                    active.push((*interval, register));
+                }
+                None => {
+                    assignments.insert(interval.value, Assignment::Spilled);
+                }
+            }
+        }
+
+        // Values with no class, e.g. structs, live in memory.
+        for value in Self::values(function) {
+            assignments.entry(value).or_insert(Assignment::Spilled);
+        }
+
+        used_callee_saved.sort();
+        RegisterAllocation {
+            assignments,
+            used_callee_saved,
+        }
+    }
+
+    /// Where `value` lives.
+    pub fn assignment(&self, value: Value) -> Assignment {
+        self.assignments[&value]
+    }
+
+    /// Live intervals sorted by start, positions of the calls, and the operand of
+    /// every `bitcast` result.
+    fn build_intervals(
+        types: &Types,
+        function: &FunctionData,
+        description: &RegisterDescription,
+    ) -> (Vec<LiveInterval>, Vec<usize>, HashMap<Value, Value>) {
+        let liveness = function.liveness();
+        let mut ranges: HashMap<Value, (usize, usize)> = HashMap::new();
+        let mut extend = |value: Value, position: usize| {
+            let range = ranges.entry(value).or_insert((position, position));
+            range.0 = range.0.min(position);
+            range.1 = range.1.max(position);
+        };
+
+        for parameter in function.parameters().iter() {
+            extend(*parameter, 0);
+        }
+
+        let mut calls = Vec::new();
+        let mut copies = HashMap::new();
+        let mut position = 0;
+        for label in block_order(function) {
+            let instructions = &function.labels().get(label).instructions;
+            let first = position + 1;
+            for value in liveness.live_in[&label].iter() {
+                extend(*value, first);
+            }
+
+            for instr in instructions.iter() {
+                position += 1;
+                for read in instr.reads().into_iter().flatten() {
+                    if function.constant(read).is_none() {
+                        extend(read, position);
+                    }
+                }
+                if let Some(dst) = instr.creates() {
+                    extend(dst, position);
+                }
+
+                match instr {
+                    Instruction::Call { .. } => calls.push(position),
+                    Instruction::Cast {
+                        cast_op: CastOp::BitCast,
+                        dst,
+                        value,
+                        ..
+                    } if function.constant(*value).is_none() => {
+                        copies.insert(*dst, *value);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Live out values have to survive the terminator.
+            for value in liveness.live_out[&label].iter() {
+                extend(*value, position + 1);
+            }
+        }
+
+        let mut intervals: Vec<LiveInterval> = ranges
+            .into_iter()
+            .filter_map(|(value, (start, end))| {
+                let ty = function.values().get(value).ty();
+                (description.class_of)(types, ty).map(|class| LiveInterval {
+                    value,
+                    start,
+                    // Created values are written even when never read.
+                    end: end.max(start + 1),
+                    class,
+                })
+            })
+            .collect();
+        // Sorted by value too, so the result does not depend on `HashMap` iteration order.
+        intervals.sort_by_key(|interval| (interval.start, interval.value));
+
+        (intervals, calls, copies)
+    }
+
+    /// Every value of `function` that is not a constant.
+    fn values(function: &FunctionData) -> impl Iterator<Item = Value> + '_ {
+        let created = function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .filter_map(|instr| instr.creates());
+        function
+            .parameters()
+            .iter()
+            .copied()
+            .chain(created)
+            .filter(|value| function.constant(*value).is_none())
+    }
+}
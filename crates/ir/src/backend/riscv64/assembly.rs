@@ -0,0 +1,179 @@
+use super::{encoding::layout, instruction::*, select};
+use crate::{
+    backend::Unsupported,
+    function::{Functions, Linkage},
+    label::Label,
+    ty::Types,
+};
+use std::fmt::Write;
+
+/// Prints every function of the module as RV64IM assembly, in GNU `as` syntax.
+/// ```text
+/// fn add(a: u32, b: u32) -> u32        add:
+///                                          addi sp, sp, -16
+///                                          sd ra, 8(sp)
+///                                          sd s0, 0(sp)
+///                                          addi s0, sp, 16
+///                                          slli t4, a0, 32
+///                                          srli t4, t4, 32
+///                                          ...
+/// let v2: u32 = add v0, v1             .LF0_B0:
+///                                          slli t0, t4, 32
+///                                          ...
+///                                          add t0, t0, t1
+/// ...
+/// ```
+pub(crate) fn emit(types: &Types, functions: &Functions) -> Result<String, Unsupported> {
+    let mut out = String::new();
+    writeln!(out, "\t.text").unwrap();
+
+    for machine_function in select(types, functions)? {
+        let id = machine_function.function.id();
+        let definition = functions.get(machine_function.function).definition();
+        let name = &definition.name;
+        writeln!(out, "\t.p2align 2").unwrap();
+        if definition.linkage == Linkage::External {
+            writeln!(out, "\t.globl {name}").unwrap();
+        }
+        writeln!(out, "\t.type {name}, @function").unwrap();
+        writeln!(out, "{name}:").unwrap();
+
+        // Long branches are spelled out as they're encoded, since not every assembler
+        // replaces the branches that don't reach.
+        let (_, is_long) = layout(&machine_function.code);
+        for (inst, is_long) in machine_function.code.into_iter().zip(is_long) {
+            match inst {
+                Inst::Block(label) => {
+                    writeln!(out, "{}:", block_name(id, label)).unwrap()
+                }
+                Inst::Branch {
+                    condition,
+                    rs1,
+                    rs2,
+                    target,
+                } if is_long => {
+                    let mnemonic = branch_mnemonic(condition.inverse());
+                    writeln!(out, "\t{mnemonic} {}, {}, 1f", rs1.name(), rs2.name())
+                        .unwrap();
+                    writeln!(out, "\tj {}", block_name(id, target)).unwrap();
+                    writeln!(out, "1:").unwrap();
+                }
+                inst => writeln!(out, "\t{}", instruction(functions, id, inst)).unwrap(),
+            }
+        }
+
+        writeln!(out, "\t.size {name}, .-{name}").unwrap();
+    }
+
+    writeln!(out, "\t.section .note.GNU-stack,\"\",@progbits").unwrap();
+    Ok(out)
+}
+
+/// The assembly of `inst`, in the function numbered `id`.
+fn instruction(functions: &Functions, id: usize, inst: Inst) -> String {
+    let function_name = |function| &functions.get(function).definition().name;
+    match inst {
+        Inst::Block(_) => unreachable!("blocks are labels"),
+        Inst::Register { op, rd, rs1, rs2 } => {
+            let mnemonic = match op {
+                RegisterOp::Add => "add",
+                RegisterOp::Sub => "sub",
+                RegisterOp::Sll => "sll",
+                RegisterOp::Slt => "slt",
+                RegisterOp::Sltu => "sltu",
+                RegisterOp::Xor => "xor",
+                RegisterOp::Srl => "srl",
+                RegisterOp::Sra => "sra",
+                RegisterOp::Or => "or",
+                RegisterOp::And => "and",
+                RegisterOp::Mul => "mul",
+                RegisterOp::Div => "div",
+                RegisterOp::Divu => "divu",
+                RegisterOp::Rem => "rem",
+                RegisterOp::Remu => "remu",
+            };
+            format!("{mnemonic} {}, {}, {}", rd.name(), rs1.name(), rs2.name())
+        }
+        Inst::Immediate { op, rd, rs1, imm } => {
+            let mnemonic = match op {
+                ImmediateOp::Addi => "addi",
+                ImmediateOp::Sltiu => "sltiu",
+                ImmediateOp::Xori => "xori",
+                ImmediateOp::Andi => "andi",
+                ImmediateOp::Slli => "slli",
+                ImmediateOp::Srli => "srli",
+                ImmediateOp::Srai => "srai",
+                ImmediateOp::Addiw => "addiw",
+            };
+            format!("{mnemonic} {}, {}, {imm}", rd.name(), rs1.name())
+        }
+        Inst::Lui { rd, imm } => format!("lui {}, {imm}", rd.name()),
+        Inst::Load {
+            size,
+            is_signed,
+            rd,
+            base,
+            offset,
+        } => {
+            let mnemonic = match (size, is_signed) {
+                (1, true) => "lb",
+                (1, false) => "lbu",
+                (2, true) => "lh",
+                (2, false) => "lhu",
+                (4, true) => "lw",
+                (4, false) => "lwu",
+                _ => "ld",
+            };
+            format!("{mnemonic} {}, {offset}({})", rd.name(), base.name())
+        }
+        Inst::Store {
+            size,
+            src,
+            base,
+            offset,
+        } => {
+            let mnemonic = match size {
+                1 => "sb",
+                2 => "sh",
+                4 => "sw",
+                _ => "sd",
+            };
+            format!("{mnemonic} {}, {offset}({})", src.name(), base.name())
+        }
+        Inst::Branch {
+            condition,
+            rs1,
+            rs2,
+            target,
+        } => {
+            format!(
+                "{} {}, {}, {}",
+                branch_mnemonic(condition),
+                rs1.name(),
+                rs2.name(),
+                block_name(id, target)
+            )
+        }
+        Inst::Jump(Target::Block(label)) => format!("j {}", block_name(id, label)),
+        Inst::Jump(Target::Function(function)) => {
+            format!("j {}", function_name(function))
+        }
+        Inst::Call(function) => format!("jal ra, {}", function_name(function)),
+        Inst::Return => "ret".to_string(),
+    }
+}
+
+fn branch_mnemonic(condition: BranchCondition) -> &'static str {
+    match condition {
+        BranchCondition::Equal => "beq",
+        BranchCondition::NotEqual => "bne",
+        BranchCondition::Less => "blt",
+        BranchCondition::GreaterOrEqual => "bge",
+        BranchCondition::LessUnsigned => "bltu",
+        BranchCondition::GreaterOrEqualUnsigned => "bgeu",
+    }
+}
+
+fn block_name(id: usize, label: Label) -> String {
+    format!(".LF{id}_B{}", label.id())
+}
@@ -0,0 +1,261 @@
+use super::{instruction::*, select};
+use crate::{
+    backend::Unsupported,
+    function::{Function, Functions},
+    label::Label,
+    ty::Types,
+};
+use std::collections::HashMap;
+
+/// Machine code of a function.
+pub(crate) struct EncodedFunction {
+    pub function: Function,
+    pub code: Vec<u8>,
+    /// Offsets in `code` of the `jal` instructions of calls and tail calls, with the
+    /// function they go to. Their offset is left to be filled in.
+    pub relocations: Vec<(usize, Function)>,
+}
+
+/// Encodes every function of the module to machine code, with the instructions selected
+/// for the assembly backend.
+///
+/// Jumps between blocks are resolved in the function. Conditional branches only reach
+/// 4 KiB away, so those going further are replaced by the opposite branch over a `jal`.
+/// Jumps to other functions are left to the caller, as relocations.
+pub(crate) fn encode(
+    types: &Types,
+    functions: &Functions,
+) -> Result<Vec<EncodedFunction>, Unsupported> {
+    let encoded = select(types, functions)?
+        .into_iter()
+        .map(|machine_function| {
+            let code = &machine_function.code;
+            let (offsets, is_long) = layout(code);
+            let blocks: HashMap<Label, usize> = code
+                .iter()
+                .zip(offsets.iter())
+                .filter_map(|(inst, offset)| match inst {
+                    Inst::Block(label) => Some((*label, *offset)),
+                    _ => None,
+                })
+                .collect();
+
+            let mut encoded = EncodedFunction {
+                function: machine_function.function,
+                code: Vec::new(),
+                relocations: Vec::new(),
+            };
+            for (i, inst) in code.iter().enumerate() {
+                let offset = offsets[i] as i64;
+                let words = match *inst {
+                    Inst::Block(_) => vec![],
+                    Inst::Branch {
+                        condition,
+                        rs1,
+                        rs2,
+                        target,
+                    } if is_long[i] => {
+                        let displacement = blocks[&target] as i64 - (offset + 4);
+                        vec![
+                            branch(condition.inverse(), rs1, rs2, 8),
+                            jal(ZERO, displacement),
+                        ]
+                    }
+                    Inst::Branch {
+                        condition,
+                        rs1,
+                        rs2,
+                        target,
+                    } => {
+                        vec![branch(condition, rs1, rs2, blocks[&target] as i64 - offset)]
+                    }
+                    Inst::Jump(Target::Block(label)) => {
+                        vec![jal(ZERO, blocks[&label] as i64 - offset)]
+                    }
+                    Inst::Jump(Target::Function(function)) => {
+                        encoded.relocations.push((offset as usize, function));
+                        vec![jal(ZERO, 0)]
+                    }
+                    Inst::Call(function) => {
+                        encoded.relocations.push((offset as usize, function));
+                        vec![jal(RA, 0)]
+                    }
+                    inst => vec![encode_instruction(inst)],
+                };
+                for word in words {
+                    encoded.code.extend(word.to_le_bytes());
+                }
+            }
+
+            encoded
+        })
+        .collect();
+    Ok(encoded)
+}
+
+/// Offset of every instruction, and whether each conditional branch has to be replaced
+/// by a branch over a `jal`.
+///
+/// Branches start short, and are made long until all the short ones reach their target,
+/// since making one long can push others out of reach.
+pub(super) fn layout(code: &[Inst]) -> (Vec<usize>, Vec<bool>) {
+    let mut is_long = vec![false; code.len()];
+    loop {
+        let mut offsets = Vec::with_capacity(code.len());
+        let mut blocks = HashMap::new();
+        let mut offset = 0;
+        for (i, inst) in code.iter().enumerate() {
+            offsets.push(offset);
+            offset += match inst {
+                Inst::Block(label) => {
+                    blocks.insert(*label, offset);
+                    0
+                }
+                Inst::Branch { .. } if is_long[i] => 8,
+                _ => 4,
+            };
+        }
+
+        let mut changed = false;
+        for (i, inst) in code.iter().enumerate() {
+            if let Inst::Branch { target, .. } = inst {
+                let displacement = blocks[target] as i64 - offsets[i] as i64;
+                if !is_long[i] && !(-4096..4096).contains(&displacement) {
+                    is_long[i] = true;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return (offsets, is_long);
+        }
+    }
+}
+
+fn encode_instruction(inst: Inst) -> u32 {
+    match inst {
+        Inst::Register { op, rd, rs1, rs2 } => {
+            let (funct7, funct3) = match op {
+                RegisterOp::Add => (0x00, 0),
+                RegisterOp::Sub => (0x20, 0),
+                RegisterOp::Sll => (0x00, 1),
+                RegisterOp::Slt => (0x00, 2),
+                RegisterOp::Sltu => (0x00, 3),
+                RegisterOp::Xor => (0x00, 4),
+                RegisterOp::Srl => (0x00, 5),
+                RegisterOp::Sra => (0x20, 5),
+                RegisterOp::Or => (0x00, 6),
+                RegisterOp::And => (0x00, 7),
+                RegisterOp::Mul => (0x01, 0),
+                RegisterOp::Div => (0x01, 4),
+                RegisterOp::Divu => (0x01, 5),
+                RegisterOp::Rem => (0x01, 6),
+                RegisterOp::Remu => (0x01, 7),
+            };
+            funct7 << 25 | rs2.index() << 20 | r_type(0x33, funct3, rd, rs1)
+        }
+        Inst::Immediate { op, rd, rs1, imm } => {
+            let (opcode, funct3, imm) = match op {
+                ImmediateOp::Addi => (0x13, 0, imm),
+                ImmediateOp::Sltiu => (0x13, 3, imm),
+                ImmediateOp::Xori => (0x13, 4, imm),
+                ImmediateOp::Andi => (0x13, 7, imm),
+                ImmediateOp::Slli => (0x13, 1, imm & 0x3F),
+                ImmediateOp::Srli => (0x13, 5, imm & 0x3F),
+                ImmediateOp::Srai => (0x13, 5, 0x400 | imm & 0x3F),
+                ImmediateOp::Addiw => (0x1B, 0, imm),
+            };
+            debug_assert!((-2048..2048).contains(&imm), "immediate out of range");
+            (imm as u32) << 20 | r_type(opcode, funct3, rd, rs1)
+        }
+        Inst::Lui { rd, imm } => (imm as u32 & 0xF_FFFF) << 12 | rd.index() << 7 | 0x37,
+        Inst::Load {
+            size,
+            is_signed,
+            rd,
+            base,
+            offset,
+        } => {
+            let funct3 = match (size, is_signed) {
+                (1, true) => 0,
+                (2, true) => 1,
+                (4, true) => 2,
+                (1, false) => 4,
+                (2, false) => 5,
+                (4, false) => 6,
+                _ => 3,
+            };
+            (offset as u32) << 20 | r_type(0x03, funct3, rd, base)
+        }
+        Inst::Store {
+            size,
+            src,
+            base,
+            offset,
+        } => {
+            let funct3 = match size {
+                1 => 0,
+                2 => 1,
+                4 => 2,
+                _ => 3,
+            };
+            let offset = offset as u32;
+            (offset >> 5 & 0x7F) << 25
+                | src.index() << 20
+                | base.index() << 15
+                | funct3 << 12
+                | (offset & 0x1F) << 7
+                | 0x23
+        }
+        // `ret`, which is `jalr zero, 0(ra)`.
+        Inst::Return => r_type(0x67, 0, ZERO, RA),
+        Inst::Block(_) | Inst::Branch { .. } | Inst::Jump(_) | Inst::Call(_) => {
+            unreachable!("jumps are encoded with their offset")
+        }
+    }
+}
+
+/// The fields shared by R and I type instructions.
+fn r_type(opcode: u32, funct3: u32, rd: Register, rs1: Register) -> u32 {
+    rs1.index() << 15 | funct3 << 12 | rd.index() << 7 | opcode
+}
+
+fn branch(
+    condition: BranchCondition,
+    rs1: Register,
+    rs2: Register,
+    displacement: i64,
+) -> u32 {
+    let funct3 = match condition {
+        BranchCondition::Equal => 0,
+        BranchCondition::NotEqual => 1,
+        BranchCondition::Less => 4,
+        BranchCondition::GreaterOrEqual => 5,
+        BranchCondition::LessUnsigned => 6,
+        BranchCondition::GreaterOrEqualUnsigned => 7,
+    };
+    let imm = displacement as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3F) << 25
+        | rs2.index() << 20
+        | rs1.index() << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xF) << 8
+        | (imm >> 11 & 1) << 7
+        | 0x63
+}
+
+/// `jal rd, displacement`, reaching 1 MiB away.
+pub(crate) fn jal(rd: Register, displacement: i64) -> u32 {
+    assert!(
+        (-(1 << 20)..1 << 20).contains(&displacement),
+        "jump too far"
+    );
+    let imm = displacement as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3FF) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xFF) << 12
+        | rd.index() << 7
+        | 0x6F
+}
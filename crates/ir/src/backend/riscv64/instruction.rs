@@ -0,0 +1,173 @@
+use crate::{function::Function, label::Label};
+
+/// An integer register, by its number `x0` to `x31`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Register(pub u8);
+
+pub(crate) const ZERO: Register = Register(0);
+pub(crate) const RA: Register = Register(1);
+pub(crate) const SP: Register = Register(2);
+pub(crate) const T0: Register = Register(5);
+pub(crate) const T1: Register = Register(6);
+pub(crate) const T2: Register = Register(7);
+/// Frame pointer.
+pub(crate) const S0: Register = Register(8);
+pub(crate) const S1: Register = Register(9);
+pub(crate) const A0: Register = Register(10);
+pub(crate) const A1: Register = Register(11);
+pub(crate) const A2: Register = Register(12);
+pub(crate) const A3: Register = Register(13);
+pub(crate) const A4: Register = Register(14);
+pub(crate) const A5: Register = Register(15);
+pub(crate) const A6: Register = Register(16);
+pub(crate) const A7: Register = Register(17);
+pub(crate) const S2: Register = Register(18);
+pub(crate) const S3: Register = Register(19);
+pub(crate) const S4: Register = Register(20);
+pub(crate) const S5: Register = Register(21);
+pub(crate) const S6: Register = Register(22);
+pub(crate) const S7: Register = Register(23);
+pub(crate) const S8: Register = Register(24);
+pub(crate) const S9: Register = Register(25);
+pub(crate) const S10: Register = Register(26);
+pub(crate) const S11: Register = Register(27);
+pub(crate) const T3: Register = Register(28);
+pub(crate) const T4: Register = Register(29);
+pub(crate) const T5: Register = Register(30);
+pub(crate) const T6: Register = Register(31);
+
+/// ABI names of the registers.
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3",
+    "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11",
+    "t3", "t4", "t5", "t6",
+];
+
+impl Register {
+    pub fn name(self) -> &'static str {
+        REGISTER_NAMES[self.0 as usize]
+    }
+
+    /// The number of the register, as encoded in instructions.
+    pub fn index(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+/// Operations between two registers, `rd = rs1 op rs2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RegisterOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+/// Operations between a register and a 12 bit signed immediate, `rd = rs1 op imm`.
+/// Shifts take a 6 bit amount instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImmediateOp {
+    Addi,
+    Sltiu,
+    Xori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+    /// Adds on the low 32 bits, and sign extends the result.
+    Addiw,
+}
+
+/// Comparisons of conditional branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BranchCondition {
+    Equal,
+    NotEqual,
+    Less,
+    GreaterOrEqual,
+    LessUnsigned,
+    GreaterOrEqualUnsigned,
+}
+
+impl BranchCondition {
+    /// The condition holding exactly when `self` doesn't.
+    pub fn inverse(self) -> Self {
+        match self {
+            BranchCondition::Equal => BranchCondition::NotEqual,
+            BranchCondition::NotEqual => BranchCondition::Equal,
+            BranchCondition::Less => BranchCondition::GreaterOrEqual,
+            BranchCondition::GreaterOrEqual => BranchCondition::Less,
+            BranchCondition::LessUnsigned => BranchCondition::GreaterOrEqualUnsigned,
+            BranchCondition::GreaterOrEqualUnsigned => BranchCondition::LessUnsigned,
+        }
+    }
+}
+
+/// Destination of a jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Block(Label),
+    /// Start of a function, for tail calls.
+    Function(Function),
+}
+
+/// A machine instruction, selected from the IR and either printed as assembly or
+/// encoded to machine code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inst {
+    /// Start of the block of a label, not an instruction.
+    Block(Label),
+    Register {
+        op: RegisterOp,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+    },
+    Immediate {
+        op: ImmediateOp,
+        rd: Register,
+        rs1: Register,
+        imm: i32,
+    },
+    /// Sets `rd` to `imm << 12` sign extended from 32 bits, `imm` being the unsigned 20
+    /// bit field of the instruction.
+    Lui {
+        rd: Register,
+        imm: i32,
+    },
+    /// Loads `size` bytes at `base + offset`, sign or zero extended to 64 bits.
+    Load {
+        size: u8,
+        is_signed: bool,
+        rd: Register,
+        base: Register,
+        offset: i32,
+    },
+    /// Stores the low `size` bytes of `src` at `base + offset`.
+    Store {
+        size: u8,
+        src: Register,
+        base: Register,
+        offset: i32,
+    },
+    Branch {
+        condition: BranchCondition,
+        rs1: Register,
+        rs2: Register,
+        target: Label,
+    },
+    Jump(Target),
+    Call(Function),
+    Return,
+}
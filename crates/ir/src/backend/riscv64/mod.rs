@@ -0,0 +1,753 @@
+//! Code generation for RV64IM, following the standard RISC-V calling convention.
+//!
+//! Instructions are selected once into [`Inst`]s, then either printed as assembly or
+//! encoded to machine code, which the bundled simulator runs.
+
+mod assembly;
+mod encoding;
+mod instruction;
+mod simulator;
+mod tiles;
+
+pub(crate) use assembly::emit;
+pub use simulator::{Program, Trap, TrapKind};
+
+use crate::{
+    backend::{
+        align_of, align_up, block_order, check_types,
+        register_allocation::{
+            AllocatableRegister, Assignment, PhysicalRegister, RegisterAllocation,
+            RegisterClass, RegisterDescription,
+        },
+        selection::{cover, Tree, Trees},
+        size_of, Unsupported,
+    },
+    function::{Function, FunctionData, Functions},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    label::Label,
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use instruction::*;
+use std::collections::HashMap;
+use tiles::{Kind, SCRATCH_REGISTERS};
+
+/// Registers of the first integer arguments, in order. The rest are passed on the stack.
+const ARGUMENT_REGISTERS: [Register; 8] = [A0, A1, A2, A3, A4, A5, A6, A7];
+
+/// Registers handed out by the register allocator, indexed by [`PhysicalRegister`].
+/// `t0` to `t3` and the argument registers are left to lower instructions.
+const ALLOCATABLE_REGISTERS: [Register; 14] =
+    [T4, T5, T6, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11];
+
+/// Integers and pointers all live in integer registers.
+const REGISTER_DESCRIPTION: RegisterDescription = RegisterDescription {
+    classes: &[RegisterClass {
+        registers: &[
+            AllocatableRegister {
+                register: PhysicalRegister(0),
+                callee_saved: false,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(1),
+                callee_saved: false,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(2),
+                callee_saved: false,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(3),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(4),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(5),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(6),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(7),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(8),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(9),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(10),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(11),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(12),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(13),
+                callee_saved: true,
+            },
+        ],
+    }],
+    class_of: register_class,
+};
+
+fn register_class(types: &Types, ty: Type) -> Option<usize> {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer {
+            num_bits: 1 | 8 | 16 | 32 | 64,
+            ..
+        }
+        | TypeKind::Pointer { .. } => Some(0),
+        _ => None,
+    }
+}
+
+/// Whether `value` fits the 12 bit signed immediate of I and S type instructions.
+fn fits_immediate(value: i64) -> bool {
+    (-2048..2048).contains(&value)
+}
+
+/// Instructions selected for a function.
+pub(crate) struct MachineFunction {
+    pub function: Function,
+    pub code: Vec<Inst>,
+}
+
+/// Selects the instructions of every function of the module.
+///
+/// Values live in the registers given by [`RegisterAllocation`], or in stack slots of
+/// the function frame when spilled, and are moved through `t0`, `t1` and `t2` by every
+/// instruction, `t3` holding addresses of slots too far from the frame pointer. Integers
+/// are kept extended to 64 bits in registers, according to the signedness of their
+/// type, and stored to memory with their size.
+///
+/// Memory accesses are selected by tiling, their operands going through the temporary
+/// and argument registers not holding values, so addresses fold into offsets of loads
+/// and stores.
+///
+/// The frame pointer `s0` holds the stack pointer at the entry of the function, the
+/// return address and the caller's `s0` being saved right below it.
+///
+/// Only integers of 1, 8, 16, 32 and 64 bits and pointers are supported as values.
+pub(crate) fn select(
+    types: &Types,
+    functions: &Functions,
+) -> Result<Vec<MachineFunction>, Unsupported> {
+    check_types(types, functions, |ty| register_class(types, ty).is_some())?;
+
+    let machine_functions = functions
+        .iter()
+        .map(|(id, function)| {
+            let mut selector = InstructionSelector::new(types, function);
+            selector.select();
+            MachineFunction {
+                function: Function(id),
+                code: selector.code,
+            }
+        })
+        .collect();
+    Ok(machine_functions)
+}
+
+/// Selects the instructions of a single function.
+struct InstructionSelector<'a> {
+    types: &'a Types,
+    function: &'a FunctionData,
+    allocation: RegisterAllocation,
+    /// Offset from `s0` of the slot of every spilled value.
+    slots: HashMap<Value, i64>,
+    /// Callee saved registers used by the function, with the offset from `s0` where
+    /// their value is kept.
+    saved_registers: Vec<(Register, i64)>,
+    /// Offset from `s0` of the memory of every `stack_alloc`.
+    allocations: HashMap<Value, i64>,
+    frame_size: u64,
+    /// Registers free for the nodes of the tree being emitted.
+    scratch: Vec<Register>,
+    code: Vec<Inst>,
+}
+
+impl<'a> InstructionSelector<'a> {
+    /// Lays out the frame of `function`.
+    fn new(types: &'a Types, function: &'a FunctionData) -> Self {
+        let allocation = RegisterAllocation::new(types, function, &REGISTER_DESCRIPTION);
+
+        // Below the return address and the saved `s0`.
+        let mut offset: u64 = 16;
+        let mut slots = HashMap::new();
+        let mut allocations = HashMap::new();
+
+        let mut spilled: Vec<Value> = allocation
+            .assignments
+            .iter()
+            .filter(|(_, assignment)| **assignment == Assignment::Spilled)
+            .map(|(value, _)| *value)
+            .collect();
+        spilled.sort();
+        for value in spilled {
+            offset += 8;
+            slots.insert(value, -(offset as i64));
+        }
+
+        let saved_registers = allocation
+            .used_callee_saved
+            .iter()
+            .map(|register| {
+                offset += 8;
+                (ALLOCATABLE_REGISTERS[register.0 as usize], -(offset as i64))
+            })
+            .collect();
+
+        for (_, data) in function.labels().iter() {
+            for instr in data.instructions.iter() {
+                if let Instruction::StackAlloc { dst, ty, size } = instr {
+                    let bytes = size_of(types, *ty) * *size as u64;
+                    offset = align_up(offset + bytes, align_of(types, *ty));
+                    allocations.insert(*dst, -(offset as i64));
+                }
+            }
+        }
+
+        InstructionSelector {
+            types,
+            function,
+            allocation,
+            slots,
+            saved_registers,
+            allocations,
+            frame_size: align_up(offset, 16),
+            scratch: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn select(&mut self) {
+        self.add_immediate(SP, SP, -16);
+        self.store_register(RA, SP, 8);
+        self.store_register(S0, SP, 0);
+        self.add_immediate(S0, SP, 16);
+        self.add_immediate(SP, SP, -(self.frame_size as i64 - 16));
+        for (register, offset) in self.saved_registers.clone() {
+            let (base, offset) = self.frame_address(offset);
+            self.store_register(register, base, offset);
+        }
+
+        let parameters = self.function.parameters();
+        for (i, parameter) in parameters.iter().enumerate() {
+            match ARGUMENT_REGISTERS.get(i) {
+                Some(register) => self.store(*register, *parameter),
+                None => {
+                    // Where the stack pointer of the caller points.
+                    let offset = 8 * (i - ARGUMENT_REGISTERS.len()) as i64;
+                    let (base, offset) = self.frame_address(offset);
+                    self.push(Inst::Load {
+                        size: 8,
+                        is_signed: false,
+                        rd: T0,
+                        base,
+                        offset,
+                    });
+                    self.store(T0, *parameter);
+                }
+            }
+        }
+
+        let trees = Trees::new(self.types, self.function);
+        let order = block_order(self.function);
+        for (i, label) in order.iter().enumerate() {
+            self.push(Inst::Block(*label));
+            let next = order.get(i + 1).copied();
+            let instructions = &self.function.labels().get(*label).instructions;
+            for (index, instr) in instructions.iter().enumerate() {
+                if let Some(tree) = trees.tree(*label, index) {
+                    self.select_tree(tree);
+                } else if !trees.is_folded(*label, index) {
+                    self.select_instruction(instr, next);
+                }
+            }
+        }
+    }
+
+    /// Covers `tree` with tiles, and stores its result to the value it defines.
+    fn select_tree(&mut self, tree: &Tree) {
+        self.scratch = SCRATCH_REGISTERS.to_vec();
+        match tree.dst {
+            Some(dst) => {
+                let register = cover(self, tree, Kind::Register).register();
+                self.store(register, dst);
+            }
+            None => {
+                cover(self, tree, Kind::Effect);
+            }
+        }
+    }
+
+    fn select_instruction(&mut self, instr: &Instruction, next: Option<Label>) {
+        match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => {
+                let is_signed = self.is_signed(*dst);
+                // Shifts in bits from above the type, so the operand has to be
+                // extended the way the shift fills the top.
+                let extend_signed = match op {
+                    BinaryOp::Shr => false,
+                    BinaryOp::Sar => true,
+                    _ => is_signed,
+                };
+                self.load_extended(*lhs, T0, extend_signed);
+                self.load(*rhs, T1);
+
+                let op = match op {
+                    BinaryOp::Add => RegisterOp::Add,
+                    BinaryOp::Sub => RegisterOp::Sub,
+                    BinaryOp::Mul => RegisterOp::Mul,
+                    BinaryOp::Div if is_signed => RegisterOp::Div,
+                    BinaryOp::Div => RegisterOp::Divu,
+                    BinaryOp::Mod if is_signed => RegisterOp::Rem,
+                    BinaryOp::Mod => RegisterOp::Remu,
+                    BinaryOp::Shl => RegisterOp::Sll,
+                    BinaryOp::Shr => RegisterOp::Srl,
+                    BinaryOp::Sar => RegisterOp::Sra,
+                    BinaryOp::And | BinaryOp::BitAnd => RegisterOp::And,
+                    BinaryOp::Or | BinaryOp::BitOr => RegisterOp::Or,
+                    BinaryOp::Xor => RegisterOp::Xor,
+                };
+                self.register_op(op, T0, T0, T1);
+                self.store(T0, *dst);
+            }
+            Instruction::ArithmeticUnary { dst, op, value } => {
+                self.load(*value, T0);
+                match op {
+                    UnaryOp::Neg => self.register_op(RegisterOp::Sub, T0, ZERO, T0),
+                    UnaryOp::Not if self.num_bits(*dst) == 1 => {
+                        self.immediate_op(ImmediateOp::Xori, T0, T0, 1)
+                    }
+                    UnaryOp::Not => self.immediate_op(ImmediateOp::Xori, T0, T0, -1),
+                }
+                self.store(T0, *dst);
+            }
+            Instruction::Branch { target } => self.jump(*target, next),
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.load(*condition, T0);
+                if Some(*on_true) == next {
+                    self.push(Inst::Branch {
+                        condition: BranchCondition::Equal,
+                        rs1: T0,
+                        rs2: ZERO,
+                        target: *on_false,
+                    });
+                } else {
+                    self.push(Inst::Branch {
+                        condition: BranchCondition::NotEqual,
+                        rs1: T0,
+                        rs2: ZERO,
+                        target: *on_true,
+                    });
+                    self.jump(*on_false, next);
+                }
+            }
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                tail,
+            } => {
+                let (in_registers, on_stack) =
+                    arguments.split_at(arguments.len().min(ARGUMENT_REGISTERS.len()));
+
+                // The frame is given back before jumping, so only calls without stack
+                // arguments can reuse it.
+                if *tail && on_stack.is_empty() {
+                    for (argument, register) in
+                        in_registers.iter().zip(ARGUMENT_REGISTERS)
+                    {
+                        self.load(*argument, register);
+                    }
+                    self.epilogue();
+                    self.push(Inst::Jump(Target::Function(*function)));
+                    return;
+                }
+
+                // The stack pointer stays 16 byte aligned.
+                let stack_size = align_up(8 * on_stack.len() as u64, 16) as i64;
+                self.add_immediate(SP, SP, -stack_size);
+                for (i, argument) in on_stack.iter().enumerate() {
+                    self.load(*argument, T0);
+                    self.store_register(T0, SP, 8 * i as i32);
+                }
+                for (argument, register) in in_registers.iter().zip(ARGUMENT_REGISTERS) {
+                    self.load(*argument, register);
+                }
+
+                self.push(Inst::Call(*function));
+                self.add_immediate(SP, SP, stack_size);
+                if let Some(dst) = dst {
+                    self.store(A0, *dst);
+                }
+            }
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                // Coalesced with its operand, which already has the right form.
+                let same_form = self.num_bits(*value) == self.num_bits(*dst)
+                    && self.is_signed(*value) == self.is_signed(*dst);
+                if *cast_op == CastOp::BitCast
+                    && same_form
+                    && self.function.constant(*value).is_none()
+                    && self.allocation.assignment(*dst)
+                        == self.allocation.assignment(*value)
+                {
+                    return;
+                }
+
+                match cast_op {
+                    CastOp::SignExtend => self.load_extended(*value, T0, true),
+                    CastOp::ZeroExtend => self.load_extended(*value, T0, false),
+                    CastOp::Truncate | CastOp::BitCast => self.load(*value, T0),
+                }
+                self.store(T0, *dst);
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let is_signed = self.is_signed(*lhs);
+                self.load(*lhs, T0);
+                self.load(*rhs, T1);
+
+                let less = if is_signed {
+                    RegisterOp::Slt
+                } else {
+                    RegisterOp::Sltu
+                };
+                match pred {
+                    IntCompareOp::Equal => {
+                        self.register_op(RegisterOp::Sub, T0, T0, T1);
+                        self.immediate_op(ImmediateOp::Sltiu, T0, T0, 1);
+                    }
+                    IntCompareOp::NotEqual => {
+                        self.register_op(RegisterOp::Sub, T0, T0, T1);
+                        self.register_op(RegisterOp::Sltu, T0, ZERO, T0);
+                    }
+                    IntCompareOp::LessThan => self.register_op(less, T0, T0, T1),
+                    IntCompareOp::GreaterThan => self.register_op(less, T0, T1, T0),
+                    IntCompareOp::LessThanOrEqual => {
+                        self.register_op(less, T0, T1, T0);
+                        self.immediate_op(ImmediateOp::Xori, T0, T0, 1);
+                    }
+                    IntCompareOp::GreaterThanOrEqual => {
+                        self.register_op(less, T0, T0, T1);
+                        self.immediate_op(ImmediateOp::Xori, T0, T0, 1);
+                    }
+                }
+                self.store(T0, *dst);
+            }
+            Instruction::Return { value } => {
+                if let Some(value) = value {
+                    self.load(*value, A0);
+                }
+                self.epilogue();
+                self.push(Inst::Return);
+            }
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => {
+                // Without conditional moves, `on_false ^ ((on_true ^ on_false) & -condition)`.
+                self.load(*on_false, T0);
+                self.load(*on_true, T1);
+                self.load(*condition, T2);
+                self.register_op(RegisterOp::Sub, T2, ZERO, T2);
+                self.register_op(RegisterOp::Xor, T1, T1, T0);
+                self.register_op(RegisterOp::And, T1, T1, T2);
+                self.register_op(RegisterOp::Xor, T0, T0, T1);
+                self.store(T0, *dst);
+            }
+            Instruction::GetElementPtr { .. }
+            | Instruction::Load { .. }
+            | Instruction::StackAlloc { .. }
+            | Instruction::Store { .. } => unreachable!("memory accesses are tiled"),
+            Instruction::Nop => {}
+        }
+    }
+
+    /// Loads `value` into `register`, extended according to the signedness of its type.
+    fn load(&mut self, value: Value, register: Register) {
+        self.load_extended(value, register, self.is_signed(value));
+    }
+
+    /// Loads `value` into `register`, sign extended if `is_signed`, zero extended
+    /// otherwise.
+    fn load_extended(&mut self, value: Value, register: Register, is_signed: bool) {
+        let num_bits = self.num_bits(value);
+        if let Some(constant) = self.function.constant(value) {
+            let bits = constant
+                .integer()
+                .expect("float values are rejected by `check_types`");
+            let shift = 64 - num_bits;
+            let bits = if is_signed {
+                (((bits << shift) as i64) >> shift) as u64
+            } else {
+                (bits << shift) >> shift
+            };
+
+            self.load_immediate(register, bits as i64);
+            return;
+        }
+
+        match self.allocation.assignment(value) {
+            Assignment::Register(source) => {
+                let source = ALLOCATABLE_REGISTERS[source.0 as usize];
+                self.extend(register, source, num_bits, is_signed);
+            }
+            Assignment::Spilled => {
+                let (base, offset) = self.frame_address(self.slots[&value]);
+                self.push(Inst::Load {
+                    size: self.value_size(self.ty(value)),
+                    is_signed,
+                    rd: register,
+                    base,
+                    offset,
+                });
+            }
+        }
+    }
+
+    /// Stores `register` to the register or the slot of `value`.
+    fn store(&mut self, register: Register, value: Value) {
+        let ty = self.ty(value);
+        match self.allocation.assignment(value) {
+            Assignment::Register(target) => {
+                let target = ALLOCATABLE_REGISTERS[target.0 as usize];
+                self.extend(
+                    target,
+                    register,
+                    self.num_bits(value),
+                    self.is_signed(value),
+                );
+            }
+            Assignment::Spilled => {
+                let (base, offset) = self.frame_address(self.slots[&value]);
+                self.store_memory(ty, register, base, offset);
+            }
+        }
+    }
+
+    /// Stores the low bytes of `register` holding a value of type `ty` at
+    /// `base + offset`.
+    fn store_memory(
+        &mut self,
+        ty: Type,
+        register: Register,
+        base: Register,
+        offset: i32,
+    ) {
+        if matches!(
+            self.types.get(ty).type_kind(),
+            TypeKind::Integer { num_bits: 1, .. }
+        ) {
+            self.immediate_op(ImmediateOp::Andi, register, register, 1);
+        }
+
+        self.push(Inst::Store {
+            size: self.value_size(ty),
+            src: register,
+            base,
+            offset,
+        });
+    }
+
+    /// Copies the low `num_bits` bits of `source` to `register`, sign extended if
+    /// `is_signed`, zero extended otherwise.
+    fn extend(
+        &mut self,
+        register: Register,
+        source: Register,
+        num_bits: u32,
+        is_signed: bool,
+    ) {
+        let shift = 64 - num_bits as i32;
+        match (num_bits, is_signed) {
+            (64, _) if register == source => {}
+            (64, _) => self.immediate_op(ImmediateOp::Addi, register, source, 0),
+            (1, _) => self.immediate_op(ImmediateOp::Andi, register, source, 1),
+            (8, false) => self.immediate_op(ImmediateOp::Andi, register, source, 0xFF),
+            (32, true) => self.immediate_op(ImmediateOp::Addiw, register, source, 0),
+            (_, false) => {
+                self.immediate_op(ImmediateOp::Slli, register, source, shift);
+                self.immediate_op(ImmediateOp::Srli, register, register, shift);
+            }
+            (_, true) => {
+                self.immediate_op(ImmediateOp::Slli, register, source, shift);
+                self.immediate_op(ImmediateOp::Srai, register, register, shift);
+            }
+        }
+    }
+
+    /// Sets `register` to `value`, with `lui` and `addiw` for 32 bit values and shifts
+    /// for wider ones.
+    fn load_immediate(&mut self, register: Register, value: i64) {
+        // The low 12 bits are added sign extended, so the rest is rounded accordingly.
+        let low = (value << 52) >> 52;
+        let high = value.wrapping_sub(low) >> 12;
+
+        if i32::try_from(value).is_ok() {
+            if high == 0 {
+                self.immediate_op(ImmediateOp::Addi, register, ZERO, low as i32);
+                return;
+            }
+            // `addiw` wraps around on 32 bits, for values just below 2^31.
+            self.push(Inst::Lui {
+                rd: register,
+                imm: high as i32 & 0xF_FFFF,
+            });
+            if low != 0 {
+                self.immediate_op(ImmediateOp::Addiw, register, register, low as i32);
+            }
+            return;
+        }
+
+        self.load_immediate(register, high);
+        self.immediate_op(ImmediateOp::Slli, register, register, 12);
+        if low != 0 {
+            self.immediate_op(ImmediateOp::Addi, register, register, low as i32);
+        }
+    }
+
+    /// Sets `register` to `source + value`.
+    fn add_immediate(&mut self, register: Register, source: Register, value: i64) {
+        if value == 0 && register == source {
+            return;
+        }
+        if fits_immediate(value) {
+            self.immediate_op(ImmediateOp::Addi, register, source, value as i32);
+        } else {
+            self.load_immediate(T3, value);
+            self.register_op(RegisterOp::Add, register, source, T3);
+        }
+    }
+
+    /// Base register and offset addressing `offset` from `s0`.
+    fn frame_address(&mut self, offset: i64) -> (Register, i32) {
+        if fits_immediate(offset) {
+            (S0, offset as i32)
+        } else {
+            self.add_immediate(T3, S0, offset);
+            (T3, 0)
+        }
+    }
+
+    /// Restores the callee saved registers and releases the frame.
+    fn epilogue(&mut self) {
+        for (register, offset) in self.saved_registers.clone() {
+            let (base, offset) = self.frame_address(offset);
+            self.push(Inst::Load {
+                size: 8,
+                is_signed: false,
+                rd: register,
+                base,
+                offset,
+            });
+        }
+        self.add_immediate(SP, S0, -16);
+        self.push(Inst::Load {
+            size: 8,
+            is_signed: false,
+            rd: RA,
+            base: SP,
+            offset: 8,
+        });
+        self.push(Inst::Load {
+            size: 8,
+            is_signed: false,
+            rd: S0,
+            base: SP,
+            offset: 0,
+        });
+        self.add_immediate(SP, SP, 16);
+    }
+
+    fn store_register(&mut self, register: Register, base: Register, offset: i32) {
+        self.push(Inst::Store {
+            size: 8,
+            src: register,
+            base,
+            offset,
+        });
+    }
+
+    fn register_op(
+        &mut self,
+        op: RegisterOp,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+    ) {
+        self.push(Inst::Register { op, rd, rs1, rs2 });
+    }
+
+    fn immediate_op(&mut self, op: ImmediateOp, rd: Register, rs1: Register, imm: i32) {
+        self.push(Inst::Immediate { op, rd, rs1, imm });
+    }
+
+    /// Size of a value of type `ty` held in a register.
+    fn value_size(&self, ty: Type) -> u8 {
+        match self.types.get(ty).type_kind() {
+            TypeKind::Integer { .. } => size_of(self.types, ty) as u8,
+            TypeKind::Pointer { .. } => 8,
+            TypeKind::Float { .. } | TypeKind::Struct { .. } => {
+                unreachable!("rejected by `check_types`")
+            }
+        }
+    }
+
+    fn jump(&mut self, target: Label, next: Option<Label>) {
+        if Some(target) != next {
+            self.push(Inst::Jump(Target::Block(target)));
+        }
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+
+    fn num_bits(&self, value: Value) -> u32 {
+        self.types
+            .integer(self.ty(value))
+            .map_or(64, |(num_bits, _)| num_bits)
+    }
+
+    fn is_signed(&self, value: Value) -> bool {
+        self.types
+            .integer(self.ty(value))
+            .is_some_and(|(_, is_signed)| is_signed)
+    }
+
+    fn push(&mut self, inst: Inst) {
+        self.code.push(inst);
+    }
+}
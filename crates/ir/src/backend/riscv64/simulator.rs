@@ -0,0 +1,425 @@
+use super::encoding::{encode, jal};
+use crate::{
+    backend::{align_up, Unsupported},
+    function::Functions,
+    ty::Types,
+};
+use std::{collections::HashMap, fmt};
+
+/// Address the code of a program is loaded at.
+const CODE_ADDRESS: u64 = 0x1_0000;
+
+/// The stack grows down from this address.
+const STACK_TOP: u64 = 0x8000_0000;
+
+/// Size of the stack of a run.
+const STACK_SIZE: u64 = 1 << 20;
+
+/// Return address of the called function, ending the run when jumped to.
+const EXIT_ADDRESS: u64 = 0;
+
+/// The RV64IM machine code of a module, linked and ready to be run by the simulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<u8>,
+    functions: HashMap<String, FunctionEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FunctionEntry {
+    /// Offset of the first instruction in the code.
+    offset: usize,
+    num_parameters: usize,
+    has_return_value: bool,
+}
+
+/// Reason of a [`Trap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapKind {
+    /// The instruction is not part of RV64IM.
+    IllegalInstruction {
+        instruction: u32,
+    },
+    /// Memory was accessed outside of the stack, or instructions fetched outside of the
+    /// code or from a misaligned address.
+    AccessFault {
+        address: u64,
+        size: u8,
+    },
+    EnvironmentCall,
+    Breakpoint,
+}
+
+/// An exception raised by the simulated hart, ending the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// Address of the instruction raising the exception.
+    pub pc: u64,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TrapKind::IllegalInstruction { instruction } => {
+                write!(f, "illegal instruction {instruction:#010x}")?
+            }
+            TrapKind::AccessFault { address, size } => {
+                write!(f, "access fault on {size} bytes at {address:#x}")?
+            }
+            TrapKind::EnvironmentCall => write!(f, "environment call")?,
+            TrapKind::Breakpoint => write!(f, "breakpoint")?,
+        }
+        write!(f, " at pc {:#x}", self.pc)
+    }
+}
+
+impl std::error::Error for Trap {}
+
+impl Program {
+    /// Encodes and links every function of the module, one after the other.
+    pub(crate) fn new(types: &Types, functions: &Functions) -> Result<Self, Unsupported> {
+        let encoded = encode(types, functions)?;
+
+        let mut offsets = HashMap::new();
+        let mut code = Vec::new();
+        for function in encoded.iter() {
+            offsets.insert(function.function, code.len());
+            code.extend(&function.code);
+        }
+        for function in encoded.iter() {
+            let start = offsets[&function.function];
+            for (offset, callee) in function.relocations.iter() {
+                let address = start + offset;
+                let displacement = offsets[callee] as i64 - address as i64;
+                // The `jal` keeps its destination register, only its offset is set.
+                let word =
+                    u32::from_le_bytes(code[address..address + 4].try_into().unwrap())
+                        | jal(super::ZERO, displacement);
+                code[address..address + 4].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+
+        let functions = functions
+            .iter()
+            .map(|(id, function)| {
+                let definition = function.definition();
+                let entry = FunctionEntry {
+                    offset: offsets[&crate::function::Function(id)],
+                    num_parameters: function.parameters().len(),
+                    has_return_value: definition.return_type.is_some(),
+                };
+                (definition.name.clone(), entry)
+            })
+            .collect();
+
+        Ok(Program { code, functions })
+    }
+
+    /// The machine code of every function, position independent.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Offset of the function called `name` in [`Program::code`].
+    pub fn offset(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).map(|entry| entry.offset)
+    }
+
+    /// Simulates a call of the function called `name` with `arguments`, and returns its
+    /// return value if it has one. Integers are passed and returned extended to 64 bits.
+    ///
+    /// The code is loaded at `0x10000` and the stack is 1 MiB right below `0x80000000`.
+    /// The run ends when the function returns, or when the hart raises an exception.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no function called `name`, or if `arguments` doesn't match
+    /// its parameters.
+    pub fn run(&self, name: &str, arguments: &[u64]) -> Result<Option<u64>, Trap> {
+        let entry = self
+            .functions
+            .get(name)
+            .unwrap_or_else(|| panic!("no function called {name}"));
+        assert_eq!(
+            entry.num_parameters,
+            arguments.len(),
+            "wrong number of arguments for {name}"
+        );
+
+        let mut hart = Hart {
+            code: &self.code,
+            registers: [0; 32],
+            pc: CODE_ADDRESS + entry.offset as u64,
+            stack: vec![0; STACK_SIZE as usize],
+        };
+
+        let (in_registers, on_stack) = arguments.split_at(arguments.len().min(8));
+        for (i, argument) in in_registers.iter().enumerate() {
+            hart.registers[10 + i] = *argument;
+        }
+        let sp = STACK_TOP - align_up(8 * on_stack.len() as u64, 16);
+        for (i, argument) in on_stack.iter().enumerate() {
+            hart.store(sp + 8 * i as u64, 8, *argument)
+                .expect("stack arguments fit the stack");
+        }
+        hart.registers[1] = EXIT_ADDRESS;
+        hart.registers[2] = sp;
+
+        while hart.pc != EXIT_ADDRESS {
+            hart.step()?;
+        }
+
+        Ok(entry.has_return_value.then_some(hart.registers[10]))
+    }
+}
+
+/// The state of a single simulated RV64IM hart.
+struct Hart<'a> {
+    code: &'a [u8],
+    registers: [u64; 32],
+    pc: u64,
+    /// Memory right below [`STACK_TOP`].
+    stack: Vec<u8>,
+}
+
+impl Hart<'_> {
+    /// Executes the instruction at `pc`.
+    fn step(&mut self) -> Result<(), Trap> {
+        let pc = self.pc;
+        let instruction = self.fetch()?;
+        let trap = |kind| Trap { kind, pc };
+        let illegal = || trap(TrapKind::IllegalInstruction { instruction });
+
+        let opcode = instruction & 0x7F;
+        let rd = (instruction >> 7 & 0x1F) as usize;
+        let funct3 = instruction >> 12 & 0x7;
+        let rs1 = self.registers[(instruction >> 15 & 0x1F) as usize];
+        let rs2 = self.registers[(instruction >> 20 & 0x1F) as usize];
+        let funct7 = instruction >> 25;
+
+        let imm_i = (instruction as i32 >> 20) as i64 as u64;
+        let imm_s =
+            ((instruction as i32 >> 25) << 5 | (instruction >> 7 & 0x1F) as i32) as i64;
+        let imm_b = ((instruction as i32 >> 31) << 12
+            | ((instruction >> 7 & 1) << 11) as i32
+            | ((instruction >> 25 & 0x3F) << 5) as i32
+            | ((instruction >> 8 & 0xF) << 1) as i32) as i64;
+        let imm_u = (instruction & 0xFFFF_F000) as i32 as i64 as u64;
+        let imm_j = ((instruction as i32 >> 31) << 20
+            | ((instruction >> 12 & 0xFF) << 12) as i32
+            | ((instruction >> 20 & 1) << 11) as i32
+            | ((instruction >> 21 & 0x3FF) << 1) as i32) as i64;
+
+        let mut next_pc = pc.wrapping_add(4);
+        let result = match opcode {
+            // LUI
+            0x37 => Some(imm_u),
+            // AUIPC
+            0x17 => Some(pc.wrapping_add(imm_u)),
+            // JAL
+            0x6F => {
+                next_pc = pc.wrapping_add_signed(imm_j);
+                Some(pc.wrapping_add(4))
+            }
+            // JALR
+            0x67 if funct3 == 0 => {
+                next_pc = rs1.wrapping_add(imm_i) & !1;
+                Some(pc.wrapping_add(4))
+            }
+            // BRANCH
+            0x63 => {
+                let taken = match funct3 {
+                    0 => rs1 == rs2,
+                    1 => rs1 != rs2,
+                    4 => (rs1 as i64) < rs2 as i64,
+                    5 => rs1 as i64 >= rs2 as i64,
+                    6 => rs1 < rs2,
+                    7 => rs1 >= rs2,
+                    _ => return Err(illegal()),
+                };
+                if taken {
+                    next_pc = pc.wrapping_add_signed(imm_b);
+                }
+                None
+            }
+            // LOAD
+            0x03 => {
+                let address = rs1.wrapping_add(imm_i);
+                let (size, is_signed) = match funct3 {
+                    0 => (1, true),
+                    1 => (2, true),
+                    2 => (4, true),
+                    3 => (8, true),
+                    4 => (1, false),
+                    5 => (2, false),
+                    6 => (4, false),
+                    _ => return Err(illegal()),
+                };
+                let value = self.load(address, size).map_err(trap)?;
+                let shift = 64 - 8 * size as u32;
+                Some(if is_signed {
+                    ((value << shift) as i64 >> shift) as u64
+                } else {
+                    value
+                })
+            }
+            // STORE
+            0x23 => {
+                let address = rs1.wrapping_add_signed(imm_s);
+                let size = match funct3 {
+                    0..=3 => 1 << funct3,
+                    _ => return Err(illegal()),
+                };
+                self.store(address, size, rs2).map_err(trap)?;
+                None
+            }
+            // OP-IMM
+            0x13 => {
+                let shamt = (imm_i & 0x3F) as u32;
+                Some(match (funct3, imm_i >> 6 & 0x3F) {
+                    (0, _) => rs1.wrapping_add(imm_i),
+                    (2, _) => ((rs1 as i64) < imm_i as i64) as u64,
+                    (3, _) => (rs1 < imm_i) as u64,
+                    (4, _) => rs1 ^ imm_i,
+                    (6, _) => rs1 | imm_i,
+                    (7, _) => rs1 & imm_i,
+                    (1, 0) => rs1 << shamt,
+                    (5, 0) => rs1 >> shamt,
+                    (5, 0x10) => (rs1 as i64 >> shamt) as u64,
+                    _ => return Err(illegal()),
+                })
+            }
+            // OP-IMM-32
+            0x1B => {
+                let shamt = (imm_i & 0x1F) as u32;
+                let value = match (funct3, funct7) {
+                    (0, _) => (rs1 as i32).wrapping_add(imm_i as i32),
+                    (1, 0) => (rs1 as i32) << shamt,
+                    (5, 0) => ((rs1 as u32) >> shamt) as i32,
+                    (5, 0x20) => rs1 as i32 >> shamt,
+                    _ => return Err(illegal()),
+                };
+                Some(value as i64 as u64)
+            }
+            // OP
+            0x33 => Some(match (funct7, funct3) {
+                (0x00, 0) => rs1.wrapping_add(rs2),
+                (0x20, 0) => rs1.wrapping_sub(rs2),
+                (0x00, 1) => rs1 << (rs2 & 0x3F),
+                (0x00, 2) => ((rs1 as i64) < rs2 as i64) as u64,
+                (0x00, 3) => (rs1 < rs2) as u64,
+                (0x00, 4) => rs1 ^ rs2,
+                (0x00, 5) => rs1 >> (rs2 & 0x3F),
+                (0x20, 5) => (rs1 as i64 >> (rs2 & 0x3F)) as u64,
+                (0x00, 6) => rs1 | rs2,
+                (0x00, 7) => rs1 & rs2,
+                (0x01, 0) => rs1.wrapping_mul(rs2),
+                (0x01, 1) => ((rs1 as i64 as i128 * rs2 as i64 as i128) >> 64) as u64,
+                (0x01, 2) => ((rs1 as i64 as i128 * rs2 as i128) >> 64) as u64,
+                (0x01, 3) => ((rs1 as u128 * rs2 as u128) >> 64) as u64,
+                (0x01, 4) => divide(rs1 as i64, rs2 as i64) as u64,
+                (0x01, 5) => rs1.checked_div(rs2).unwrap_or(u64::MAX),
+                (0x01, 6) => remainder(rs1 as i64, rs2 as i64) as u64,
+                (0x01, 7) => rs1.checked_rem(rs2).unwrap_or(rs1),
+                _ => return Err(illegal()),
+            }),
+            // OP-32
+            0x3B => {
+                let (a, b) = (rs1 as i32, rs2 as i32);
+                let value = match (funct7, funct3) {
+                    (0x00, 0) => a.wrapping_add(b),
+                    (0x20, 0) => a.wrapping_sub(b),
+                    (0x00, 1) => a << (b & 0x1F),
+                    (0x00, 5) => ((a as u32) >> (b & 0x1F)) as i32,
+                    (0x20, 5) => a >> (b & 0x1F),
+                    (0x01, 0) => a.wrapping_mul(b),
+                    (0x01, 4) => divide(a as i64, b as i64) as i32,
+                    (0x01, 5) => {
+                        (a as u32).checked_div(b as u32).unwrap_or(u32::MAX) as i32
+                    }
+                    (0x01, 6) => remainder(a as i64, b as i64) as i32,
+                    (0x01, 7) => {
+                        (a as u32).checked_rem(b as u32).unwrap_or(a as u32) as i32
+                    }
+                    _ => return Err(illegal()),
+                };
+                Some(value as i64 as u64)
+            }
+            // MISC-MEM, a single hart has nothing to order.
+            0x0F => None,
+            // SYSTEM
+            0x73 => match instruction {
+                0x0000_0073 => return Err(trap(TrapKind::EnvironmentCall)),
+                0x0010_0073 => return Err(trap(TrapKind::Breakpoint)),
+                _ => return Err(illegal()),
+            },
+            _ => return Err(illegal()),
+        };
+
+        if let Some(value) = result {
+            if rd != 0 {
+                self.registers[rd] = value;
+            }
+        }
+        self.pc = next_pc;
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<u32, Trap> {
+        let fault = Trap {
+            kind: TrapKind::AccessFault {
+                address: self.pc,
+                size: 4,
+            },
+            pc: self.pc,
+        };
+        let offset = self.pc.checked_sub(CODE_ADDRESS).ok_or(fault.clone())? as usize;
+        if self.pc & 3 != 0 || offset + 4 > self.code.len() {
+            return Err(fault);
+        }
+        Ok(u32::from_le_bytes(
+            self.code[offset..offset + 4].try_into().unwrap(),
+        ))
+    }
+
+    /// Offset in the stack memory of the `size` bytes at `address`.
+    fn stack_offset(&self, address: u64, size: u8) -> Result<usize, TrapKind> {
+        let bottom = STACK_TOP - STACK_SIZE;
+        if address < bottom || address.saturating_add(size as u64) > STACK_TOP {
+            return Err(TrapKind::AccessFault { address, size });
+        }
+        Ok((address - bottom) as usize)
+    }
+
+    fn load(&self, address: u64, size: u8) -> Result<u64, TrapKind> {
+        let offset = self.stack_offset(address, size)?;
+        let mut bytes = [0; 8];
+        bytes[..size as usize]
+            .copy_from_slice(&self.stack[offset..offset + size as usize]);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store(&mut self, address: u64, size: u8, value: u64) -> Result<(), TrapKind> {
+        let offset = self.stack_offset(address, size)?;
+        self.stack[offset..offset + size as usize]
+            .copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+}
+
+/// Signed division, which gives -1 when dividing by zero and wraps on overflow.
+fn divide(lhs: i64, rhs: i64) -> i64 {
+    match rhs {
+        0 => -1,
+        _ => lhs.wrapping_div(rhs),
+    }
+}
+
+/// Signed remainder, which gives the dividend when dividing by zero and 0 on overflow.
+fn remainder(lhs: i64, rhs: i64) -> i64 {
+    match rhs {
+        0 => lhs,
+        _ => lhs.wrapping_rem(rhs),
+    }
+}
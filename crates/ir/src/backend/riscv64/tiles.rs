@@ -0,0 +1,289 @@
+use super::{fits_immediate, instruction::*, InstructionSelector};
+use crate::backend::selection::{Binding, Node, Pattern, Tile, TileSet};
+
+/// Registers handed out to the nodes of a tree, taken from the end. None of them
+/// holds a value between instructions.
+pub(super) const SCRATCH_REGISTERS: [Register; 11] =
+    [A7, A6, A5, A4, A3, A2, A1, A0, T2, T1, T0];
+
+/// Results of the RV64IM tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Register,
+    /// A base register and a 12 bit offset, as loads and stores take.
+    Address,
+    /// Nothing, for stores.
+    Effect,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Output {
+    Register(Register),
+    Address(Register, i32),
+    Effect,
+}
+
+impl Output {
+    pub fn register(self) -> Register {
+        match self {
+            Output::Register(register) => register,
+            _ => panic!("{self:?} is not a register"),
+        }
+    }
+
+    fn address(self) -> (Register, i32) {
+        match self {
+            Output::Address(base, offset) => (base, offset),
+            _ => panic!("{self:?} is not an address"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Rule {
+    LoadValue,
+    LoadImmediate,
+    LoadConstant,
+    Add,
+    AddImmediate,
+    Mul,
+    ShiftLeft,
+    Load,
+    AddOffset,
+    Base,
+    BaseOffset,
+    Frame,
+    FrameOffset,
+    Store,
+}
+
+const REGISTER: Pattern<Kind> = Pattern::Operand(Kind::Register);
+const ADDRESS: Pattern<Kind> = Pattern::Operand(Kind::Address);
+const IMMEDIATE: Pattern<Kind> = Pattern::Constant(fits_immediate);
+
+fn is_power_of_two(value: i64) -> bool {
+    value > 0 && value & (value - 1) == 0
+}
+
+/// Whether `value` can be added to an offset in the frame without overflowing.
+fn fits_frame_displacement(value: i64) -> bool {
+    (-(1 << 40)..1 << 40).contains(&value)
+}
+
+impl TileSet for InstructionSelector<'_> {
+    type Kind = Kind;
+    type Rule = Rule;
+    type Output = Output;
+
+    const TILES: &'static [Tile<Kind, Rule>] = &[
+        Tile {
+            rule: Rule::LoadValue,
+            result: Kind::Register,
+            pattern: Pattern::Value,
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::LoadImmediate,
+            result: Kind::Register,
+            pattern: IMMEDIATE,
+            cost: 1,
+        },
+        // `lui` and `addiw`, or more for wider constants.
+        Tile {
+            rule: Rule::LoadConstant,
+            result: Kind::Register,
+            pattern: Pattern::Constant(|_| true),
+            cost: 2,
+        },
+        Tile {
+            rule: Rule::Add,
+            result: Kind::Register,
+            pattern: Pattern::Add(&REGISTER, &REGISTER),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::AddImmediate,
+            result: Kind::Register,
+            pattern: Pattern::Add(&REGISTER, &IMMEDIATE),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Mul,
+            result: Kind::Register,
+            pattern: Pattern::Mul(&REGISTER, &REGISTER),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::ShiftLeft,
+            result: Kind::Register,
+            pattern: Pattern::Mul(&REGISTER, &Pattern::Constant(is_power_of_two)),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Load,
+            result: Kind::Register,
+            pattern: Pattern::Load(&ADDRESS),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::AddOffset,
+            result: Kind::Register,
+            pattern: ADDRESS,
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Base,
+            result: Kind::Address,
+            pattern: REGISTER,
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::BaseOffset,
+            result: Kind::Address,
+            pattern: Pattern::Add(&REGISTER, &IMMEDIATE),
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::Frame,
+            result: Kind::Address,
+            pattern: Pattern::StackAddress,
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::FrameOffset,
+            result: Kind::Address,
+            pattern: Pattern::Add(
+                &Pattern::StackAddress,
+                &Pattern::Constant(fits_frame_displacement),
+            ),
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::Store,
+            result: Kind::Effect,
+            pattern: Pattern::Store(&ADDRESS, &REGISTER),
+            cost: 1,
+        },
+    ];
+
+    fn emit(&mut self, rule: Rule, node: Node, bindings: &[Binding<Output>]) -> Output {
+        match rule {
+            Rule::LoadValue => {
+                let register = self.scratch();
+                self.load(bindings[0].value(), register);
+                Output::Register(register)
+            }
+            Rule::LoadImmediate | Rule::LoadConstant => {
+                let register = self.scratch();
+                self.load_immediate(register, bindings[0].constant());
+                Output::Register(register)
+            }
+            Rule::Add | Rule::Mul => {
+                let rd = bindings[0].operand().register();
+                let rs2 = bindings[1].operand().register();
+                let op = match rule {
+                    Rule::Add => RegisterOp::Add,
+                    _ => RegisterOp::Mul,
+                };
+                self.register_op(op, rd, rd, rs2);
+                self.release(rs2);
+                Output::Register(rd)
+            }
+            Rule::AddImmediate => {
+                let rd = bindings[0].operand().register();
+                let imm = bindings[1].constant() as i32;
+                self.immediate_op(ImmediateOp::Addi, rd, rd, imm);
+                Output::Register(rd)
+            }
+            Rule::ShiftLeft => {
+                let rd = bindings[0].operand().register();
+                let shift = bindings[1].constant().trailing_zeros() as i32;
+                if shift != 0 {
+                    self.immediate_op(ImmediateOp::Slli, rd, rd, shift);
+                }
+                Output::Register(rd)
+            }
+            Rule::Load => {
+                let Node::Load { ty, .. } = node else {
+                    unreachable!()
+                };
+                let (base, offset) = bindings[0].operand().address();
+                let rd = self.reuse(base);
+                self.push(Inst::Load {
+                    size: self.value_size(ty),
+                    is_signed: self.types.integer(ty).is_some_and(|(_, signed)| signed),
+                    rd,
+                    base,
+                    offset,
+                });
+                Output::Register(rd)
+            }
+            Rule::AddOffset => {
+                let (base, offset) = bindings[0].operand().address();
+                let rd = self.reuse(base);
+                self.add_immediate(rd, base, offset as i64);
+                Output::Register(rd)
+            }
+            Rule::Base => Output::Address(bindings[0].operand().register(), 0),
+            Rule::BaseOffset => Output::Address(
+                bindings[0].operand().register(),
+                bindings[1].constant() as i32,
+            ),
+            Rule::Frame => {
+                let offset = self.allocations[&bindings[0].value()];
+                self.stack_address(offset)
+            }
+            Rule::FrameOffset => {
+                let offset =
+                    self.allocations[&bindings[0].value()] + bindings[1].constant();
+                self.stack_address(offset)
+            }
+            Rule::Store => {
+                let Node::Store { ty, .. } = node else {
+                    unreachable!()
+                };
+                let (base, offset) = bindings[0].operand().address();
+                let src = bindings[1].operand().register();
+                self.store_memory(ty, src, base, offset);
+                self.release(src);
+                if base != S0 {
+                    self.release(base);
+                }
+                Output::Effect
+            }
+        }
+    }
+}
+
+impl InstructionSelector<'_> {
+    /// Takes a register no node of the tree being emitted holds.
+    fn scratch(&mut self) -> Register {
+        self.scratch.pop().expect("tree too deep")
+    }
+
+    /// Hands back a register taken with [`Self::scratch`].
+    fn release(&mut self, register: Register) {
+        self.scratch.push(register);
+    }
+
+    /// Register for the result of an instruction reading `base`, which is `base` itself
+    /// when no other node needs it anymore.
+    fn reuse(&mut self, base: Register) -> Register {
+        if base == S0 {
+            self.scratch()
+        } else {
+            base
+        }
+    }
+
+    /// Address of `offset` from `s0`, computed in a scratch register when too far.
+    fn stack_address(&mut self, offset: i64) -> Output {
+        if fits_immediate(offset) {
+            Output::Address(S0, offset as i32)
+        } else {
+            let register = self.scratch();
+            self.add_immediate(register, S0, offset);
+            Output::Address(register, 0)
+        }
+    }
+}
@@ -0,0 +1,576 @@
+//! Instruction selection by tiling trees of low level operations.
+//!
+//! Memory accesses and address computations of a function are turned into [`Tree`]s of
+//! [`Node`]s, operations on 64 bit machine words where a `getelementptr` is spelled out
+//! as additions and multiplications. An instruction whose result is only used by the
+//! next one is folded into the tree of its user, so a `load` through a
+//! `getelementptr` becomes a single tree.
+//!
+//! Targets describe their instructions as [`Tile`]s, patterns of nodes with a cost, and
+//! [`cover`] picks the tiles of lowest total cost covering a tree, from its leaves up.
+//! An instruction with an addressing mode, e.g. `mov 8(%rax), %rcx`, is then a single
+//! tile covering the load along with the addition.
+
+use crate::{
+    backend::{field_offset, size_of},
+    function::FunctionData,
+    instruction::Instruction,
+    label::Label,
+    ty::{Type, Types},
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Index of a node in its [`Tree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NodeId(usize);
+
+/// An operation of a tree. Integers are extended to 64 bits according to their type,
+/// as in registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Node {
+    /// A value computed outside of the tree, where the register allocator put it.
+    Value(Value),
+    Constant(i64),
+    /// Address of the memory of a `stack_alloc`, which is known from the frame layout.
+    StackAddress(Value),
+    Add(NodeId, NodeId),
+    Mul(NodeId, NodeId),
+    /// Reads a value of type `ty` at an address.
+    Load {
+        address: NodeId,
+        ty: Type,
+    },
+    /// Writes `value`, of type `ty`, at an address.
+    Store {
+        address: NodeId,
+        value: NodeId,
+        ty: Type,
+    },
+}
+
+/// Nodes selected together, for an instruction and those folded into it.
+#[derive(Debug)]
+pub(crate) struct Tree {
+    /// Value defined by the instruction, which the result of the root is stored to.
+    pub dst: Option<Value>,
+    /// Operands come before the nodes using them.
+    nodes: Vec<Node>,
+    root: NodeId,
+}
+
+impl Tree {
+    pub fn node(&self, id: NodeId) -> Node {
+        self.nodes[id.0]
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+}
+
+/// Trees of the instructions of a function selected by tiling: `getelementptr`,
+/// `load`, `stack_alloc` and `store`.
+pub(crate) struct Trees {
+    trees: HashMap<(Label, usize), Tree>,
+    /// Instructions folded into the tree of a later one, which emit no code of their own.
+    folded: HashSet<(Label, usize)>,
+}
+
+impl Trees {
+    /// Builds the trees of `function`.
+    ///
+    /// A `getelementptr` or a `load` is folded into its user when it's its only use, in
+    /// the same block, and only instructions also folded into the tree stand between
+    /// them. Values of the tree are then read at its root instead of their original
+    /// use, without any other instruction writing registers or memory in between.
+    /// Results of `stack_alloc` are turned into [`Node::StackAddress`] everywhere.
+    pub fn new(types: &Types, function: &FunctionData) -> Self {
+        let mut uses: HashMap<Value, usize> = HashMap::new();
+        let mut stack_allocations = HashSet::new();
+        for (_, data) in function.labels().iter() {
+            for instr in data.instructions.iter() {
+                for value in instr.reads().into_iter().flatten() {
+                    *uses.entry(value).or_default() += 1;
+                }
+                if let Instruction::StackAlloc { dst, .. } = instr {
+                    stack_allocations.insert(*dst);
+                }
+            }
+        }
+
+        let mut trees = Trees {
+            trees: HashMap::new(),
+            folded: HashSet::new(),
+        };
+        for (label, data) in function.labels().iter() {
+            let definitions: HashMap<Value, usize> = data
+                .instructions
+                .iter()
+                .enumerate()
+                .filter_map(|(i, instr)| Some((instr.creates()?, i)))
+                .collect();
+
+            for (i, instr) in data.instructions.iter().enumerate() {
+                if !is_tiled(instr) {
+                    continue;
+                }
+                let mut builder = TreeBuilder {
+                    types,
+                    function,
+                    instructions: &data.instructions,
+                    definitions: &definitions,
+                    uses: &uses,
+                    stack_allocations: &stack_allocations,
+                    root: i,
+                    folded: Vec::new(),
+                    nodes: Vec::new(),
+                };
+                let root = builder.instruction(instr);
+                trees
+                    .folded
+                    .extend(builder.folded.iter().map(|j| (*label, *j)));
+                let tree = Tree {
+                    dst: instr.creates(),
+                    nodes: builder.nodes,
+                    root,
+                };
+                trees.trees.insert((*label, i), tree);
+            }
+        }
+
+        // Folded instructions were given a tree too, before their user was reached.
+        for location in trees.folded.iter() {
+            trees.trees.remove(location);
+        }
+        trees
+    }
+
+    /// The tree rooted at instruction `index` of block `label`, if it's selected by
+    /// tiling and not folded into another one.
+    pub fn tree(&self, label: Label, index: usize) -> Option<&Tree> {
+        self.trees.get(&(label, index))
+    }
+
+    /// Whether instruction `index` of block `label` is selected as part of a later one.
+    pub fn is_folded(&self, label: Label, index: usize) -> bool {
+        self.folded.contains(&(label, index))
+    }
+}
+
+fn is_tiled(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::GetElementPtr { .. }
+            | Instruction::Load { .. }
+            | Instruction::StackAlloc { .. }
+            | Instruction::Store { .. }
+    )
+}
+
+/// Builds the tree of the instruction at `root` of a block.
+struct TreeBuilder<'a> {
+    types: &'a Types,
+    function: &'a FunctionData,
+    instructions: &'a [Instruction],
+    /// Position in the block of the instruction creating every value created there.
+    definitions: &'a HashMap<Value, usize>,
+    /// Number of instructions reading every value.
+    uses: &'a HashMap<Value, usize>,
+    stack_allocations: &'a HashSet<Value>,
+    root: usize,
+    /// Positions of the instructions folded into the tree.
+    folded: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl TreeBuilder<'_> {
+    fn instruction(&mut self, instr: &Instruction) -> NodeId {
+        match instr {
+            Instruction::GetElementPtr { dst, ptr, index } => {
+                let ptr_type = self.ty(*ptr);
+                let pointee = self.types.strip_pointer(ptr_type).unwrap();
+
+                let same_type = self.types.get(ptr_type) == self.types.get(self.ty(*dst));
+                if !same_type && self.types.is_struct(pointee) {
+                    let field = self
+                        .function
+                        .constant(*index)
+                        .and_then(|c| c.integer())
+                        .expect("struct field index must be a constant");
+                    let offset = field_offset(self.types, pointee, field as usize);
+                    let [ptr] = self.operands([*ptr]);
+                    let offset = self.push(Node::Constant(offset as i64));
+                    self.add(ptr, offset)
+                } else {
+                    let [ptr, index] = self.operands([*ptr, *index]);
+                    let size = size_of(self.types, pointee) as i64;
+                    let offset = match self.nodes[index.0] {
+                        Node::Constant(index) => {
+                            self.push(Node::Constant(index.wrapping_mul(size)))
+                        }
+                        _ if size == 1 => index,
+                        _ => {
+                            let size = self.push(Node::Constant(size));
+                            self.push(Node::Mul(index, size))
+                        }
+                    };
+                    self.add(ptr, offset)
+                }
+            }
+            Instruction::Load { dst, ptr } => {
+                let [address] = self.operands([*ptr]);
+                self.push(Node::Load {
+                    address,
+                    ty: self.ty(*dst),
+                })
+            }
+            Instruction::StackAlloc { dst, .. } => self.push(Node::StackAddress(*dst)),
+            Instruction::Store { ptr, value } => {
+                let [address, node] = self.operands([*ptr, *value]);
+                self.push(Node::Store {
+                    address,
+                    value: node,
+                    ty: self.ty(*value),
+                })
+            }
+            _ => unreachable!("{instr:?} is not selected by tiling"),
+        }
+    }
+
+    /// Nodes of the operands `values`.
+    ///
+    /// Operands created last are visited first, so that an instruction right before the
+    /// root is folded before the ones it makes adjacent to the root.
+    fn operands<const N: usize>(&mut self, values: [Value; N]) -> [NodeId; N] {
+        let mut order: Vec<usize> = (0..N).collect();
+        order.sort_by_key(|i| std::cmp::Reverse(self.definitions.get(&values[*i])));
+
+        let mut nodes = [NodeId(0); N];
+        for i in order {
+            nodes[i] = self.operand(values[i]);
+        }
+        nodes
+    }
+
+    fn operand(&mut self, value: Value) -> NodeId {
+        if let Some(bits) = self.function.constant(value).and_then(|c| c.integer()) {
+            let (num_bits, is_signed) =
+                self.types.integer(self.ty(value)).unwrap_or((64, false));
+            let shift = 64 - num_bits;
+            let bits = if is_signed {
+                ((bits << shift) as i64) >> shift
+            } else {
+                ((bits << shift) >> shift) as i64
+            };
+            return self.push(Node::Constant(bits));
+        }
+        if self.stack_allocations.contains(&value) {
+            return self.push(Node::StackAddress(value));
+        }
+
+        if let Some(&definition) = self.definitions.get(&value) {
+            let instr = &self.instructions[definition];
+            let is_foldable = matches!(
+                instr,
+                Instruction::GetElementPtr { .. } | Instruction::Load { .. }
+            );
+            let is_adjacent = (definition + 1..self.root).all(|i| {
+                matches!(self.instructions[i], Instruction::Nop)
+                    || self.folded.contains(&i)
+            });
+            if is_foldable
+                && is_adjacent
+                && definition < self.root
+                && self.uses.get(&value) == Some(&1)
+            {
+                self.folded.push(definition);
+                return self.instruction(instr);
+            }
+        }
+
+        self.push(Node::Value(value))
+    }
+
+    /// `lhs + rhs`, with constant offsets added together.
+    fn add(&mut self, lhs: NodeId, rhs: NodeId) -> NodeId {
+        match (self.nodes[lhs.0], self.nodes[rhs.0]) {
+            (_, Node::Constant(0)) => lhs,
+            (Node::Add(base, offset), Node::Constant(constant)) => {
+                match self.nodes[offset.0] {
+                    Node::Constant(offset) => {
+                        let offset =
+                            self.push(Node::Constant(offset.wrapping_add(constant)));
+                        self.push(Node::Add(base, offset))
+                    }
+                    _ => self.push(Node::Add(lhs, rhs)),
+                }
+            }
+            _ => self.push(Node::Add(lhs, rhs)),
+        }
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+}
+
+/// Shape of the nodes a tile covers.
+#[derive(Debug)]
+pub(crate) enum Pattern<K: 'static> {
+    /// Any node, covered by other tiles producing a `K`.
+    Operand(K),
+    /// A [`Node::Value`].
+    Value,
+    /// A [`Node::Constant`] accepted by the predicate.
+    Constant(fn(i64) -> bool),
+    /// A [`Node::StackAddress`].
+    StackAddress,
+    Add(&'static Pattern<K>, &'static Pattern<K>),
+    Mul(&'static Pattern<K>, &'static Pattern<K>),
+    Load(&'static Pattern<K>),
+    Store(&'static Pattern<K>, &'static Pattern<K>),
+}
+
+/// A target instruction, or a short sequence of them, covering the nodes matching
+/// `pattern` and producing a `K`, e.g. a value in a register or an addressing mode.
+#[derive(Debug)]
+pub(crate) struct Tile<K: 'static, R> {
+    pub rule: R,
+    pub result: K,
+    pub pattern: Pattern<K>,
+    /// Cost of the instructions, which selection minimizes the sum of.
+    pub cost: u32,
+}
+
+/// What the leaves of the pattern of a tile were matched with, in order.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Binding<O> {
+    /// Result of the tiles covering an [`Pattern::Operand`].
+    Operand(O),
+    Value(Value),
+    Constant(i64),
+    StackAddress(Value),
+}
+
+impl<O: Copy> Binding<O> {
+    pub fn operand(self) -> O {
+        match self {
+            Binding::Operand(output) => output,
+            _ => panic!("binding is not an operand"),
+        }
+    }
+
+    pub fn value(self) -> Value {
+        match self {
+            Binding::Value(value) | Binding::StackAddress(value) => value,
+            _ => panic!("binding is not a value"),
+        }
+    }
+
+    pub fn constant(self) -> i64 {
+        match self {
+            Binding::Constant(constant) => constant,
+            _ => panic!("binding is not a constant"),
+        }
+    }
+}
+
+/// A target selecting instructions with tiles.
+pub(crate) trait TileSet {
+    /// Kinds of results of tiles.
+    type Kind: Copy + PartialEq + std::fmt::Debug + 'static;
+    /// Names the instructions emitted for a tile.
+    type Rule: Copy + 'static;
+    /// Result of emitted tiles, e.g. the register holding a value.
+    type Output: Copy;
+
+    const TILES: &'static [Tile<Self::Kind, Self::Rule>];
+
+    /// Emits the instructions of `rule`, covering `node` with the leaves of its pattern
+    /// matched to `bindings`.
+    fn emit(
+        &mut self,
+        rule: Self::Rule,
+        node: Node,
+        bindings: &[Binding<Self::Output>],
+    ) -> Self::Output;
+}
+
+/// Cheapest tile producing a kind at a node, with the total cost of the tiles covering
+/// the node.
+#[derive(Debug, Clone, Copy)]
+struct Choice<K> {
+    kind: K,
+    cost: u32,
+    tile: usize,
+}
+
+/// Covers `tree` with the tiles of `target` of lowest total cost producing `kind` at
+/// its root, and emits them.
+///
+/// The cheapest tile producing every kind is found for each node from the leaves up,
+/// tiles whose pattern is a single [`Pattern::Operand`] converting between kinds at the
+/// same node. Tiles are then emitted from the root down, operands first.
+pub(crate) fn cover<T: TileSet>(target: &mut T, tree: &Tree, kind: T::Kind) -> T::Output {
+    let mut choices: Vec<Vec<Choice<T::Kind>>> = Vec::with_capacity(tree.nodes.len());
+    for id in 0..tree.nodes.len() {
+        let mut node_choices: Vec<Choice<T::Kind>> = Vec::new();
+        for (i, tile) in T::TILES.iter().enumerate() {
+            if matches!(tile.pattern, Pattern::Operand(_)) {
+                continue;
+            }
+            if let Some(cost) = match_cost(tree, &choices, NodeId(id), &tile.pattern) {
+                let choice = Choice {
+                    kind: tile.result,
+                    cost: cost + tile.cost,
+                    tile: i,
+                };
+                keep_cheapest(&mut node_choices, choice);
+            }
+        }
+
+        // Conversions only apply when strictly cheaper, so they never loop.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, tile) in T::TILES.iter().enumerate() {
+                let Pattern::Operand(from) = tile.pattern else {
+                    continue;
+                };
+                let Some(from) = node_choices.iter().find(|c| c.kind == from) else {
+                    continue;
+                };
+                let choice = Choice {
+                    kind: tile.result,
+                    cost: from.cost + tile.cost,
+                    tile: i,
+                };
+                changed |= keep_cheapest(&mut node_choices, choice);
+            }
+        }
+        choices.push(node_choices);
+    }
+
+    reduce(target, tree, &choices, tree.root(), kind)
+}
+
+/// Records `choice` if no cheaper tile produces its kind, returning whether it did.
+fn keep_cheapest<K: PartialEq>(choices: &mut Vec<Choice<K>>, choice: Choice<K>) -> bool {
+    match choices.iter_mut().find(|c| c.kind == choice.kind) {
+        Some(current) if current.cost <= choice.cost => false,
+        Some(current) => {
+            *current = choice;
+            true
+        }
+        None => {
+            choices.push(choice);
+            true
+        }
+    }
+}
+
+/// Cost of the tiles covering the operands of `pattern` matched at `id`, or `None` if
+/// it doesn't match.
+fn match_cost<K: Copy + PartialEq>(
+    tree: &Tree,
+    choices: &[Vec<Choice<K>>],
+    id: NodeId,
+    pattern: &Pattern<K>,
+) -> Option<u32> {
+    let node = tree.node(id);
+    match (pattern, node) {
+        (Pattern::Operand(kind), _) => choices
+            .get(id.0)?
+            .iter()
+            .find(|c| c.kind == *kind)
+            .map(|c| c.cost),
+        (Pattern::Value, Node::Value(_))
+        | (Pattern::StackAddress, Node::StackAddress(_)) => Some(0),
+        (Pattern::Constant(accepts), Node::Constant(constant)) => {
+            accepts(constant).then_some(0)
+        }
+        (Pattern::Add(lhs, rhs), Node::Add(a, b))
+        | (Pattern::Mul(lhs, rhs), Node::Mul(a, b))
+        | (
+            Pattern::Store(lhs, rhs),
+            Node::Store {
+                address: a,
+                value: b,
+                ..
+            },
+        ) => {
+            Some(match_cost(tree, choices, a, lhs)? + match_cost(tree, choices, b, rhs)?)
+        }
+        (Pattern::Load(address), Node::Load { address: a, .. }) => {
+            match_cost(tree, choices, a, address)
+        }
+        _ => None,
+    }
+}
+
+/// Emits the tile chosen to produce `kind` at `id`, and the ones of its operands.
+fn reduce<T: TileSet>(
+    target: &mut T,
+    tree: &Tree,
+    choices: &[Vec<Choice<T::Kind>>],
+    id: NodeId,
+    kind: T::Kind,
+) -> T::Output {
+    let choice = choices[id.0]
+        .iter()
+        .find(|c| c.kind == kind)
+        .unwrap_or_else(|| panic!("no tile produces {kind:?} from {:?}", tree.node(id)));
+    let tile = &T::TILES[choice.tile];
+
+    let mut bindings = Vec::new();
+    bind(target, tree, choices, id, &tile.pattern, &mut bindings);
+    target.emit(tile.rule, tree.node(id), &bindings)
+}
+
+/// Matches the leaves of `pattern` at `id` into `bindings`, emitting the tiles of its
+/// operands.
+fn bind<T: TileSet>(
+    target: &mut T,
+    tree: &Tree,
+    choices: &[Vec<Choice<T::Kind>>],
+    id: NodeId,
+    pattern: &Pattern<T::Kind>,
+    bindings: &mut Vec<Binding<T::Output>>,
+) {
+    match (pattern, tree.node(id)) {
+        (Pattern::Operand(kind), _) => {
+            let output = reduce(target, tree, choices, id, *kind);
+            bindings.push(Binding::Operand(output));
+        }
+        (Pattern::Value, Node::Value(value)) => bindings.push(Binding::Value(value)),
+        (Pattern::Constant(_), Node::Constant(constant)) => {
+            bindings.push(Binding::Constant(constant))
+        }
+        (Pattern::StackAddress, Node::StackAddress(value)) => {
+            bindings.push(Binding::StackAddress(value))
+        }
+        (Pattern::Add(lhs, rhs), Node::Add(a, b))
+        | (Pattern::Mul(lhs, rhs), Node::Mul(a, b))
+        | (
+            Pattern::Store(lhs, rhs),
+            Node::Store {
+                address: a,
+                value: b,
+                ..
+            },
+        ) => {
+            bind(target, tree, choices, a, lhs, bindings);
+            bind(target, tree, choices, b, rhs, bindings);
+        }
+        (Pattern::Load(address), Node::Load { address: a, .. }) => {
+            bind(target, tree, choices, a, address, bindings)
+        }
+        _ => unreachable!("tile chosen for a node it doesn't match"),
+    }
+}
@@ -0,0 +1,770 @@
+use crate::{
+    backend::{align_of, align_up, check_types, field_offset, size_of, Unsupported},
+    constant::ConstantValue,
+    function::{FunctionData, Functions, Linkage},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    label::Label,
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+/// Bytes of linear memory. The stack grows down from the end of the first page.
+const PAGE_SIZE: u64 = 65536;
+
+/// Lowers every function of the module to a WebAssembly text module.
+///
+/// Integers up to 32 bits and pointers are `i32`, 64 bit integers `i64`, and floats
+/// `f32` and `f64`. Values are locals, kept extended to their wasm type according to
+/// the signedness of their IR type. Other integers, struct values and float
+/// remainders, which wasm has no instruction for, are not supported. `stack_alloc` memory is carved out of a stack in linear memory, whose top
+/// is held by the `$__stack_pointer` global.
+///
+/// Structured control flow is recovered from the control flow graph with the
+/// algorithm of Ramsey's "Beyond Relooper": blocks are placed along the dominator
+/// tree, loop headers open a `loop`, and blocks reached by several forward edges are
+/// placed right after a `block` the edges break out of.
+/// ```text
+///                 (func $max (param $v0 i32) (param $v1 i32) (result i32)
+/// L0:               block
+///   v2 = v0 > v1      local.get $v0  local.get $v1  i32.gt_u  local.set $v2
+///   br v2, L1, L2     local.get $v2
+///                     if
+/// L1:                   ...  br 1
+///   br L3             else
+/// L2:                   ...  br 1
+///   br L3             end
+/// L3:               end
+///   ret ...         ...  return
+/// ```
+/// Irreducible control flow is not supported.
+pub(crate) fn emit(types: &Types, functions: &Functions) -> Result<String, Unsupported> {
+    check_types(types, functions, |ty| match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } => *num_bits <= 32 || *num_bits == 64,
+        TypeKind::Float { num_bits } => matches!(num_bits, 32 | 64),
+        TypeKind::Pointer { .. } => true,
+        TypeKind::Struct { .. } => false,
+    })?;
+    for (_, function) in functions.iter() {
+        let float_remainder = function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .any(|instr| match instr {
+                Instruction::ArithmeticBinary {
+                    dst,
+                    op: BinaryOp::Mod,
+                    ..
+                } => {
+                    let ty = function.values().get(*dst).ty();
+                    matches!(types.get(ty).type_kind(), TypeKind::Float { .. })
+                }
+                _ => false,
+            });
+        if float_remainder {
+            return Err(Unsupported {
+                function: function.definition().name.clone(),
+                what: "float remainders".to_string(),
+            });
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "(module").unwrap();
+    writeln!(out, "  (memory (export \"memory\") 1)").unwrap();
+    writeln!(
+        out,
+        "  (global $__stack_pointer (mut i32) (i32.const {PAGE_SIZE}))"
+    )
+    .unwrap();
+
+    for (_, function) in functions.iter() {
+        let mut emitter = FunctionEmitter::new(types, functions, function);
+        emitter.emit();
+        out.push_str(&emitter.out);
+    }
+
+    writeln!(out, ")").unwrap();
+    Ok(out)
+}
+
+/// The wasm type of values of type `ty`.
+fn wasm_type(types: &Types, ty: Type) -> &'static str {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer { num_bits, .. } if *num_bits <= 32 => "i32",
+        TypeKind::Integer { .. } => "i64",
+        TypeKind::Float { num_bits: 32 } => "f32",
+        TypeKind::Float { .. } => "f64",
+        TypeKind::Pointer { .. } => "i32",
+        TypeKind::Struct { .. } => unreachable!("rejected by `check_types`"),
+    }
+}
+
+/// Enclosing constructs a `br` may target, innermost last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Enclosing {
+    IfThenElse,
+    /// Branching to a `loop` jumps back to its header.
+    LoopHeadedBy(Label),
+    /// Branching to a `block` jumps to the block placed right after it.
+    BlockFollowedBy(Label),
+}
+
+/// Emits the `func` of a single function.
+struct FunctionEmitter<'a> {
+    types: &'a Types,
+    functions: &'a Functions,
+    function: &'a FunctionData,
+    /// Position of every reachable block in reverse postorder.
+    rpo: HashMap<Label, usize>,
+    /// Children of every block in the dominator tree, in reverse postorder.
+    dominated: HashMap<Label, Vec<Label>>,
+    /// Blocks reached by more than one forward edge.
+    merge_nodes: HashSet<Label>,
+    /// Targets of back edges.
+    loop_headers: HashSet<Label>,
+    /// Offset from the frame pointer of the memory of every `stack_alloc`.
+    allocations: HashMap<Value, u64>,
+    frame_size: u64,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> FunctionEmitter<'a> {
+    /// Analyzes the control flow and lays out the frame of `function`.
+    fn new(
+        types: &'a Types,
+        functions: &'a Functions,
+        function: &'a FunctionData,
+    ) -> Self {
+        let labels = function.labels();
+        let loop_analysis = function.loop_analysis();
+
+        // Postorder with an explicit stack of `(block, next successor to visit)`.
+        let mut postorder = Vec::new();
+        let mut visited = HashSet::from([labels.entry()]);
+        let mut stack = vec![(labels.entry(), 0)];
+        while let Some((label, next)) = stack.pop() {
+            let targets = labels.targets(label);
+            match targets.get(next) {
+                Some(target) => {
+                    stack.push((label, next + 1));
+                    if visited.insert(*target) {
+                        stack.push((*target, 0));
+                    }
+                }
+                None => postorder.push(label),
+            }
+        }
+        let rpo: HashMap<Label, usize> = postorder
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, label)| (*label, i))
+            .collect();
+
+        let mut forward_edges: HashMap<Label, usize> = HashMap::new();
+        let mut loop_headers = HashSet::new();
+        for label in postorder.iter() {
+            for target in labels.targets(*label) {
+                if rpo[&target] > rpo[label] {
+                    *forward_edges.entry(target).or_default() += 1;
+                } else {
+                    assert!(
+                        loop_analysis.dominates(target, *label),
+                        "irreducible control flow is not supported"
+                    );
+                    loop_headers.insert(target);
+                }
+            }
+        }
+        let merge_nodes = forward_edges
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(label, _)| label)
+            .collect();
+
+        // The immediate dominator is the strict dominator dominated by all the others.
+        let mut dominated: HashMap<Label, Vec<Label>> = HashMap::new();
+        for label in postorder.iter().rev() {
+            let dominators = &loop_analysis.dominators[label];
+            let immediate = dominators
+                .iter()
+                .filter(|dominator| *dominator != label)
+                .max_by_key(|dominator| loop_analysis.dominators[*dominator].len());
+            if let Some(immediate) = immediate {
+                dominated.entry(*immediate).or_default().push(*label);
+            }
+        }
+
+        let mut allocations = HashMap::new();
+        let mut offset: u64 = 0;
+        for label in postorder.iter().rev() {
+            for instr in labels.get(*label).instructions.iter() {
+                if let Instruction::StackAlloc { dst, ty, size } = instr {
+                    offset = align_up(offset, align_of(types, *ty));
+                    allocations.insert(*dst, offset);
+                    offset += size_of(types, *ty) * *size as u64;
+                }
+            }
+        }
+
+        FunctionEmitter {
+            types,
+            functions,
+            function,
+            rpo,
+            dominated,
+            merge_nodes,
+            loop_headers,
+            allocations,
+            frame_size: align_up(offset, 16),
+            out: String::new(),
+            indent: 2,
+        }
+    }
+
+    fn emit(&mut self) {
+        let definition = self.function.definition();
+        let mut header = format!("(func ${}", definition.name);
+        if definition.linkage == Linkage::External {
+            write!(header, " (export \"{}\")", definition.name).unwrap();
+        }
+        for parameter in self.function.parameters() {
+            let ty = wasm_type(self.types, self.ty(*parameter));
+            write!(header, " (param $v{} {ty})", parameter.id()).unwrap();
+        }
+        if let Some(ty) = definition.return_type {
+            write!(header, " (result {})", wasm_type(self.types, ty)).unwrap();
+        }
+        self.line(header);
+        self.indent += 2;
+
+        let mut created: Vec<Value> = self
+            .function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .filter_map(|instr| instr.creates())
+            .collect();
+        created.sort();
+        created.dedup();
+        for value in created {
+            let ty = wasm_type(self.types, self.ty(value));
+            self.line(format!("(local $v{} {ty})", value.id()));
+        }
+
+        if self.frame_size > 0 {
+            self.line("(local $fp i32)");
+            self.line("global.get $__stack_pointer");
+            self.line(format!("i32.const {}", self.frame_size));
+            self.line("i32.sub");
+            self.line("local.tee $fp");
+            self.line("global.set $__stack_pointer");
+        }
+
+        // Callers may pass any bits above the width of the parameter.
+        for parameter in self.function.parameters() {
+            if self.num_bits(*parameter) < 32 {
+                self.get(*parameter);
+                self.set(*parameter);
+            }
+        }
+
+        let mut enclosing = Vec::new();
+        self.do_tree(self.function.labels().entry(), &mut enclosing);
+        if definition.return_type.is_some() {
+            // Every path returned, but the validator only knows about straight-line code.
+            self.line("unreachable");
+        }
+
+        self.indent -= 2;
+        self.line(")");
+    }
+
+    /// Emits `label` and the blocks it dominates.
+    fn do_tree(&mut self, label: Label, enclosing: &mut Vec<Enclosing>) {
+        // Placed after the code of `label`, the last one outermost.
+        let mut merge_children: Vec<Label> = self
+            .dominated
+            .get(&label)
+            .into_iter()
+            .flatten()
+            .filter(|child| self.merge_nodes.contains(child))
+            .copied()
+            .collect();
+        merge_children.sort_by_key(|child| std::cmp::Reverse(self.rpo[child]));
+
+        if self.loop_headers.contains(&label) {
+            self.open("loop", Enclosing::LoopHeadedBy(label), enclosing);
+            self.node_within(label, &merge_children, enclosing);
+            self.close(enclosing);
+        } else {
+            self.node_within(label, &merge_children, enclosing);
+        }
+    }
+
+    /// Emits `label` nested in a `block` for each of `merge_children`, each followed by
+    /// its merge child.
+    fn node_within(
+        &mut self,
+        label: Label,
+        merge_children: &[Label],
+        enclosing: &mut Vec<Enclosing>,
+    ) {
+        let Some((child, rest)) = merge_children.split_first() else {
+            let instructions = &self.function.labels().get(label).instructions;
+            for instr in instructions.iter() {
+                self.emit_instruction(label, instr, enclosing);
+            }
+            return;
+        };
+
+        self.open("block", Enclosing::BlockFollowedBy(*child), enclosing);
+        self.node_within(label, rest, enclosing);
+        self.close(enclosing);
+        self.do_tree(*child, enclosing);
+    }
+
+    /// Emits the edge from `from` to `to`.
+    fn do_branch(&mut self, from: Label, to: Label, enclosing: &mut Vec<Enclosing>) {
+        let target = if self.rpo[&to] <= self.rpo[&from] {
+            Enclosing::LoopHeadedBy(to)
+        } else if self.merge_nodes.contains(&to) {
+            Enclosing::BlockFollowedBy(to)
+        } else {
+            // Only reached from `from`, so it can be placed right here.
+            self.do_tree(to, enclosing);
+            return;
+        };
+
+        let depth = enclosing
+            .iter()
+            .rev()
+            .position(|other| *other == target)
+            .expect("branch target must enclose the branch");
+        self.line(format!("br {depth}"));
+    }
+
+    fn open(
+        &mut self,
+        construct: &str,
+        entry: Enclosing,
+        enclosing: &mut Vec<Enclosing>,
+    ) {
+        self.line(construct);
+        self.indent += 2;
+        enclosing.push(entry);
+    }
+
+    fn close(&mut self, enclosing: &mut Vec<Enclosing>) {
+        enclosing.pop();
+        self.indent -= 2;
+        self.line("end");
+    }
+
+    fn emit_instruction(
+        &mut self,
+        label: Label,
+        instr: &Instruction,
+        enclosing: &mut Vec<Enclosing>,
+    ) {
+        match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs }
+                if self.is_float(*dst) =>
+            {
+                let ty = wasm_type(self.types, self.ty(*dst));
+                self.get(*lhs);
+                self.get(*rhs);
+                let op = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Sub => "sub",
+                    BinaryOp::Mul => "mul",
+                    BinaryOp::Div => "div",
+                    op => panic!("{op:?} is not defined on floats"),
+                };
+                self.line(format!("{ty}.{op}"));
+                self.set(*dst);
+            }
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => {
+                let ty = wasm_type(self.types, self.ty(*dst));
+                let is_signed = self.is_signed(*dst);
+                let bits = self.num_bits(*lhs);
+
+                self.get(*lhs);
+                // Bits above the type are shifted in, so they have to be what the shift
+                // expects.
+                match op {
+                    BinaryOp::Shr if is_signed && bits < 32 => self.zero_extend(bits),
+                    BinaryOp::Sar if !is_signed && bits < 32 => self.sign_extend(bits),
+                    _ => {}
+                }
+                self.get_as(*rhs, ty);
+
+                let sign = if is_signed { "s" } else { "u" };
+                let op = match op {
+                    BinaryOp::Add => "add".to_string(),
+                    BinaryOp::Sub => "sub".to_string(),
+                    BinaryOp::Mul => "mul".to_string(),
+                    BinaryOp::Div => format!("div_{sign}"),
+                    BinaryOp::Mod => format!("rem_{sign}"),
+                    BinaryOp::Shl => "shl".to_string(),
+                    BinaryOp::Shr => "shr_u".to_string(),
+                    BinaryOp::Sar => "shr_s".to_string(),
+                    BinaryOp::And | BinaryOp::BitAnd => "and".to_string(),
+                    BinaryOp::Or | BinaryOp::BitOr => "or".to_string(),
+                    BinaryOp::Xor => "xor".to_string(),
+                };
+                self.line(format!("{ty}.{op}"));
+                self.set(*dst);
+            }
+            Instruction::ArithmeticUnary { dst, op, value } => {
+                let ty = wasm_type(self.types, self.ty(*dst));
+                match op {
+                    UnaryOp::Neg if self.is_float(*dst) => {
+                        self.get(*value);
+                        self.line(format!("{ty}.neg"));
+                    }
+                    UnaryOp::Neg => {
+                        self.line(format!("{ty}.const 0"));
+                        self.get(*value);
+                        self.line(format!("{ty}.sub"));
+                    }
+                    UnaryOp::Not if self.num_bits(*dst) == 1 => {
+                        self.get(*value);
+                        self.line("i32.eqz");
+                    }
+                    UnaryOp::Not => {
+                        self.get(*value);
+                        self.line(format!("{ty}.const -1"));
+                        self.line(format!("{ty}.xor"));
+                    }
+                }
+                self.set(*dst);
+            }
+            Instruction::Branch { target } => self.do_branch(label, *target, enclosing),
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.get(*condition);
+                self.open("if", Enclosing::IfThenElse, enclosing);
+                self.do_branch(label, *on_true, enclosing);
+                self.indent -= 2;
+                self.line("else");
+                self.indent += 2;
+                self.do_branch(label, *on_false, enclosing);
+                self.close(enclosing);
+            }
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                ..
+            } => {
+                for argument in arguments.iter() {
+                    self.get(*argument);
+                }
+                let name = &self.functions.get(*function).definition().name;
+                self.line(format!("call ${name}"));
+                if let Some(dst) = dst {
+                    self.set(*dst);
+                }
+            }
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                let from = wasm_type(self.types, self.ty(*value));
+                let to = wasm_type(self.types, self.ty(*dst));
+                let bits = self.num_bits(*value);
+
+                self.get(*value);
+                match cast_op {
+                    CastOp::BitCast if self.is_float(*value) != self.is_float(*dst) => {
+                        self.line(format!("{to}.reinterpret_{from}"));
+                    }
+                    _ if self.is_float(*value) || self.is_float(*dst) => {
+                        self.convert_float(*value, *dst)
+                    }
+                    CastOp::ZeroExtend => {
+                        if self.is_signed(*value) && bits < 32 {
+                            self.zero_extend(bits);
+                        }
+                        if from == "i32" && to == "i64" {
+                            self.line("i64.extend_i32_u");
+                        }
+                    }
+                    CastOp::SignExtend => {
+                        if !self.is_signed(*value) && bits < 32 {
+                            self.sign_extend(bits);
+                        }
+                        if from == "i32" && to == "i64" {
+                            self.line("i64.extend_i32_s");
+                        }
+                    }
+                    CastOp::Truncate | CastOp::BitCast => self.convert(from, to),
+                }
+                self.set(*dst);
+            }
+            Instruction::GetElementPtr { dst, ptr, index } => {
+                let ptr_type = self.ty(*ptr);
+                let pointee = self.types.strip_pointer(ptr_type).unwrap();
+                let same_type = self.types.get(ptr_type) == self.types.get(self.ty(*dst));
+
+                self.get(*ptr);
+                if !same_type && self.types.is_struct(pointee) {
+                    let field = self
+                        .function
+                        .constant(*index)
+                        .and_then(|c| c.integer())
+                        .expect("struct field index must be a constant");
+                    let offset = field_offset(self.types, pointee, field as usize);
+                    self.line(format!("i32.const {offset}"));
+                } else {
+                    self.get_as(*index, "i32");
+                    let size = size_of(self.types, pointee);
+                    if size != 1 {
+                        self.line(format!("i32.const {size}"));
+                        self.line("i32.mul");
+                    }
+                }
+                self.line("i32.add");
+                self.set(*dst);
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let ty = wasm_type(self.types, self.ty(*lhs));
+                let sign = if self.is_float(*lhs) {
+                    ""
+                } else if self.is_signed(*lhs) {
+                    "_s"
+                } else {
+                    "_u"
+                };
+                self.get(*lhs);
+                self.get(*rhs);
+                let op = match pred {
+                    IntCompareOp::Equal => "eq".to_string(),
+                    IntCompareOp::NotEqual => "ne".to_string(),
+                    IntCompareOp::GreaterThan => format!("gt{sign}"),
+                    IntCompareOp::GreaterThanOrEqual => format!("ge{sign}"),
+                    IntCompareOp::LessThan => format!("lt{sign}"),
+                    IntCompareOp::LessThanOrEqual => format!("le{sign}"),
+                };
+                self.line(format!("{ty}.{op}"));
+                self.set(*dst);
+            }
+            Instruction::Load { dst, ptr } => {
+                self.get(*ptr);
+                let ty = wasm_type(self.types, self.ty(*dst));
+                let sign = if self.is_signed(*dst) { "s" } else { "u" };
+                let load = match (ty, self.num_bits(*dst)) {
+                    ("i32", 1 | 8) => format!("i32.load8_{sign}"),
+                    ("i32", 16) => format!("i32.load16_{sign}"),
+                    _ => format!("{ty}.load"),
+                };
+                self.line(load);
+                self.set(*dst);
+            }
+            Instruction::Return { value } => {
+                if let Some(value) = value {
+                    self.get(*value);
+                }
+                if self.frame_size > 0 {
+                    self.line("local.get $fp");
+                    self.line(format!("i32.const {}", self.frame_size));
+                    self.line("i32.add");
+                    self.line("global.set $__stack_pointer");
+                }
+                self.line("return");
+            }
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.get(*on_true);
+                self.get(*on_false);
+                self.get(*condition);
+                self.line("select");
+                self.set(*dst);
+            }
+            Instruction::StackAlloc { dst, .. } => {
+                self.line("local.get $fp");
+                self.line(format!("i32.const {}", self.allocations[dst]));
+                self.line("i32.add");
+                self.set(*dst);
+            }
+            Instruction::Store { ptr, value } => {
+                self.get(*ptr);
+                self.get(*value);
+                let ty = wasm_type(self.types, self.ty(*value));
+                let store = match (ty, self.num_bits(*value)) {
+                    ("i32", 1 | 8) => "i32.store8".to_string(),
+                    ("i32", 16) => "i32.store16".to_string(),
+                    _ => format!("{ty}.store"),
+                };
+                self.line(store);
+            }
+            Instruction::Nop => {}
+        }
+    }
+
+    /// Pushes `value`.
+    fn get(&mut self, value: Value) {
+        let Some(constant) = self.function.constant(value) else {
+            self.line(format!("local.get $v{}", value.id()));
+            return;
+        };
+
+        let ty = wasm_type(self.types, self.ty(value));
+        match constant {
+            ConstantValue::Integer { value: bits, .. } => {
+                // Extended to the wasm type like any other value.
+                let num_bits = self.num_bits(value);
+                let shift = 64 - num_bits;
+                let bits = if self.is_signed(value) {
+                    ((bits << shift) as i64) >> shift
+                } else {
+                    ((bits << shift) >> shift) as i64
+                };
+                let bits = if ty == "i32" {
+                    i64::from(bits as i32)
+                } else {
+                    bits
+                };
+                self.line(format!("{ty}.const {bits}"));
+            }
+            ConstantValue::Float { value, .. } if value.is_nan() => {
+                self.line(format!("{ty}.const nan"))
+            }
+            // The shortest literal reading back as the value, rounded to `f32` first.
+            ConstantValue::Float { value, .. } if ty == "f32" => {
+                self.line(format!("{ty}.const {:?}", value as f32))
+            }
+            ConstantValue::Float { value, .. } => {
+                self.line(format!("{ty}.const {value:?}"))
+            }
+        }
+    }
+
+    /// Pushes `value` converted to the wasm type `ty`, e.g. for the shift amount.
+    fn get_as(&mut self, value: Value, ty: &str) {
+        self.get(value);
+        let from = wasm_type(self.types, self.ty(value));
+        self.convert(from, ty);
+    }
+
+    /// Converts the integer on the stack from the wasm type `from` to `to`, keeping the
+    /// low bits.
+    fn convert(&mut self, from: &str, to: &str) {
+        match (from, to) {
+            ("i64", "i32") => self.line("i32.wrap_i64"),
+            ("i32", "i64") => self.line("i64.extend_i32_u"),
+            _ => {}
+        }
+    }
+
+    /// Converts the number on the stack from the type of `value` to the type of `dst`,
+    /// when one of them is a float.
+    fn convert_float(&mut self, value: Value, dst: Value) {
+        let from = wasm_type(self.types, self.ty(value));
+        let to = wasm_type(self.types, self.ty(dst));
+        let conversion = match (from, to) {
+            (from, to) if from == to => return,
+            ("f64", "f32") => "demote_f64".to_string(),
+            ("f32", "f64") => "promote_f32".to_string(),
+            (_, "f32" | "f64") => {
+                let sign = if self.is_signed(value) { "s" } else { "u" };
+                format!("convert_{from}_{sign}")
+            }
+            _ => {
+                let sign = if self.is_signed(dst) { "s" } else { "u" };
+                format!("trunc_{from}_{sign}")
+            }
+        };
+        self.line(format!("{to}.{conversion}"));
+    }
+
+    /// Pops the result into `dst`, after extending its low bits to the wasm type.
+    fn set(&mut self, dst: Value) {
+        let bits = self.num_bits(dst);
+        if bits < 32 {
+            if self.is_signed(dst) {
+                self.sign_extend(bits);
+            } else {
+                self.zero_extend(bits);
+            }
+        }
+        self.line(format!("local.set $v{}", dst.id()));
+    }
+
+    /// Clears the bits of the `i32` on the stack above the low `bits`.
+    fn zero_extend(&mut self, bits: u32) {
+        self.line(format!("i32.const {}", (1u32 << bits) - 1));
+        self.line("i32.and");
+    }
+
+    /// Copies bit `bits - 1` of the `i32` on the stack to the bits above it.
+    fn sign_extend(&mut self, bits: u32) {
+        match bits {
+            8 => self.line("i32.extend8_s"),
+            16 => self.line("i32.extend16_s"),
+            _ => {
+                self.line(format!("i32.const {}", 32 - bits));
+                self.line("i32.shl");
+                self.line(format!("i32.const {}", 32 - bits));
+                self.line("i32.shr_s");
+            }
+        }
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+
+    /// Width of integer values, pointers counting as 32 bits.
+    fn num_bits(&self, value: Value) -> u32 {
+        match self.types.get(self.ty(value)).type_kind() {
+            TypeKind::Integer { num_bits, .. } => *num_bits,
+            TypeKind::Float { num_bits } => *num_bits,
+            _ => 32,
+        }
+    }
+
+    fn is_float(&self, value: Value) -> bool {
+        matches!(
+            self.types.get(self.ty(value)).type_kind(),
+            TypeKind::Float { .. }
+        )
+    }
+
+    fn is_signed(&self, value: Value) -> bool {
+        self.types
+            .integer(self.ty(value))
+            .is_some_and(|(_, is_signed)| is_signed)
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(
+            self.out,
+            "{:indent$}{}",
+            "",
+            line.as_ref(),
+            indent = self.indent
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use super::{instruction::*, select};
+use crate::{
+    backend::{
+        dwarf::{self, DebugFunction, Fixup},
+        Unsupported,
+    },
+    debug_location::DebugLocation,
+    function::{Functions, Linkage},
+    label::Label,
+    ty::Types,
+};
+use itertools::Itertools;
+use std::fmt::Write;
+
+/// Prints every function of the module as x86-64 assembly, in GNU `as` (AT&T) syntax.
+/// ```text
+/// fn add(a: u32, b: u32) -> u32        add:
+///                                          push %rbp
+///                                          mov %rsp, %rbp
+///                                          movl %edi, %r10d
+///                                          movl %esi, %r11d
+/// let v2: u32 = add v0, v1             .LF0_B0:
+///                                          movl %r10d, %eax
+///                                          movl %r11d, %ecx
+///                                          addq %rcx, %rax
+///                                          movl %eax, %r10d
+/// ...
+/// ```
+///
+/// With `source_files`, the code is annotated with `.loc` directives, and the module
+/// is described in DWARF debug sections.
+pub(crate) fn emit(
+    types: &Types,
+    functions: &Functions,
+    source_files: &[String],
+) -> Result<String, Unsupported> {
+    let debug = !source_files.is_empty();
+    let mut out = String::new();
+    writeln!(out, "\t.text").unwrap();
+    if debug {
+        for (i, source_file) in source_files.iter().enumerate() {
+            writeln!(out, "\t.file {} \"{source_file}\"", i + 1).unwrap();
+        }
+        writeln!(out, ".Ltext0:").unwrap();
+    }
+
+    let mut debug_functions = Vec::new();
+    for machine_function in select(types, functions)? {
+        let id = machine_function.function.id();
+        let definition = functions.get(machine_function.function).definition();
+        let name = &definition.name;
+        if definition.linkage == Linkage::External {
+            writeln!(out, "\t.globl {name}").unwrap();
+        }
+        writeln!(out, "\t.type {name}, @function").unwrap();
+        writeln!(out, "{name}:").unwrap();
+
+        for inst in machine_function.code {
+            match inst {
+                Inst::Block(label) => {
+                    writeln!(out, "{}:", block_name(id, label)).unwrap()
+                }
+                Inst::Location(location) => {
+                    if debug {
+                        let DebugLocation { file, line, column } = location;
+                        let file = dwarf::file_number(file);
+                        writeln!(out, "\t.loc {file} {line} {column}").unwrap();
+                    }
+                }
+                inst => writeln!(out, "\t{}", instruction(functions, id, inst)).unwrap(),
+            }
+        }
+
+        if debug {
+            writeln!(out, "{}:", function_end(id)).unwrap();
+        }
+        writeln!(out, "\t.size {name}, .-{name}").unwrap();
+        debug_functions.push(DebugFunction {
+            function: machine_function.function,
+            frame_base: RBP.dwarf_number(),
+            parameters: machine_function.parameters,
+        });
+    }
+
+    if debug {
+        writeln!(out, ".Ltext_end0:").unwrap();
+        debug_sections(&mut out, types, functions, source_files, &debug_functions);
+    }
+    writeln!(out, "\t.section .note.GNU-stack,\"\",@progbits").unwrap();
+    Ok(out)
+}
+
+/// Prints the debug sections describing the module. The assembler builds the line
+/// table from the `.loc` directives.
+fn debug_sections(
+    out: &mut String,
+    types: &Types,
+    functions: &Functions,
+    source_files: &[String],
+    debug_functions: &[DebugFunction],
+) {
+    writeln!(out, "\t.section .debug_abbrev,\"\",@progbits").unwrap();
+    writeln!(out, ".Ldebug_abbrev0:").unwrap();
+    bytes(out, &dwarf::abbreviations());
+
+    writeln!(out, "\t.section .debug_info,\"\",@progbits").unwrap();
+    let info = dwarf::debug_info(types, functions, source_files, debug_functions);
+    let mut start = 0;
+    for (offset, fixup) in info.fixups.iter() {
+        bytes(out, &info.data[start..*offset]);
+        start = offset + fixup.size();
+        let function_name = |function| &functions.get(function).definition().name;
+        match fixup {
+            Fixup::TextStart => writeln!(out, "\t.quad .Ltext0"),
+            Fixup::TextSize => writeln!(out, "\t.quad .Ltext_end0-.Ltext0"),
+            Fixup::FunctionStart(function) => {
+                writeln!(out, "\t.quad {}", function_name(*function))
+            }
+            Fixup::FunctionSize(function) => writeln!(
+                out,
+                "\t.quad {}-{}",
+                function_end(function.id()),
+                function_name(*function)
+            ),
+            Fixup::LineTable => writeln!(out, "\t.long .Ldebug_line0"),
+            Fixup::Abbreviations => writeln!(out, "\t.long .Ldebug_abbrev0"),
+        }
+        .unwrap();
+    }
+    bytes(out, &info.data[start..]);
+
+    writeln!(out, "\t.section .debug_line,\"\",@progbits").unwrap();
+    writeln!(out, ".Ldebug_line0:").unwrap();
+}
+
+/// Prints `data` with `.byte` directives.
+fn bytes(out: &mut String, data: &[u8]) {
+    for chunk in data.chunks(16) {
+        let chunk = chunk.iter().map(|byte| format!("{byte:#04x}")).join(", ");
+        writeln!(out, "\t.byte {chunk}").unwrap();
+    }
+}
+
+/// The assembly of `inst`, in the function numbered `id`.
+fn instruction(functions: &Functions, id: usize, inst: Inst) -> String {
+    let function_name = |function| &functions.get(function).definition().name;
+    match inst {
+        Inst::Block(_) => unreachable!("blocks are labels"),
+        Inst::Location(_) => unreachable!("locations are directives"),
+        Inst::Push(register) => format!("push {}", register.name(8)),
+        Inst::Move {
+            size,
+            is_signed,
+            src,
+            dst,
+        } => {
+            let (l, q) = (dst.name(4), dst.name(8));
+            let src = operand(src, size);
+            match (size, is_signed) {
+                (1, false) => format!("movzbq {src}, {q}"),
+                (1, true) => format!("movsbq {src}, {q}"),
+                (2, false) => format!("movzwq {src}, {q}"),
+                (2, true) => format!("movswq {src}, {q}"),
+                // Writing a 32 bit register clears the upper half.
+                (4, false) => format!("movl {src}, {l}"),
+                (4, true) => format!("movslq {src}, {q}"),
+                _ => format!("movq {src}, {q}"),
+            }
+        }
+        Inst::MoveImmediate { value, dst } => {
+            if i32::try_from(value).is_ok() {
+                format!("movq ${value}, {}", dst.name(8))
+            } else {
+                format!("movabsq ${value}, {}", dst.name(8))
+            }
+        }
+        Inst::Store { size, src, dst } => {
+            let suffix = match size {
+                1 => 'b',
+                2 => 'w',
+                4 => 'l',
+                _ => 'q',
+            };
+            format!("mov{suffix} {}, {}", src.name(size), memory(dst))
+        }
+        Inst::Lea { src, dst } => format!("leaq {}, {}", memory(src), dst.name(8)),
+        Inst::Alu { op, src, dst } => {
+            format!("{}q {}, {}", alu_mnemonic(op), src.name(8), dst.name(8))
+        }
+        Inst::AluImmediate { op, value, dst } => {
+            format!("{}q ${value}, {}", alu_mnemonic(op), dst.name(8))
+        }
+        Inst::ImulImmediate { value, src, dst } => {
+            format!("imulq ${value}, {}, {}", src.name(8), dst.name(8))
+        }
+        Inst::Shift { op, dst } => {
+            let mnemonic = match op {
+                ShiftOp::Shl => "shl",
+                ShiftOp::Shr => "shr",
+                ShiftOp::Sar => "sar",
+            };
+            format!("{mnemonic}q %cl, {}", dst.name(8))
+        }
+        Inst::Neg(register) => format!("negq {}", register.name(8)),
+        Inst::Not(register) => format!("notq {}", register.name(8)),
+        Inst::Cqto => "cqto".to_string(),
+        Inst::Div { is_signed, src } => {
+            let mnemonic = if is_signed { "idivq" } else { "divq" };
+            format!("{mnemonic} {}", src.name(8))
+        }
+        Inst::Set { condition, dst } => {
+            format!("set{} {}", condition.suffix(), dst.name(1))
+        }
+        Inst::Cmov {
+            condition,
+            src,
+            dst,
+        } => format!(
+            "cmov{}q {}, {}",
+            condition.suffix(),
+            src.name(8),
+            dst.name(8)
+        ),
+        Inst::Jump(Target::Block(label)) => format!("jmp {}", block_name(id, label)),
+        Inst::Jump(Target::Function(function)) => {
+            format!("jmp {}", function_name(function))
+        }
+        Inst::JumpIf(condition, label) => {
+            format!("j{} {}", condition.suffix(), block_name(id, label))
+        }
+        Inst::Call(function) => format!("call {}", function_name(function)),
+        Inst::Leave => "leave".to_string(),
+        Inst::Ret => "ret".to_string(),
+    }
+}
+
+fn alu_mnemonic(op: AluOp) -> &'static str {
+    match op {
+        AluOp::Add => "add",
+        AluOp::Sub => "sub",
+        AluOp::Imul => "imul",
+        AluOp::And => "and",
+        AluOp::Or => "or",
+        AluOp::Xor => "xor",
+        AluOp::Cmp => "cmp",
+        AluOp::Test => "test",
+    }
+}
+
+/// `operand` accessed as `size` bytes.
+fn operand(operand: Operand, size: u8) -> String {
+    match operand {
+        Operand::Register(register) => register.name(size).to_string(),
+        Operand::Memory(m) => memory(m),
+    }
+}
+
+fn memory(memory: Memory) -> String {
+    match memory.displacement {
+        0 => format!("({})", memory.base.name(8)),
+        displacement => format!("{displacement}({})", memory.base.name(8)),
+    }
+}
+
+fn block_name(id: usize, label: Label) -> String {
+    format!(".LF{id}_B{}", label.id())
+}
+
+/// Label of the end of the function numbered `id`.
+fn function_end(id: usize) -> String {
+    format!(".LF{id}_end")
+}
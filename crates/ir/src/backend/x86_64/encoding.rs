@@ -0,0 +1,295 @@
+use super::{instruction::*, select};
+use crate::{
+    backend::{dwarf::VariableLocation, Unsupported},
+    debug_location::DebugLocation,
+    function::{Function, Functions},
+    label::Label,
+    ty::Types,
+};
+use std::collections::HashMap;
+
+/// Machine code of a function.
+pub(crate) struct EncodedFunction {
+    pub function: Function,
+    pub code: Vec<u8>,
+    /// Offsets in `code` of the 32 bit displacements of calls and tail calls, with the
+    /// function they go to. A displacement is relative to the end of its field.
+    pub relocations: Vec<(usize, Function)>,
+    /// Offsets in `code` where the code of a source location starts.
+    pub lines: Vec<(usize, DebugLocation)>,
+    /// Where every parameter is kept after the prologue.
+    pub parameters: Vec<Option<VariableLocation>>,
+}
+
+/// Encodes every function of the module to machine code, with the instructions selected
+/// for the assembly backend.
+///
+/// Jumps between blocks are resolved in the function, always with 32 bit
+/// displacements. Jumps to other functions are left to the caller, as relocations.
+pub(crate) fn encode(
+    types: &Types,
+    functions: &Functions,
+) -> Result<Vec<EncodedFunction>, Unsupported> {
+    let encoded = select(types, functions)?
+        .into_iter()
+        .map(|machine_function| {
+            let mut encoder = Encoder::default();
+            for inst in machine_function.code {
+                encoder.encode(inst);
+            }
+            encoder.resolve_jumps();
+
+            EncodedFunction {
+                function: machine_function.function,
+                code: encoder.code,
+                relocations: encoder.relocations,
+                lines: encoder.lines,
+                parameters: machine_function.parameters,
+            }
+        })
+        .collect();
+    Ok(encoded)
+}
+
+#[derive(Default)]
+struct Encoder {
+    code: Vec<u8>,
+    /// Offset of the start of every block.
+    blocks: HashMap<Label, usize>,
+    /// Offsets of the 32 bit displacements of jumps between blocks, with their target.
+    jumps: Vec<(usize, Label)>,
+    relocations: Vec<(usize, Function)>,
+    lines: Vec<(usize, DebugLocation)>,
+}
+
+impl Encoder {
+    fn encode(&mut self, inst: Inst) {
+        match inst {
+            Inst::Block(label) => {
+                self.blocks.insert(label, self.code.len());
+            }
+            Inst::Location(location) => {
+                self.lines.push((self.code.len(), location));
+            }
+            Inst::Push(register) => {
+                if register.0 >= 8 {
+                    self.code.push(0x41);
+                }
+                self.code.push(0x50 + (register.0 & 7));
+            }
+            Inst::Move {
+                size,
+                is_signed,
+                src,
+                dst,
+            } => match (size, is_signed) {
+                (1, false) => self.instruction(true, &[0x0F, 0xB6], dst.0, src, false),
+                (1, true) => self.instruction(true, &[0x0F, 0xBE], dst.0, src, false),
+                (2, false) => self.instruction(true, &[0x0F, 0xB7], dst.0, src, false),
+                (2, true) => self.instruction(true, &[0x0F, 0xBF], dst.0, src, false),
+                // Writing a 32 bit register clears the upper half.
+                (4, false) => self.instruction(false, &[0x8B], dst.0, src, false),
+                (4, true) => self.instruction(true, &[0x63], dst.0, src, false),
+                _ => self.instruction(true, &[0x8B], dst.0, src, false),
+            },
+            Inst::MoveImmediate { value, dst } => match i32::try_from(value) {
+                Ok(value) => {
+                    self.instruction(true, &[0xC7], 0, Operand::Register(dst), false);
+                    self.code.extend(value.to_le_bytes());
+                }
+                Err(_) => {
+                    self.rex(true, 0, dst.0, false);
+                    self.code.push(0xB8 + (dst.0 & 7));
+                    self.code.extend(value.to_le_bytes());
+                }
+            },
+            Inst::Store { size, src, dst } => {
+                let dst = Operand::Memory(dst);
+                match size {
+                    1 => self.instruction(false, &[0x88], src.0, dst, true),
+                    2 => {
+                        self.code.push(0x66);
+                        self.instruction(false, &[0x89], src.0, dst, false);
+                    }
+                    4 => self.instruction(false, &[0x89], src.0, dst, false),
+                    _ => self.instruction(true, &[0x89], src.0, dst, false),
+                }
+            }
+            Inst::Lea { src, dst } => {
+                self.instruction(true, &[0x8D], dst.0, Operand::Memory(src), false)
+            }
+            Inst::Alu { op, src, dst } => {
+                let opcode = match op {
+                    AluOp::Imul => {
+                        let src = Operand::Register(src);
+                        self.instruction(true, &[0x0F, 0xAF], dst.0, src, false);
+                        return;
+                    }
+                    AluOp::Add => 0x01,
+                    AluOp::Sub => 0x29,
+                    AluOp::And => 0x21,
+                    AluOp::Or => 0x09,
+                    AluOp::Xor => 0x31,
+                    AluOp::Cmp => 0x39,
+                    AluOp::Test => 0x85,
+                };
+                self.instruction(true, &[opcode], src.0, Operand::Register(dst), false);
+            }
+            Inst::AluImmediate { op, value, dst } => {
+                let extension = match op {
+                    AluOp::Add => 0,
+                    AluOp::Or => 1,
+                    AluOp::And => 4,
+                    AluOp::Sub => 5,
+                    AluOp::Xor => 6,
+                    AluOp::Cmp => 7,
+                    AluOp::Imul | AluOp::Test => {
+                        unreachable!("no {op:?} with an immediate")
+                    }
+                };
+                let dst = Operand::Register(dst);
+                match i8::try_from(value) {
+                    Ok(value) => {
+                        self.instruction(true, &[0x83], extension, dst, false);
+                        self.code.push(value as u8);
+                    }
+                    Err(_) => {
+                        self.instruction(true, &[0x81], extension, dst, false);
+                        self.code.extend(value.to_le_bytes());
+                    }
+                }
+            }
+            Inst::ImulImmediate { value, src, dst } => {
+                let src = Operand::Register(src);
+                match i8::try_from(value) {
+                    Ok(value) => {
+                        self.instruction(true, &[0x6B], dst.0, src, false);
+                        self.code.push(value as u8);
+                    }
+                    Err(_) => {
+                        self.instruction(true, &[0x69], dst.0, src, false);
+                        self.code.extend(value.to_le_bytes());
+                    }
+                }
+            }
+            Inst::Shift { op, dst } => {
+                let extension = match op {
+                    ShiftOp::Shl => 4,
+                    ShiftOp::Shr => 5,
+                    ShiftOp::Sar => 7,
+                };
+                self.instruction(true, &[0xD3], extension, Operand::Register(dst), false);
+            }
+            Inst::Neg(register) => {
+                self.instruction(true, &[0xF7], 3, Operand::Register(register), false)
+            }
+            Inst::Not(register) => {
+                self.instruction(true, &[0xF7], 2, Operand::Register(register), false)
+            }
+            Inst::Cqto => self.code.extend([0x48, 0x99]),
+            Inst::Div { is_signed, src } => {
+                let extension = if is_signed { 7 } else { 6 };
+                self.instruction(true, &[0xF7], extension, Operand::Register(src), false);
+            }
+            Inst::Set { condition, dst } => {
+                let opcode = [0x0F, 0x90 + condition as u8];
+                self.instruction(false, &opcode, 0, Operand::Register(dst), true);
+            }
+            Inst::Cmov {
+                condition,
+                src,
+                dst,
+            } => {
+                let opcode = [0x0F, 0x40 + condition as u8];
+                self.instruction(true, &opcode, dst.0, Operand::Register(src), false);
+            }
+            Inst::Jump(Target::Block(label)) => {
+                self.code.push(0xE9);
+                self.jump_to(label);
+            }
+            Inst::Jump(Target::Function(function)) => {
+                self.code.push(0xE9);
+                self.relocation(function);
+            }
+            Inst::JumpIf(condition, label) => {
+                self.code.extend([0x0F, 0x80 + condition as u8]);
+                self.jump_to(label);
+            }
+            Inst::Call(function) => {
+                self.code.push(0xE8);
+                self.relocation(function);
+            }
+            Inst::Leave => self.code.push(0xC9),
+            Inst::Ret => self.code.push(0xC3),
+        }
+    }
+
+    /// Encodes an instruction with a ModR/M byte, `reg` being a register or an opcode
+    /// extension and `rm` the other operand. `wide` selects 64 bit operands, and
+    /// `byte_registers` that register operands are accessed by their low byte.
+    fn instruction(
+        &mut self,
+        wide: bool,
+        opcode: &[u8],
+        reg: u8,
+        rm: Operand,
+        byte_registers: bool,
+    ) {
+        let base = match rm {
+            Operand::Register(register) => register.0,
+            Operand::Memory(memory) => memory.base.0,
+        };
+        // Without a REX prefix, byte registers 4 to 7 are `%ah` to `%bh`.
+        let needs_rex = byte_registers
+            && ((4..8).contains(&reg)
+                || matches!(rm, Operand::Register(r) if (4..8).contains(&r.0)));
+        self.rex(wide, reg, base, needs_rex);
+        self.code.extend(opcode);
+
+        let reg = (reg & 7) << 3;
+        match rm {
+            Operand::Register(register) => self.code.push(0xC0 | reg | (register.0 & 7)),
+            Operand::Memory(Memory { base, displacement }) => {
+                // `%rbp` and `%r13` have no form without displacement.
+                let (mode, displacement_size) = match displacement {
+                    0 if base.0 & 7 != 5 => (0x00, 0),
+                    -128..=127 => (0x40, 1),
+                    _ => (0x80, 4),
+                };
+                self.code.push(mode | reg | (base.0 & 7));
+                // `%rsp` and `%r12` as a base are only encoded with a SIB byte.
+                if base.0 & 7 == 4 {
+                    self.code.push(0x24);
+                }
+                let bytes = displacement.to_le_bytes();
+                self.code.extend(&bytes[..displacement_size]);
+            }
+        }
+    }
+
+    /// Emits the REX prefix extending `reg` and `base`, when it is needed.
+    fn rex(&mut self, wide: bool, reg: u8, base: u8, force: bool) {
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | base >> 3;
+        if rex != 0x40 || force {
+            self.code.push(rex);
+        }
+    }
+
+    fn jump_to(&mut self, label: Label) {
+        self.jumps.push((self.code.len(), label));
+        self.code.extend([0; 4]);
+    }
+
+    fn relocation(&mut self, function: Function) {
+        self.relocations.push((self.code.len(), function));
+        self.code.extend([0; 4]);
+    }
+
+    fn resolve_jumps(&mut self) {
+        for (offset, label) in std::mem::take(&mut self.jumps) {
+            let displacement = self.blocks[&label] as i64 - (offset + 4) as i64;
+            let displacement = i32::try_from(displacement).expect("function too large");
+            self.code[offset..offset + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{debug_location::DebugLocation, function::Function, label::Label};
+
+/// A general purpose register, by its number in instruction encodings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Register(pub u8);
+
+pub(crate) const RAX: Register = Register(0);
+pub(crate) const RCX: Register = Register(1);
+pub(crate) const RDX: Register = Register(2);
+pub(crate) const RBX: Register = Register(3);
+pub(crate) const RSP: Register = Register(4);
+pub(crate) const RBP: Register = Register(5);
+pub(crate) const RSI: Register = Register(6);
+pub(crate) const RDI: Register = Register(7);
+pub(crate) const R8: Register = Register(8);
+pub(crate) const R9: Register = Register(9);
+pub(crate) const R10: Register = Register(10);
+pub(crate) const R11: Register = Register(11);
+pub(crate) const R12: Register = Register(12);
+pub(crate) const R13: Register = Register(13);
+pub(crate) const R14: Register = Register(14);
+pub(crate) const R15: Register = Register(15);
+
+/// Names of the 64, 32, 16 and 8 bit parts of every register.
+const REGISTER_NAMES: [[&str; 4]; 16] = [
+    ["%rax", "%eax", "%ax", "%al"],
+    ["%rcx", "%ecx", "%cx", "%cl"],
+    ["%rdx", "%edx", "%dx", "%dl"],
+    ["%rbx", "%ebx", "%bx", "%bl"],
+    ["%rsp", "%esp", "%sp", "%spl"],
+    ["%rbp", "%ebp", "%bp", "%bpl"],
+    ["%rsi", "%esi", "%si", "%sil"],
+    ["%rdi", "%edi", "%di", "%dil"],
+    ["%r8", "%r8d", "%r8w", "%r8b"],
+    ["%r9", "%r9d", "%r9w", "%r9b"],
+    ["%r10", "%r10d", "%r10w", "%r10b"],
+    ["%r11", "%r11d", "%r11w", "%r11b"],
+    ["%r12", "%r12d", "%r12w", "%r12b"],
+    ["%r13", "%r13d", "%r13w", "%r13b"],
+    ["%r14", "%r14d", "%r14w", "%r14b"],
+    ["%r15", "%r15d", "%r15w", "%r15b"],
+];
+
+impl Register {
+    /// Name of the part of the register holding `size` bytes.
+    pub fn name(self, size: u8) -> &'static str {
+        let names = &REGISTER_NAMES[self.0 as usize];
+        match size {
+            1 => names[3],
+            2 => names[2],
+            4 => names[1],
+            _ => names[0],
+        }
+    }
+
+    /// Number of the register in DWARF debug information.
+    pub fn dwarf_number(self) -> u16 {
+        // DWARF numbers the first eight registers in another order.
+        const DWARF_NUMBERS: [u16; 8] = [0, 2, 1, 3, 7, 6, 4, 5];
+        match DWARF_NUMBERS.get(self.0 as usize) {
+            Some(number) => *number,
+            None => self.0 as u16,
+        }
+    }
+}
+
+/// Memory at `base + displacement`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Memory {
+    pub base: Register,
+    pub displacement: i32,
+}
+
+/// Where an operand is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operand {
+    Register(Register),
+    Memory(Memory),
+}
+
+/// Two operand integer operations, `dst = dst op src`. `Cmp` and `Test` only set the
+/// flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AluOp {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+    Cmp,
+    Test,
+}
+
+/// Shifts of a register by `%cl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShiftOp {
+    Shl,
+    Shr,
+    Sar,
+}
+
+/// Condition codes of `jcc`, `setcc` and `cmovcc`, by their number in encodings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Condition {
+    Below = 2,
+    AboveOrEqual = 3,
+    Equal = 4,
+    NotEqual = 5,
+    BelowOrEqual = 6,
+    Above = 7,
+    Less = 12,
+    GreaterOrEqual = 13,
+    LessOrEqual = 14,
+    Greater = 15,
+}
+
+impl Condition {
+    /// Suffix of the mnemonics testing the condition.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Condition::Below => "b",
+            Condition::AboveOrEqual => "ae",
+            Condition::Equal => "e",
+            Condition::NotEqual => "ne",
+            Condition::BelowOrEqual => "be",
+            Condition::Above => "a",
+            Condition::Less => "l",
+            Condition::GreaterOrEqual => "ge",
+            Condition::LessOrEqual => "le",
+            Condition::Greater => "g",
+        }
+    }
+}
+
+/// Destination of a jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Block(Label),
+    /// Start of a function, for tail calls.
+    Function(Function),
+}
+
+/// A machine instruction, selected from the IR and either printed as assembly or
+/// encoded to machine code. Operations on registers are on all 64 bits unless they
+/// have a size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inst {
+    /// Start of the block of a label, not an instruction.
+    Block(Label),
+    /// Start of the code of a source location, not an instruction.
+    Location(DebugLocation),
+    Push(Register),
+    /// Moves `size` bytes from `src` to `dst`, sign or zero extended to 64 bits.
+    Move {
+        size: u8,
+        is_signed: bool,
+        src: Operand,
+        dst: Register,
+    },
+    MoveImmediate {
+        value: i64,
+        dst: Register,
+    },
+    /// Stores the low `size` bytes of `src`.
+    Store {
+        size: u8,
+        src: Register,
+        dst: Memory,
+    },
+    Lea {
+        src: Memory,
+        dst: Register,
+    },
+    Alu {
+        op: AluOp,
+        src: Register,
+        dst: Register,
+    },
+    /// `dst = dst op value`, for all operations but `Imul` and `Test`.
+    AluImmediate {
+        op: AluOp,
+        value: i32,
+        dst: Register,
+    },
+    /// `dst = src * value`.
+    ImulImmediate {
+        value: i32,
+        src: Register,
+        dst: Register,
+    },
+    Shift {
+        op: ShiftOp,
+        dst: Register,
+    },
+    Neg(Register),
+    Not(Register),
+    /// Sign extends `%rax` into `%rdx`.
+    Cqto,
+    /// Divides `%rdx:%rax` by `src`, the quotient in `%rax` and the remainder in `%rdx`.
+    Div {
+        is_signed: bool,
+        src: Register,
+    },
+    /// Sets the low byte of `dst` to 1 if `condition` holds, 0 otherwise.
+    Set {
+        condition: Condition,
+        dst: Register,
+    },
+    Cmov {
+        condition: Condition,
+        src: Register,
+        dst: Register,
+    },
+    Jump(Target),
+    JumpIf(Condition, Label),
+    Call(Function),
+    Leave,
+    Ret,
+}
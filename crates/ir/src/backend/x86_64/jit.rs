@@ -0,0 +1,195 @@
+use super::encode;
+use crate::{backend::align_up, function::Functions, ty::Types};
+use std::{collections::HashMap, ffi::c_void, io};
+
+extern "C" {
+    fn mmap(
+        address: *mut c_void,
+        length: usize,
+        protection: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: i32) -> i32;
+    fn munmap(address: *mut c_void, length: usize) -> i32;
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+/// Alignment of the start of every function in memory.
+const FUNCTION_ALIGNMENT: usize = 16;
+
+/// Most arguments a function can be called with from Rust.
+const MAX_ARGUMENTS: usize = 8;
+
+/// Machine code of a module, in executable memory of the current process.
+///
+/// Functions are compiled by the x86-64 backend and called directly, following the
+/// System V calling convention. The memory is released when the module is dropped.
+pub struct JitModule {
+    memory: *mut c_void,
+    length: usize,
+    functions: HashMap<String, JitFunction>,
+}
+
+struct JitFunction {
+    /// Offset of the first instruction in the memory of the module.
+    offset: usize,
+    num_parameters: usize,
+    has_return_value: bool,
+}
+
+impl JitModule {
+    pub(crate) fn new(types: &Types, functions: &Functions) -> io::Result<JitModule> {
+        let encoded = encode(types, functions)?;
+
+        let mut offsets = HashMap::new();
+        let mut code = Vec::new();
+        for function in encoded.iter() {
+            let start = align_up(code.len() as u64, FUNCTION_ALIGNMENT as u64);
+            code.resize(start as usize, 0xCC);
+            offsets.insert(function.function, code.len());
+            code.extend(&function.code);
+        }
+        for function in encoded.iter() {
+            let start = offsets[&function.function];
+            for (offset, callee) in function.relocations.iter() {
+                let field = start + offset;
+                let displacement = offsets[callee] as i64 - (field + 4) as i64;
+                let displacement = i32::try_from(displacement).expect("module too large");
+                code[field..field + 4].copy_from_slice(&displacement.to_le_bytes());
+            }
+        }
+
+        let length = code.len().max(1);
+        // SAFETY: a new private anonymous mapping doesn't alias any memory.
+        let memory = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                length,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if memory == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let module = JitModule {
+            memory,
+            length,
+            functions: functions
+                .iter()
+                .map(|(id, function)| {
+                    let definition = function.definition();
+                    let jit_function = JitFunction {
+                        offset: offsets[&crate::function::Function(id)],
+                        num_parameters: function.parameters().len(),
+                        has_return_value: definition.return_type.is_some(),
+                    };
+                    (definition.name.clone(), jit_function)
+                })
+                .collect(),
+        };
+
+        // SAFETY: the mapping is `length` bytes long, and writable until it is made
+        // executable.
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), memory.cast(), code.len());
+            if mprotect(memory, length, PROT_READ | PROT_EXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Calls the function called `name` with `arguments`, and returns its return value
+    /// if it has one. Integers are passed and returned extended to 64 bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no function called `name`, if `arguments` doesn't match its
+    /// parameters, or if it has more than 8 parameters.
+    ///
+    /// # Safety
+    ///
+    /// The function runs natively, without any checks: it must not access memory it
+    /// doesn't own, through pointer arguments or otherwise, nor divide by zero or
+    /// recurse deeper than the stack of the thread allows.
+    pub unsafe fn call(&self, name: &str, arguments: &[u64]) -> Option<u64> {
+        let function = self
+            .functions
+            .get(name)
+            .unwrap_or_else(|| panic!("no function called {name}"));
+        assert_eq!(
+            function.num_parameters,
+            arguments.len(),
+            "wrong number of arguments for {name}"
+        );
+        assert!(
+            arguments.len() <= MAX_ARGUMENTS,
+            "{name} has more than {MAX_ARGUMENTS} parameters"
+        );
+
+        let address = self
+            .memory
+            .cast::<u8>()
+            .add(function.offset)
+            .cast::<c_void>();
+        let a = |i: usize| arguments[i];
+        // Integer arguments and return values are all passed in 64 bit registers, or
+        // 8 byte stack slots, so the machine code can be called as taking `u64`s. A
+        // function without a return value leaves `%rax` unspecified.
+        let value = match arguments.len() {
+            0 => std::mem::transmute::<*mut c_void, extern "C" fn() -> u64>(address)(),
+            1 => std::mem::transmute::<*mut c_void, extern "C" fn(u64) -> u64>(address)(
+                a(0),
+            ),
+            2 => std::mem::transmute::<*mut c_void, extern "C" fn(u64, u64) -> u64>(
+                address,
+            )(a(0), a(1)),
+            3 => std::mem::transmute::<*mut c_void, extern "C" fn(u64, u64, u64) -> u64>(
+                address,
+            )(a(0), a(1), a(2)),
+            4 => std::mem::transmute::<
+                *mut c_void,
+                extern "C" fn(u64, u64, u64, u64) -> u64,
+            >(address)(a(0), a(1), a(2), a(3)),
+            5 => std::mem::transmute::<
+                *mut c_void,
+                extern "C" fn(u64, u64, u64, u64, u64) -> u64,
+            >(address)(a(0), a(1), a(2), a(3), a(4)),
+            6 => std::mem::transmute::<
+                *mut c_void,
+                extern "C" fn(u64, u64, u64, u64, u64, u64) -> u64,
+            >(address)(a(0), a(1), a(2), a(3), a(4), a(5)),
+            7 => std::mem::transmute::<
+                *mut c_void,
+                extern "C" fn(u64, u64, u64, u64, u64, u64, u64) -> u64,
+            >(address)(a(0), a(1), a(2), a(3), a(4), a(5), a(6)),
+            _ => std::mem::transmute::<
+                *mut c_void,
+                extern "C" fn(u64, u64, u64, u64, u64, u64, u64, u64) -> u64,
+            >(address)(a(0), a(1), a(2), a(3), a(4), a(5), a(6), a(7)),
+        };
+
+        function.has_return_value.then_some(value)
+    }
+}
+
+impl Drop for JitModule {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `new`, and nothing borrows it past `self`.
+        unsafe {
+            munmap(self.memory, self.length);
+        }
+    }
+}
@@ -0,0 +1,711 @@
+//! Code generation for x86-64, following the System V calling convention.
+//!
+//! Instructions are selected once into [`Inst`]s, then either printed as assembly or
+//! encoded to machine code, for the JIT or an object file.
+
+mod assembly;
+mod encoding;
+mod instruction;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod jit;
+mod object;
+mod tiles;
+
+pub(crate) use assembly::emit;
+pub(crate) use encoding::encode;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub use jit::JitModule;
+pub(crate) use object::emit_object;
+
+use crate::{
+    backend::{
+        align_of, align_up, block_order, check_types,
+        dwarf::VariableLocation,
+        register_allocation::{
+            AllocatableRegister, Assignment, PhysicalRegister, RegisterAllocation,
+            RegisterClass, RegisterDescription,
+        },
+        selection::{cover, Tree, Trees},
+        size_of, Unsupported,
+    },
+    function::{Function, FunctionData, Functions},
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    label::Label,
+    ty::{Type, TypeKind, Types},
+    value::Value,
+};
+use instruction::*;
+use std::collections::HashMap;
+use tiles::{Kind, SCRATCH_REGISTERS};
+
+/// Registers of the first integer arguments, in order. The rest are passed on the stack.
+const ARGUMENT_REGISTERS: [Register; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+
+/// Registers handed out by the register allocator, indexed by [`PhysicalRegister`].
+/// `%rax`, `%rcx`, `%rdx` and the argument registers are left to lower instructions.
+const ALLOCATABLE_REGISTERS: [Register; 7] = [R10, R11, RBX, R12, R13, R14, R15];
+
+/// Integers and pointers all live in general purpose registers.
+const REGISTER_DESCRIPTION: RegisterDescription = RegisterDescription {
+    classes: &[RegisterClass {
+        registers: &[
+            AllocatableRegister {
+                register: PhysicalRegister(0),
+                callee_saved: false,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(1),
+                callee_saved: false,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(2),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(3),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(4),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(5),
+                callee_saved: true,
+            },
+            AllocatableRegister {
+                register: PhysicalRegister(6),
+                callee_saved: true,
+            },
+        ],
+    }],
+    class_of: register_class,
+};
+
+fn register_class(types: &Types, ty: Type) -> Option<usize> {
+    match types.get(ty).type_kind() {
+        TypeKind::Integer {
+            num_bits: 1 | 8 | 16 | 32 | 64,
+            ..
+        }
+        | TypeKind::Pointer { .. } => Some(0),
+        _ => None,
+    }
+}
+
+/// Instructions selected for a function.
+pub(crate) struct MachineFunction {
+    pub function: Function,
+    pub code: Vec<Inst>,
+    /// Where every parameter is kept after the prologue, for debug information.
+    pub parameters: Vec<Option<VariableLocation>>,
+}
+
+/// Selects the instructions of every function of the module.
+///
+/// Values live in the registers given by [`RegisterAllocation`], or in stack slots of
+/// the function frame when spilled, and are moved through `%rax`, `%rcx` and `%rdx` by
+/// every instruction. Integers are kept extended to 64 bits in registers, according
+/// to the signedness of their type, and stored to memory with their size.
+///
+/// Memory accesses are selected by tiling, their operands going through any of the
+/// registers not holding values, so addresses fold into memory operands.
+///
+/// Only integers of 1, 8, 16, 32 and 64 bits and pointers are supported as values.
+pub(crate) fn select(
+    types: &Types,
+    functions: &Functions,
+) -> Result<Vec<MachineFunction>, Unsupported> {
+    check_types(types, functions, |ty| register_class(types, ty).is_some())?;
+
+    let machine_functions = functions
+        .iter()
+        .map(|(id, function)| {
+            let mut selector = InstructionSelector::new(types, function);
+            selector.select();
+            MachineFunction {
+                function: Function(id),
+                parameters: selector.parameter_locations(),
+                code: selector.code,
+            }
+        })
+        .collect();
+    Ok(machine_functions)
+}
+
+/// Selects the instructions of a single function.
+struct InstructionSelector<'a> {
+    types: &'a Types,
+    function: &'a FunctionData,
+    allocation: RegisterAllocation,
+    /// Offset from `%rbp` of the slot of every spilled value.
+    slots: HashMap<Value, i32>,
+    /// Callee saved registers used by the function, with the offset from `%rbp` where
+    /// their value is kept.
+    saved_registers: Vec<(Register, i32)>,
+    /// Offset from `%rbp` of the memory of every `stack_alloc`.
+    allocations: HashMap<Value, i32>,
+    frame_size: u64,
+    /// Registers free for the nodes of the tree being emitted.
+    scratch: Vec<Register>,
+    code: Vec<Inst>,
+}
+
+impl<'a> InstructionSelector<'a> {
+    /// Lays out the frame of `function`.
+    fn new(types: &'a Types, function: &'a FunctionData) -> Self {
+        let allocation = RegisterAllocation::new(types, function, &REGISTER_DESCRIPTION);
+
+        let mut offset: u64 = 0;
+        let mut slots = HashMap::new();
+        let mut allocations = HashMap::new();
+
+        let mut spilled: Vec<Value> = allocation
+            .assignments
+            .iter()
+            .filter(|(_, assignment)| **assignment == Assignment::Spilled)
+            .map(|(value, _)| *value)
+            .collect();
+        spilled.sort();
+        for value in spilled {
+            offset += 8;
+            slots.insert(value, -(offset as i32));
+        }
+
+        let saved_registers = allocation
+            .used_callee_saved
+            .iter()
+            .map(|register| {
+                offset += 8;
+                (ALLOCATABLE_REGISTERS[register.0 as usize], -(offset as i32))
+            })
+            .collect();
+
+        for (_, data) in function.labels().iter() {
+            for instr in data.instructions.iter() {
+                if let Instruction::StackAlloc { dst, ty, size } = instr {
+                    let bytes = size_of(types, *ty) * *size as u64;
+                    offset = align_up(offset + bytes, align_of(types, *ty));
+                    allocations.insert(*dst, -(offset as i32));
+                }
+            }
+        }
+
+        InstructionSelector {
+            types,
+            function,
+            allocation,
+            slots,
+            saved_registers,
+            allocations,
+            frame_size: align_up(offset, 16),
+            scratch: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn select(&mut self) {
+        if let Some(debug_info) = &self.function.definition().debug_info {
+            self.push(Inst::Location(debug_info.location));
+        }
+        self.push(Inst::Push(RBP));
+        self.push(Inst::Move {
+            size: 8,
+            is_signed: false,
+            src: Operand::Register(RSP),
+            dst: RBP,
+        });
+        if self.frame_size > 0 {
+            let frame_size = i32::try_from(self.frame_size).expect("frame too large");
+            self.push(Inst::AluImmediate {
+                op: AluOp::Sub,
+                value: frame_size,
+                dst: RSP,
+            });
+        }
+        for (register, offset) in self.saved_registers.clone() {
+            self.push(Inst::Store {
+                size: 8,
+                src: register,
+                dst: frame(offset),
+            });
+        }
+
+        let parameters = self.function.parameters();
+        for (i, parameter) in parameters.iter().enumerate() {
+            match ARGUMENT_REGISTERS.get(i) {
+                Some(register) => self.store(*register, *parameter),
+                None => {
+                    // Above the return address and the saved `%rbp`.
+                    let offset = 16 + 8 * (i - ARGUMENT_REGISTERS.len()) as i32;
+                    self.push(Inst::Move {
+                        size: 8,
+                        is_signed: false,
+                        src: Operand::Memory(frame(offset)),
+                        dst: RAX,
+                    });
+                    self.store(RAX, *parameter);
+                }
+            }
+        }
+
+        let trees = Trees::new(self.types, self.function);
+        let order = block_order(self.function);
+        let mut current_location = None;
+        for (i, label) in order.iter().enumerate() {
+            self.push(Inst::Block(*label));
+            let next = order.get(i + 1).copied();
+            let instructions = &self.function.labels().get(*label).instructions;
+            for (index, (instr, location)) in instructions.iter_located().enumerate() {
+                if trees.is_folded(*label, index) {
+                    continue;
+                }
+                if let Some(location) = location.filter(|_| location != current_location)
+                {
+                    self.push(Inst::Location(location));
+                    current_location = Some(location);
+                }
+                match trees.tree(*label, index) {
+                    Some(tree) => self.select_tree(tree),
+                    None => self.select_instruction(instr, next),
+                }
+            }
+        }
+    }
+
+    /// Where every parameter is kept once stored by the prologue.
+    fn parameter_locations(&self) -> Vec<Option<VariableLocation>> {
+        self.function
+            .parameters()
+            .iter()
+            .map(|parameter| {
+                let location = match self.allocation.assignments.get(parameter)? {
+                    Assignment::Register(register) => VariableLocation::Register(
+                        ALLOCATABLE_REGISTERS[register.0 as usize].dwarf_number(),
+                    ),
+                    Assignment::Spilled => {
+                        VariableLocation::Frame(self.slots[parameter] as i64)
+                    }
+                };
+                Some(location)
+            })
+            .collect()
+    }
+
+    /// Covers `tree` with tiles, and stores its result to the value it defines.
+    fn select_tree(&mut self, tree: &Tree) {
+        self.scratch = SCRATCH_REGISTERS.to_vec();
+        match tree.dst {
+            Some(dst) => {
+                let register = cover(self, tree, Kind::Register).register();
+                self.store(register, dst);
+            }
+            None => {
+                cover(self, tree, Kind::Effect);
+            }
+        }
+    }
+
+    fn select_instruction(&mut self, instr: &Instruction, next: Option<Label>) {
+        match instr {
+            Instruction::ArithmeticBinary { dst, lhs, op, rhs } => {
+                let is_signed = self.is_signed(*dst);
+                // Shifts in bits from above the type, so the operand has to be
+                // extended the way the shift fills the top.
+                let extend_signed = match op {
+                    BinaryOp::Shr => false,
+                    BinaryOp::Sar => true,
+                    _ => is_signed,
+                };
+                self.load_extended(*lhs, RAX, extend_signed);
+                self.load(*rhs, RCX);
+
+                let alu = |op| Inst::Alu {
+                    op,
+                    src: RCX,
+                    dst: RAX,
+                };
+                match op {
+                    BinaryOp::Add => self.push(alu(AluOp::Add)),
+                    BinaryOp::Sub => self.push(alu(AluOp::Sub)),
+                    BinaryOp::Mul => self.push(alu(AluOp::Imul)),
+                    BinaryOp::Div | BinaryOp::Mod => {
+                        if is_signed {
+                            self.push(Inst::Cqto);
+                        } else {
+                            self.push(Inst::Alu {
+                                op: AluOp::Xor,
+                                src: RDX,
+                                dst: RDX,
+                            });
+                        }
+                        self.push(Inst::Div {
+                            is_signed,
+                            src: RCX,
+                        });
+                        if *op == BinaryOp::Mod {
+                            self.copy(RDX, RAX);
+                        }
+                    }
+                    BinaryOp::Shl => self.push(Inst::Shift {
+                        op: ShiftOp::Shl,
+                        dst: RAX,
+                    }),
+                    BinaryOp::Shr => self.push(Inst::Shift {
+                        op: ShiftOp::Shr,
+                        dst: RAX,
+                    }),
+                    BinaryOp::Sar => self.push(Inst::Shift {
+                        op: ShiftOp::Sar,
+                        dst: RAX,
+                    }),
+                    BinaryOp::And | BinaryOp::BitAnd => self.push(alu(AluOp::And)),
+                    BinaryOp::Or | BinaryOp::BitOr => self.push(alu(AluOp::Or)),
+                    BinaryOp::Xor => self.push(alu(AluOp::Xor)),
+                }
+                self.store(RAX, *dst);
+            }
+            Instruction::ArithmeticUnary { dst, op, value } => {
+                self.load(*value, RAX);
+                match op {
+                    UnaryOp::Neg => self.push(Inst::Neg(RAX)),
+                    UnaryOp::Not if self.num_bits(*dst) == 1 => {
+                        self.push(Inst::AluImmediate {
+                            op: AluOp::Xor,
+                            value: 1,
+                            dst: RAX,
+                        })
+                    }
+                    UnaryOp::Not => self.push(Inst::Not(RAX)),
+                }
+                self.store(RAX, *dst);
+            }
+            Instruction::Branch { target } => self.jump(*target, next),
+            Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.load(*condition, RAX);
+                self.push(Inst::Alu {
+                    op: AluOp::Test,
+                    src: RAX,
+                    dst: RAX,
+                });
+                if Some(*on_true) == next {
+                    self.push(Inst::JumpIf(Condition::Equal, *on_false));
+                } else {
+                    self.push(Inst::JumpIf(Condition::NotEqual, *on_true));
+                    self.jump(*on_false, next);
+                }
+            }
+            Instruction::Call {
+                function,
+                arguments,
+                dst,
+                tail,
+            } => {
+                let (in_registers, on_stack) =
+                    arguments.split_at(arguments.len().min(ARGUMENT_REGISTERS.len()));
+
+                // The frame is given back before jumping, so only calls without stack
+                // arguments can reuse it.
+                if *tail && on_stack.is_empty() {
+                    for (argument, register) in
+                        in_registers.iter().zip(ARGUMENT_REGISTERS)
+                    {
+                        self.load(*argument, register);
+                    }
+                    self.epilogue();
+                    self.push(Inst::Jump(Target::Function(*function)));
+                    return;
+                }
+
+                // `%rsp` is 16 byte aligned in the body, and has to be at the call.
+                let padding = if on_stack.len() % 2 == 1 { 8 } else { 0 };
+                if padding != 0 {
+                    self.push(Inst::AluImmediate {
+                        op: AluOp::Sub,
+                        value: padding,
+                        dst: RSP,
+                    });
+                }
+                for argument in on_stack.iter().rev() {
+                    self.load(*argument, RAX);
+                    self.push(Inst::Push(RAX));
+                }
+                for (argument, register) in in_registers.iter().zip(ARGUMENT_REGISTERS) {
+                    self.load(*argument, register);
+                }
+
+                self.push(Inst::Call(*function));
+                let cleanup = 8 * on_stack.len() as i32 + padding;
+                if cleanup != 0 {
+                    self.push(Inst::AluImmediate {
+                        op: AluOp::Add,
+                        value: cleanup,
+                        dst: RSP,
+                    });
+                }
+                if let Some(dst) = dst {
+                    self.store(RAX, *dst);
+                }
+            }
+            Instruction::Cast {
+                cast_op,
+                dst,
+                value,
+                ..
+            } => {
+                // Coalesced with its operand, which already has the right form.
+                let same_form = self.value_size(self.ty(*value))
+                    == self.value_size(self.ty(*dst))
+                    && self.is_signed(*value) == self.is_signed(*dst);
+                if *cast_op == CastOp::BitCast
+                    && same_form
+                    && self.function.constant(*value).is_none()
+                    && self.allocation.assignment(*dst)
+                        == self.allocation.assignment(*value)
+                {
+                    return;
+                }
+
+                match cast_op {
+                    CastOp::SignExtend => self.load_extended(*value, RAX, true),
+                    CastOp::ZeroExtend => self.load_extended(*value, RAX, false),
+                    CastOp::Truncate | CastOp::BitCast => self.load(*value, RAX),
+                }
+                self.store(RAX, *dst);
+            }
+            Instruction::IntCompare {
+                pred,
+                dst,
+                lhs,
+                rhs,
+            } => {
+                let is_signed = self.is_signed(*lhs);
+                self.load(*lhs, RAX);
+                self.load(*rhs, RCX);
+                self.push(Inst::Alu {
+                    op: AluOp::Cmp,
+                    src: RCX,
+                    dst: RAX,
+                });
+
+                let condition = match (pred, is_signed) {
+                    (IntCompareOp::Equal, _) => Condition::Equal,
+                    (IntCompareOp::NotEqual, _) => Condition::NotEqual,
+                    (IntCompareOp::GreaterThan, true) => Condition::Greater,
+                    (IntCompareOp::GreaterThanOrEqual, true) => Condition::GreaterOrEqual,
+                    (IntCompareOp::LessThan, true) => Condition::Less,
+                    (IntCompareOp::LessThanOrEqual, true) => Condition::LessOrEqual,
+                    (IntCompareOp::GreaterThan, false) => Condition::Above,
+                    (IntCompareOp::GreaterThanOrEqual, false) => Condition::AboveOrEqual,
+                    (IntCompareOp::LessThan, false) => Condition::Below,
+                    (IntCompareOp::LessThanOrEqual, false) => Condition::BelowOrEqual,
+                };
+                self.push(Inst::Set {
+                    condition,
+                    dst: RAX,
+                });
+                self.push(Inst::Move {
+                    size: 1,
+                    is_signed: false,
+                    src: Operand::Register(RAX),
+                    dst: RAX,
+                });
+                self.store(RAX, *dst);
+            }
+            Instruction::Return { value } => {
+                if let Some(value) = value {
+                    self.load(*value, RAX);
+                }
+                self.epilogue();
+                self.push(Inst::Ret);
+            }
+            Instruction::Select {
+                dst,
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.load(*on_false, RAX);
+                self.load(*on_true, RCX);
+                self.load(*condition, RDX);
+                self.push(Inst::Alu {
+                    op: AluOp::Test,
+                    src: RDX,
+                    dst: RDX,
+                });
+                self.push(Inst::Cmov {
+                    condition: Condition::NotEqual,
+                    src: RCX,
+                    dst: RAX,
+                });
+                self.store(RAX, *dst);
+            }
+            Instruction::GetElementPtr { .. }
+            | Instruction::Load { .. }
+            | Instruction::StackAlloc { .. }
+            | Instruction::Store { .. } => unreachable!("memory accesses are tiled"),
+            Instruction::Nop => {}
+        }
+    }
+
+    /// Loads `value` into `register`, extended according to the signedness of its type.
+    fn load(&mut self, value: Value, register: Register) {
+        self.load_extended(value, register, self.is_signed(value));
+    }
+
+    /// Loads `value` into `register`, sign extended if `is_signed`, zero extended
+    /// otherwise.
+    fn load_extended(&mut self, value: Value, register: Register, is_signed: bool) {
+        if let Some(constant) = self.function.constant(value) {
+            let bits = constant
+                .integer()
+                .expect("float values are rejected by `check_types`");
+            let num_bits = self.num_bits(value);
+            let shift = 64 - num_bits;
+            let bits = if is_signed {
+                (((bits << shift) as i64) >> shift) as u64
+            } else {
+                (bits << shift) >> shift
+            };
+
+            self.push(Inst::MoveImmediate {
+                value: bits as i64,
+                dst: register,
+            });
+            return;
+        }
+
+        let operand = self.operand(value);
+        self.push(Inst::Move {
+            size: self.value_size(self.ty(value)),
+            is_signed,
+            src: operand,
+            dst: register,
+        });
+    }
+
+    /// Stores `register` to the register or the slot of `value`.
+    fn store(&mut self, register: Register, value: Value) {
+        let ty = self.ty(value);
+        match self.operand(value) {
+            Operand::Register(target) => {
+                if self.num_bits(value) == 1 {
+                    self.push(Inst::AluImmediate {
+                        op: AluOp::And,
+                        value: 1,
+                        dst: register,
+                    });
+                }
+                self.push(Inst::Move {
+                    size: self.value_size(ty),
+                    is_signed: self.is_signed(value),
+                    src: Operand::Register(register),
+                    dst: target,
+                });
+            }
+            Operand::Memory(memory) => self.store_memory(ty, register, memory),
+        }
+    }
+
+    /// Where `value`, which is not a constant, lives.
+    fn operand(&self, value: Value) -> Operand {
+        match self.allocation.assignment(value) {
+            Assignment::Register(register) => {
+                Operand::Register(ALLOCATABLE_REGISTERS[register.0 as usize])
+            }
+            Assignment::Spilled => Operand::Memory(frame(self.slots[&value])),
+        }
+    }
+
+    /// Restores the callee saved registers and releases the frame.
+    fn epilogue(&mut self) {
+        for (register, offset) in self.saved_registers.clone() {
+            self.push(Inst::Move {
+                size: 8,
+                is_signed: false,
+                src: Operand::Memory(frame(offset)),
+                dst: register,
+            });
+        }
+        self.push(Inst::Leave);
+    }
+
+    /// Stores the low bytes of `register` holding a value of type `ty` to `memory`.
+    fn store_memory(&mut self, ty: Type, register: Register, memory: Memory) {
+        if matches!(
+            self.types.get(ty).type_kind(),
+            TypeKind::Integer { num_bits: 1, .. }
+        ) {
+            self.push(Inst::AluImmediate {
+                op: AluOp::And,
+                value: 1,
+                dst: register,
+            });
+        }
+
+        self.push(Inst::Store {
+            size: self.value_size(ty),
+            src: register,
+            dst: memory,
+        });
+    }
+
+    /// Copies all the bits of `src` to `dst`.
+    fn copy(&mut self, src: Register, dst: Register) {
+        self.push(Inst::Move {
+            size: 8,
+            is_signed: false,
+            src: Operand::Register(src),
+            dst,
+        });
+    }
+
+    /// Size of a value of type `ty` held in a register.
+    fn value_size(&self, ty: Type) -> u8 {
+        match self.types.get(ty).type_kind() {
+            TypeKind::Integer { .. } => size_of(self.types, ty) as u8,
+            TypeKind::Pointer { .. } => 8,
+            TypeKind::Float { .. } | TypeKind::Struct { .. } => {
+                unreachable!("rejected by `check_types`")
+            }
+        }
+    }
+
+    fn jump(&mut self, target: Label, next: Option<Label>) {
+        if Some(target) != next {
+            self.push(Inst::Jump(Target::Block(target)));
+        }
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.values().get(value).ty()
+    }
+
+    fn num_bits(&self, value: Value) -> u32 {
+        self.types
+            .integer(self.ty(value))
+            .map_or(64, |(num_bits, _)| num_bits)
+    }
+
+    fn is_signed(&self, value: Value) -> bool {
+        self.types
+            .integer(self.ty(value))
+            .is_some_and(|(_, is_signed)| is_signed)
+    }
+
+    fn push(&mut self, inst: Inst) {
+        self.code.push(inst);
+    }
+}
+
+/// Memory at `offset` from the frame pointer.
+fn frame(offset: i32) -> Memory {
+    Memory {
+        base: RBP,
+        displacement: offset,
+    }
+}
@@ -0,0 +1,193 @@
+use super::{encode, RBP};
+use crate::{
+    backend::{
+        align_up,
+        dwarf::{self, DebugFunction, DebugSection, Fixup, LineSequence},
+        elf::{
+            ObjectFile, Relocation, Section, Symbol, SymbolKind, EM_X86_64, R_X86_64_32,
+            R_X86_64_64, R_X86_64_PLT32,
+        },
+        Unsupported,
+    },
+    function::{Function, Functions, Linkage},
+    ty::Types,
+};
+use std::collections::HashMap;
+
+/// Alignment of the start of every function in `.text`.
+const FUNCTION_ALIGNMENT: usize = 16;
+
+/// Encodes every function of the module into an ELF64 relocatable object file, with a
+/// `.text` section holding the functions one after the other.
+///
+/// Every function gets a symbol, global unless it has internal linkage, and calls
+/// between functions are left to the linker as `R_X86_64_PLT32` relocations.
+///
+/// With `source_files`, the object also holds the DWARF line table and debug
+/// information of the module.
+pub(crate) fn emit_object(
+    types: &Types,
+    functions: &Functions,
+    source_files: &[String],
+) -> Result<Vec<u8>, Unsupported> {
+    let encoded = encode(types, functions)?;
+
+    let mut text = Vec::new();
+    let mut symbols = Vec::new();
+    let mut symbol_of = HashMap::new();
+    let mut starts = Vec::new();
+    for function in encoded.iter() {
+        // Padding is never executed, so fill it with `int3`.
+        let start = align_up(text.len() as u64, FUNCTION_ALIGNMENT as u64);
+        text.resize(start as usize, 0xCC);
+        let definition = functions.get(function.function).definition();
+        symbol_of.insert(function.function, symbols.len());
+        starts.push(text.len());
+        symbols.push(Symbol {
+            name: definition.name.clone(),
+            kind: SymbolKind::Function,
+            section: 0,
+            offset: text.len() as u64,
+            size: function.code.len() as u64,
+            is_global: definition.linkage == Linkage::External,
+        });
+        text.extend(&function.code);
+    }
+
+    let relocations = encoded
+        .iter()
+        .zip(starts)
+        .flat_map(|(function, start)| {
+            let symbol_of = &symbol_of;
+            function
+                .relocations
+                .iter()
+                .map(move |(offset, callee)| Relocation {
+                    offset: (start + offset) as u64,
+                    symbol: symbol_of[callee],
+                    kind: R_X86_64_PLT32,
+                    // The displacement is relative to the end of its field.
+                    addend: -4,
+                })
+        })
+        .collect();
+
+    let text_size = text.len();
+    let mut sections = vec![
+        Section {
+            name: ".text".to_string(),
+            data: text,
+            alignment: FUNCTION_ALIGNMENT as u64,
+            is_executable: true,
+            is_allocated: true,
+            relocations,
+        },
+        // Tells the linker the stack doesn't need to be executable.
+        Section::note(".note.GNU-stack"),
+    ];
+
+    if !source_files.is_empty() {
+        let debug_functions: Vec<DebugFunction> = encoded
+            .iter()
+            .map(|function| DebugFunction {
+                function: function.function,
+                frame_base: RBP.dwarf_number(),
+                parameters: function.parameters.clone(),
+            })
+            .collect();
+        let sequences: Vec<LineSequence> = encoded
+            .iter()
+            .map(|function| LineSequence {
+                function: function.function,
+                rows: function.lines.clone(),
+                size: function.code.len(),
+            })
+            .collect();
+
+        let info = dwarf::debug_info(types, functions, source_files, &debug_functions);
+        let lines = dwarf::line_table(source_files, &sequences);
+
+        let layout = DebugLayout {
+            text_symbol: symbols.len(),
+            abbreviations_symbol: symbols.len() + 1,
+            line_table_symbol: symbols.len() + 2,
+            function_symbols: &symbol_of,
+            text_size,
+            function_sizes: encoded
+                .iter()
+                .map(|function| (function.function, function.code.len()))
+                .collect(),
+        };
+        symbols.push(Symbol::section(0));
+        symbols.push(Symbol::section(sections.len()));
+        symbols.push(Symbol::section(sections.len() + 2));
+
+        let abbreviations = DebugSection {
+            data: dwarf::abbreviations(),
+            fixups: Vec::new(),
+        };
+        sections.push(layout.section(".debug_abbrev", abbreviations));
+        sections.push(layout.section(".debug_info", info));
+        sections.push(layout.section(".debug_line", lines));
+    }
+
+    let object = ObjectFile {
+        machine: EM_X86_64,
+        sections,
+        symbols,
+    };
+    Ok(object.write())
+}
+
+/// Symbols and sizes the fixups of the debug sections are resolved with.
+struct DebugLayout<'a> {
+    text_symbol: usize,
+    abbreviations_symbol: usize,
+    line_table_symbol: usize,
+    function_symbols: &'a HashMap<Function, usize>,
+    text_size: usize,
+    function_sizes: HashMap<Function, usize>,
+}
+
+impl DebugLayout<'_> {
+    /// The section `name` with the contents of `debug_section`, sizes written in place
+    /// and addresses and offsets in other sections left to relocations.
+    fn section(&self, name: &str, debug_section: DebugSection) -> Section {
+        let DebugSection { mut data, fixups } = debug_section;
+        let mut relocations = Vec::new();
+        for (offset, fixup) in fixups {
+            let (symbol, kind) = match fixup {
+                Fixup::TextSize | Fixup::FunctionSize(_) => {
+                    let size = match fixup {
+                        Fixup::FunctionSize(function) => self.function_sizes[&function],
+                        _ => self.text_size,
+                    };
+                    data[offset..offset + 8]
+                        .copy_from_slice(&(size as u64).to_le_bytes());
+                    continue;
+                }
+                Fixup::TextStart => (self.text_symbol, R_X86_64_64),
+                Fixup::FunctionStart(function) => {
+                    (self.function_symbols[&function], R_X86_64_64)
+                }
+                Fixup::LineTable => (self.line_table_symbol, R_X86_64_32),
+                Fixup::Abbreviations => (self.abbreviations_symbol, R_X86_64_32),
+            };
+            relocations.push(Relocation {
+                offset: offset as u64,
+                symbol,
+                kind,
+                addend: 0,
+            });
+        }
+
+        Section {
+            name: name.to_string(),
+            data,
+            alignment: 1,
+            is_executable: false,
+            is_allocated: false,
+            relocations,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use super::{frame, instruction::*, InstructionSelector};
+use crate::backend::selection::{Binding, Node, Pattern, Tile, TileSet};
+
+/// Registers handed out to the nodes of a tree, taken from the end. None of them
+/// holds a value between instructions.
+pub(super) const SCRATCH_REGISTERS: [Register; 7] = [R9, R8, RDI, RSI, RDX, RCX, RAX];
+
+/// Results of the x86-64 tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Register,
+    /// A memory operand.
+    Address,
+    /// Nothing, for stores.
+    Effect,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Output {
+    Register(Register),
+    Address(Memory),
+    Effect,
+}
+
+impl Output {
+    pub fn register(self) -> Register {
+        match self {
+            Output::Register(register) => register,
+            _ => panic!("{self:?} is not a register"),
+        }
+    }
+
+    fn address(self) -> Memory {
+        match self {
+            Output::Address(memory) => memory,
+            _ => panic!("{self:?} is not an address"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Rule {
+    LoadValue,
+    LoadConstant,
+    Add,
+    AddImmediate,
+    Mul,
+    MulImmediate,
+    Load,
+    Lea,
+    Base,
+    BaseDisplacement,
+    Frame,
+    FrameDisplacement,
+    Store,
+}
+
+const REGISTER: Pattern<Kind> = Pattern::Operand(Kind::Register);
+const ADDRESS: Pattern<Kind> = Pattern::Operand(Kind::Address);
+const IMMEDIATE: Pattern<Kind> = Pattern::Constant(fits_i32);
+
+fn fits_i32(value: i64) -> bool {
+    i32::try_from(value).is_ok()
+}
+
+/// Whether `value` stays a 32 bit displacement once added to an offset in the frame.
+fn fits_frame_displacement(value: i64) -> bool {
+    (-(1 << 30)..1 << 30).contains(&value)
+}
+
+impl TileSet for InstructionSelector<'_> {
+    type Kind = Kind;
+    type Rule = Rule;
+    type Output = Output;
+
+    const TILES: &'static [Tile<Kind, Rule>] = &[
+        Tile {
+            rule: Rule::LoadValue,
+            result: Kind::Register,
+            pattern: Pattern::Value,
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::LoadConstant,
+            result: Kind::Register,
+            pattern: Pattern::Constant(|_| true),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Add,
+            result: Kind::Register,
+            pattern: Pattern::Add(&REGISTER, &REGISTER),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::AddImmediate,
+            result: Kind::Register,
+            pattern: Pattern::Add(&REGISTER, &IMMEDIATE),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Mul,
+            result: Kind::Register,
+            pattern: Pattern::Mul(&REGISTER, &REGISTER),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::MulImmediate,
+            result: Kind::Register,
+            pattern: Pattern::Mul(&REGISTER, &IMMEDIATE),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Load,
+            result: Kind::Register,
+            pattern: Pattern::Load(&ADDRESS),
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Lea,
+            result: Kind::Register,
+            pattern: ADDRESS,
+            cost: 1,
+        },
+        Tile {
+            rule: Rule::Base,
+            result: Kind::Address,
+            pattern: REGISTER,
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::BaseDisplacement,
+            result: Kind::Address,
+            pattern: Pattern::Add(&REGISTER, &IMMEDIATE),
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::Frame,
+            result: Kind::Address,
+            pattern: Pattern::StackAddress,
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::FrameDisplacement,
+            result: Kind::Address,
+            pattern: Pattern::Add(
+                &Pattern::StackAddress,
+                &Pattern::Constant(fits_frame_displacement),
+            ),
+            cost: 0,
+        },
+        Tile {
+            rule: Rule::Store,
+            result: Kind::Effect,
+            pattern: Pattern::Store(&ADDRESS, &REGISTER),
+            cost: 1,
+        },
+    ];
+
+    fn emit(&mut self, rule: Rule, node: Node, bindings: &[Binding<Output>]) -> Output {
+        match rule {
+            Rule::LoadValue => {
+                let register = self.scratch();
+                self.load(bindings[0].value(), register);
+                Output::Register(register)
+            }
+            Rule::LoadConstant => {
+                let register = self.scratch();
+                self.push(Inst::MoveImmediate {
+                    value: bindings[0].constant(),
+                    dst: register,
+                });
+                Output::Register(register)
+            }
+            Rule::Add | Rule::Mul => {
+                let dst = bindings[0].operand().register();
+                let src = bindings[1].operand().register();
+                let op = match rule {
+                    Rule::Add => AluOp::Add,
+                    _ => AluOp::Imul,
+                };
+                self.push(Inst::Alu { op, src, dst });
+                self.release(src);
+                Output::Register(dst)
+            }
+            Rule::AddImmediate => {
+                let dst = bindings[0].operand().register();
+                self.push(Inst::AluImmediate {
+                    op: AluOp::Add,
+                    value: bindings[1].constant() as i32,
+                    dst,
+                });
+                Output::Register(dst)
+            }
+            Rule::MulImmediate => {
+                let dst = bindings[0].operand().register();
+                self.push(Inst::ImulImmediate {
+                    value: bindings[1].constant() as i32,
+                    src: dst,
+                    dst,
+                });
+                Output::Register(dst)
+            }
+            Rule::Load => {
+                let Node::Load { ty, .. } = node else {
+                    unreachable!()
+                };
+                let memory = bindings[0].operand().address();
+                let dst = self.reuse(memory);
+                self.push(Inst::Move {
+                    size: self.value_size(ty),
+                    is_signed: self.types.integer(ty).is_some_and(|(_, signed)| signed),
+                    src: Operand::Memory(memory),
+                    dst,
+                });
+                Output::Register(dst)
+            }
+            Rule::Lea => {
+                let memory = bindings[0].operand().address();
+                let dst = self.reuse(memory);
+                self.push(Inst::Lea { src: memory, dst });
+                Output::Register(dst)
+            }
+            Rule::Base => Output::Address(Memory {
+                base: bindings[0].operand().register(),
+                displacement: 0,
+            }),
+            Rule::BaseDisplacement => Output::Address(Memory {
+                base: bindings[0].operand().register(),
+                displacement: bindings[1].constant() as i32,
+            }),
+            Rule::Frame => Output::Address(frame(self.allocations[&bindings[0].value()])),
+            Rule::FrameDisplacement => {
+                let offset = self.allocations[&bindings[0].value()] as i64
+                    + bindings[1].constant();
+                Output::Address(frame(i32::try_from(offset).expect("frame too large")))
+            }
+            Rule::Store => {
+                let Node::Store { ty, .. } = node else {
+                    unreachable!()
+                };
+                let memory = bindings[0].operand().address();
+                let src = bindings[1].operand().register();
+                self.store_memory(ty, src, memory);
+                self.release(src);
+                if memory.base != RBP {
+                    self.release(memory.base);
+                }
+                Output::Effect
+            }
+        }
+    }
+}
+
+impl InstructionSelector<'_> {
+    /// Takes a register no node of the tree being emitted holds.
+    fn scratch(&mut self) -> Register {
+        self.scratch.pop().expect("tree too deep")
+    }
+
+    /// Hands back a register taken with [`Self::scratch`].
+    fn release(&mut self, register: Register) {
+        self.scratch.push(register);
+    }
+
+    /// Register for the result of an instruction reading `memory`, which is the base
+    /// when no other node needs it anymore.
+    fn reuse(&mut self, memory: Memory) -> Register {
+        if memory.base == RBP {
+            self.scratch()
+        } else {
+            memory.base
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    function::{Function, Functions},
+    instruction::Instruction,
+    location::Location,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Calls between the functions of a module, found from [`Instruction::Call`].
+#[derive(Debug)]
+pub(crate) struct CallGraph {
+    /// Maps every function to the functions it calls.
+    pub callees: HashMap<Function, HashSet<Function>>,
+    /// Maps every function to the calls to it, `(caller, location in the caller)`.
+    pub call_sites: HashMap<Function, Vec<(Function, Location)>>,
+}
+
+impl CallGraph {
+    /// Collects the calls of every block, reachable or not.
+    pub fn new(functions: &Functions) -> Self {
+        let mut callees: HashMap<Function, HashSet<Function>> = HashMap::new();
+        let mut call_sites: HashMap<Function, Vec<(Function, Location)>> = HashMap::new();
+        for (id, _) in functions.iter() {
+            callees.insert(Function(id), HashSet::new());
+            call_sites.insert(Function(id), Vec::new());
+        }
+
+        for (id, data) in functions.iter() {
+            let caller = Function(id);
+            for (label, label_data) in data.labels().iter() {
+                for (i, instr) in label_data.instructions.iter().enumerate() {
+                    let Instruction::Call { function, .. } = instr else {
+                        continue;
+                    };
+                    let location = Location {
+                        label: *label,
+                        instruction: i as u32,
+                    };
+
+                    callees.get_mut(&caller).unwrap().insert(*function);
+                    call_sites
+                        .get_mut(function)
+                        .unwrap()
+                        .push((caller, location));
+                }
+            }
+        }
+
+        // Sorted, so the result does not depend on `Labels` iteration order.
+        for sites in call_sites.values_mut() {
+            sites.sort();
+        }
+
+        CallGraph {
+            callees,
+            call_sites,
+        }
+    }
+
+    /// Functions reachable from `roots` through zero or more calls.
+    pub fn reachable_from(
+        &self,
+        roots: impl IntoIterator<Item = Function>,
+    ) -> HashSet<Function> {
+        let mut reachable = HashSet::new();
+        let mut stack = roots.into_iter().collect::<Vec<_>>();
+        while let Some(function) = stack.pop() {
+            if reachable.insert(function) {
+                stack.extend(self.callees[&function].iter().copied());
+            }
+        }
+
+        reachable
+    }
+
+    /// Maps every function to the functions reachable through one or more calls.
+    pub fn reachability(&self) -> HashMap<Function, HashSet<Function>> {
+        self.callees
+            .iter()
+            .map(|(function, callees)| {
+                (*function, self.reachable_from(callees.iter().copied()))
+            })
+            .collect()
+    }
+
+    /// Orders functions so that callees come before their callers, where possible.
+    pub fn bottom_up_order(&self) -> Vec<Function> {
+        fn visit(
+            function: Function,
+            callees: &HashMap<Function, HashSet<Function>>,
+            visited: &mut HashSet<Function>,
+            order: &mut Vec<Function>,
+        ) {
+            if !visited.insert(function) {
+                return;
+            }
+
+            let mut sorted = callees[&function].iter().copied().collect::<Vec<_>>();
+            sorted.sort();
+            for callee in sorted {
+                visit(callee, callees, visited, order);
+            }
+            order.push(function);
+        }
+
+        let mut functions = self.callees.keys().copied().collect::<Vec<_>>();
+        functions.sort();
+
+        let mut visited = HashSet::new();
+        let mut order = Vec::with_capacity(functions.len());
+        for function in functions {
+            visit(function, &self.callees, &mut visited, &mut order);
+        }
+
+        order
+    }
+}
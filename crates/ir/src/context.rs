@@ -1,7 +1,9 @@
 use crate::{
-    dump_ir::{format_instruction, IrFormatter},
-    function::{Function, Functions},
+    debug_location::{FunctionDebugInfo, SourceFile},
+    dump_ir::{format_instruction, format_location, IrFormatter},
+    function::{Function, Functions, Inline, Linkage},
     function_builder::FunctionBuilder,
+    function_summary::FunctionSummary,
     ty::{Type, TypeKind, Types},
     value::Value,
 };
@@ -10,6 +12,9 @@ use crate::{
 pub struct Context {
     types: Types,
     functions: Functions,
+    /// Names of the files the module was compiled from, indexed by [`SourceFile`]. The
+    /// native backends only emit debug information when there are some.
+    source_files: Vec<String>,
 }
 
 impl Context {
@@ -18,6 +23,7 @@ impl Context {
         Self {
             types: Types::new(),
             functions: Functions::new(),
+            source_files: Vec::new(),
         }
     }
 
@@ -38,7 +44,42 @@ impl Context {
 
     ///
     pub fn builder(&mut self, function: Function) -> FunctionBuilder<'_> {
-        FunctionBuilder::new(&mut self.types, self.functions.get_mut(function))
+        let return_types = self
+            .functions
+            .iter()
+            .map(|(id, data)| (Function(id), data.definition().return_type))
+            .collect();
+
+        FunctionBuilder::new(
+            &mut self.types,
+            self.functions.get_mut(function),
+            return_types,
+        )
+    }
+
+    /// Sets the [`Inline`] attribute of `function`.
+    pub fn set_inline(&mut self, function: Function, inline: Inline) {
+        self.functions.get_mut(function).definition_mut().inline = inline;
+    }
+
+    /// Sets the [`Linkage`] of `function`.
+    pub fn set_linkage(&mut self, function: Function, linkage: Linkage) {
+        self.functions.get_mut(function).definition_mut().linkage = linkage;
+    }
+
+    /// Sets the [`FunctionDebugInfo`] of `function`.
+    pub fn set_debug_info(&mut self, function: Function, debug_info: FunctionDebugInfo) {
+        self.functions.get_mut(function).definition_mut().debug_info = Some(debug_info);
+    }
+
+    /// Adds a file the module was compiled from, for the [`DebugLocation`]s of its
+    /// instructions to refer to. The native backends describe the code in DWARF debug
+    /// information once there is one.
+    ///
+    /// [`DebugLocation`]: crate::debug_location::DebugLocation
+    pub fn add_source_file(&mut self, name: &str) -> SourceFile {
+        self.source_files.push(name.to_string());
+        SourceFile(self.source_files.len() as u32 - 1)
     }
 
     pub fn validate(&mut self) {
@@ -70,9 +111,77 @@ impl Context {
 
     pub fn optimize(&mut self) {
         use crate::passes;
-        let mut passes: [&mut dyn passes::Pass; 4] = [
+
+        // Simplify callees first, so the inliner sees their real size.
+        self.run_function_passes();
+        // Recursive functions turned into loops can be inlined, and unrolled.
+        self.run_module_pass(&mut passes::tail_call_elimination::TailCallEliminationPass);
+        self.run_module_pass(
+            &mut passes::interprocedural_constant_propagation::InterproceduralConstantPropagationPass,
+        );
+        self.run_function_passes();
+        self.run_module_pass(&mut passes::inlining::InliningPass::default());
+        self.run_module_pass(
+            &mut passes::dead_function_elimination::DeadFunctionEliminationPass,
+        );
+        self.run_function_passes();
+
+        // Unrolled loops need another round to fold the copies into straight-line code.
+        self.run_loop_passes();
+        self.run_function_passes();
+
+        // Inlining and constant propagation may have moved calls out of tail position.
+        self.run_module_pass(&mut passes::tail_call_elimination::TailCallEliminationPass);
+    }
+
+    /// Runs a transformation over the whole module.
+    fn run_module_pass(&mut self, pass: &mut dyn crate::passes::ModulePass) {
+        let mut ctx =
+            crate::passes::ModuleContext::new(&mut self.types, &mut self.functions);
+        pass.run(&mut ctx);
+    }
+
+    /// Gives every function the summaries of the functions it calls.
+    fn update_callee_summaries(&mut self) {
+        let summaries = FunctionSummary::compute_all(&self.types, &self.functions);
+        let call_graph = self.functions.call_graph();
+        for (id, function) in self.functions.iter_mut() {
+            function.callee_summaries = call_graph.callees[&Function(id)]
+                .iter()
+                .map(|callee| (*callee, summaries[callee].clone()))
+                .collect();
+        }
+    }
+
+    /// Runs the loop transformations on every function.
+    fn run_loop_passes(&mut self) {
+        use crate::passes;
+        self.update_callee_summaries();
+        let mut passes: [&mut dyn passes::Pass; 2] = [
+            &mut passes::induction_variable_simplification::InductionVariableSimplificationPass,
+            &mut passes::loop_unrolling::LoopUnrollingPass::default(),
+        ];
+
+        for (_, function) in self.functions.iter_mut() {
+            let mut ctx = crate::passes::FunctionContext::new(&self.types, function);
+            for pass in passes.iter_mut() {
+                pass.run(&mut ctx);
+            }
+        }
+    }
+
+    /// Runs the per-function passes on every function.
+    fn run_function_passes(&mut self) {
+        use crate::passes;
+        self.update_callee_summaries();
+        let mut passes: [&mut dyn passes::Pass; 9] = [
+            &mut passes::memory_optimization::MemoryOptimizationPass,
             &mut passes::constant_folding::ConstantFoldingPass,
+            &mut passes::instruction_combining::InstructionCombiningPass,
+            &mut passes::value_range_propagation::ValueRangePropagationPass,
+            &mut passes::jump_threading::JumpThreadingPass::default(),
             &mut passes::simplify_cfg::SimplifyCfgPass,
+            &mut passes::if_conversion::IfConversionPass::default(),
             &mut passes::dead_code_elimination::DeadCodeEliminationPass,
             &mut passes::remove_noops::RemoveNoopsPass,
         ];
@@ -85,6 +194,98 @@ impl Context {
         }
     }
 
+    /// Compiles the module to bytecode, to be run without generating native code.
+    ///
+    /// Fails for modules with float or struct values, or integers wider than 64 bits.
+    pub fn compile_bytecode(
+        &self,
+    ) -> Result<crate::backend::bytecode::Program, crate::backend::Unsupported> {
+        crate::backend::bytecode::Program::new(&self.types, &self.functions)
+    }
+
+    /// Writes the x86-64 assembly of the module to `path`, in GNU `as` syntax.
+    ///
+    /// Fails with [`std::io::ErrorKind::Unsupported`] for modules with values other
+    /// than pointers and integers of 1, 8, 16, 32 or 64 bits, see
+    /// [`crate::backend::Unsupported`].
+    pub fn emit_x86_64(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let assembly = crate::backend::x86_64::emit(
+            &self.types,
+            &self.functions,
+            &self.source_files,
+        )?;
+        std::fs::write(path, assembly)
+    }
+
+    /// Writes the x86-64 machine code of the module to `path`, as an ELF relocatable
+    /// object file for the system linker.
+    ///
+    /// Fails on the same modules as [`Context::emit_x86_64`].
+    pub fn emit_x86_64_object(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let object = crate::backend::x86_64::emit_object(
+            &self.types,
+            &self.functions,
+            &self.source_files,
+        )?;
+        std::fs::write(path, object)
+    }
+
+    /// Compiles the module to x86-64 machine code in executable memory, for its
+    /// functions to be called from Rust.
+    ///
+    /// Fails on the same modules as [`Context::emit_x86_64`].
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    pub fn jit(&self) -> std::io::Result<crate::backend::x86_64::JitModule> {
+        crate::backend::x86_64::JitModule::new(&self.types, &self.functions)
+    }
+
+    /// Writes the RV64IM assembly of the module to `path`, in GNU `as` syntax.
+    ///
+    /// Fails with [`std::io::ErrorKind::Unsupported`] for modules with values other
+    /// than pointers and integers of 1, 8, 16, 32 or 64 bits, see
+    /// [`crate::backend::Unsupported`].
+    pub fn emit_riscv64(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let assembly = crate::backend::riscv64::emit(&self.types, &self.functions)?;
+        std::fs::write(path, assembly)
+    }
+
+    /// Compiles the module to RV64IM machine code, to be run by the bundled simulator.
+    ///
+    /// Fails on the same modules as [`Context::emit_riscv64`].
+    pub fn compile_riscv64(
+        &self,
+    ) -> Result<crate::backend::riscv64::Program, crate::backend::Unsupported> {
+        crate::backend::riscv64::Program::new(&self.types, &self.functions)
+    }
+
+    /// Writes the module to `path` as a C99 translation unit.
+    ///
+    /// Fails with [`std::io::ErrorKind::Unsupported`] for modules with integers other
+    /// than 1, 8, 16, 32 or 64 bits, or floats other than 32 or 64 bits.
+    pub fn emit_c(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let source = crate::backend::c::emit(&self.types, &self.functions)?;
+        std::fs::write(path, source)
+    }
+
+    /// Writes the module to `path` as textual LLVM IR.
+    ///
+    /// Fails with [`std::io::ErrorKind::Unsupported`] for modules with floats other
+    /// than 32 or 64 bits.
+    pub fn emit_llvm(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let module = crate::backend::llvm::emit(&self.types, &self.functions)?;
+        std::fs::write(path, module)
+    }
+
+    /// Writes the module to `path` as a WebAssembly text module.
+    ///
+    /// Fails with [`std::io::ErrorKind::Unsupported`] for modules with integers of 33
+    /// to 63 or more than 64 bits, floats other than 32 or 64 bits, struct values, or
+    /// float remainders.
+    pub fn emit_wat(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let module = crate::backend::wasm::emit(&self.types, &self.functions)?;
+        std::fs::write(path, module)
+    }
+
     pub fn dump_ir(&self, path: &std::path::Path) -> std::io::Result<()> {
         use itertools::*;
         use std::io::prelude::*;
@@ -110,9 +311,14 @@ impl Context {
                 )
                 .unwrap();
                 let data = function.labels().get(label);
-                for instruction in data.instructions.iter() {
+                for (instruction, location) in data.instructions.iter_located() {
                     let instr = format_instruction(&formatter, instruction);
-                    write!(file, "<tr><td align=\"left\">{instr}</td></tr>").unwrap();
+                    write!(file, "<tr><td align=\"left\">{instr}").unwrap();
+                    if let Some(location) = location {
+                        let location = format_location(&self.source_files, location);
+                        write!(file, " <font color=\"gray\">{location}</font>").unwrap();
+                    }
+                    write!(file, "</td></tr>").unwrap();
                 }
                 writeln!(file, "</table> > ]").unwrap();
             });
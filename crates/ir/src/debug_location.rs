@@ -0,0 +1,42 @@
+//! Positions in the source a module was compiled from, carried by instructions down
+//! to the debug information of the native backends.
+
+use crate::handle_impl;
+
+handle_impl! {
+    /// A file of the source a module was compiled from.
+    impl SourceFile
+}
+
+/// File, line and column in the source of the module. Lines and columns are counted
+/// from 1, a column of 0 means the location covers the whole line.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DebugLocation {
+    pub file: SourceFile,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl DebugLocation {
+    /// Location of an instruction standing for both `self` and `other`, like a
+    /// `select` replacing stores from both sides of a branch. Keeps the line when
+    /// the columns differ, and gives up when the lines do.
+    pub fn merge(self, other: DebugLocation) -> Option<DebugLocation> {
+        if self == other {
+            Some(self)
+        } else if self.file == other.file && self.line == other.line {
+            Some(DebugLocation { column: 0, ..self })
+        } else {
+            None
+        }
+    }
+}
+
+/// Debug information of a function, besides the locations of its instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDebugInfo {
+    /// Where the function is declared.
+    pub location: DebugLocation,
+    /// Names of the parameters, in order.
+    pub parameter_names: Vec<String>,
+}
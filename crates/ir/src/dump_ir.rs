@@ -1,6 +1,7 @@
 use crate::{
     constant::ConstantValue,
     context::Context,
+    debug_location::DebugLocation,
     function::FunctionData,
     instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
     label::Label,
@@ -91,37 +92,7 @@ impl<'a> IrFormatter<'a> {
     }
 
     pub fn value_type(&self, value: Value) -> String {
-        let value_data = self.function_data.values().get(value);
-        let ty = value_data.ty();
-        let mut type_data = self.types.get(ty);
-        let mut type_kind = type_data.type_kind();
-
-        let mut indirection = 0;
-
-        while let crate::ty::TypeKind::Pointer { ty } = type_kind {
-            indirection += 1;
-            type_data = self.types.get(*ty);
-            type_kind = type_data.type_kind();
-        }
-
-        match type_kind {
-            crate::ty::TypeKind::Integer {
-                num_bits,
-                is_signed,
-            } => {
-                let ptr = "*".repeat(indirection);
-                if *is_signed {
-                    format!("{ptr}i{num_bits}")
-                } else {
-                    format!("{ptr}u{num_bits}")
-                }
-            }
-            crate::ty::TypeKind::Float { num_bits } => {
-                let ptr = "*".repeat(indirection);
-                format!("{ptr}f{num_bits}")
-            }
-            _ => panic!(),
-        }
+        self.ty(self.function_data.values().get(value).ty())
     }
 
     pub fn ty(&self, handle: Type) -> String {
@@ -152,6 +123,11 @@ impl<'a> IrFormatter<'a> {
                 let ptr = "*".repeat(indirection);
                 format!("{ptr}f{num_bits}")
             }
+            crate::ty::TypeKind::Struct { types } => {
+                let ptr = "*".repeat(indirection);
+                let fields = types.iter().map(|ty| self.ty(*ty)).collect::<Vec<_>>();
+                format!("{ptr}{{{}}}", fields.join(", "))
+            }
             _ => panic!(),
         }
     }
@@ -212,14 +188,51 @@ pub(crate) fn format_instruction(
             function,
             arguments,
             dst,
-        } => todo!(),
+            tail,
+        } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| formatter.value(*argument))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let call = if *tail { "tail call" } else { "call" };
+            if let Some(dst) = dst {
+                format!(
+                    "let {}: {} = {call} function_{}({arguments})",
+                    formatter.value(*dst),
+                    formatter.value_type(*dst),
+                    function.id(),
+                )
+            } else {
+                format!("{call} function_{}({arguments})", function.id())
+            }
+        }
         Instruction::Cast {
             cast_op,
-            to_type,
             dst,
             value,
-        } => todo!(),
-        Instruction::GetElementPtr { dst, ptr, index } => todo!(),
+            ..
+        } => {
+            format!(
+                "let {}: {} = {}.{} {}",
+                formatter.value(*dst),
+                formatter.value_type(*dst),
+                cast_op,
+                formatter.value_type(*value),
+                formatter.value(*value)
+            )
+        }
+        Instruction::GetElementPtr { dst, ptr, index } => {
+            format!(
+                "let {}: {} = get_element_ptr.{} {}, {}",
+                formatter.value(*dst),
+                formatter.value_type(*dst),
+                formatter.value_type(*ptr),
+                formatter.value(*ptr),
+                formatter.value(*index),
+            )
+        }
         Instruction::IntCompare {
             pred,
             dst,
@@ -263,8 +276,8 @@ pub(crate) fn format_instruction(
                 formatter.value(*dst),
                 formatter.value_type(*dst),
                 formatter.value(*condition),
-                on_true,
-                on_false
+                formatter.value(*on_true),
+                formatter.value(*on_false)
             )
         }
         Instruction::StackAlloc { dst, ty, size } => {
@@ -287,3 +300,16 @@ pub(crate) fn format_instruction(
         Instruction::Nop => format!("nop"),
     }
 }
+
+/// Formats `location` as `file:line:column`, leaving out a column of 0.
+pub(crate) fn format_location(
+    source_files: &[String],
+    location: DebugLocation,
+) -> String {
+    let DebugLocation { file, line, column } = location;
+    let file = &source_files[file.id()];
+    match column {
+        0 => format!("{file}:{line}"),
+        column => format!("{file}:{line}:{column}"),
+    }
+}
@@ -1,14 +1,21 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::{
+    call_graph::CallGraph,
     constant::{Constant, ConstantValue, Constants},
+    debug_location::FunctionDebugInfo,
+    function_summary::{FunctionSummary, ParameterEffects},
     handle_impl,
+    induction_variable_analysis::InductionVariableAnalysis,
     instruction::Instruction,
     label::{Label, Labels},
+    liveness_analysis::LivenessAnalysis,
     location::Location,
+    loop_analysis::LoopAnalysis,
     pointer_analysis::PointerAnalysis,
     ty::{Type, Types},
     value::{Value, Values},
+    value_range_analysis::ValueRangeAnalysis,
 };
 
 handle_impl! {
@@ -16,6 +23,34 @@ handle_impl! {
     impl Function
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////
+// Inline
+
+/// Controls whether calls to a function may be inlined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Inline {
+    /// The inliner decides based on its cost model.
+    #[default]
+    Auto,
+    /// Always inline, regardless of the cost. Recursive calls are still never inlined.
+    Always,
+    /// Never inline.
+    Never,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////
+// Linkage
+
+/// Controls whether a function may be called from outside of the module.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Linkage {
+    /// Callable from outside of the module, with any arguments.
+    #[default]
+    External,
+    /// Only called from within the module. Removed when no external function reaches it.
+    Internal,
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////
 // FunctionDefinition
 
@@ -25,6 +60,10 @@ pub(crate) struct FunctionDefinition {
     pub return_type: Option<Type>,
     /// Variables in a method definition.
     pub parameter_types: Vec<Type>,
+    pub inline: Inline,
+    pub linkage: Linkage,
+    /// Described in the debug information of the native backends when set.
+    pub debug_info: Option<FunctionDebugInfo>,
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////
@@ -38,6 +77,12 @@ pub(crate) struct FunctionData {
     parameters: Vec<Value>,
     pub constants: Constants,
     pub value_to_constant: HashMap<Value, Constant>,
+    /// Summaries of the functions called, refreshed by the [`Context`] before running
+    /// the function passes. Calls to functions missing here may do anything with
+    /// their pointer arguments.
+    ///
+    /// [`Context`]: crate::context::Context
+    pub callee_summaries: HashMap<Function, FunctionSummary>,
 }
 
 impl FunctionData {
@@ -56,6 +101,9 @@ impl FunctionData {
             name: name.to_string(),
             return_type,
             parameter_types,
+            inline: Inline::default(),
+            linkage: Linkage::default(),
+            debug_info: None,
         };
 
         FunctionData {
@@ -65,6 +113,7 @@ impl FunctionData {
             parameters,
             constants,
             value_to_constant: HashMap::new(),
+            callee_summaries: HashMap::new(),
         }
     }
 
@@ -78,11 +127,51 @@ impl FunctionData {
         value
     }
 
+    /// Returns the constant bound to `value`, if any.
+    pub fn constant(&self, value: Value) -> Option<ConstantValue> {
+        self.value_to_constant
+            .get(&value)
+            .map(|constant| self.constants.get(*constant).value)
+    }
+
+    /// Replaces every read of `from` with `to` in the whole function.
+    pub fn replace_uses(&mut self, from: Value, to: Value) {
+        for (_, data) in self.labels.iter_mut() {
+            for instr in data.instructions.iter_mut() {
+                instr.replace_reads(from, to);
+            }
+        }
+    }
+
+    /// Maps every value to the location of the instruction that creates it.
+    /// Parameters and constants are not created by any instruction.
+    pub fn value_creators(&self) -> HashMap<Value, Location> {
+        let mut creators = HashMap::new();
+        for (label, data) in self.labels.iter() {
+            for (i, instr) in data.instructions.iter().enumerate() {
+                if let Some(value) = instr.creates() {
+                    let location = Location {
+                        label: *label,
+                        instruction: i as u32,
+                    };
+                    creators.insert(value, location);
+                }
+            }
+        }
+
+        creators
+    }
+
     ///
     pub fn definition(&self) -> &FunctionDefinition {
         &self.definition
     }
 
+    /// Mutable access to the definition, e.g. to change its [`Inline`] attribute.
+    pub fn definition_mut(&mut self) -> &mut FunctionDefinition {
+        &mut self.definition
+    }
+
     ///
     pub fn values(&self) -> &Values {
         &self.values
@@ -257,14 +346,48 @@ impl FunctionData {
     pub fn pointer_analysis(&self, types: &Types) -> PointerAnalysis {
         PointerAnalysis::new(types, self, &self.labels)
     }
+
+    /// What the call to `callee` may do with its argument `index`.
+    pub fn parameter_effects(&self, callee: Function, index: usize) -> ParameterEffects {
+        self.callee_summaries
+            .get(&callee)
+            .and_then(|summary| summary.parameters.get(index))
+            .copied()
+            .unwrap_or_else(ParameterEffects::unknown)
+    }
+
+    /// Values live at the boundaries of every block.
+    pub fn liveness(&self) -> LivenessAnalysis {
+        LivenessAnalysis::new(self)
+    }
+
+    ///
+    pub fn loop_analysis(&self) -> LoopAnalysis {
+        LoopAnalysis::new(&self.labels)
+    }
+
+    ///
+    pub fn induction_variables(
+        &self,
+        types: &Types,
+        loop_analysis: &LoopAnalysis,
+    ) -> InductionVariableAnalysis {
+        InductionVariableAnalysis::new(types, self, loop_analysis)
+    }
+
+    /// Ranges and known bits of the integer values.
+    pub fn value_ranges(&self, types: &Types) -> ValueRangeAnalysis {
+        ValueRangeAnalysis::new(types, self)
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////
 // Functions
 
-///
+/// Functions of a module. A removed function keeps its handle, so the handles of
+/// the others stay valid.
 pub(crate) struct Functions {
-    functions: Vec<FunctionData>,
+    functions: Vec<Option<FunctionData>>,
 }
 
 impl Functions {
@@ -284,36 +407,46 @@ impl Functions {
     ) -> Function {
         let index = self.functions.len();
         self.functions
-            .push(FunctionData::new(name, return_type, parameter_types));
+            .push(Some(FunctionData::new(name, return_type, parameter_types)));
 
         Function(index.try_into().unwrap())
     }
 
+    /// Removes `handle`, which must not be called anymore.
+    pub fn remove(&mut self, handle: Function) {
+        self.functions[handle.id()] = None;
+    }
+
     ///
     pub fn get(&self, handle: Function) -> &FunctionData {
         let index = handle.id();
-        self.functions.get(index).unwrap()
+        self.functions.get(index).unwrap().as_ref().unwrap()
     }
 
     ///
     pub fn get_mut(&mut self, handle: Function) -> &mut FunctionData {
         let index = handle.id();
-        self.functions.get_mut(index).unwrap()
+        self.functions.get_mut(index).unwrap().as_mut().unwrap()
     }
 
-    ///
+    /// Iterates over the functions that were not removed.
     pub fn iter(&self) -> impl Iterator<Item = (u32, &FunctionData)> {
         self.functions
             .iter()
             .enumerate()
-            .map(|(id, label)| (id as u32, label))
+            .filter_map(|(id, function)| Some((id as u32, function.as_ref()?)))
     }
 
-    ///
+    /// Iterates over the functions that were not removed.
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut FunctionData)> {
         self.functions
             .iter_mut()
             .enumerate()
-            .map(|(id, label)| (id as u32, label))
+            .filter_map(|(id, function)| Some((id as u32, function.as_mut()?)))
+    }
+
+    ///
+    pub fn call_graph(&self) -> CallGraph {
+        CallGraph::new(self)
     }
 }
@@ -1,5 +1,8 @@
+use std::collections::HashMap;
+
 use crate::{
     constant::ConstantValue,
+    debug_location::DebugLocation,
     function::{Function, FunctionData, Functions},
     instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
     label::{Label, LabelData},
@@ -67,16 +70,26 @@ macro_rules! impl_int_compare {
 pub struct FunctionBuilder<'a> {
     types: &'a mut Types,
     function: &'a mut FunctionData,
+    /// Return types of all functions in the context.
+    return_types: HashMap<Function, Option<Type>>,
     current_label: Option<Label>,
+    /// Location given to the instructions inserted from now on.
+    current_location: Option<DebugLocation>,
 }
 
 impl<'a> FunctionBuilder<'a> {
     ///
-    pub(crate) fn new(types: &'a mut Types, function: &'a mut FunctionData) -> Self {
+    pub(crate) fn new(
+        types: &'a mut Types,
+        function: &'a mut FunctionData,
+        return_types: HashMap<Function, Option<Type>>,
+    ) -> Self {
         Self {
             types,
             function,
+            return_types,
             current_label: None,
+            current_location: None,
         }
     }
 
@@ -110,6 +123,26 @@ impl<'a> FunctionBuilder<'a> {
         self.current_label = Some(label);
     }
 
+    /// Sets the source location of the instructions inserted from now on, or clears it
+    /// with `None`.
+    pub fn set_location(&mut self, location: Option<DebugLocation>) {
+        self.current_location = location;
+    }
+
+    /// The source location given to the instructions inserted from now on.
+    pub fn location(&self) -> Option<DebugLocation> {
+        self.current_location
+    }
+
+    /// Returns `true` if the current label already ends with a branch or a return.
+    pub fn is_terminated(&self) -> bool {
+        let label = self.function.labels().get(self.current_label.unwrap());
+        label
+            .instructions
+            .last()
+            .is_some_and(|instr| instr.targets().is_some())
+    }
+
     fn label(&mut self) -> &mut LabelData {
         self.function
             .labels_mut()
@@ -122,7 +155,10 @@ impl<'a> FunctionBuilder<'a> {
 
     ///
     fn insert_instruction(&mut self, instruction: Instruction) {
-        self.label().instructions.push(instruction);
+        let location = self.current_location;
+        self.label()
+            .instructions
+            .push_located(instruction, location);
     }
 
     ///
@@ -192,13 +228,23 @@ impl<'a> FunctionBuilder<'a> {
         });
     }
 
-    /// #TODO:
+    /// Returns the value holding the result, or `None` if `function` returns nothing.
     pub fn call(
         &mut self,
         function: Function, //
         arguments: &[Value],
-    ) -> Option<Vec<Value>> {
-        todo!()
+    ) -> Option<Value> {
+        let arguments = arguments.to_vec();
+        let dst = self.return_types[&function].map(|ty| self.values().alloc(ty));
+
+        self.insert_instruction(Instruction::Call {
+            function,
+            arguments,
+            dst,
+            tail: false,
+        });
+
+        dst
     }
 
     ///
@@ -211,7 +257,8 @@ impl<'a> FunctionBuilder<'a> {
         })
     }
 
-    ///
+    /// Offsets `ptr` by `index` elements. If `ptr` points to a struct, points to its
+    /// field `index` instead, which must be a constant.
     pub fn get_element_ptr(
         &mut self,
         ptr: Value, //
@@ -220,22 +267,28 @@ impl<'a> FunctionBuilder<'a> {
         let ptr_type = self.values().get(ptr).ty();
         let index_type = self.values().get(index).ty();
         assert!(self.types.is_pointer(ptr_type));
+        assert!(self.types.is_arithmetic(index_type));
 
         let ty = self.types.strip_pointer(ptr_type).unwrap();
 
-        if self.types.is_struct(ty) {
-            assert!(self.types.is_arithmetic(index_type));
-            // #TODO: The `index` should be a constant
-            todo!()
-        } else if self.types.is_pointer(ty) {
-            self.with_output(ptr_type, |dst| Instruction::GetElementPtr {
-                dst,
-                ptr,
-                index,
-            })
+        let dst_type = if let TypeKind::Struct { types } = self.types.get(ty).type_kind()
+        {
+            let field = self
+                .function
+                .constant(index)
+                .and_then(|constant| constant.integer())
+                .expect("struct fields are selected by a constant index");
+            let field = types[field as usize];
+            self.types.add_pointer(field)
         } else {
-            panic!("Invalid `ptr` type")
-        }
+            ptr_type
+        };
+
+        self.with_output(dst_type, |dst| Instruction::GetElementPtr {
+            dst,
+            ptr,
+            index,
+        })
     }
 
     ///
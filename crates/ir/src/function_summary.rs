@@ -0,0 +1,196 @@
+use crate::{
+    function::{Function, FunctionData, Functions},
+    instruction::Instruction,
+    location::Location,
+    ty::Types,
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+/// What a function may do with a pointer parameter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ParameterEffects {
+    /// The memory it points into may be read.
+    pub read: bool,
+    /// The memory it points into may be written.
+    pub written: bool,
+    /// The pointer may outlive the call, e.g. stored to memory or turned into an integer.
+    pub captured: bool,
+    /// The pointer, or a pointer derived from it, may be returned.
+    pub returned: bool,
+}
+
+impl ParameterEffects {
+    /// Anything may happen to the pointer.
+    pub fn unknown() -> Self {
+        ParameterEffects {
+            read: true,
+            written: true,
+            captured: true,
+            returned: true,
+        }
+    }
+
+    /// Returns `true` if the pointer may still be used once the call returned.
+    pub fn escapes(&self) -> bool {
+        self.captured || self.returned
+    }
+
+    fn merge(&mut self, other: ParameterEffects) {
+        self.read |= other.read;
+        self.written |= other.written;
+        self.captured |= other.captured;
+        self.returned |= other.returned;
+    }
+}
+
+/// Effects of a function on its pointer parameters, so callers passing pointers to
+/// their stack slots don't have to assume the worst.
+///
+/// ```text
+/// fn get(p: *u32) -> u32 { return *p; }     // p: read
+/// fn set(p: *u32) { *p = 1; }               // p: written
+/// fn keep(p: *u32, q: **u32) { *q = p; }    // p: captured, q: written
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FunctionSummary {
+    /// Effects on every parameter, in order. Parameters that are not pointers have none.
+    pub parameters: Vec<ParameterEffects>,
+}
+
+impl FunctionSummary {
+    /// Computes the summaries of every function, callees before their callers.
+    ///
+    /// Summaries start empty and only grow, so recursive functions are iterated until
+    /// they stop changing.
+    pub fn compute_all(types: &Types, functions: &Functions) -> HashMap<Function, Self> {
+        let order = functions.call_graph().bottom_up_order();
+        let mut summaries: HashMap<Function, Self> = order
+            .iter()
+            .map(|function| {
+                let count = functions.get(*function).parameters().len();
+                let summary = FunctionSummary {
+                    parameters: vec![ParameterEffects::default(); count],
+                };
+                (*function, summary)
+            })
+            .collect();
+
+        loop {
+            let mut changed = false;
+            for function in order.iter() {
+                let summary = Self::compute(types, functions.get(*function), &summaries);
+                if summaries[function] != summary {
+                    summaries.insert(*function, summary);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        summaries
+    }
+
+    /// Summarizes `function`, using `summaries` for the calls it makes.
+    fn compute(
+        types: &Types,
+        function: &FunctionData,
+        summaries: &HashMap<Function, Self>,
+    ) -> Self {
+        let users = function.variable_users();
+        let parameters = function
+            .parameters()
+            .iter()
+            .map(|parameter| {
+                if types.is_pointer(function.values().get(*parameter).ty()) {
+                    Self::parameter_effects(
+                        types, function, &users, summaries, *parameter,
+                    )
+                } else {
+                    ParameterEffects::default()
+                }
+            })
+            .collect();
+
+        FunctionSummary { parameters }
+    }
+
+    /// Follows `parameter`, and every pointer derived from it, through its users.
+    fn parameter_effects(
+        types: &Types,
+        function: &FunctionData,
+        users: &HashMap<Value, HashSet<Location>>,
+        summaries: &HashMap<Function, Self>,
+        parameter: Value,
+    ) -> ParameterEffects {
+        let mut effects = ParameterEffects::default();
+        let mut visited = HashSet::new();
+        let mut stack = vec![parameter];
+        while let Some(pointer) = stack.pop() {
+            if !visited.insert(pointer) {
+                continue;
+            }
+
+            for location in users.get(&pointer).into_iter().flatten() {
+                match function.instruction(location) {
+                    Instruction::Load { .. } => effects.read = true,
+                    Instruction::Store { ptr, value } => {
+                        if *ptr == pointer {
+                            effects.written = true;
+                        }
+                        if *value == pointer {
+                            effects.captured = true;
+                        }
+                    }
+                    Instruction::Call {
+                        function: callee,
+                        arguments,
+                        dst,
+                        ..
+                    } => {
+                        for (index, argument) in arguments.iter().enumerate() {
+                            if *argument != pointer {
+                                continue;
+                            }
+
+                            let callee_effects = summaries
+                                .get(callee)
+                                .and_then(|summary| summary.parameters.get(index))
+                                .copied()
+                                .unwrap_or_else(ParameterEffects::unknown);
+                            effects.merge(ParameterEffects {
+                                returned: false,
+                                ..callee_effects
+                            });
+                            if let (true, Some(dst)) = (callee_effects.returned, dst) {
+                                stack.push(*dst);
+                            }
+                        }
+                    }
+                    Instruction::Return { .. } => effects.returned = true,
+                    Instruction::GetElementPtr { dst, ptr, .. } if *ptr == pointer => {
+                        stack.push(*dst);
+                    }
+                    Instruction::Select { dst, condition, .. }
+                        if *condition != pointer =>
+                    {
+                        stack.push(*dst);
+                    }
+                    Instruction::Cast { dst, .. }
+                        if types.is_pointer(function.values().get(*dst).ty()) =>
+                    {
+                        stack.push(*dst);
+                    }
+                    Instruction::IntCompare { .. } => {}
+                    // Turned into an integer, which may be stored anywhere.
+                    _ => effects.captured = true,
+                }
+            }
+        }
+
+        effects
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{
+    function::FunctionData,
+    instruction::{BinaryOp, Instruction, IntCompareOp},
+    label::Label,
+    location::Location,
+    loop_analysis::{Loop, LoopAnalysis},
+    ty::{Type, Types},
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Loops running more iterations than this are not simulated to find their trip count.
+const MAX_TRIP_COUNT: u64 = 1 << 16;
+
+/// A variable changed by a constant step once per iteration.
+///
+/// Variables live in stack slots, so an induction variable is a slot stored once
+/// in the loop, with a value computed from a load of the same slot:
+/// ```text
+/// header:
+///     let v3: u32 = load.*u32 v2      // `value`, the variable at the start of the iteration
+///     ...
+///     let v5: u32 = add.u32 v3, 1_u32
+///     store.*u32 v2, v5               // `store`
+///     branch header
+/// ```
+#[derive(Debug, Clone)]
+pub(crate) struct BasicInductionVariable {
+    /// Stack slot holding the variable.
+    pub slot: Value,
+    /// Load of the slot holding the value at the start of the iteration.
+    pub value: Value,
+    /// The only store to the slot in the loop.
+    pub store: Location,
+    /// Value stored to the slot before entering the loop, if it is known.
+    pub init: Option<Value>,
+    /// Either [`BinaryOp::Add`] or [`BinaryOp::Sub`].
+    pub op: BinaryOp,
+    /// Constant added to (or subtracted from) the variable every iteration.
+    pub step: u64,
+    pub ty: Type,
+}
+
+/// A value computed from a basic induction variable and a loop invariant operand,
+/// e.g. `i * b`.
+#[derive(Debug, Clone)]
+pub(crate) struct DerivedInductionVariable {
+    pub value: Value,
+    /// Index into [`LoopInductionVariables::basic`].
+    pub basic: usize,
+    pub op: BinaryOp,
+    /// Loop invariant operand.
+    pub operand: Value,
+}
+
+/// A loop whose number of iterations is known at compile time.
+#[derive(Debug, Clone)]
+pub(crate) struct TripCount {
+    /// Location of the compare deciding whether the loop continues.
+    pub compare: Location,
+    /// Number of times the body runs.
+    pub count: u64,
+    /// Value of the induction variable before the first iteration.
+    pub init: u64,
+    pub op: BinaryOp,
+    pub step: u64,
+    pub num_bits: u32,
+    pub ty: Type,
+    /// Successor of the header inside the loop.
+    pub body: Label,
+    /// Successor of the header outside the loop.
+    pub exit: Label,
+    /// Whether the loop continues when the compare is `true`.
+    pub continue_on_true: bool,
+}
+
+impl TripCount {
+    /// Value of the induction variable after `iterations` iterations.
+    pub fn value_after(&self, iterations: u64) -> u64 {
+        let mut value = self.init;
+        for _ in 0..iterations {
+            value = self.next(value);
+        }
+        value
+    }
+
+    fn next(&self, value: u64) -> u64 {
+        let value = match self.op {
+            BinaryOp::Add => value.wrapping_add(self.step),
+            BinaryOp::Sub => value.wrapping_sub(self.step),
+            _ => unreachable!(),
+        };
+        value & mask(self.num_bits)
+    }
+}
+
+/// Induction variables of a single loop.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LoopInductionVariables {
+    pub basic: Vec<BasicInductionVariable>,
+    pub derived: Vec<DerivedInductionVariable>,
+    pub trip_count: Option<TripCount>,
+}
+
+/// Induction variables and trip counts of every loop in a function.
+#[derive(Debug)]
+pub(crate) struct InductionVariableAnalysis {
+    /// Indexed like [`LoopAnalysis::loops`].
+    pub loops: Vec<LoopInductionVariables>,
+}
+
+impl InductionVariableAnalysis {
+    /// Finds induction variables of every loop in `loop_analysis`.
+    pub fn new(
+        types: &Types,
+        function: &FunctionData,
+        loop_analysis: &LoopAnalysis,
+    ) -> Self {
+        let creators = function.value_creators();
+        let users = function.variable_users();
+
+        let loops = loop_analysis
+            .loops
+            .iter()
+            .map(|l| {
+                let basic = Self::basic_induction_variables(
+                    types,
+                    function,
+                    loop_analysis,
+                    l,
+                    &creators,
+                    &users,
+                );
+                let derived =
+                    Self::derived_induction_variables(function, l, &basic, &creators);
+                let trip_count = Self::trip_count(types, function, l, &basic, &creators);
+
+                LoopInductionVariables {
+                    basic,
+                    derived,
+                    trip_count,
+                }
+            })
+            .collect();
+
+        InductionVariableAnalysis { loops }
+    }
+
+    /// Returns `true` if `value` has the same value in every iteration of `l`.
+    pub fn is_loop_invariant(
+        l: &Loop,
+        value: Value,
+        creators: &HashMap<Value, Location>,
+    ) -> bool {
+        creators
+            .get(&value)
+            .map_or(true, |location| !l.blocks.contains(&location.label))
+    }
+
+    /// Slots stored once per iteration with their own value plus a constant.
+    fn basic_induction_variables(
+        types: &Types,
+        function: &FunctionData,
+        loop_analysis: &LoopAnalysis,
+        l: &Loop,
+        creators: &HashMap<Value, Location>,
+        users: &HashMap<Value, HashSet<Location>>,
+    ) -> Vec<BasicInductionVariable> {
+        // Blocks of nested loops run several times per iteration.
+        let nested = loop_analysis
+            .loops
+            .iter()
+            .filter(|other| other.header != l.header && l.blocks.contains(&other.header))
+            .flat_map(|other| other.blocks.iter().copied())
+            .collect::<HashSet<_>>();
+
+        let mut slots = l
+            .blocks
+            .iter()
+            .flat_map(|label| function.labels().get(*label).instructions.iter())
+            .filter_map(|instr| match instr {
+                Instruction::Store { ptr, .. } => Some(*ptr),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        slots.sort();
+        slots.dedup();
+
+        let mut basic = Vec::new();
+        for slot in slots {
+            let Some(creator) = creators.get(&slot) else {
+                continue;
+            };
+            if !matches!(
+                function.instruction(creator),
+                Instruction::StackAlloc { size: 1, .. }
+            ) {
+                continue;
+            }
+
+            // The slot must only be loaded and stored, and stored once in the loop.
+            let mut stores = Vec::new();
+            let mut only_loads_and_stores = true;
+            for user in users[&slot].iter() {
+                match function.instruction(user) {
+                    Instruction::Load { .. } => {}
+                    Instruction::Store { ptr, value }
+                        if *ptr == slot && *value != slot =>
+                    {
+                        if l.blocks.contains(&user.label) {
+                            stores.push((*user, *value));
+                        }
+                    }
+                    _ => only_loads_and_stores = false,
+                }
+            }
+            let [(store, stored)] = stores[..] else {
+                continue;
+            };
+            if !only_loads_and_stores
+                || nested.contains(&store.label)
+                || !l
+                    .latches
+                    .iter()
+                    .all(|latch| loop_analysis.dominates(store.label, *latch))
+            {
+                continue;
+            }
+
+            let Some(Instruction::ArithmeticBinary { lhs, op, rhs, .. }) = creators
+                .get(&stored)
+                .map(|location| function.instruction(location))
+            else {
+                continue;
+            };
+            if !matches!(op, BinaryOp::Add | BinaryOp::Sub) {
+                continue;
+            }
+            let Some(step) = function.constant(*rhs).and_then(|step| step.integer())
+            else {
+                continue;
+            };
+
+            // The value at the start of the iteration, loaded before the store.
+            let Some(load) = creators.get(lhs) else {
+                continue;
+            };
+            let is_load_of_slot = matches!(function.instruction(load), Instruction::Load { ptr, .. } if *ptr == slot);
+            let is_before_store = if load.label == store.label {
+                load.instruction < store.instruction
+            } else {
+                loop_analysis.dominates(load.label, store.label)
+            };
+            if !is_load_of_slot || !l.blocks.contains(&load.label) || !is_before_store {
+                continue;
+            }
+
+            let ty = function.values().get(*lhs).ty();
+            if types.integer(ty).is_none() {
+                continue;
+            }
+
+            let cfg = function.labels().cfg();
+            let init = match l.entering(&cfg)[..] {
+                [preheader] => incoming_store(function, preheader, slot),
+                _ => None,
+            };
+
+            basic.push(BasicInductionVariable {
+                slot,
+                value: *lhs,
+                store,
+                init,
+                op: *op,
+                step,
+                ty,
+            });
+        }
+
+        basic
+    }
+
+    /// Arithmetic on a basic induction variable and a loop invariant.
+    fn derived_induction_variables(
+        function: &FunctionData,
+        l: &Loop,
+        basic: &[BasicInductionVariable],
+        creators: &HashMap<Value, Location>,
+    ) -> Vec<DerivedInductionVariable> {
+        let mut blocks = l.blocks.iter().copied().collect::<Vec<_>>();
+        blocks.sort();
+
+        let mut derived = Vec::new();
+        for label in blocks {
+            for instr in function.labels().get(label).instructions.iter() {
+                let Instruction::ArithmeticBinary { dst, lhs, op, rhs } = *instr else {
+                    continue;
+                };
+                if !matches!(
+                    op,
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Shl
+                ) {
+                    continue;
+                }
+
+                let find = |value: Value| basic.iter().position(|iv| iv.value == value);
+                let commutative = matches!(op, BinaryOp::Add | BinaryOp::Mul);
+                let (index, operand) = match (find(lhs), find(rhs)) {
+                    (Some(index), _) => (index, rhs),
+                    (None, Some(index)) if commutative => (index, lhs),
+                    _ => continue,
+                };
+
+                if Self::is_loop_invariant(l, operand, creators) {
+                    derived.push(DerivedInductionVariable {
+                        value: dst,
+                        basic: index,
+                        op,
+                        operand,
+                    });
+                }
+            }
+        }
+
+        derived
+    }
+
+    /// Computes how many times the loop runs, when it is controlled by a basic
+    /// induction variable with a known start compared against a constant in the header.
+    fn trip_count(
+        types: &Types,
+        function: &FunctionData,
+        l: &Loop,
+        basic: &[BasicInductionVariable],
+        creators: &HashMap<Value, Location>,
+    ) -> Option<TripCount> {
+        // The header must be the only way out of the loop.
+        if l.exits.len() != 1 || l.exits[0].0 != l.header {
+            return None;
+        }
+
+        let Instruction::BranchConditional {
+            condition,
+            on_true,
+            on_false,
+        } = *function.last_instruction(l.header)
+        else {
+            return None;
+        };
+        let (body, exit, continue_on_true) =
+            match (l.blocks.contains(&on_true), l.blocks.contains(&on_false)) {
+                (true, false) => (on_true, on_false, true),
+                (false, true) => (on_false, on_true, false),
+                _ => return None,
+            };
+
+        let compare = *creators.get(&condition)?;
+        let Instruction::IntCompare { pred, lhs, rhs, .. } =
+            *function.instruction(&compare)
+        else {
+            return None;
+        };
+        let bound = function.constant(rhs)?.integer()?;
+
+        let iv = basic.iter().find(|iv| iv.value == lhs)?;
+        if compare.label != l.header || creators[&iv.value].label != l.header {
+            return None;
+        }
+        let init = function.constant(iv.init?)?.integer()?;
+        let (num_bits, is_signed) = types.integer(iv.ty)?;
+
+        let mut trip_count = TripCount {
+            compare,
+            count: 0,
+            init,
+            op: iv.op,
+            step: iv.step,
+            num_bits,
+            ty: iv.ty,
+            body,
+            exit,
+            continue_on_true,
+        };
+
+        let mut value = init;
+        while evaluate(pred, value, bound, num_bits, is_signed) == continue_on_true {
+            trip_count.count += 1;
+            if trip_count.count > MAX_TRIP_COUNT {
+                return None;
+            }
+            value = trip_count.next(value);
+        }
+
+        Some(trip_count)
+    }
+}
+
+/// Finds the value last stored to `slot` before leaving `label`,
+/// following blocks with a single predecessor.
+fn incoming_store(
+    function: &FunctionData,
+    mut label: Label,
+    slot: Value,
+) -> Option<Value> {
+    let cfg = function.labels().cfg();
+    let mut visited = HashSet::new();
+
+    while visited.insert(label) {
+        let instructions = &function.labels().get(label).instructions;
+        for instr in instructions.iter().rev() {
+            if let Instruction::Store { ptr, value } = instr {
+                if *ptr == slot {
+                    return Some(*value);
+                }
+            }
+        }
+
+        let [predecessor] = cfg
+            .incoming(label)
+            .map(|(from, _, _)| from)
+            .collect::<Vec<_>>()[..]
+        else {
+            return None;
+        };
+        label = predecessor;
+    }
+
+    None
+}
+
+fn mask(num_bits: u32) -> u64 {
+    if num_bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << num_bits) - 1
+    }
+}
+
+/// Evaluates an integer compare on zero-extended constants.
+pub(crate) fn evaluate(
+    pred: IntCompareOp,
+    lhs: u64,
+    rhs: u64,
+    num_bits: u32,
+    is_signed: bool,
+) -> bool {
+    let extend = |value: u64| -> i128 {
+        if is_signed && num_bits < 64 && value >> (num_bits - 1) & 1 == 1 {
+            (value | !mask(num_bits)) as i64 as i128
+        } else if is_signed {
+            value as i64 as i128
+        } else {
+            value as i128
+        }
+    };
+    let (lhs, rhs) = (extend(lhs), extend(rhs));
+
+    match pred {
+        IntCompareOp::Equal => lhs == rhs,
+        IntCompareOp::NotEqual => lhs != rhs,
+        IntCompareOp::GreaterThan => lhs > rhs,
+        IntCompareOp::GreaterThanOrEqual => lhs >= rhs,
+        IntCompareOp::LessThan => lhs < rhs,
+        IntCompareOp::LessThanOrEqual => lhs <= rhs,
+    }
+}
@@ -3,7 +3,7 @@ use smallvec::{smallvec, SmallVec};
 use crate::{function::Function, label::Label, ty::Type, value::Value};
 
 ///
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum BinaryOp {
     Add,
     Sub,
@@ -21,14 +21,14 @@ pub(crate) enum BinaryOp {
 }
 
 ///
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum UnaryOp {
     Neg,
     Not,
 }
 
 ///
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum CastOp {
     BitCast,
     SignExtend,
@@ -37,7 +37,7 @@ pub(crate) enum CastOp {
 }
 
 ///
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum IntCompareOp {
     Equal,
     NotEqual,
@@ -49,6 +49,32 @@ pub(crate) enum IntCompareOp {
     LessThanOrEqual,
 }
 
+impl IntCompareOp {
+    /// The predicate to use when the operands are swapped.
+    pub fn swapped(self) -> Self {
+        match self {
+            Self::Equal => Self::Equal,
+            Self::NotEqual => Self::NotEqual,
+            Self::GreaterThan => Self::LessThan,
+            Self::GreaterThanOrEqual => Self::LessThanOrEqual,
+            Self::LessThan => Self::GreaterThan,
+            Self::LessThanOrEqual => Self::GreaterThanOrEqual,
+        }
+    }
+
+    /// The predicate giving the opposite result on the same operands.
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Equal => Self::NotEqual,
+            Self::NotEqual => Self::Equal,
+            Self::GreaterThan => Self::LessThanOrEqual,
+            Self::GreaterThanOrEqual => Self::LessThan,
+            Self::LessThan => Self::GreaterThanOrEqual,
+            Self::LessThanOrEqual => Self::GreaterThan,
+        }
+    }
+}
+
 ///
 #[derive(Debug, Clone)]
 pub(crate) enum Instruction {
@@ -78,6 +104,9 @@ pub(crate) enum Instruction {
         arguments: Vec<Value>,
         /// Values where the function return values are going to be stored.
         dst: Option<Value>,
+        /// The result is returned right away, so the call can reuse the caller stack
+        /// frame. Set by the tail call elimination pass.
+        tail: bool,
     },
     Cast {
         cast_op: CastOp,
@@ -155,6 +184,37 @@ impl Instruction {
         }
     }
 
+    /// Mutable counterpart of [`Instruction::targets`].
+    pub fn targets_mut(&mut self) -> SmallVec<[&mut Label; 2]> {
+        match self {
+            Instruction::Branch { target } => smallvec![target],
+            Instruction::BranchConditional {
+                on_true, on_false, ..
+            } => smallvec![on_true, on_false],
+            _ => smallvec![],
+        }
+    }
+
+    /// Mutable counterpart of [`Instruction::creates`].
+    pub fn creates_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            Instruction::ArithmeticBinary { dst, .. } => Some(dst),
+            Instruction::ArithmeticUnary { dst, .. } => Some(dst),
+            Instruction::Branch { .. } => None,
+            Instruction::BranchConditional { .. } => None,
+            Instruction::Call { dst, .. } => dst.as_mut(),
+            Instruction::Cast { dst, .. } => Some(dst),
+            Instruction::GetElementPtr { dst, .. } => Some(dst),
+            Instruction::IntCompare { dst, .. } => Some(dst),
+            Instruction::Load { dst, .. } => Some(dst),
+            Instruction::Return { .. } => None,
+            Instruction::Select { dst, .. } => Some(dst),
+            Instruction::StackAlloc { dst, .. } => Some(dst),
+            Instruction::Store { .. } => None,
+            Instruction::Nop => None,
+        }
+    }
+
     ///
     pub fn reads(&self) -> Option<SmallVec<[Value; 4]>> {
         match self {
@@ -189,4 +249,43 @@ impl Instruction {
             Instruction::Nop => None,
         }
     }
+
+    /// Mutable counterpart of [`Instruction::reads`].
+    pub fn reads_mut(&mut self) -> SmallVec<[&mut Value; 4]> {
+        match self {
+            Instruction::ArithmeticBinary { lhs, rhs, .. } => smallvec![lhs, rhs],
+            Instruction::ArithmeticUnary { value, .. } => smallvec![value],
+            Instruction::Branch { .. } => smallvec![],
+            Instruction::BranchConditional { condition, .. } => smallvec![condition],
+            Instruction::Call { arguments, .. } => arguments.iter_mut().collect(),
+            Instruction::Cast { value, .. } => smallvec![value],
+            Instruction::GetElementPtr { ptr, index, .. } => smallvec![ptr, index],
+            Instruction::IntCompare { lhs, rhs, .. } => smallvec![lhs, rhs],
+            Instruction::Load { ptr, .. } => smallvec![ptr],
+            Instruction::Return { value } => value.iter_mut().collect(),
+            Instruction::Select {
+                condition,
+                on_true,
+                on_false,
+                ..
+            } => smallvec![condition, on_true, on_false],
+            Instruction::StackAlloc { .. } => smallvec![],
+            Instruction::Store { ptr, value } => smallvec![ptr, value],
+            Instruction::Nop => smallvec![],
+        }
+    }
+
+    /// Replaces every read of `from` with `to`.
+    /// Returns `true` if at least one operand was replaced.
+    pub fn replace_reads(&mut self, from: Value, to: Value) -> bool {
+        let mut replaced = false;
+        for read in self.reads_mut() {
+            if *read == from {
+                *read = to;
+                replaced = true;
+            }
+        }
+
+        replaced
+    }
 }
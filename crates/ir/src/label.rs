@@ -1,6 +1,9 @@
-use crate::{handle_impl, instruction::Instruction};
+use crate::{debug_location::DebugLocation, handle_impl, instruction::Instruction};
 use smallvec::{smallvec, SmallVec};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::{Deref, DerefMut},
+};
 
 handle_impl! {
     ///
@@ -9,7 +12,160 @@ handle_impl! {
 
 pub(crate) struct LabelData {
     pub name: String,
-    pub instructions: Vec<Instruction>,
+    pub instructions: Instructions,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////
+// Instructions
+
+/// Instructions of a label, each with the source location it was built for.
+///
+/// Derefs to the instructions alone, so replacing one in place keeps its location.
+/// Instructions added without a location, like the ones a pass creates from scratch,
+/// have none.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Instructions {
+    instructions: Vec<Instruction>,
+    locations: Vec<Option<DebugLocation>>,
+}
+
+impl Instructions {
+    /// No instructions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Location of the instruction at `index`.
+    pub fn location(&self, index: usize) -> Option<DebugLocation> {
+        self.locations[index]
+    }
+
+    /// Adds `instruction` at the end, at `location`.
+    pub fn push_located(
+        &mut self,
+        instruction: Instruction,
+        location: Option<DebugLocation>,
+    ) {
+        self.instructions.push(instruction);
+        self.locations.push(location);
+    }
+
+    /// Inserts `instruction` before `index`, without a location.
+    pub fn insert(&mut self, index: usize, instruction: Instruction) {
+        self.instructions.insert(index, instruction);
+        self.locations.insert(index, None);
+    }
+
+    /// Inserts `instructions` before `index`, all at `location`.
+    pub fn insert_all(
+        &mut self,
+        index: usize,
+        instructions: impl IntoIterator<Item = Instruction>,
+        location: Option<DebugLocation>,
+    ) {
+        let instructions: Vec<_> = instructions.into_iter().collect();
+        let count = instructions.len();
+        self.instructions.splice(index..index, instructions);
+        self.locations
+            .splice(index..index, std::iter::repeat(location).take(count));
+    }
+
+    /// Removes the last instruction and returns it.
+    pub fn pop(&mut self) -> Option<Instruction> {
+        self.locations.pop();
+        self.instructions.pop()
+    }
+
+    /// Keeps the first `len` instructions.
+    pub fn truncate(&mut self, len: usize) {
+        self.instructions.truncate(len);
+        self.locations.truncate(len);
+    }
+
+    /// Keeps the instructions `keep` returns `true` for.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Instruction) -> bool) {
+        let mut kept = self.instructions.iter().map(&mut keep);
+        self.locations.retain(|_| kept.next().unwrap());
+        self.instructions.retain(keep);
+    }
+
+    /// Moves the instructions of `other` to the end.
+    pub fn append(&mut self, other: &mut Instructions) {
+        self.instructions.append(&mut other.instructions);
+        self.locations.append(&mut other.locations);
+    }
+
+    /// Splits off the instructions from `at` on, with their locations.
+    pub fn split_off(&mut self, at: usize) -> Instructions {
+        Instructions {
+            instructions: self.instructions.split_off(at),
+            locations: self.locations.split_off(at),
+        }
+    }
+
+    /// Iterates over the instructions with their locations.
+    pub fn iter_located(
+        &self,
+    ) -> impl Iterator<Item = (&Instruction, Option<DebugLocation>)> + '_ {
+        self.instructions.iter().zip(self.locations.iter().copied())
+    }
+}
+
+impl Deref for Instructions {
+    type Target = [Instruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.instructions
+    }
+}
+
+impl DerefMut for Instructions {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.instructions
+    }
+}
+
+impl From<Vec<Instruction>> for Instructions {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        let locations = vec![None; instructions.len()];
+        Self {
+            instructions,
+            locations,
+        }
+    }
+}
+
+impl IntoIterator for Instructions {
+    type Item = (Instruction, Option<DebugLocation>);
+    type IntoIter = std::iter::Zip<
+        std::vec::IntoIter<Instruction>,
+        std::vec::IntoIter<Option<DebugLocation>>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.instructions.into_iter().zip(self.locations)
+    }
+}
+
+impl Extend<(Instruction, Option<DebugLocation>)> for Instructions {
+    fn extend<T: IntoIterator<Item = (Instruction, Option<DebugLocation>)>>(
+        &mut self,
+        iter: T,
+    ) {
+        for (instruction, location) in iter {
+            self.push_located(instruction, location);
+        }
+    }
+}
+
+impl FromIterator<(Instruction, Option<DebugLocation>)> for Instructions {
+    fn from_iter<T: IntoIterator<Item = (Instruction, Option<DebugLocation>)>>(
+        iter: T,
+    ) -> Self {
+        let mut instructions = Instructions::new();
+        instructions.extend(iter);
+        instructions
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////
@@ -39,7 +195,7 @@ impl Labels {
             Label(id),
             LabelData {
                 name: name.to_string(),
-                instructions: Vec::new(),
+                instructions: Instructions::new(),
             },
         );
 
@@ -62,7 +218,7 @@ impl Labels {
     }
 
     ///
-    pub fn remove(&mut self, label: Label) -> Vec<Instruction> {
+    pub fn remove(&mut self, label: Label) -> Instructions {
         assert_ne!(label, self.entry());
 
         let data = self.labels.remove(&label).unwrap();
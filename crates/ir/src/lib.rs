@@ -1,14 +1,23 @@
+mod alias_analysis;
+pub mod backend;
+mod call_graph;
 mod cfg;
 pub mod constant;
 pub mod context;
+pub mod debug_location;
 pub mod dump_ir;
 pub mod function;
 pub mod function_builder;
+mod function_summary;
 pub mod handle;
+mod induction_variable_analysis;
 pub mod instruction;
 pub mod label;
+mod liveness_analysis;
 mod location;
+mod loop_analysis;
 mod passes;
 mod pointer_analysis;
 pub mod ty;
 pub mod value;
+mod value_range_analysis;
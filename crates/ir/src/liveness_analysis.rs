@@ -0,0 +1,77 @@
+use crate::{function::FunctionData, label::Label, value::Value};
+use std::collections::{HashMap, HashSet};
+
+/// Values live at the boundaries of every block: read on some path from there before
+/// being created again. Constants are never live.
+///
+/// Only blocks reachable from the entry block are considered.
+#[derive(Debug)]
+pub(crate) struct LivenessAnalysis {
+    /// Values live at the start of every block.
+    pub live_in: HashMap<Label, HashSet<Value>>,
+    /// Values live at the end of every block, the union of the `live_in` of its
+    /// successors.
+    pub live_out: HashMap<Label, HashSet<Value>>,
+}
+
+impl LivenessAnalysis {
+    /// Computes the values live around every block of `function`.
+    pub fn new(function: &FunctionData) -> Self {
+        let cfg = function.labels().cfg();
+        let mut order = Vec::new();
+        cfg.bfs(|label| order.push(label));
+        order.reverse();
+
+        // Values read before being created, and values created, by every block.
+        let mut uses: HashMap<Label, HashSet<Value>> = HashMap::new();
+        let mut defs: HashMap<Label, HashSet<Value>> = HashMap::new();
+        for label in order.iter() {
+            let mut used = HashSet::new();
+            let mut defined = HashSet::new();
+            for instr in function.labels().get(*label).instructions.iter() {
+                for read in instr.reads().into_iter().flatten() {
+                    if function.constant(read).is_none() && !defined.contains(&read) {
+                        used.insert(read);
+                    }
+                }
+                if let Some(dst) = instr.creates() {
+                    defined.insert(dst);
+                }
+            }
+            uses.insert(*label, used);
+            defs.insert(*label, defined);
+        }
+
+        let mut live_in: HashMap<Label, HashSet<Value>> =
+            order.iter().map(|label| (*label, HashSet::new())).collect();
+        let mut live_out = live_in.clone();
+        loop {
+            let mut changed = false;
+            for label in order.iter() {
+                let mut output = HashSet::new();
+                for (_, to, _) in cfg.outgoing(*label) {
+                    output.extend(live_in[&to].iter().copied());
+                }
+
+                let mut input: HashSet<Value> = output
+                    .iter()
+                    .filter(|value| !defs[label].contains(value))
+                    .copied()
+                    .collect();
+                input.extend(uses[label].iter().copied());
+
+                if live_in[label] != input {
+                    live_in.insert(*label, input);
+                    changed = true;
+                }
+                live_out.insert(*label, output);
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        LivenessAnalysis { live_in, live_out }
+    }
+}
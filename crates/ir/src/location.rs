@@ -1,7 +1,7 @@
 use crate::label::Label;
 
 ///
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct Location {
     pub label: Label,
     pub instruction: u32,
@@ -0,0 +1,190 @@
+use crate::{
+    cfg::Cfg,
+    label::{Label, Labels},
+};
+use std::collections::{HashMap, HashSet};
+
+/// A natural loop.
+///
+/// ```text
+///          preheader
+///              │
+///              ▼
+///   ┌──────► header ───────► exit
+///   │          │
+///   │          ▼
+///   └─────── latch
+/// ```
+#[derive(Debug, Clone)]
+pub(crate) struct Loop {
+    /// The only block of the loop reachable from outside of it.
+    pub header: Label,
+    /// Blocks with a back edge to the header.
+    pub latches: Vec<Label>,
+    /// Every block of the loop, including the header and the latches.
+    pub blocks: HashSet<Label>,
+    /// Edges leaving the loop, `(inside, outside)`.
+    pub exits: Vec<(Label, Label)>,
+    /// Index of the innermost loop containing this one.
+    pub parent: Option<usize>,
+    /// Number of loops containing this one, `1` for outermost loops.
+    pub depth: usize,
+}
+
+impl Loop {
+    /// Predecessors of the header that are not part of the loop.
+    pub fn entering(&self, cfg: &Cfg) -> Vec<Label> {
+        let mut entering = cfg
+            .incoming(self.header)
+            .map(|(from, _, _)| from)
+            .filter(|from| !self.blocks.contains(from))
+            .collect::<Vec<_>>();
+        entering.sort();
+        entering
+    }
+}
+
+/// Dominators and natural loops of a function.
+///
+/// Only blocks reachable from the entry block are considered.
+#[derive(Debug)]
+pub(crate) struct LoopAnalysis {
+    /// Maps every block to the blocks dominating it, including itself.
+    pub dominators: HashMap<Label, HashSet<Label>>,
+    /// Loops ordered by header, so the result does not depend on `Labels` iteration order.
+    pub loops: Vec<Loop>,
+}
+
+impl LoopAnalysis {
+    /// Computes dominators, then loops from the back edges.
+    pub fn new(labels: &Labels) -> Self {
+        let cfg = labels.cfg();
+
+        let dominators = Self::compute_dominators(labels, &cfg);
+        let loops = Self::compute_loops(&cfg, &dominators);
+
+        LoopAnalysis { dominators, loops }
+    }
+
+    /// Returns `true` if every path from the entry to `b` goes through `a`.
+    pub fn dominates(&self, a: Label, b: Label) -> bool {
+        self.dominators
+            .get(&b)
+            .is_some_and(|dominators| dominators.contains(&a))
+    }
+
+    /// Returns `true` if no other loop is nested inside `loop_index`.
+    pub fn is_innermost(&self, loop_index: usize) -> bool {
+        !self.loops.iter().any(|l| l.parent == Some(loop_index))
+    }
+
+    /// Iterative data-flow formulation:
+    /// `dom(n) = {n} ∪ (∩ dom(p) for p in predecessors(n))`.
+    fn compute_dominators(labels: &Labels, cfg: &Cfg) -> HashMap<Label, HashSet<Label>> {
+        let mut order = Vec::new();
+        cfg.bfs(|label| order.push(label));
+        let reachable: HashSet<Label> = order.iter().copied().collect();
+
+        let mut dominators: HashMap<Label, HashSet<Label>> = order
+            .iter()
+            .map(|label| (*label, reachable.clone()))
+            .collect();
+        dominators.insert(labels.entry(), HashSet::from([labels.entry()]));
+
+        loop {
+            let mut changed = false;
+            for label in order.iter().skip(1) {
+                let mut new = cfg
+                    .incoming(*label)
+                    .filter(|(from, _, _)| reachable.contains(from))
+                    .map(|(from, _, _)| dominators[&from].clone())
+                    .reduce(|a, b| a.intersection(&b).copied().collect())
+                    .unwrap_or_default();
+                new.insert(*label);
+
+                if new != dominators[label] {
+                    dominators.insert(*label, new);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        dominators
+    }
+
+    /// Finds back edges `latch -> header`, where the header dominates the latch,
+    /// and collects the blocks that reach the latch without going through the header.
+    fn compute_loops(
+        cfg: &Cfg,
+        dominators: &HashMap<Label, HashSet<Label>>,
+    ) -> Vec<Loop> {
+        let mut latches: HashMap<Label, Vec<Label>> = HashMap::new();
+        for (label, label_dominators) in dominators.iter() {
+            for (_, to, _) in cfg.outgoing(*label) {
+                if label_dominators.contains(&to) {
+                    latches.entry(to).or_default().push(*label);
+                }
+            }
+        }
+
+        let mut loops = latches
+            .into_iter()
+            .map(|(header, mut latches)| {
+                latches.sort();
+
+                let mut blocks = HashSet::from([header]);
+                let mut stack = latches.clone();
+                while let Some(label) = stack.pop() {
+                    if blocks.insert(label) {
+                        let predecessors = cfg
+                            .incoming(label)
+                            .map(|(from, _, _)| from)
+                            .filter(|from| dominators.contains_key(from));
+                        stack.extend(predecessors);
+                    }
+                }
+
+                let mut exits = blocks
+                    .iter()
+                    .flat_map(|from| cfg.outgoing(*from))
+                    .filter(|(_, to, _)| !blocks.contains(to))
+                    .map(|(from, to, _)| (from, to))
+                    .collect::<Vec<_>>();
+                exits.sort();
+
+                Loop {
+                    header,
+                    latches,
+                    blocks,
+                    exits,
+                    parent: None,
+                    depth: 1,
+                }
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|l| l.header);
+
+        // The parent is the smallest loop containing the header.
+        for i in 0..loops.len() {
+            let parent = (0..loops.len())
+                .filter(|j| *j != i && loops[*j].blocks.contains(&loops[i].header))
+                .min_by_key(|j| loops[*j].blocks.len());
+            loops[i].parent = parent;
+        }
+        for i in 0..loops.len() {
+            let mut depth = 1;
+            let mut parent = loops[i].parent;
+            while let Some(p) = parent {
+                depth += 1;
+                parent = loops[p].parent;
+            }
+            loops[i].depth = depth;
+        }
+
+        loops
+    }
+}
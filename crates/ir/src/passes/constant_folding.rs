@@ -509,72 +509,9 @@ fn replace(
 
             let variable_users = users.get(&needle).unwrap();
             for user in variable_users.iter() {
-                match ctx.function.instruction_mut(user) {
-                    Instruction::ArithmeticBinary { dst, lhs, rhs, .. } => {
-                        let values = [dst, lhs, rhs];
-                        for value in values {
-                            if *value == needle {
-                                *value = constant;
-                            }
-                        }
-                    }
-                    Instruction::ArithmeticUnary { value, .. } => {
-                        if *value == needle {
-                            *value = constant;
-                        }
-                    }
-                    Instruction::Call { arguments, .. } => {
-                        for arg in arguments.iter_mut() {
-                            if *arg == needle {
-                                *arg = constant;
-                            }
-                        }
-                    }
-                    Instruction::Cast {
-                        cast_op,
-                        to_type,
-                        dst,
-                        value,
-                    } => todo!(),
-                    Instruction::GetElementPtr { dst, ptr, index } => {}
-                    Instruction::IntCompare { dst, lhs, rhs, .. } => {
-                        let values = [dst, lhs, rhs];
-                        for value in values {
-                            if *value == needle {
-                                *value = constant;
-                            }
-                        }
-                    }
-                    Instruction::Load { ptr, .. } => {
-                        if *ptr == needle {
-                            *ptr = constant;
-                        }
-                    }
-                    Instruction::Return { value: Some(value) } => {
-                        if *value == needle {
-                            *value = constant;
-                        }
-                    }
-                    Instruction::Select {
-                        dst,
-                        condition,
-                        on_true,
-                        on_false,
-                    } => {
-                        let values = [dst, condition, on_true, on_false];
-                        for value in values {
-                            if *value == needle {
-                                *value = constant;
-                            }
-                        }
-                    }
-                    Instruction::Store { value, .. } => {
-                        if *value == needle {
-                            *value = constant;
-                        }
-                    }
-                    _ => {}
-                }
+                ctx.function
+                    .instruction_mut(user)
+                    .replace_reads(needle, constant);
             }
         }
         Replacement::Instruction {
@@ -0,0 +1,37 @@
+use crate::{
+    function::{Function, Linkage},
+    passes::{ModuleContext, ModulePass},
+};
+
+/// Removes functions that can't be called: every [`Linkage::External`] function is a
+/// root, and [`Linkage::Internal`] functions are kept only when a root reaches them
+/// through calls.
+///
+/// Calls in unreachable blocks still count, so run [`SimplifyCfgPass`] first to
+/// remove more functions.
+///
+/// [`SimplifyCfgPass`]: crate::passes::simplify_cfg::SimplifyCfgPass
+#[derive(Default)]
+pub(crate) struct DeadFunctionEliminationPass;
+
+impl ModulePass for DeadFunctionEliminationPass {
+    fn run(&mut self, ctx: &mut ModuleContext<'_>) {
+        let call_graph = ctx.functions.call_graph();
+        let roots = ctx
+            .functions
+            .iter()
+            .filter(|(_, data)| data.definition().linkage == Linkage::External)
+            .map(|(id, _)| Function(id));
+        let reachable = call_graph.reachable_from(roots);
+
+        let dead = ctx
+            .functions
+            .iter()
+            .map(|(id, _)| Function(id))
+            .filter(|function| !reachable.contains(function))
+            .collect::<Vec<_>>();
+        for function in dead {
+            ctx.functions.remove(function);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::{
+    debug_location::DebugLocation,
+    function::FunctionData,
+    instruction::{BinaryOp, Instruction},
+    label::Label,
+    passes::{FunctionContext, Pass},
+    ty::Type,
+    value::Value,
+};
+
+/// A conditional branch whose sides join again right away.
+/// A missing side is a triangle: the branch jumps straight to `merge`.
+struct Diamond {
+    head: Label,
+    condition: Value,
+    on_true: Option<Label>,
+    on_false: Option<Label>,
+    merge: Label,
+}
+
+/// Replaces small if/else diamonds with `select`.
+///
+/// Both sides are executed unconditionally, so they may only contain instructions
+/// without side effects. Stores to stack slots are allowed, the stored values are
+/// merged with a `select`:
+/// ```text
+/// block_0:                                   block_0:
+///     branch_if v2 block_1, block_2              let v5: u32 = add.u32 v0, 1_u32
+/// block_1:                                       let v6: u32 = select v2, v5, v1
+///     let v5: u32 = add.u32 v0, 1_u32     ->     store.*u32 v3, v6
+///     store.*u32 v3, v5                          branch block_3
+///     branch block_3
+/// block_2:
+///     store.*u32 v3, v1
+///     branch block_3
+/// ```
+pub(crate) struct IfConversionPass {
+    /// Maximum number of instructions executed speculatively, including the `select`s.
+    pub max_cost: usize,
+}
+
+impl Default for IfConversionPass {
+    fn default() -> Self {
+        Self { max_cost: 8 }
+    }
+}
+
+impl Pass for IfConversionPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        loop {
+            let mut order = Vec::new();
+            ctx.function.labels().cfg().bfs(|label| order.push(label));
+
+            let diamond = order.into_iter().find_map(|label| {
+                let diamond = find_diamond(ctx.function, label)?;
+                self.can_convert(ctx.function, &diamond).then_some(diamond)
+            });
+
+            let Some(diamond) = diamond else {
+                break;
+            };
+            convert(ctx.function, &diamond);
+        }
+    }
+}
+
+impl IfConversionPass {
+    /// Checks that both sides can be executed speculatively, and are cheap enough.
+    fn can_convert(&self, function: &FunctionData, diamond: &Diamond) -> bool {
+        let mut cost = 0;
+        let mut stored_slots = Vec::new();
+
+        for side in [diamond.on_true, diamond.on_false].into_iter().flatten() {
+            let instructions = &function.labels().get(side).instructions;
+            let mut stored = Vec::new();
+
+            for instr in instructions[..instructions.len() - 1].iter() {
+                match instr {
+                    Instruction::Store { ptr, .. } => {
+                        if slot_type(function, *ptr).is_none() {
+                            return false;
+                        }
+                        stored.push(*ptr);
+                    }
+                    // Loads see memory as it was before the branch, so they may not
+                    // follow a store to the same slot.
+                    Instruction::Load { ptr, .. } => {
+                        if slot_type(function, *ptr).is_none() || stored.contains(ptr) {
+                            return false;
+                        }
+                        cost += 1;
+                    }
+                    Instruction::Nop => {}
+                    instr if is_speculatable(instr) => cost += 1,
+                    _ => return false,
+                }
+            }
+
+            stored_slots.extend(stored);
+        }
+
+        stored_slots.sort();
+        stored_slots.dedup();
+        cost + stored_slots.len() <= self.max_cost
+    }
+}
+
+/// Finds a diamond, or a triangle, starting at `head`.
+fn find_diamond(function: &FunctionData, head: Label) -> Option<Diamond> {
+    let Instruction::BranchConditional {
+        condition,
+        on_true,
+        on_false,
+    } = *function.last_instruction(head)
+    else {
+        return None;
+    };
+    if on_true == on_false {
+        return None;
+    }
+
+    let cfg = function.labels().cfg();
+
+    // A side is a block only reached from `head`, jumping unconditionally to the merge block.
+    let side = |label: Label| -> Option<Label> {
+        let mut predecessors = cfg.incoming(label);
+        let only_from_head = predecessors.next().is_some_and(|(from, _, _)| from == head)
+            && predecessors.next().is_none();
+        if label == head || !only_from_head {
+            return None;
+        }
+
+        match *function.last_instruction(label) {
+            Instruction::Branch { target } if target != label && target != head => {
+                Some(target)
+            }
+            _ => None,
+        }
+    };
+
+    let (on_true, on_false, merge) = match (side(on_true), side(on_false)) {
+        (Some(a), Some(b)) if a == b => (Some(on_true), Some(on_false), a),
+        (Some(a), _) if a == on_false => (Some(on_true), None, on_false),
+        (_, Some(b)) if b == on_true => (None, Some(on_false), on_true),
+        _ => return None,
+    };
+    if merge == head {
+        return None;
+    }
+
+    Some(Diamond {
+        head,
+        condition,
+        on_true,
+        on_false,
+        merge,
+    })
+}
+
+/// Moves both sides into the head, and merges stored values with `select`.
+fn convert(function: &mut FunctionData, diamond: &Diamond) {
+    let mut hoisted = Vec::new();
+    let mut slots = Vec::new();
+    let mut stores: [HashMap<Value, (Value, Option<DebugLocation>)>; 2] =
+        Default::default();
+
+    let sides = [diamond.on_true, diamond.on_false];
+    for (side, stores) in sides.iter().zip(stores.iter_mut()) {
+        let Some(side) = side else {
+            continue;
+        };
+
+        let mut instructions = function.labels_mut().remove(*side);
+        instructions.pop();
+        for (instr, location) in instructions {
+            match instr {
+                Instruction::Store { ptr, value } => {
+                    if !slots.contains(&ptr) {
+                        slots.push(ptr);
+                    }
+                    stores.insert(ptr, (value, location));
+                }
+                Instruction::Nop => {}
+                instr => hoisted.push((instr, location)),
+            }
+        }
+    }
+
+    // A side not storing to a slot keeps the value the slot had before the branch.
+    for slot in slots {
+        let ty = slot_type(function, slot).unwrap();
+        let mut merge = |stores: &HashMap<_, (Value, _)>, hoisted: &mut Vec<_>| {
+            stores
+                .get(&slot)
+                .map(|(value, _)| *value)
+                .unwrap_or_else(|| {
+                    let dst = function.values_mut().alloc(ty);
+                    hoisted.push((Instruction::Load { dst, ptr: slot }, None));
+                    dst
+                })
+        };
+        let on_true = merge(&stores[0], &mut hoisted);
+        let on_false = merge(&stores[1], &mut hoisted);
+        // The select stands for the stores of both sides.
+        let location = match (stores[0].get(&slot), stores[1].get(&slot)) {
+            (Some((_, Some(a))), Some((_, Some(b)))) => a.merge(*b),
+            (Some((_, location)), None) | (None, Some((_, location))) => *location,
+            _ => None,
+        };
+
+        let dst = function.values_mut().alloc(ty);
+        hoisted.push((
+            Instruction::Select {
+                dst,
+                condition: diamond.condition,
+                on_true,
+                on_false,
+            },
+            location,
+        ));
+        hoisted.push((
+            Instruction::Store {
+                ptr: slot,
+                value: dst,
+            },
+            location,
+        ));
+    }
+
+    let instructions = &mut function.labels_mut().get_mut(diamond.head).instructions;
+    let location = instructions.location(instructions.len() - 1);
+    instructions.pop();
+    instructions.extend(hoisted);
+    instructions.push_located(
+        Instruction::Branch {
+            target: diamond.merge,
+        },
+        location,
+    );
+}
+
+/// The type of the values held by `ptr`, if it is created by a `stack_alloc`.
+fn slot_type(function: &FunctionData, ptr: Value) -> Option<Type> {
+    function
+        .labels()
+        .iter()
+        .flat_map(|(_, data)| data.instructions.iter())
+        .find_map(|instr| match instr {
+            Instruction::StackAlloc { dst, ty, .. } if *dst == ptr => Some(*ty),
+            _ => None,
+        })
+}
+
+/// Returns `true` if `instr` can be executed even when its block would not be.
+fn is_speculatable(instr: &Instruction) -> bool {
+    match instr {
+        // Division by zero traps.
+        Instruction::ArithmeticBinary { op, .. } => {
+            !matches!(op, BinaryOp::Div | BinaryOp::Mod)
+        }
+        Instruction::ArithmeticUnary { .. }
+        | Instruction::Cast { .. }
+        | Instruction::GetElementPtr { .. }
+        | Instruction::IntCompare { .. }
+        | Instruction::Select { .. } => true,
+        _ => false,
+    }
+}
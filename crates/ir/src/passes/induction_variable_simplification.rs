@@ -0,0 +1,261 @@
+use crate::{
+    constant::ConstantValue,
+    function::FunctionData,
+    induction_variable_analysis::{DerivedInductionVariable, LoopInductionVariables},
+    instruction::{BinaryOp, Instruction},
+    label::Label,
+    location::Location,
+    loop_analysis::{Loop, LoopAnalysis},
+    passes::{FunctionContext, Pass},
+    ty::Types,
+    value::Value,
+};
+
+/// Simplifies induction variables found by
+/// [`InductionVariableAnalysis`](crate::induction_variable_analysis::InductionVariableAnalysis):
+/// - multiplications of an induction variable by a loop invariant are replaced with
+///   a new variable, increased by a multiple of the step every iteration,
+/// - induction variables always holding the same value as another are replaced with it.
+///
+/// ```text
+/// for i in 0..n {             let t: u32 = 0;
+///     a += i * b;      ->     for i in 0..n {
+/// }                               a += t;
+///                                 t += b;
+///                             }
+/// ```
+#[derive(Default)]
+pub(crate) struct InductionVariableSimplificationPass;
+
+impl Pass for InductionVariableSimplificationPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        loop {
+            let loop_analysis = ctx.function.loop_analysis();
+            let induction_variables =
+                ctx.function.induction_variables(ctx.types, &loop_analysis);
+
+            let changed = loop_analysis
+                .loops
+                .iter()
+                .zip(induction_variables.loops.iter())
+                .any(|(l, ivs)| {
+                    Self::eliminate_redundant(ctx.function, &loop_analysis, ivs)
+                        || Self::strength_reduce(ctx.types, ctx.function, l, ivs)
+                });
+
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+impl InductionVariableSimplificationPass {
+    /// Replaces a basic induction variable with another one starting at the same
+    /// constant and changed by the same step. Returns `true` if anything changed.
+    fn eliminate_redundant(
+        function: &mut FunctionData,
+        loop_analysis: &LoopAnalysis,
+        ivs: &LoopInductionVariables,
+    ) -> bool {
+        let creators = function.value_creators();
+        let start = |function: &FunctionData, init: Option<Value>| {
+            init.and_then(|init| function.constant(init))
+                .and_then(|init| init.integer())
+        };
+
+        for (i, a) in ivs.basic.iter().enumerate() {
+            for b in ivs.basic.iter().skip(i + 1) {
+                let (Some(a_start), Some(b_start)) =
+                    (start(function, a.init), start(function, b.init))
+                else {
+                    continue;
+                };
+                if a_start != b_start || a.op != b.op || a.step != b.step || a.ty != b.ty
+                {
+                    continue;
+                }
+
+                // Keep the one computed first, so it is available to all users of the other.
+                let (a_location, b_location) = (creators[&a.value], creators[&b.value]);
+                let (keep, replace) = if dominates(loop_analysis, a_location, b_location)
+                {
+                    (a.value, b.value)
+                } else if dominates(loop_analysis, b_location, a_location) {
+                    (b.value, a.value)
+                } else {
+                    continue;
+                };
+
+                function.replace_uses(replace, keep);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Replaces the first multiplication of an induction variable by a loop invariant
+    /// with an additive recurrence. Returns `true` if anything changed.
+    fn strength_reduce(
+        types: &Types,
+        function: &mut FunctionData,
+        l: &Loop,
+        ivs: &LoopInductionVariables,
+    ) -> bool {
+        let Some(derived) = ivs.derived.iter().find(|derived| {
+            derived.op == BinaryOp::Mul && ivs.basic[derived.basic].init.is_some()
+        }) else {
+            return false;
+        };
+        let DerivedInductionVariable {
+            value: product,
+            basic,
+            operand,
+            ..
+        } = *derived;
+        let iv = &ivs.basic[basic];
+        let Some((num_bits, _)) = types.integer(iv.ty) else {
+            return false;
+        };
+
+        let cfg = function.labels().cfg();
+        let [preheader] = l.entering(&cfg)[..] else {
+            return false;
+        };
+
+        let ty = iv.ty;
+        let slot_ty = function.values().get(iv.slot).ty();
+        let slot = function.values_mut().alloc(slot_ty);
+
+        // Increment of the new variable, folded right away when possible.
+        let step = match function.constant(operand).and_then(|c| c.integer()) {
+            Some(operand) => {
+                let mask = if num_bits >= 64 {
+                    u64::MAX
+                } else {
+                    (1 << num_bits) - 1
+                };
+                function.alloc_constant(ConstantValue::Integer {
+                    ty,
+                    value: operand.wrapping_mul(iv.step) & mask,
+                })
+            }
+            None => function.values_mut().alloc(ty),
+        };
+
+        // Uses of the product read the new variable instead.
+        let current = function.values_mut().alloc(ty);
+        let creators = function.value_creators();
+        *function.instruction_mut(&creators[&product]) = Instruction::Nop;
+        function.replace_uses(product, current);
+
+        // Update the new variable together with the induction variable.
+        let next = function.values_mut().alloc(ty);
+        let store_index = position(
+            function,
+            iv.store.label,
+            |instr| matches!(instr, Instruction::Store { ptr, .. } if *ptr == iv.slot),
+        );
+        let instructions =
+            &mut function.labels_mut().get_mut(iv.store.label).instructions;
+        let location = instructions.location(store_index);
+        instructions.insert_all(
+            store_index,
+            [
+                Instruction::ArithmeticBinary {
+                    dst: next,
+                    lhs: current,
+                    op: iv.op,
+                    rhs: step,
+                },
+                Instruction::Store {
+                    ptr: slot,
+                    value: next,
+                },
+            ],
+            location,
+        );
+
+        let load = creators[&iv.value];
+        let load_index = position(function, load.label, |instr| {
+            instr.creates() == Some(iv.value)
+        });
+        function
+            .labels_mut()
+            .get_mut(load.label)
+            .instructions
+            .insert(
+                load_index + 1,
+                Instruction::Load {
+                    dst: current,
+                    ptr: slot,
+                },
+            );
+
+        // Initialize the new variable before entering the loop.
+        let init = function.values_mut().alloc(ty);
+        let mut setup = vec![
+            Instruction::ArithmeticBinary {
+                dst: init,
+                lhs: iv.init.unwrap(),
+                op: BinaryOp::Mul,
+                rhs: operand,
+            },
+            Instruction::Store {
+                ptr: slot,
+                value: init,
+            },
+        ];
+        if function.constant(step).is_none() {
+            let step_constant =
+                function.alloc_constant(ConstantValue::Integer { ty, value: iv.step });
+            setup.push(Instruction::ArithmeticBinary {
+                dst: step,
+                lhs: operand,
+                op: BinaryOp::Mul,
+                rhs: step_constant,
+            });
+        }
+        let instructions = &mut function.labels_mut().get_mut(preheader).instructions;
+        let terminator = instructions.len() - 1;
+        let location = instructions.location(terminator);
+        instructions.insert_all(terminator, setup, location);
+
+        let entry = function.labels().entry();
+        function.labels_mut().get_mut(entry).instructions.insert(
+            0,
+            Instruction::StackAlloc {
+                dst: slot,
+                ty,
+                size: 1,
+            },
+        );
+
+        true
+    }
+}
+
+/// Returns `true` if `a` runs before `b` on every path reaching `b`.
+fn dominates(loop_analysis: &LoopAnalysis, a: Location, b: Location) -> bool {
+    if a.label == b.label {
+        a.instruction < b.instruction
+    } else {
+        loop_analysis.dominates(a.label, b.label)
+    }
+}
+
+/// Index of the first instruction in `label` matching `predicate`.
+fn position(
+    function: &FunctionData,
+    label: Label,
+    predicate: impl Fn(&Instruction) -> bool,
+) -> usize {
+    function
+        .labels()
+        .get(label)
+        .instructions
+        .iter()
+        .position(predicate)
+        .unwrap()
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    call_graph::CallGraph,
+    constant::ConstantValue,
+    function::{Function, FunctionData, Functions, Inline},
+    instruction::Instruction,
+    label::{Instructions, Label},
+    location::Location,
+    passes::{ModuleContext, ModulePass},
+    ty::{Type, Types},
+    value::Value,
+};
+
+/// Bonus for every constant argument, as constant folding will likely shrink the body.
+const CONSTANT_ARGUMENT_BONUS: i32 = 4;
+
+/// Bonus when the call is the only one to the callee, so the body is not duplicated.
+const SINGLE_CALL_SITE_BONUS: i32 = 16;
+
+/// Replaces calls with a copy of the callee body.
+///
+/// The call site is split in two: the instructions before the call jump to a copy
+/// of the callee entry, and every `ret` of the copy jumps to a new block holding the
+/// instructions after the call.
+/// ```text
+/// block_0:                                  block_0:
+///     let v3: u32 = call function_1(v1)         branch block_4
+///     let v4: u32 = add.u32 v3, v3          block_4: // copy of `function_1` entry
+///     ret v4                                    let v5: u32 = mul.u32 v1, v1
+///                                ->             branch block_5
+///                                           block_5:
+///                                               let v4: u32 = add.u32 v5, v5
+///                                               ret v4
+/// ```
+/// When the callee returns from more than one place, the results are merged
+/// through a stack slot allocated in the caller entry block.
+///
+/// Whether a call is inlined is decided by [`Inline`] on the callee definition, and
+/// otherwise by a size/benefit heuristic (see [`InliningPass::cost`]).
+/// Recursive calls are never inlined.
+pub(crate) struct InliningPass {
+    /// Calls whose cost is at most this are inlined.
+    pub threshold: i32,
+    /// Inlining stops once a caller grows past this number of instructions.
+    pub max_caller_size: usize,
+}
+
+impl Default for InliningPass {
+    fn default() -> Self {
+        Self {
+            threshold: 24,
+            max_caller_size: 1024,
+        }
+    }
+}
+
+impl ModulePass for InliningPass {
+    /// Inlines calls in every function, callees first.
+    fn run(&mut self, ctx: &mut ModuleContext<'_>) {
+        let ModuleContext { types, functions } = ctx;
+        let call_graph = functions.call_graph();
+        let reachable = call_graph.reachability();
+
+        for caller in call_graph.bottom_up_order() {
+            loop {
+                let call_graph = functions.call_graph();
+                let Some(location) =
+                    self.find_call_to_inline(functions, caller, &reachable, &call_graph)
+                else {
+                    break;
+                };
+
+                let Instruction::Call { function, .. } =
+                    functions.get(caller).instruction(&location)
+                else {
+                    unreachable!()
+                };
+                let body = CalleeBody::new(functions.get(*function));
+
+                inline_call(types, functions.get_mut(caller), location, &body);
+            }
+        }
+    }
+}
+
+impl InliningPass {
+    /// Estimates how expensive inlining a call is. Lower is better.
+    ///
+    /// The cost is the callee size, minus the call overhead that disappears,
+    /// minus bonuses for constant arguments and for the callee being called only once.
+    pub fn cost(
+        &self,
+        caller: &FunctionData,
+        callee: &FunctionData,
+        arguments: &[Value],
+        call_sites: usize,
+    ) -> i32 {
+        let size = function_size(callee) as i32;
+
+        // Argument setup, the call itself and the return.
+        let call_overhead = arguments.len() as i32 + 2;
+
+        let constant_arguments = arguments
+            .iter()
+            .filter(|argument| caller.constant(**argument).is_some())
+            .count() as i32;
+
+        let mut cost =
+            size - call_overhead - constant_arguments * CONSTANT_ARGUMENT_BONUS;
+        if call_sites == 1 {
+            cost -= SINGLE_CALL_SITE_BONUS;
+        }
+
+        cost
+    }
+
+    /// Finds the first call in `caller` that should be inlined.
+    fn find_call_to_inline(
+        &self,
+        functions: &Functions,
+        caller: Function,
+        reachable: &HashMap<Function, HashSet<Function>>,
+        call_graph: &CallGraph,
+    ) -> Option<Location> {
+        let caller_data = functions.get(caller);
+        let caller_size = function_size(caller_data);
+
+        let mut locations = Vec::new();
+        caller_data.labels().cfg().bfs(|label| {
+            let instructions = &caller_data.labels().get(label).instructions;
+            for (i, instr) in instructions.iter().enumerate() {
+                if matches!(instr, Instruction::Call { .. }) {
+                    locations.push(Location {
+                        label,
+                        instruction: i as u32,
+                    });
+                }
+            }
+        });
+
+        locations.into_iter().find(|location| {
+            let Instruction::Call {
+                function: callee,
+                arguments,
+                ..
+            } = caller_data.instruction(location)
+            else {
+                unreachable!()
+            };
+
+            // Inlining a recursive callee, or one that can reach the caller,
+            // would never terminate.
+            if reachable[callee].contains(callee) || reachable[callee].contains(&caller) {
+                return false;
+            }
+
+            let callee_data = functions.get(*callee);
+            match callee_data.definition().inline {
+                Inline::Always => true,
+                Inline::Never => false,
+                Inline::Auto => {
+                    let call_sites = call_graph.call_sites[callee].len();
+                    let cost = self.cost(caller_data, callee_data, arguments, call_sites);
+
+                    cost <= self.threshold
+                        && caller_size + function_size(callee_data)
+                            <= self.max_caller_size
+                }
+            }
+        })
+    }
+}
+
+/// A copy of everything needed to clone a callee into a caller.
+struct CalleeBody {
+    entry: Label,
+    labels: Vec<(Label, String, Instructions)>,
+    parameters: Vec<Value>,
+    value_types: Vec<Type>,
+    constants: HashMap<Value, ConstantValue>,
+}
+
+impl CalleeBody {
+    fn new(function: &FunctionData) -> Self {
+        let mut labels = Vec::new();
+        function.labels().cfg().bfs(|label| {
+            let data = function.labels().get(label);
+            labels.push((label, data.name.clone(), data.instructions.clone()));
+        });
+
+        let value_types = function
+            .values()
+            .iter()
+            .map(|(_, data)| data.ty())
+            .collect();
+        let constants = function
+            .value_to_constant
+            .keys()
+            .map(|value| (*value, function.constant(*value).unwrap()))
+            .collect();
+
+        Self {
+            entry: function.labels().entry(),
+            labels,
+            parameters: function.parameters().to_vec(),
+            value_types,
+            constants,
+        }
+    }
+}
+
+/// Replaces the call at `location` with a copy of `callee`.
+fn inline_call(
+    types: &mut Types,
+    caller: &mut FunctionData,
+    location: Location,
+    callee: &CalleeBody,
+) {
+    let Instruction::Call { arguments, dst, .. } = caller.instruction(&location).clone()
+    else {
+        panic!("expected a call at {location:?}")
+    };
+
+    // Split the block at the call site.
+    let (tail, call_location) = {
+        let instructions = &mut caller.labels_mut().get_mut(location.label).instructions;
+        let tail = instructions.split_off(location.instruction as usize + 1);
+        let call_location = instructions.location(location.instruction as usize);
+        instructions.pop();
+        (tail, call_location)
+    };
+    let continuation = caller.labels_mut().create("inline_continue");
+    caller.labels_mut().get_mut(continuation).instructions = tail;
+
+    // Map callee values to caller values.
+    let mut value_map: HashMap<Value, Value> = callee
+        .parameters
+        .iter()
+        .copied()
+        .zip(arguments.iter().copied())
+        .collect();
+    for (value, constant) in callee.constants.iter() {
+        value_map.insert(*value, caller.alloc_constant(*constant));
+    }
+
+    let label_map: HashMap<Label, Label> = callee
+        .labels
+        .iter()
+        .map(|(label, name, _)| (*label, caller.labels_mut().create(name)))
+        .collect();
+
+    let return_count = callee
+        .labels
+        .iter()
+        .flat_map(|(_, _, instructions)| instructions.iter())
+        .filter(|instr| matches!(instr, Instruction::Return { .. }))
+        .count();
+
+    // With several returns the results are merged through a stack slot.
+    let return_slot = match dst {
+        Some(dst) if return_count > 1 => {
+            let ty = caller.values().get(dst).ty();
+            let ptr = caller.values_mut().alloc(types.add_pointer(ty));
+            let entry = caller.labels().entry();
+            caller.labels_mut().get_mut(entry).instructions.insert_all(
+                0,
+                [Instruction::StackAlloc {
+                    dst: ptr,
+                    ty,
+                    size: 1,
+                }],
+                call_location,
+            );
+            caller
+                .labels_mut()
+                .get_mut(continuation)
+                .instructions
+                .insert_all(0, [Instruction::Load { dst, ptr }], call_location);
+
+            Some(ptr)
+        }
+        _ => None,
+    };
+
+    let mut returned_value = None;
+    for (label, _, instructions) in callee.labels.iter() {
+        let mut cloned = Instructions::new();
+
+        for (instr, location) in instructions.iter_located() {
+            let mut instr = instr.clone();
+
+            if let Some(created) = instr.creates_mut() {
+                let ty = callee.value_types[created.id()];
+                let value = *value_map
+                    .entry(*created)
+                    .or_insert_with(|| caller.values_mut().alloc(ty));
+                *created = value;
+            }
+            for read in instr.reads_mut() {
+                let ty = callee.value_types[read.id()];
+                let value = *value_map
+                    .entry(*read)
+                    .or_insert_with(|| caller.values_mut().alloc(ty));
+                *read = value;
+            }
+            for target in instr.targets_mut() {
+                *target = label_map[target];
+            }
+
+            if let Instruction::Return { value } = instr {
+                match (return_slot, value) {
+                    (Some(ptr), Some(value)) => {
+                        cloned.push_located(Instruction::Store { ptr, value }, location);
+                    }
+                    (None, Some(value)) => returned_value = Some(value),
+                    _ => {}
+                }
+                cloned.push_located(
+                    Instruction::Branch {
+                        target: continuation,
+                    },
+                    location,
+                );
+            } else {
+                cloned.push_located(instr, location);
+            }
+        }
+
+        caller.labels_mut().get_mut(label_map[label]).instructions = cloned;
+    }
+
+    if let (Some(dst), Some(value)) = (dst, returned_value) {
+        caller.replace_uses(dst, value);
+    }
+
+    caller
+        .labels_mut()
+        .get_mut(location.label)
+        .instructions
+        .push_located(
+            Instruction::Branch {
+                target: label_map[&callee.entry],
+            },
+            call_location,
+        );
+}
+
+/// Number of instructions, not counting `nop`s.
+fn function_size(function: &FunctionData) -> usize {
+    function
+        .labels()
+        .iter()
+        .flat_map(|(_, data)| data.instructions.iter())
+        .filter(|instr| !matches!(instr, Instruction::Nop))
+        .count()
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+
+use crate::{
+    constant::ConstantValue,
+    function::FunctionData,
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp},
+    location::Location,
+    passes::{FunctionContext, Pass},
+    ty::{Type, Types},
+    value::Value,
+};
+
+/// The outcome of a single combining rule.
+#[derive(Debug)]
+pub(crate) enum Combination {
+    /// Every use of the instruction result can read this value instead.
+    Value(Value),
+    /// The instruction can be replaced with a cheaper one producing the same result.
+    Instruction(Instruction),
+}
+
+/// Algebraic simplification of single instructions, in the spirit of LLVM's `instcombine`.
+///
+/// Unlike [`ConstantFoldingPass`](super::constant_folding::ConstantFoldingPass) this pass
+/// only needs *some* operands to be known. For example:
+/// ```text
+/// let v3: u32 = mul.u32 v2, 8_u32   ->   let v3: u32 = shl.u32 v2, 3_u32
+/// let v4: u32 = sub.u32 v3, v3      ->   (uses of v4 read 0_u32)
+/// ```
+#[derive(Default)]
+pub(crate) struct InstructionCombiningPass;
+
+impl Pass for InstructionCombiningPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        loop {
+            let mut combiner = Combiner::new(ctx.types, ctx.function);
+            let mut changed = false;
+
+            let mut locations = Vec::new();
+            combiner.function.labels().cfg().bfs(|label| {
+                let count = combiner.function.labels().get(label).instructions.len();
+                locations.extend((0..count).map(|instruction| Location {
+                    label,
+                    instruction: instruction as u32,
+                }));
+            });
+
+            for location in locations {
+                let instr = combiner.function.instruction(&location).clone();
+                let combination = Combiner::RULES
+                    .iter()
+                    .find_map(|rule| rule(&mut combiner, &instr));
+
+                if let Some(combination) = combination {
+                    combiner.apply(&location, combination);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+/// Applies the combining rules to a single function.
+pub(crate) struct Combiner<'a> {
+    types: &'a Types,
+    function: &'a mut FunctionData,
+    creators: HashMap<Value, Location>,
+}
+
+impl<'a> Combiner<'a> {
+    /// All rules, in the order they are tried.
+    pub const RULES: [fn(&mut Self, &Instruction) -> Option<Combination>; 7] = [
+        Self::identity,
+        Self::annihilator,
+        Self::self_inverse,
+        Self::strength_reduction,
+        Self::canonicalize_compare,
+        Self::redundant_cast,
+        Self::redundant_select,
+    ];
+
+    /// Creates a combiner for `function`.
+    /// Value creators are cached, so it should be recreated after unrelated edits.
+    pub fn new(types: &'a Types, function: &'a mut FunctionData) -> Self {
+        let creators = function.value_creators();
+        Self {
+            types,
+            function,
+            creators,
+        }
+    }
+
+    /// Rewrites the instruction at `location` according to `combination`.
+    pub fn apply(&mut self, location: &Location, combination: Combination) {
+        match combination {
+            Combination::Value(value) => {
+                let dst = self.function.instruction(location).creates().unwrap();
+                *self.function.instruction_mut(location) = Instruction::Nop;
+                self.function.replace_uses(dst, value);
+            }
+            Combination::Instruction(instruction) => {
+                *self.function.instruction_mut(location) = instruction;
+            }
+        }
+    }
+
+    /// Removes operations with an identity element:
+    /// `x + 0`, `x - 0`, `x * 1`, `x / 1`, `x | 0`, `x ^ 0`, `x << 0`, `x & !0`, `x & x` and `x | x`.
+    pub fn identity(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::ArithmeticBinary { dst, lhs, op, rhs } = instr else {
+            return None;
+        };
+        let all_ones = self.all_ones(*dst)?;
+
+        if lhs == rhs && is_idempotent(*op) {
+            return Some(Combination::Value(*lhs));
+        }
+
+        let lhs_const = self.integer(*lhs);
+        let rhs_const = self.integer(*rhs);
+        let (rhs_identity, lhs_identity) = match op {
+            BinaryOp::Add | BinaryOp::Or | BinaryOp::Xor | BinaryOp::BitOr => {
+                (Some(0), Some(0))
+            }
+            BinaryOp::Sub | BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Sar => {
+                (Some(0), None)
+            }
+            BinaryOp::Mul => (Some(1), Some(1)),
+            BinaryOp::Div => (Some(1), None),
+            BinaryOp::And | BinaryOp::BitAnd => (Some(all_ones), Some(all_ones)),
+            BinaryOp::Mod => (None, None),
+        };
+
+        if rhs_const.is_some() && rhs_const == rhs_identity {
+            Some(Combination::Value(*lhs))
+        } else if lhs_const.is_some() && lhs_const == lhs_identity {
+            Some(Combination::Value(*rhs))
+        } else {
+            None
+        }
+    }
+
+    /// Folds operations with an absorbing element:
+    /// `x * 0`, `x & 0`, `x | !0`, `x % 1`, `0 << x`, `0 >> x` and `0 / x`.
+    pub fn annihilator(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::ArithmeticBinary { dst, lhs, op, rhs } = instr else {
+            return None;
+        };
+        let all_ones = self.all_ones(*dst)?;
+
+        let lhs_const = self.integer(*lhs);
+        let rhs_const = self.integer(*rhs);
+        let is = |value: Option<u64>, expected: u64| value == Some(expected);
+
+        let result = match op {
+            BinaryOp::Mul | BinaryOp::And | BinaryOp::BitAnd => {
+                (is(lhs_const, 0) || is(rhs_const, 0)).then_some(0)
+            }
+            BinaryOp::Or | BinaryOp::BitOr => {
+                (is(lhs_const, all_ones) || is(rhs_const, all_ones)).then_some(all_ones)
+            }
+            BinaryOp::Mod => is(rhs_const, 1).then_some(0),
+            BinaryOp::Div | BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Sar => {
+                is(lhs_const, 0).then_some(0)
+            }
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Xor => None,
+        }?;
+
+        Some(self.constant(*dst, result))
+    }
+
+    /// Folds operations of a value with itself that always produce zero: `x - x` and `x ^ x`.
+    pub fn self_inverse(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::ArithmeticBinary { dst, lhs, op, rhs } = instr else {
+            return None;
+        };
+        self.integer_type(*dst)?;
+
+        if lhs == rhs && matches!(op, BinaryOp::Sub | BinaryOp::Xor) {
+            Some(self.constant(*dst, 0))
+        } else {
+            None
+        }
+    }
+
+    /// Replaces multiplications by a power of two with shifts,
+    /// and unsigned divisions and remainders by a power of two with shifts and masks.
+    pub fn strength_reduction(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::ArithmeticBinary { dst, lhs, op, rhs } = instr else {
+            return None;
+        };
+        let (_, is_signed) = self.integer_type(*dst)?;
+
+        let (value, power) = match op {
+            BinaryOp::Mul => match (self.integer(*lhs), self.integer(*rhs)) {
+                (_, Some(power)) if power.is_power_of_two() => (*lhs, power),
+                (Some(power), _) if power.is_power_of_two() => (*rhs, power),
+                _ => return None,
+            },
+            BinaryOp::Div | BinaryOp::Mod if !is_signed => {
+                let power = self.integer(*rhs).filter(|power| power.is_power_of_two())?;
+                (*lhs, power)
+            }
+            _ => return None,
+        };
+
+        let ty = self.function.values().get(*dst).ty();
+        let (op, rhs) = match op {
+            BinaryOp::Mul => (BinaryOp::Shl, power.trailing_zeros() as u64),
+            BinaryOp::Div => (BinaryOp::Shr, power.trailing_zeros() as u64),
+            BinaryOp::Mod => (BinaryOp::BitAnd, power - 1),
+            _ => unreachable!(),
+        };
+        let rhs = self
+            .function
+            .alloc_constant(ConstantValue::Integer { ty, value: rhs });
+
+        Some(Combination::Instruction(Instruction::ArithmeticBinary {
+            dst: *dst,
+            lhs: value,
+            op,
+            rhs,
+        }))
+    }
+
+    /// Canonicalizes integer comparisons:
+    /// - comparisons of a value with itself are folded,
+    /// - comparisons that are always true or false because of the type bounds are folded,
+    /// - constants are moved to the right-hand side,
+    /// - `x <= C` and `x >= C` become the strict `x < C + 1` and `x > C - 1`.
+    pub fn canonicalize_compare(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::IntCompare {
+            pred,
+            dst,
+            lhs,
+            rhs,
+        } = instr
+        else {
+            return None;
+        };
+        let (num_bits, is_signed) = self.integer_type(*lhs)?;
+
+        if lhs == rhs {
+            let result = matches!(
+                pred,
+                IntCompareOp::Equal
+                    | IntCompareOp::GreaterThanOrEqual
+                    | IntCompareOp::LessThanOrEqual
+            );
+            return Some(self.constant(*dst, result as u64));
+        }
+
+        if self.integer(*lhs).is_some() && self.integer(*rhs).is_none() {
+            return Some(Combination::Instruction(Instruction::IntCompare {
+                pred: pred.swapped(),
+                dst: *dst,
+                lhs: *rhs,
+                rhs: *lhs,
+            }));
+        }
+
+        let constant = self.integer(*rhs)?;
+        let min = min_value(num_bits, is_signed);
+        let max = max_value(num_bits, is_signed);
+
+        let always = match pred {
+            IntCompareOp::LessThan if constant == min => Some(false),
+            IntCompareOp::GreaterThanOrEqual if constant == min => Some(true),
+            IntCompareOp::GreaterThan if constant == max => Some(false),
+            IntCompareOp::LessThanOrEqual if constant == max => Some(true),
+            _ => None,
+        };
+        if let Some(always) = always {
+            return Some(self.constant(*dst, always as u64));
+        }
+
+        let mask = all_ones(num_bits);
+        let (pred, constant) = match pred {
+            IntCompareOp::LessThanOrEqual => {
+                (IntCompareOp::LessThan, constant.wrapping_add(1) & mask)
+            }
+            IntCompareOp::GreaterThanOrEqual => {
+                (IntCompareOp::GreaterThan, constant.wrapping_sub(1) & mask)
+            }
+            _ => return None,
+        };
+
+        let ty = self.function.values().get(*rhs).ty();
+        let rhs = self.function.alloc_constant(ConstantValue::Integer {
+            ty,
+            value: constant,
+        });
+
+        Some(Combination::Instruction(Instruction::IntCompare {
+            pred,
+            dst: *dst,
+            lhs: *lhs,
+            rhs,
+        }))
+    }
+
+    /// Removes casts that are no-ops or undo a previous cast:
+    /// - a cast to the type the value already has,
+    /// - `truncate(zero_extend(x))` and `truncate(sign_extend(x))` back to the type of `x`,
+    ///   or to a type narrower or wider than the type of `x`,
+    /// - `zero_extend(zero_extend(x))` and `sign_extend(sign_extend(x))`.
+    pub fn redundant_cast(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::Cast {
+            cast_op,
+            to_type,
+            dst,
+            value,
+        } = instr
+        else {
+            return None;
+        };
+
+        let value_type = self.function.values().get(*value).ty();
+        if self.same_type(value_type, *to_type) {
+            return Some(Combination::Value(*value));
+        }
+
+        let location = self.creators.get(value)?;
+        let Instruction::Cast {
+            cast_op: inner_op,
+            value: inner_value,
+            ..
+        } = self.function.instruction(location)
+        else {
+            return None;
+        };
+        let (inner_op, inner_value) = (*inner_op, *inner_value);
+
+        let inner_type = self.function.values().get(inner_value).ty();
+        let (inner_bits, _) = self.types.integer(inner_type)?;
+        let (to_bits, _) = self.types.integer(*to_type)?;
+
+        let cast_op = match (cast_op, inner_op) {
+            (CastOp::Truncate, CastOp::ZeroExtend | CastOp::SignExtend) => {
+                if to_bits == inner_bits && self.same_type(inner_type, *to_type) {
+                    return Some(Combination::Value(inner_value));
+                } else if to_bits < inner_bits {
+                    CastOp::Truncate
+                } else if to_bits > inner_bits {
+                    inner_op
+                } else {
+                    return None;
+                }
+            }
+            (CastOp::ZeroExtend, CastOp::ZeroExtend)
+            | (CastOp::SignExtend, CastOp::SignExtend) => inner_op,
+            _ => return None,
+        };
+
+        Some(Combination::Instruction(Instruction::Cast {
+            cast_op,
+            to_type: *to_type,
+            dst: *dst,
+            value: inner_value,
+        }))
+    }
+
+    /// Folds `select` with a constant condition, or with the same value on both sides.
+    pub fn redundant_select(&mut self, instr: &Instruction) -> Option<Combination> {
+        let Instruction::Select {
+            condition,
+            on_true,
+            on_false,
+            ..
+        } = instr
+        else {
+            return None;
+        };
+
+        if on_true == on_false {
+            return Some(Combination::Value(*on_true));
+        }
+
+        match self.integer(*condition)? {
+            0 => Some(Combination::Value(*on_false)),
+            _ => Some(Combination::Value(*on_true)),
+        }
+    }
+
+    fn integer(&self, value: Value) -> Option<u64> {
+        self.function.constant(value)?.integer()
+    }
+
+    fn integer_type(&self, value: Value) -> Option<(u32, bool)> {
+        let ty = self.function.values().get(value).ty();
+        self.types.integer(ty)
+    }
+
+    fn all_ones(&self, value: Value) -> Option<u64> {
+        let (num_bits, _) = self.integer_type(value)?;
+        Some(all_ones(num_bits))
+    }
+
+    fn same_type(&self, a: Type, b: Type) -> bool {
+        self.types.get(a) == self.types.get(b)
+    }
+
+    fn constant(&mut self, dst: Value, value: u64) -> Combination {
+        let ty = self.function.values().get(dst).ty();
+        let constant = self
+            .function
+            .alloc_constant(ConstantValue::Integer { ty, value });
+
+        Combination::Value(constant)
+    }
+}
+
+/// `x op x == x`
+fn is_idempotent(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::And | BinaryOp::Or | BinaryOp::BitAnd | BinaryOp::BitOr
+    )
+}
+
+fn all_ones(num_bits: u32) -> u64 {
+    u64::MAX >> (64 - num_bits)
+}
+
+/// The smallest value of an integer type, as stored in a [`ConstantValue::Integer`].
+fn min_value(num_bits: u32, is_signed: bool) -> u64 {
+    if is_signed {
+        1 << (num_bits - 1)
+    } else {
+        0
+    }
+}
+
+/// The largest value of an integer type, as stored in a [`ConstantValue::Integer`].
+fn max_value(num_bits: u32, is_signed: bool) -> u64 {
+    if is_signed {
+        all_ones(num_bits) >> 1
+    } else {
+        all_ones(num_bits)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    call_graph::CallGraph,
+    constant::ConstantValue,
+    function::{Function, FunctionData, Functions, Linkage},
+    instruction::Instruction,
+    location::Location,
+    passes::{ModuleContext, ModulePass},
+};
+
+/// Propagates constants across calls:
+/// - a parameter receiving the same constant at every call site is replaced with
+///   that constant in the callee body,
+/// - the result of a call to a function always returning the same constant is
+///   replaced with that constant in the caller.
+///
+/// ```text
+/// fn f(x: u32) -> u32 { return x * 2; }       fn f(x: u32) -> u32 { return 4 * 2; }
+/// fn g() -> u32 { return f(4) + f(4); }  ->   fn g() -> u32 { return f(4) + f(4); }
+///
+/// fn f(x: u32) -> u32 { return 8; }           fn f(x: u32) -> u32 { return 8; }
+/// fn g() -> u32 { return f(4) + f(4); }  ->   fn g() -> u32 { f(4); f(4); return 8 + 8; }
+/// ```
+/// Only [`Linkage::Internal`] functions have all their call sites known, so the
+/// parameters of external functions are left alone. Calls are kept, as the callee may
+/// still have side effects.
+#[derive(Default)]
+pub(crate) struct InterproceduralConstantPropagationPass;
+
+impl ModulePass for InterproceduralConstantPropagationPass {
+    fn run(&mut self, ctx: &mut ModuleContext<'_>) {
+        let call_graph = ctx.functions.call_graph();
+        Self::propagate_arguments(ctx.functions, &call_graph);
+        Self::propagate_returns(ctx.functions, &call_graph);
+    }
+}
+
+impl InterproceduralConstantPropagationPass {
+    /// Replaces parameters with the constant every call site passes.
+    ///
+    /// Callers are visited before their callees, so a constant parameter passed on
+    /// to another function is propagated in the same run.
+    fn propagate_arguments(functions: &mut Functions, call_graph: &CallGraph) {
+        let mut order = call_graph.bottom_up_order();
+        order.reverse();
+
+        for callee in order {
+            let call_sites = &call_graph.call_sites[&callee];
+            let callee_data = functions.get(callee);
+            if callee_data.definition().linkage != Linkage::Internal
+                || call_sites.is_empty()
+            {
+                continue;
+            }
+
+            let parameters = callee_data.parameters().to_vec();
+            for (index, parameter) in parameters.into_iter().enumerate() {
+                let Some(constant) =
+                    common_argument(functions, callee, call_sites, index)
+                else {
+                    continue;
+                };
+
+                let callee_data = functions.get_mut(callee);
+                let constant = callee_data.alloc_constant(constant);
+                callee_data.replace_uses(parameter, constant);
+            }
+        }
+    }
+
+    /// Replaces the results of calls with the constant the callee always returns.
+    fn propagate_returns(functions: &mut Functions, call_graph: &CallGraph) {
+        let mut callees = call_graph.call_sites.keys().copied().collect::<Vec<_>>();
+        callees.sort();
+
+        for callee in callees {
+            let Some(constant) = constant_return(functions.get(callee)) else {
+                continue;
+            };
+
+            for (caller, location) in call_graph.call_sites[&callee].iter() {
+                let caller_data = functions.get_mut(*caller);
+                let Instruction::Call { dst: Some(dst), .. } =
+                    *caller_data.instruction(location)
+                else {
+                    continue;
+                };
+
+                let constant = caller_data.alloc_constant(constant);
+                caller_data.replace_uses(dst, constant);
+            }
+        }
+    }
+}
+
+/// The constant passed as argument `index` by every call to `callee`, if any.
+fn common_argument(
+    functions: &Functions,
+    callee: Function,
+    call_sites: &[(Function, Location)],
+    index: usize,
+) -> Option<ConstantValue> {
+    let mut common = None;
+    for (caller, location) in call_sites.iter() {
+        let caller_data = functions.get(*caller);
+        let Instruction::Call { arguments, .. } = caller_data.instruction(location)
+        else {
+            unreachable!()
+        };
+        let argument = arguments[index];
+
+        // A recursive call passing the parameter on unchanged agrees with any constant.
+        if *caller == callee && argument == caller_data.parameters()[index] {
+            continue;
+        }
+
+        let constant = caller_data.constant(argument)?;
+        match common {
+            None => common = Some(constant),
+            Some(common) if same_constant(common, constant) => {}
+            Some(_) => return None,
+        }
+    }
+
+    common
+}
+
+/// The constant returned by every reachable `ret` of `function`, if any.
+fn constant_return(function: &FunctionData) -> Option<ConstantValue> {
+    let mut returns = Vec::new();
+    function.labels().cfg().bfs(|label| {
+        if let Instruction::Return { value } = function.last_instruction(label) {
+            returns.push(*value);
+        }
+    });
+
+    let mut common = None;
+    for value in returns {
+        let constant = function.constant(value?)?;
+        match common {
+            None => common = Some(constant),
+            Some(common) if same_constant(common, constant) => {}
+            Some(_) => return None,
+        }
+    }
+
+    common
+}
+
+fn same_constant(a: ConstantValue, b: ConstantValue) -> bool {
+    match (a, b) {
+        (
+            ConstantValue::Integer { ty: a_ty, value: a },
+            ConstantValue::Integer { ty: b_ty, value: b },
+        ) => a_ty == b_ty && a == b,
+        (
+            ConstantValue::Float { ty: a_ty, value: a },
+            ConstantValue::Float { ty: b_ty, value: b },
+        ) => a_ty == b_ty && a.to_bits() == b.to_bits(),
+        _ => false,
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    cfg::Cfg,
+    constant::ConstantValue,
+    function::FunctionData,
+    induction_variable_analysis::evaluate,
+    instruction::{Instruction, IntCompareOp},
+    label::Label,
+    location::Location,
+    passes::{FunctionContext, Pass},
+    ty::Types,
+    value::Value,
+};
+
+/// Maximum number of blocks walked back from an edge, looking for known values.
+const MAX_DEPTH: usize = 8;
+
+/// Redirects edges into a conditional branch whose result is known along that edge,
+/// straight to the successor it would take.
+///
+/// A block with other predecessors is duplicated for the threaded edge:
+/// ```text
+/// block_1:                                 block_1:
+///     store.*u32 v3, 1_u32                     store.*u32 v3, 1_u32
+///     branch block_3                           branch block_6
+/// block_2:                                 block_2:
+///     store.*u32 v3, 0_u32          ->         store.*u32 v3, 0_u32
+///     branch block_3                           branch block_3
+/// block_3:                                 block_3:
+///     let v4: u32 = load.*u32 v3               let v4: u32 = load.*u32 v3
+///     let v5: u1 = eq.u32 v4, 1_u32            let v5: u1 = eq.u32 v4, 1_u32
+///     branch_if v5 block_4, block_5            branch block_5
+///                                          block_6:
+///                                              let v6: u32 = load.*u32 v3
+///                                              let v7: u1 = eq.u32 v6, 1_u32
+///                                              branch block_4
+/// ```
+/// A result is known when a store along the path decides the compared values, or
+/// when a branch along the path decides the same compare.
+/// Loop headers are never threaded, so loops stay natural.
+pub(crate) struct JumpThreadingPass {
+    /// Maximum number of instructions copied into duplicated blocks, per function.
+    pub duplication_budget: usize,
+}
+
+impl Default for JumpThreadingPass {
+    fn default() -> Self {
+        Self {
+            duplication_budget: 32,
+        }
+    }
+}
+
+/// The edge `predecessor -> block` can go to `target` directly.
+struct Thread {
+    predecessor: Label,
+    block: Label,
+    target: Label,
+}
+
+impl Pass for JumpThreadingPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        let mut budget = self.duplication_budget;
+        while let Some((thread, cost)) = find_thread(ctx.types, ctx.function, budget) {
+            budget -= cost;
+            apply(ctx.function, &thread);
+        }
+    }
+}
+
+/// Finds the first edge, in bfs order, that can be threaded within `budget`.
+/// Returns it with the number of instructions it copies.
+fn find_thread(
+    types: &Types,
+    function: &FunctionData,
+    budget: usize,
+) -> Option<(Thread, usize)> {
+    let cfg = function.labels().cfg();
+    let headers = function
+        .loop_analysis()
+        .loops
+        .iter()
+        .map(|l| l.header)
+        .collect::<HashSet<_>>();
+    let paths = Paths::new(types, function, cfg);
+    let users = function.variable_users();
+
+    let mut order = Vec::new();
+    paths.cfg.bfs(|label| order.push(label));
+
+    for block in order {
+        let Instruction::BranchConditional {
+            condition,
+            on_true,
+            on_false,
+        } = *function.last_instruction(block)
+        else {
+            continue;
+        };
+        if on_true == on_false || headers.contains(&block) {
+            continue;
+        }
+
+        let mut predecessors = paths
+            .cfg
+            .incoming(block)
+            .map(|(from, _, _)| from)
+            .filter(|from| *from != block)
+            .collect::<Vec<_>>();
+        predecessors.sort();
+
+        let cost = match predecessors.len() {
+            1 => 0,
+            _ => match duplication_cost(function, &users, block) {
+                Some(cost) if cost <= budget => cost,
+                _ => continue,
+            },
+        };
+
+        for predecessor in predecessors {
+            if let Some(result) = paths.condition((predecessor, block), condition) {
+                let target = if result { on_true } else { on_false };
+                let thread = Thread {
+                    predecessor,
+                    block,
+                    target,
+                };
+                return Some((thread, cost));
+            }
+        }
+    }
+
+    None
+}
+
+/// Number of instructions copied when duplicating `block`, or `None` if values
+/// created in it are used elsewhere, and can't have two definitions.
+fn duplication_cost(
+    function: &FunctionData,
+    users: &HashMap<Value, HashSet<Location>>,
+    block: Label,
+) -> Option<usize> {
+    let instructions = &function.labels().get(block).instructions;
+    for instr in instructions.iter() {
+        if let Some(created) = instr.creates() {
+            if users[&created].iter().any(|user| user.label != block) {
+                return None;
+            }
+        }
+    }
+
+    Some(
+        instructions
+            .iter()
+            .filter(|instr| !matches!(instr, Instruction::Nop))
+            .count(),
+    )
+}
+
+/// Makes the edge `predecessor -> block` go to `target`, duplicating `block` when it
+/// has other predecessors.
+fn apply(function: &mut FunctionData, thread: &Thread) {
+    let cfg = function.labels().cfg();
+    let shared = cfg
+        .incoming(thread.block)
+        .any(|(from, _, _)| from != thread.predecessor);
+    if !shared {
+        *function.last_instruction_mut(thread.block) = Instruction::Branch {
+            target: thread.target,
+        };
+        return;
+    }
+
+    let name = function.labels().get(thread.block).name.clone();
+    let copy = function.labels_mut().create(&name);
+
+    let mut instructions = function.labels().get(thread.block).instructions.clone();
+    let mut value_map = HashMap::new();
+    for instr in instructions.iter_mut() {
+        for read in instr.reads_mut() {
+            if let Some(value) = value_map.get(read) {
+                *read = *value;
+            }
+        }
+        if let Some(created) = instr.creates_mut() {
+            let ty = function.values().get(*created).ty();
+            let value = function.values_mut().alloc(ty);
+            value_map.insert(*created, value);
+            *created = value;
+        }
+    }
+    *instructions.last_mut().unwrap() = Instruction::Branch {
+        target: thread.target,
+    };
+    function.labels_mut().get_mut(copy).instructions = instructions;
+
+    for target in function
+        .last_instruction_mut(thread.predecessor)
+        .targets_mut()
+    {
+        if *target == thread.block {
+            *target = copy;
+        }
+    }
+}
+
+/// Answers what is known about values along an edge `(from, to)`, from the paths
+/// reaching it.
+struct Paths<'a> {
+    types: &'a Types,
+    function: &'a FunctionData,
+    cfg: Cfg,
+    creators: HashMap<Value, Location>,
+    slots: HashSet<Value>,
+}
+
+impl<'a> Paths<'a> {
+    fn new(types: &'a Types, function: &'a FunctionData, cfg: Cfg) -> Self {
+        let slots = function
+            .labels()
+            .iter()
+            .flat_map(|(_, data)| data.instructions.iter())
+            .filter_map(|instr| match instr {
+                Instruction::StackAlloc { dst, .. } => Some(*dst),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            types,
+            function,
+            cfg,
+            creators: function.value_creators(),
+            slots,
+        }
+    }
+
+    /// The result of `condition`, computed in `edge.1`, when entered through `edge`.
+    fn condition(&self, edge: (Label, Label), condition: Value) -> Option<bool> {
+        if let Some(value) = self.value(edge, condition) {
+            return Some(value != 0);
+        }
+
+        if let Some(Instruction::IntCompare { pred, lhs, rhs, .. }) =
+            self.creator(condition)
+        {
+            if let (Some(l), Some(r)) = (self.value(edge, *lhs), self.value(edge, *rhs)) {
+                let ty = self.function.values().get(*lhs).ty();
+                let (num_bits, is_signed) = self.types.integer(ty)?;
+                return Some(evaluate(*pred, l, r, num_bits, is_signed));
+            }
+        }
+
+        self.implied(edge, condition)
+    }
+
+    /// The integer `value` holds when entering `edge.1` through `edge`: a constant,
+    /// or a load of a constant stored along the path.
+    fn value(&self, edge: (Label, Label), value: Value) -> Option<u64> {
+        if let Some(constant) = self.function.constant(value) {
+            return constant.integer();
+        }
+
+        let location = self.creators.get(&value)?;
+        let Instruction::Load { ptr, .. } = *self.function.instruction(location) else {
+            return None;
+        };
+        if location.label != edge.1 {
+            return None;
+        }
+
+        let before = &self.function.labels().get(edge.1).instructions
+            [..location.instruction as usize];
+        if let Some(stored) = self.last_store(before, ptr) {
+            return stored;
+        }
+
+        let mut visited = HashSet::new();
+        let mut label = edge.0;
+        for _ in 0..MAX_DEPTH {
+            let instructions = &self.function.labels().get(label).instructions;
+            if let Some(stored) = self.last_store(instructions, ptr) {
+                return stored;
+            }
+
+            visited.insert(label);
+            match self.single_predecessor(label) {
+                Some(predecessor) if !visited.contains(&predecessor) => {
+                    label = predecessor
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Looks backwards through `instructions` for the value stored to `ptr`.
+    /// Returns `None` if `ptr` is not written, `Some(None)` if it is written with an
+    /// unknown value.
+    fn last_store(
+        &self,
+        instructions: &[Instruction],
+        ptr: Value,
+    ) -> Option<Option<u64>> {
+        for instr in instructions.iter().rev() {
+            match instr {
+                Instruction::Store { ptr: p, value } if *p == ptr => {
+                    let constant = self.function.constant(*value);
+                    return Some(constant.and_then(|constant| constant.integer()));
+                }
+                // Distinct stack slots never alias.
+                Instruction::Store { ptr: p, .. }
+                    if self.slots.contains(p) && self.slots.contains(&ptr) => {}
+                Instruction::Store { .. } | Instruction::Call { .. } => {
+                    return Some(None)
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Looks for a branch deciding `condition` on the path to `edge`.
+    fn implied(&self, edge: (Label, Label), condition: Value) -> Option<bool> {
+        let mut visited = HashSet::new();
+        let (mut from, mut to) = edge;
+        for _ in 0..MAX_DEPTH {
+            if let Instruction::BranchConditional {
+                condition: known,
+                on_true,
+                on_false,
+            } = *self.function.last_instruction(from)
+            {
+                if on_true != on_false {
+                    if let Some(result) = self.implies(known, to == on_true, condition) {
+                        return Some(result);
+                    }
+                }
+            }
+
+            visited.insert(from);
+            match self.single_predecessor(from) {
+                Some(predecessor) if !visited.contains(&predecessor) => {
+                    (from, to) = (predecessor, from);
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// The result of `query`, knowing that `known` is `result`.
+    fn implies(&self, known: Value, result: bool, query: Value) -> Option<bool> {
+        if known == query {
+            return Some(result);
+        }
+
+        let (
+            Some(Instruction::IntCompare {
+                pred: known_pred,
+                lhs: known_lhs,
+                rhs: known_rhs,
+                ..
+            }),
+            Some(Instruction::IntCompare { pred, lhs, rhs, .. }),
+        ) = (self.creator(known), self.creator(query))
+        else {
+            return None;
+        };
+
+        let pred = if self.same(*known_lhs, *lhs) && self.same(*known_rhs, *rhs) {
+            *pred
+        } else if self.same(*known_lhs, *rhs) && self.same(*known_rhs, *lhs) {
+            pred.swapped()
+        } else {
+            return None;
+        };
+        let known_pred = if result {
+            *known_pred
+        } else {
+            known_pred.inverse()
+        };
+
+        implied_by(known_pred, pred)
+    }
+
+    fn creator(&self, value: Value) -> Option<&Instruction> {
+        let location = self.creators.get(&value)?;
+        Some(self.function.instruction(location))
+    }
+
+    fn single_predecessor(&self, label: Label) -> Option<Label> {
+        let mut predecessors = self.cfg.incoming(label);
+        let (predecessor, _, _) = predecessors.next()?;
+        predecessors.next().is_none().then_some(predecessor)
+    }
+
+    /// Returns `true` if `a` and `b` always hold the same value.
+    fn same(&self, a: Value, b: Value) -> bool {
+        if a == b {
+            return true;
+        }
+
+        match (self.function.constant(a), self.function.constant(b)) {
+            (
+                Some(ConstantValue::Integer { ty: a_ty, value: a }),
+                Some(ConstantValue::Integer { ty: b_ty, value: b }),
+            ) => a_ty == b_ty && a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The result of a compare with predicate `query`, knowing that the compare with
+/// predicate `known` on the same operands is true.
+fn implied_by(known: IntCompareOp, query: IntCompareOp) -> Option<bool> {
+    use IntCompareOp::*;
+
+    if query == known {
+        return Some(true);
+    }
+    if query == known.inverse() {
+        return Some(false);
+    }
+
+    match (known, query) {
+        (GreaterThan | LessThan, NotEqual) => Some(true),
+        (GreaterThan | LessThan, Equal) => Some(false),
+        (GreaterThan, GreaterThanOrEqual) | (LessThan, LessThanOrEqual) => Some(true),
+        (GreaterThan, LessThan) | (LessThan, GreaterThan) => Some(false),
+        (Equal, GreaterThanOrEqual | LessThanOrEqual) => Some(true),
+        (Equal, GreaterThan | LessThan) => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    constant::ConstantValue,
+    function::FunctionData,
+    induction_variable_analysis::TripCount,
+    instruction::{Instruction, IntCompareOp},
+    label::Label,
+    location::Location,
+    loop_analysis::Loop,
+    passes::{FunctionContext, Pass},
+};
+
+/// Unrolls innermost loops whose trip count is known at compile time.
+///
+/// Small loops are unrolled fully, one copy of the loop per iteration:
+/// ```text
+/// for i in 0..3 { a += i; }   ->   a += 0; a += 1; a += 2;
+/// ```
+/// Bigger loops are unrolled partially. The unrolled loop runs the body `factor` times
+/// per iteration, and the original loop is kept as a remainder loop running the
+/// iterations left over:
+/// ```text
+/// for i in 0..10 { a += i; }  ->   for i in 0..8 step 4 { a += i; a += i + 1; ... }
+///                                  for i in 8..10 { a += i; }
+/// ```
+/// Only loops with a trip count known from [`InductionVariableAnalysis`] are unrolled.
+///
+/// [`InductionVariableAnalysis`]: crate::induction_variable_analysis::InductionVariableAnalysis
+pub(crate) struct LoopUnrollingPass {
+    /// Loops are fully unrolled when `trip count * loop size` is at most this.
+    pub full_unroll_threshold: usize,
+    /// Maximum number of copies of the body in a partially unrolled loop.
+    pub unroll_factor: usize,
+    /// Partially unrolled loops may grow up to this number of instructions.
+    pub partial_unroll_threshold: usize,
+    /// Headers of the loops already considered, by function name. This includes the
+    /// loops created by partial unrolling, which must not be unrolled again.
+    visited: HashMap<String, HashSet<Label>>,
+}
+
+impl Default for LoopUnrollingPass {
+    fn default() -> Self {
+        Self {
+            full_unroll_threshold: 256,
+            unroll_factor: 4,
+            partial_unroll_threshold: 128,
+            visited: HashMap::new(),
+        }
+    }
+}
+
+impl Pass for LoopUnrollingPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        let name = ctx.function.definition().name.clone();
+        let visited = self.visited.entry(name).or_default();
+        loop {
+            let analysis = ctx.function.loop_analysis();
+            let candidate = (0..analysis.loops.len()).find(|i| {
+                analysis.is_innermost(*i) && !visited.contains(&analysis.loops[*i].header)
+            });
+            let Some(index) = candidate else {
+                break;
+            };
+
+            let l = &analysis.loops[index];
+            visited.insert(l.header);
+
+            let induction_variables =
+                ctx.function.induction_variables(ctx.types, &analysis);
+            let Some(trip_count) = &induction_variables.loops[index].trip_count else {
+                continue;
+            };
+
+            let size = loop_size(ctx.function, l);
+            if (trip_count.count as usize).saturating_mul(size)
+                <= self.full_unroll_threshold
+            {
+                full_unroll(ctx.function, l, trip_count);
+                continue;
+            }
+
+            let mut factor = self.unroll_factor;
+            while factor >= 2 && factor * size > self.partial_unroll_threshold {
+                factor /= 2;
+            }
+            if factor >= 2 && trip_count.count >= factor as u64 {
+                let header = partial_unroll(ctx.function, l, trip_count, factor);
+                visited.insert(header);
+            }
+        }
+    }
+}
+
+/// Number of instructions in the loop, not counting `nop`s.
+fn loop_size(function: &FunctionData, l: &Loop) -> usize {
+    l.blocks
+        .iter()
+        .flat_map(|label| function.labels().get(*label).instructions.iter())
+        .filter(|instr| !matches!(instr, Instruction::Nop))
+        .count()
+}
+
+/// Replaces the loop with `count` copies of itself, one per iteration.
+/// The original loop is kept for the final header check, which always exits.
+fn full_unroll(function: &mut FunctionData, l: &Loop, trip_count: &TripCount) {
+    let cfg = function.labels().cfg();
+    let entering = l.entering(&cfg);
+
+    let copies = (0..trip_count.count)
+        .map(|_| clone_loop(function, l))
+        .collect::<Vec<_>>();
+
+    let first = copies.first().map_or(l.header, |copy| copy[&l.header]);
+    for label in entering {
+        retarget(function, label, l.header, first);
+    }
+
+    for (i, copy) in copies.iter().enumerate() {
+        let next = copies.get(i + 1).map_or(l.header, |next| next[&l.header]);
+        for latch in l.latches.iter() {
+            retarget(function, copy[latch], copy[&l.header], next);
+        }
+
+        *function.last_instruction_mut(copy[&l.header]) = Instruction::Branch {
+            target: copy[&trip_count.body],
+        };
+    }
+
+    *function.last_instruction_mut(l.header) = Instruction::Branch {
+        target: trip_count.exit,
+    };
+}
+
+/// Runs `factor` copies of the body per iteration, as long as at least `factor`
+/// iterations are left. The original loop runs the remaining iterations.
+/// Returns the header of the unrolled loop.
+fn partial_unroll(
+    function: &mut FunctionData,
+    l: &Loop,
+    trip_count: &TripCount,
+    factor: usize,
+) -> Label {
+    let cfg = function.labels().cfg();
+    let entering = l.entering(&cfg);
+
+    let copies = (0..factor)
+        .map(|_| clone_loop(function, l))
+        .collect::<Vec<_>>();
+    let header = copies[0][&l.header];
+
+    for label in entering {
+        retarget(function, label, l.header, header);
+    }
+
+    for (i, copy) in copies.iter().enumerate() {
+        let next = copies[(i + 1) % factor][&l.header];
+        for latch in l.latches.iter() {
+            retarget(function, copy[latch], copy[&l.header], next);
+        }
+
+        if i != 0 {
+            *function.last_instruction_mut(copy[&l.header]) = Instruction::Branch {
+                target: copy[&trip_count.body],
+            };
+        }
+    }
+
+    // The unrolled loop stops once the induction variable reaches its value after
+    // the last complete group of iterations, and continues in the remainder loop.
+    let iterations = trip_count.count - trip_count.count % factor as u64;
+    let end = function.alloc_constant(ConstantValue::Integer {
+        ty: trip_count.ty,
+        value: trip_count.value_after(iterations),
+    });
+
+    let compare = Location {
+        label: header,
+        instruction: trip_count.compare.instruction,
+    };
+    let Instruction::IntCompare { dst, lhs, .. } = *function.instruction(&compare) else {
+        unreachable!()
+    };
+    *function.instruction_mut(&compare) = Instruction::IntCompare {
+        pred: if trip_count.continue_on_true {
+            IntCompareOp::NotEqual
+        } else {
+            IntCompareOp::Equal
+        },
+        dst,
+        lhs,
+        rhs: end,
+    };
+    retarget(function, header, trip_count.exit, l.header);
+
+    header
+}
+
+/// Copies the blocks of `l`, with new labels and values.
+/// Edges leaving the loop keep their targets. Returns the label mapping.
+fn clone_loop(function: &mut FunctionData, l: &Loop) -> HashMap<Label, Label> {
+    let mut blocks = l.blocks.iter().copied().collect::<Vec<_>>();
+    blocks.sort();
+
+    let label_map: HashMap<Label, Label> = blocks
+        .iter()
+        .map(|label| {
+            let name = function.labels().get(*label).name.clone();
+            (*label, function.labels_mut().create(&name))
+        })
+        .collect();
+
+    let mut value_map = HashMap::new();
+    for label in blocks.iter() {
+        let count = function.labels().get(*label).instructions.len();
+        for i in 0..count {
+            let location = Location {
+                label: *label,
+                instruction: i as u32,
+            };
+            if let Some(created) = function.instruction(&location).creates() {
+                let ty = function.values().get(created).ty();
+                value_map.insert(created, function.values_mut().alloc(ty));
+            }
+        }
+    }
+
+    for label in blocks.iter() {
+        let mut instructions = function.labels().get(*label).instructions.clone();
+        for instr in instructions.iter_mut() {
+            if let Some(created) = instr.creates_mut() {
+                *created = value_map[created];
+            }
+            for read in instr.reads_mut() {
+                if let Some(value) = value_map.get(read) {
+                    *read = *value;
+                }
+            }
+            for target in instr.targets_mut() {
+                if let Some(label) = label_map.get(target) {
+                    *target = *label;
+                }
+            }
+        }
+
+        function.labels_mut().get_mut(label_map[label]).instructions = instructions;
+    }
+
+    label_map
+}
+
+/// Redirects the edges `label -> from` to `label -> to`.
+fn retarget(function: &mut FunctionData, label: Label, from: Label, to: Label) {
+    for target in function.last_instruction_mut(label).targets_mut() {
+        if *target == from {
+            *target = to;
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    alias_analysis::{Alias, AliasAnalysis},
+    constant::ConstantValue,
+    function::FunctionData,
+    instruction::Instruction,
+    label::Label,
+    location::Location,
+    passes::{FunctionContext, Pass},
+    pointer_analysis::PointerAnalysis,
+    value::Value,
+};
+
+/// Values known to be held by memory, `pointer -> value`.
+type Available = HashMap<Value, Value>;
+
+/// Stack slots that may still be read, before they are overwritten.
+type Live = HashSet<Value>;
+
+/// How a memory access relates to the tracked stack slots.
+#[derive(Debug, Clone, Copy)]
+enum Access {
+    /// The pointer is exactly the slot.
+    Direct(Value),
+    /// The pointer points somewhere inside the slot, e.g. through a `GetElementPtr`.
+    Partial(Value),
+    /// The pointer can not point to any tracked slot.
+    Untracked,
+}
+
+/// Memory optimizations:
+/// - store-to-load forwarding, within and across blocks,
+/// - dead store elimination on stack slots that do not escape the function,
+/// - removal of slots that are no longer used.
+///
+/// ```text
+/// block_0:                                 block_0:
+///     let v2: *u32 = stack_alloc.u32 1
+///     store.*u32 v2, v0
+///     let v3: u32 = load.*u32 v2      ->
+///     let v4: u32 = add.u32 v3, v3             let v4: u32 = add.u32 v0, v0
+///     ret v4                                   ret v4
+/// ```
+/// Forwarding works through any pointer, and asks [`AliasAnalysis`] which stores and
+/// calls may overwrite a known value. The other optimizations only track slots that
+/// do not escape (see [`PointerAnalysis`]), so calls and accesses through other
+/// pointers can never touch them.
+#[derive(Default)]
+pub(crate) struct MemoryOptimizationPass;
+
+impl Pass for MemoryOptimizationPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        loop {
+            let pointer_analysis = ctx.function.pointer_analysis(ctx.types);
+            let alias_analysis =
+                AliasAnalysis::new(ctx.types, ctx.function, &pointer_analysis);
+            let slots = Self::tracked_slots(ctx.function, &pointer_analysis);
+
+            let forwarded = Self::forward_stores(ctx.function, &alias_analysis);
+            let removed =
+                Self::remove_dead_stores(ctx.function, &pointer_analysis, &slots);
+            let allocas = Self::remove_unused_slots(ctx.function, &slots);
+
+            if !forwarded && !removed && !allocas {
+                break;
+            }
+        }
+    }
+}
+
+impl MemoryOptimizationPass {
+    /// Stack slots that do not escape, mapped to their size.
+    /// A slot also escapes when any pointer derived from it does.
+    fn tracked_slots(
+        function: &FunctionData,
+        pointer_analysis: &PointerAnalysis,
+    ) -> HashMap<Value, usize> {
+        let escaped: HashSet<Value> = pointer_analysis
+            .escaped_pointers
+            .iter()
+            .map(|pointer| pointer_analysis.pointer_origins[pointer])
+            .chain(pointer_analysis.escaped_pointers.iter().copied())
+            .collect();
+
+        pointer_analysis
+            .creators
+            .iter()
+            .filter(|(value, _)| !escaped.contains(value))
+            .filter_map(|(value, location)| match function.instruction(location) {
+                Instruction::StackAlloc { size, .. } => Some((*value, *size)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Classifies an access through `ptr`.
+    fn access(
+        ptr: Value,
+        pointer_analysis: &PointerAnalysis,
+        slots: &HashMap<Value, usize>,
+    ) -> Access {
+        if slots.contains_key(&ptr) {
+            return Access::Direct(ptr);
+        }
+
+        match pointer_analysis.pointer_origins.get(&ptr) {
+            Some(origin) if slots.contains_key(origin) => Access::Partial(*origin),
+            _ => Access::Untracked,
+        }
+    }
+
+    /// Replaces loads from slots whose content is known with the known value.
+    /// Returns `true` if anything changed.
+    fn forward_stores(
+        function: &mut FunctionData,
+        alias_analysis: &AliasAnalysis,
+    ) -> bool {
+        let cfg = function.labels().cfg();
+        let mut order = Vec::new();
+        cfg.bfs(|label| order.push(label));
+
+        // Forward data-flow analysis. Predecessors that were not visited yet are skipped,
+        // so loops start optimistic and only shrink until a fixed point is reached.
+        let mut outputs: HashMap<Label, Available> = HashMap::new();
+        let mut inputs: HashMap<Label, Available> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for label in order.iter() {
+                let input = if *label == function.labels().entry() {
+                    Available::new()
+                } else {
+                    let predecessors = cfg
+                        .incoming(*label)
+                        .filter_map(|(from, _, _)| outputs.get(&from));
+                    meet(function, predecessors)
+                };
+
+                let mut output = input.clone();
+                for instr in function.labels().get(*label).instructions.iter() {
+                    Self::transfer(function, instr, &mut output, alias_analysis);
+                }
+
+                inputs.insert(*label, input);
+                if outputs.get(label) != Some(&output) {
+                    outputs.insert(*label, output);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        // Rewrite loads. Forwarded values may themselves have been replaced already.
+        let mut replaced: HashMap<Value, Value> = HashMap::new();
+        let resolve = |replaced: &HashMap<Value, Value>, mut value: Value| {
+            while let Some(next) = replaced.get(&value) {
+                value = *next;
+            }
+            value
+        };
+
+        for label in order.iter() {
+            let mut available = inputs.remove(label).unwrap();
+            let count = function.labels().get(*label).instructions.len();
+
+            for i in 0..count {
+                let location = Location {
+                    label: *label,
+                    instruction: i as u32,
+                };
+                let instr = function.instruction(&location).clone();
+
+                if let Instruction::Load { dst, ptr } = instr {
+                    if let Some(value) = known_value(&available, ptr, alias_analysis) {
+                        let value = resolve(&replaced, value);
+                        replaced.insert(dst, value);
+
+                        *function.instruction_mut(&location) = Instruction::Nop;
+                        function.replace_uses(dst, value);
+                        continue;
+                    }
+                }
+
+                Self::transfer(function, &instr, &mut available, alias_analysis);
+            }
+        }
+
+        !replaced.is_empty()
+    }
+
+    /// Updates the memory contents known after `instr`.
+    fn transfer(
+        function: &FunctionData,
+        instr: &Instruction,
+        available: &mut Available,
+        alias_analysis: &AliasAnalysis,
+    ) {
+        // A value that is computed again no longer matches what was stored earlier,
+        // e.g. in the next iteration of a loop. The same goes for pointers.
+        if let Some(created) = instr.creates() {
+            available.retain(|ptr, value| *ptr != created && *value != created);
+        }
+
+        match instr {
+            Instruction::Store { ptr, value } => {
+                available
+                    .retain(|other, _| alias_analysis.alias(*ptr, *other) == Alias::No);
+                available.insert(*ptr, *value);
+            }
+            Instruction::Load { dst, ptr }
+                if known_value(available, *ptr, alias_analysis).is_none() =>
+            {
+                available.insert(*ptr, *dst);
+            }
+            // Calls may write through their arguments, and to any memory that escaped.
+            Instruction::Call {
+                function: callee,
+                arguments,
+                ..
+            } => {
+                available.retain(|ptr, _| {
+                    alias_analysis.is_local(*ptr)
+                        && !arguments.iter().enumerate().any(|(index, argument)| {
+                            function.parameter_effects(*callee, index).written
+                                && alias_analysis.same_object(*argument, *ptr)
+                        })
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes stores to slots that are never read afterwards.
+    /// Returns `true` if anything changed.
+    fn remove_dead_stores(
+        function: &mut FunctionData,
+        pointer_analysis: &PointerAnalysis,
+        slots: &HashMap<Value, usize>,
+    ) -> bool {
+        let cfg = function.labels().cfg();
+        let mut order = Vec::new();
+        cfg.bfs(|label| order.push(label));
+        order.reverse();
+
+        // Backward liveness analysis of slots.
+        let mut inputs: HashMap<Label, Live> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for label in order.iter() {
+                let mut live = Live::new();
+                for (_, to, _) in cfg.outgoing(*label) {
+                    if let Some(input) = inputs.get(&to) {
+                        live.extend(input.iter().copied());
+                    }
+                }
+
+                for instr in function.labels().get(*label).instructions.iter().rev() {
+                    Self::is_dead_store(
+                        function,
+                        instr,
+                        &mut live,
+                        pointer_analysis,
+                        slots,
+                    );
+                }
+
+                if inputs.get(label) != Some(&live) {
+                    inputs.insert(*label, live);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let mut removed = false;
+        for label in order.iter() {
+            let mut live = Live::new();
+            for (_, to, _) in cfg.outgoing(*label) {
+                live.extend(inputs[&to].iter().copied());
+            }
+
+            let mut dead = Vec::new();
+            let instructions = &function.labels().get(*label).instructions;
+            for (i, instr) in instructions.iter().enumerate().rev() {
+                if Self::is_dead_store(
+                    function,
+                    instr,
+                    &mut live,
+                    pointer_analysis,
+                    slots,
+                ) {
+                    dead.push(i);
+                }
+            }
+
+            let instructions = &mut function.labels_mut().get_mut(*label).instructions;
+            for i in dead {
+                instructions[i] = Instruction::Nop;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+
+    /// Updates the live slots before `instr`, and returns `true` if `instr` is a dead store.
+    fn is_dead_store(
+        function: &FunctionData,
+        instr: &Instruction,
+        live: &mut Live,
+        pointer_analysis: &PointerAnalysis,
+        slots: &HashMap<Value, usize>,
+    ) -> bool {
+        match instr {
+            Instruction::Store { ptr, .. } => {
+                match Self::access(*ptr, pointer_analysis, slots) {
+                    Access::Direct(slot) => {
+                        let is_dead = !live.contains(&slot);
+                        // Only a store to a single element slot overwrites all of it.
+                        if slots[&slot] == 1 {
+                            live.remove(&slot);
+                        }
+                        is_dead
+                    }
+                    Access::Partial(slot) => !live.contains(&slot),
+                    Access::Untracked => false,
+                }
+            }
+            Instruction::Load { ptr, .. } => {
+                match Self::access(*ptr, pointer_analysis, slots) {
+                    Access::Direct(slot) | Access::Partial(slot) => {
+                        live.insert(slot);
+                    }
+                    Access::Untracked => {}
+                }
+                false
+            }
+            // Slots passed to a call are read if the callee reads its parameter.
+            Instruction::Call {
+                function: callee,
+                arguments,
+                ..
+            } => {
+                for (index, argument) in arguments.iter().enumerate() {
+                    if let Access::Direct(slot) | Access::Partial(slot) =
+                        Self::access(*argument, pointer_analysis, slots)
+                    {
+                        if function.parameter_effects(*callee, index).read {
+                            live.insert(slot);
+                        }
+                    }
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes slots, and pointers derived from them, that are not used anymore.
+    /// Returns `true` if anything changed.
+    fn remove_unused_slots(
+        function: &mut FunctionData,
+        slots: &HashMap<Value, usize>,
+    ) -> bool {
+        let mut used = HashSet::new();
+        for (_, data) in function.labels().iter() {
+            for instr in data.instructions.iter() {
+                if let Some(reads) = instr.reads() {
+                    used.extend(reads);
+                }
+            }
+        }
+
+        let mut removed = false;
+        for (_, data) in function.labels_mut().iter_mut() {
+            for instr in data.instructions.iter_mut() {
+                if let Instruction::StackAlloc { dst, .. } = instr {
+                    if slots.contains_key(dst) && !used.contains(dst) {
+                        *instr = Instruction::Nop;
+                        removed = true;
+                    }
+                }
+            }
+        }
+
+        removed
+    }
+}
+
+/// The value known to be held by the memory `ptr` points to.
+fn known_value(
+    available: &Available,
+    ptr: Value,
+    alias_analysis: &AliasAnalysis,
+) -> Option<Value> {
+    if let Some(value) = available.get(&ptr) {
+        return Some(*value);
+    }
+
+    // Stores kill every other pointer that may alias, so at most one matches.
+    available
+        .iter()
+        .find(|(other, _)| alias_analysis.alias(ptr, **other) == Alias::Must)
+        .map(|(_, value)| *value)
+}
+
+/// Keeps only the memory contents that all predecessors agree on.
+fn meet<'a>(
+    function: &FunctionData,
+    mut predecessors: impl Iterator<Item = &'a Available>,
+) -> Available {
+    let Some(first) = predecessors.next() else {
+        return Available::new();
+    };
+
+    let mut result = first.clone();
+    for other in predecessors {
+        result.retain(|slot, value| {
+            other
+                .get(slot)
+                .is_some_and(|other| same_value(function, *value, *other))
+        });
+    }
+
+    result
+}
+
+/// Returns `true` if `a` and `b` are the same value, or equal integer constants.
+/// Every constant in the source gets its own value, so both branches of an `if`
+/// storing `1` store different values.
+fn same_value(function: &FunctionData, a: Value, b: Value) -> bool {
+    if a == b {
+        return true;
+    }
+
+    match (function.constant(a), function.constant(b)) {
+        (
+            Some(ConstantValue::Integer { ty: a_ty, value: a }),
+            Some(ConstantValue::Integer { ty: b_ty, value: b }),
+        ) => a_ty == b_ty && a == b,
+        _ => false,
+    }
+}
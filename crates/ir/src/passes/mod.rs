@@ -3,9 +3,23 @@
 
 pub mod constant_folding;
 pub mod dead_code_elimination;
+pub mod dead_function_elimination;
+pub mod if_conversion;
+pub mod induction_variable_simplification;
+pub mod inlining;
+pub mod instruction_combining;
+pub mod interprocedural_constant_propagation;
+pub mod jump_threading;
+pub mod loop_unrolling;
+pub mod memory_optimization;
 pub mod remove_noops;
 pub mod simplify_cfg;
-use crate::{function::FunctionData, ty::Types};
+pub mod tail_call_elimination;
+pub mod value_range_propagation;
+use crate::{
+    function::{FunctionData, Functions},
+    ty::Types,
+};
 
 ///
 pub(crate) struct FunctionContext<'a> {
@@ -24,3 +38,21 @@ pub(crate) trait Pass {
     ///
     fn run(&mut self, ctx: &mut FunctionContext<'_>);
 }
+
+/// Everything a [`ModulePass`] may change: all functions, and the types they use.
+pub(crate) struct ModuleContext<'a> {
+    pub types: &'a mut Types,
+    pub functions: &'a mut Functions,
+}
+
+impl<'a> ModuleContext<'a> {
+    pub fn new(types: &'a mut Types, functions: &'a mut Functions) -> Self {
+        Self { types, functions }
+    }
+}
+
+/// A transformation looking at several functions at once.
+pub(crate) trait ModulePass {
+    /// Transforms the module in place.
+    fn run(&mut self, ctx: &mut ModuleContext<'_>);
+}
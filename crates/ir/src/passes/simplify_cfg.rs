@@ -1,25 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     instruction::Instruction,
     passes::{FunctionContext, Pass},
 };
 
-///
+/// Cleans up the control flow graph:
+/// - folds `branch_if` whose both targets are the same block,
+/// - skips empty blocks that only jump somewhere else,
+/// - deletes blocks that are unreachable from the entry block,
+/// - merges blocks into their only predecessor.
 #[derive(Default)]
 pub(crate) struct SimplifyCfgPass;
 
 impl Pass for SimplifyCfgPass {
-    ///
+    /// Runs the simplifications until none of them changes anything, since each one can
+    /// expose more work for the others.
     fn run(&mut self, ctx: &mut FunctionContext<'_>) {
-        self.optimize_jumps(ctx);
-        self.optimize_branches(ctx);
+        loop {
+            let mut changed = self.fold_same_target_branches(ctx);
+            changed |= self.optimize_branches(ctx);
+            changed |= self.remove_unreachable_blocks(ctx);
+            changed |= self.optimize_jumps(ctx);
+
+            if !changed {
+                break;
+            }
+        }
     }
 }
 
 impl SimplifyCfgPass {
-    ///
-    fn optimize_jumps(&mut self, ctx: &mut FunctionContext<'_>) {
+    /// Merges blocks into their only predecessor, when it jumps to them unconditionally.
+    /// Returns `true` if anything changed.
+    fn optimize_jumps(&mut self, ctx: &mut FunctionContext<'_>) -> bool {
+        let mut changed = false;
         let mut labels = Vec::new();
         'merge_loop: loop {
             // #TODO: Do something with it in the future.
@@ -66,14 +81,18 @@ impl SimplifyCfgPass {
 
                 dominator_instructions.append(&mut instructions);
 
+                changed = true;
                 continue 'merge_loop;
             }
             break;
         }
+
+        changed
     }
 
-    ///
-    fn optimize_branches(&mut self, ctx: &mut FunctionContext<'_>) {
+    /// Redirects branches to empty forwarding blocks to their final destination.
+    /// Returns `true` if anything changed.
+    fn optimize_branches(&mut self, ctx: &mut FunctionContext<'_>) -> bool {
         // Now optimize branches
         // For example:
         // block_0:
@@ -93,48 +112,88 @@ impl SimplifyCfgPass {
         //     ...
         let mut branch_to_label = HashMap::new();
         for (label, data) in ctx.function.labels().iter() {
-            let instructions = &data.instructions;
-            if instructions.len() != 1 {
-                continue;
+            if let [Instruction::Branch { target }] = &data.instructions[..] {
+                branch_to_label.insert(*label, *target);
             }
+        }
 
-            if let Instruction::Branch { target } = ctx.function.last_instruction(*label)
-            {
-                if label == target {
-                    // It is a loop, skip
-                    continue;
+        // Follow chains of forwarding blocks, e.g. the empty latch of a loop jumping
+        // to an empty block jumping to the loop header.
+        // A chain ending in a cycle is an infinite loop and is left alone.
+        let mut destinations = HashMap::new();
+        for &label in branch_to_label.keys() {
+            let mut visited = HashSet::from([label]);
+            let mut destination = branch_to_label[&label];
+            while let Some(&next) = branch_to_label.get(&destination) {
+                if !visited.insert(destination) {
+                    break;
                 }
+                destination = next;
+            }
 
-                branch_to_label.insert(*label, *target);
+            if !branch_to_label.contains_key(&destination) {
+                destinations.insert(label, destination);
             }
         }
 
-        let labels = ctx.function.labels().labels().cloned().collect::<Vec<_>>();
-
-        for label in labels.iter() {
-            if branch_to_label.get(label).is_some() {
-                // It is a loop, skip
+        let mut changed = false;
+        for (_, data) in ctx.function.labels_mut().iter_mut() {
+            let Some(last) = data.instructions.last_mut() else {
                 continue;
-            }
+            };
 
-            match ctx.function.last_instruction_mut(*label) {
-                Instruction::Branch { target } => {
-                    if let Some(&new_target) = branch_to_label.get(target) {
-                        *target = new_target;
-                    }
+            for target in last.targets_mut() {
+                if let Some(&destination) = destinations.get(target) {
+                    *target = destination;
+                    changed = true;
                 }
-                Instruction::BranchConditional {
+            }
+        }
+
+        changed
+    }
+
+    /// Replaces `branch_if` whose both targets are the same block with a `branch`.
+    /// Returns `true` if anything changed.
+    fn fold_same_target_branches(&mut self, ctx: &mut FunctionContext<'_>) -> bool {
+        let mut changed = false;
+        for (_, data) in ctx.function.labels_mut().iter_mut() {
+            if let Some(last) = data.instructions.last_mut() {
+                if let Instruction::BranchConditional {
                     on_true, on_false, ..
-                } => {
-                    if let Some(&new_target) = branch_to_label.get(on_true) {
-                        *on_true = new_target;
-                    }
-                    if let Some(&new_target) = branch_to_label.get(on_false) {
-                        *on_false = new_target;
+                } = *last
+                {
+                    if on_true == on_false {
+                        *last = Instruction::Branch { target: on_true };
+                        changed = true;
                     }
                 }
-                _ => {}
             }
         }
+
+        changed
+    }
+
+    /// Deletes blocks that can not be reached from the entry block.
+    /// Returns `true` if anything changed.
+    fn remove_unreachable_blocks(&mut self, ctx: &mut FunctionContext<'_>) -> bool {
+        let mut reachable = HashSet::new();
+        ctx.function.labels().cfg().bfs(|label| {
+            reachable.insert(label);
+        });
+
+        let unreachable = ctx
+            .function
+            .labels()
+            .labels()
+            .filter(|label| !reachable.contains(*label))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for label in unreachable.iter() {
+            ctx.function.labels_mut().remove(*label);
+        }
+
+        !unreachable.is_empty()
     }
 }
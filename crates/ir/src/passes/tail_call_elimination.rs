@@ -0,0 +1,214 @@
+use crate::{
+    function::{Function, FunctionData},
+    instruction::Instruction,
+    label::Instructions,
+    location::Location,
+    passes::{ModuleContext, ModulePass},
+    ty::Types,
+};
+
+/// Turns self-recursive tail calls into a loop, so the recursion runs in constant
+/// stack space, and marks the other tail calls with `tail`.
+///
+/// The entry block becomes the loop header, reading the parameters from stack slots.
+/// A new entry block stores the incoming parameters into the slots, and every tail
+/// call stores its arguments into them and jumps back to the header:
+/// ```text
+/// block_0:                                  block_0:
+///     ...                                       let v5: *u32 = stack_alloc.u32 1
+///     branch_if v2 block_1, block_2             store.*u32 v5, v0
+/// block_1:                                      branch block_3
+///     let v3: u32 = call function_0(v4)  ->   block_3:
+///     ret v3                                    let v6: u32 = load.*u32 v5
+/// block_2:                                      ...
+///     ret v0                                    branch_if v2 block_1, block_2
+///                                           block_1:
+///                                               store.*u32 v5, v4
+///                                               branch block_3
+///                                           block_2:
+///                                               ret v6
+/// ```
+/// Calls with pointer arguments are never tail calls, as the pointers may point into
+/// the caller stack frame.
+///
+/// Passes moving calls around may leave stale `tail` marks, so this should run
+/// again after them.
+#[derive(Default)]
+pub(crate) struct TailCallEliminationPass;
+
+impl ModulePass for TailCallEliminationPass {
+    fn run(&mut self, ctx: &mut ModuleContext<'_>) {
+        for (id, function) in ctx.functions.iter_mut() {
+            eliminate_self_calls(ctx.types, Function(id), function);
+            mark_tail_calls(ctx.types, function);
+        }
+    }
+}
+
+/// Replaces tail calls of `handle` to itself with jumps back to the beginning.
+fn eliminate_self_calls(
+    types: &mut Types,
+    handle: Function,
+    function: &mut FunctionData,
+) {
+    let is_self_call = |function: &FunctionData, location: &Location| {
+        matches!(
+            function.instruction(location),
+            Instruction::Call { function: callee, .. } if *callee == handle
+        )
+    };
+    if !tail_calls(types, function)
+        .iter()
+        .any(|location| is_self_call(function, location))
+    {
+        return;
+    }
+
+    // Move the entry block into a header block, except the stack slots, which are
+    // reused by every iteration.
+    let entry = function.labels().entry();
+    let header = function.labels_mut().create("tail_call_header");
+    let (mut slots, mut body): (Instructions, Instructions) =
+        std::mem::take(&mut function.labels_mut().get_mut(entry).instructions)
+            .into_iter()
+            .partition(|(instr, _)| matches!(instr, Instruction::StackAlloc { .. }));
+    for instr in body.iter_mut() {
+        for target in instr.targets_mut() {
+            if *target == entry {
+                *target = header;
+            }
+        }
+    }
+    for (_, data) in function.labels_mut().iter_mut() {
+        for instr in data.instructions.iter_mut() {
+            for target in instr.targets_mut() {
+                if *target == entry {
+                    *target = header;
+                }
+            }
+        }
+    }
+    function.labels_mut().get_mut(header).instructions = body;
+
+    // Parameters are read from stack slots at the beginning of every iteration. The
+    // slots belong to the declaration of the function, like the parameters.
+    let declaration = function
+        .definition()
+        .debug_info
+        .as_ref()
+        .map(|debug_info| debug_info.location);
+    let entry_instructions = &mut slots;
+    let mut loads = Vec::new();
+    let mut parameter_slots = Vec::new();
+    for parameter in function.parameters().to_vec() {
+        let ty = function.values().get(parameter).ty();
+        let slot = function.values_mut().alloc(types.add_pointer(ty));
+        let current = function.values_mut().alloc(ty);
+        function.replace_uses(parameter, current);
+
+        entry_instructions.push_located(
+            Instruction::StackAlloc {
+                dst: slot,
+                ty,
+                size: 1,
+            },
+            declaration,
+        );
+        entry_instructions.push_located(
+            Instruction::Store {
+                ptr: slot,
+                value: parameter,
+            },
+            declaration,
+        );
+        loads.push(Instruction::Load {
+            dst: current,
+            ptr: slot,
+        });
+        parameter_slots.push(slot);
+    }
+    entry_instructions.push_located(Instruction::Branch { target: header }, declaration);
+    function.labels_mut().get_mut(entry).instructions = slots;
+    function
+        .labels_mut()
+        .get_mut(header)
+        .instructions
+        .insert_all(0, loads, declaration);
+
+    let self_calls = tail_calls(types, function)
+        .into_iter()
+        .filter(|location| is_self_call(function, location))
+        .collect::<Vec<_>>();
+    for location in self_calls {
+        let instructions =
+            &mut function.labels_mut().get_mut(location.label).instructions;
+        let Instruction::Call { arguments, .. } =
+            instructions[location.instruction as usize].clone()
+        else {
+            unreachable!()
+        };
+
+        let call_location = instructions.location(location.instruction as usize);
+
+        instructions.truncate(location.instruction as usize);
+        for (slot, argument) in parameter_slots.iter().zip(arguments) {
+            instructions.push_located(
+                Instruction::Store {
+                    ptr: *slot,
+                    value: argument,
+                },
+                call_location,
+            );
+        }
+        instructions.push_located(Instruction::Branch { target: header }, call_location);
+    }
+}
+
+/// Sets `tail` on the tail calls of `function`, and clears it on every other call.
+fn mark_tail_calls(types: &Types, function: &mut FunctionData) {
+    let tail_calls = tail_calls(types, function);
+    for (label, data) in function.labels_mut().iter_mut() {
+        for (i, instr) in data.instructions.iter_mut().enumerate() {
+            if let Instruction::Call { tail, .. } = instr {
+                let location = Location {
+                    label: *label,
+                    instruction: i as u32,
+                };
+                *tail = tail_calls.contains(&location);
+            }
+        }
+    }
+}
+
+/// Locations of the calls whose result is returned right away, sorted.
+fn tail_calls(types: &Types, function: &FunctionData) -> Vec<Location> {
+    let mut locations = Vec::new();
+    for (label, data) in function.labels().iter() {
+        let Some((Instruction::Return { value }, rest)) = data.instructions.split_last()
+        else {
+            continue;
+        };
+        let Some(index) = rest
+            .iter()
+            .rposition(|instr| !matches!(instr, Instruction::Nop))
+        else {
+            continue;
+        };
+        let Instruction::Call { arguments, dst, .. } = &rest[index] else {
+            continue;
+        };
+
+        let has_pointer_argument = arguments
+            .iter()
+            .any(|argument| types.is_pointer(function.values().get(*argument).ty()));
+        if dst == value && !has_pointer_argument {
+            locations.push(Location {
+                label: *label,
+                instruction: index as u32,
+            });
+        }
+    }
+
+    locations.sort();
+    locations
+}
@@ -0,0 +1,166 @@
+use crate::{
+    constant::ConstantValue,
+    instruction::{BinaryOp, CastOp, Instruction},
+    location::Location,
+    passes::{FunctionContext, Pass},
+    value::Value,
+    value_range_analysis::{IntegerRange, ValueRangeAnalysis},
+};
+
+/// Simplifies integer code with the ranges of [`ValueRangeAnalysis`]:
+/// - operands known to hold a single value where they are read are replaced with
+///   that constant, which folds comparisons the ranges decide,
+/// - masks and remainders that can't change their operand are removed,
+/// - zero extensions undoing a truncation that lost no bits are removed.
+///
+/// ```text
+/// for i in 0..10 {                          for i in 0..10 {
+///     if i < 100 { a += i; }          ->        if true { a += i; }
+///     b += (i & 15) % 16;                       b += i;
+/// }                                         }
+/// ```
+#[derive(Default)]
+pub(crate) struct ValueRangePropagationPass;
+
+impl Pass for ValueRangePropagationPass {
+    fn run(&mut self, ctx: &mut FunctionContext<'_>) {
+        let analysis = ctx.function.value_ranges(ctx.types);
+        Self::replace_constants(ctx, &analysis);
+        Self::remove_redundant_operations(ctx, &analysis);
+    }
+}
+
+impl ValueRangePropagationPass {
+    /// Replaces the reads of values whose range is a single value in the block.
+    fn replace_constants(ctx: &mut FunctionContext<'_>, analysis: &ValueRangeAnalysis) {
+        let function = &mut *ctx.function;
+
+        let mut replacements = Vec::new();
+        for (label, data) in function.labels().iter() {
+            for (i, instr) in data.instructions.iter().enumerate() {
+                for read in instr.reads().into_iter().flatten() {
+                    if function.constant(read).is_some() {
+                        continue;
+                    }
+                    let Some(value) = analysis
+                        .range_at(read, *label)
+                        .and_then(|range| range.as_constant())
+                    else {
+                        continue;
+                    };
+
+                    let location = Location {
+                        label: *label,
+                        instruction: i as u32,
+                    };
+                    let ty = function.values().get(read).ty();
+                    replacements.push((
+                        location,
+                        read,
+                        ConstantValue::Integer { ty, value },
+                    ));
+                }
+            }
+        }
+
+        for (location, read, constant) in replacements {
+            let constant = function.alloc_constant(constant);
+            function
+                .instruction_mut(&location)
+                .replace_reads(read, constant);
+        }
+    }
+
+    /// Replaces the results of operations that return their operand unchanged.
+    fn remove_redundant_operations(
+        ctx: &mut FunctionContext<'_>,
+        analysis: &ValueRangeAnalysis,
+    ) {
+        let function = &mut *ctx.function;
+        let creators = function.value_creators();
+        let range_at =
+            |value: Value, location: &Location| analysis.range_at(value, location.label);
+
+        let mut replacements = Vec::new();
+        for (dst, location) in creators.iter() {
+            let replacement = match function.instruction(location) {
+                // `x & mask` where the bits cleared by the mask are already 0.
+                Instruction::ArithmeticBinary {
+                    lhs,
+                    op: BinaryOp::And | BinaryOp::BitAnd,
+                    rhs,
+                    ..
+                } => [(*lhs, *rhs), (*rhs, *lhs)]
+                    .into_iter()
+                    .find(|(value, mask)| {
+                        let (Some(range), Some(mask)) = (
+                            range_at(*value, location),
+                            function.constant(*mask).and_then(|c| c.integer()),
+                        ) else {
+                            return false;
+                        };
+                        let all = IntegerRange::full(range.num_bits).max;
+                        (range.known_zero | mask) & all == all
+                    })
+                    .map(|(value, _)| value),
+                // `x % y` where `x < y`.
+                Instruction::ArithmeticBinary {
+                    lhs,
+                    op: BinaryOp::Mod,
+                    rhs,
+                    ..
+                } => {
+                    let (Some(a), Some(b)) =
+                        (range_at(*lhs, location), range_at(*rhs, location))
+                    else {
+                        continue;
+                    };
+                    let is_signed = ctx
+                        .types
+                        .integer(function.values().get(*dst).ty())
+                        .is_some_and(|(_, is_signed)| is_signed);
+                    let non_negative =
+                        !is_signed || (a.is_non_negative() && b.is_non_negative());
+                    (non_negative && a.max < b.min).then_some(*lhs)
+                }
+                // `zext(trunc(x))` back to the type of `x`, where `x` fits the truncated type.
+                Instruction::Cast {
+                    cast_op: CastOp::ZeroExtend,
+                    value: truncated,
+                    ..
+                } => {
+                    let Some(Instruction::Cast {
+                        cast_op: CastOp::Truncate,
+                        value,
+                        ..
+                    }) = creators.get(truncated).map(|l| function.instruction(l))
+                    else {
+                        continue;
+                    };
+                    let integer = |value: Value| {
+                        ctx.types.integer(function.values().get(value).ty())
+                    };
+                    let (Some((truncated_bits, _)), Some(range)) =
+                        (integer(*truncated), range_at(*value, location))
+                    else {
+                        continue;
+                    };
+
+                    let fits = range.max <= IntegerRange::full(truncated_bits).max;
+                    (fits && integer(*value) == integer(*dst)).then_some(*value)
+                }
+                _ => None,
+            };
+
+            if let Some(value) = replacement {
+                replacements.push((*dst, value));
+            }
+        }
+
+        // Sorted, so the result does not depend on `HashMap` iteration order.
+        replacements.sort();
+        for (from, to) in replacements {
+            function.replace_uses(from, to);
+        }
+    }
+}
@@ -99,7 +99,9 @@ impl PointerAnalysis {
                         }
                     }
                     Instruction::StackAlloc { .. } => value,
-                    other => panic!("{other:?}"),
+                    // Pointers made up from integers, or by any other instruction, are
+                    // origins of their own.
+                    _ => value,
                 }
             } else {
                 // This could be a constant or a function parameter.
@@ -155,19 +157,27 @@ impl PointerAnalysis {
                     Instruction::ArithmeticUnary { .. } => true,
                     Instruction::Branch { .. } => panic!(),
                     Instruction::BranchConditional { .. } => panic!(),
-                    Instruction::Call { .. } => true,
+                    // The callee summary tells if it keeps the pointer around.
+                    Instruction::Call {
+                        function: callee,
+                        arguments,
+                        ..
+                    } => arguments.iter().enumerate().any(|(index, argument)| {
+                        *argument == *pointer
+                            && function.parameter_effects(*callee, index).escapes()
+                    }),
                     Instruction::Cast { .. } => true,
+                    // A derived pointer escaping takes the pointer with it, and a pointer
+                    // used as an index is turned into an integer.
                     Instruction::GetElementPtr { dst, ptr, .. } => {
-                        *ptr != *pointer && !escaped_pointers.contains(dst)
+                        *ptr != *pointer || escaped_pointers.contains(dst)
                     }
                     Instruction::IntCompare { .. } => false,
                     Instruction::Load { .. } => false,
                     Instruction::Return { .. } => false,
                     Instruction::Select { .. } => true,
                     Instruction::StackAlloc { .. } => panic!(),
-                    Instruction::Store { ptr, value } => {
-                        *ptr != *pointer && *value == *pointer
-                    }
+                    Instruction::Store { value, .. } => *value == *pointer,
                     Instruction::Nop => panic!(),
                 };
 
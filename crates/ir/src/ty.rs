@@ -83,6 +83,17 @@ impl Types {
         matches!(type_data.ty, TypeKind::Integer { .. })
     }
 
+    /// Returns `(num_bits, is_signed)` of an integer type.
+    pub fn integer(&self, handle: Type) -> Option<(u32, bool)> {
+        match self.get(handle).ty {
+            TypeKind::Integer {
+                num_bits,
+                is_signed,
+            } => Some((num_bits, is_signed)),
+            _ => None,
+        }
+    }
+
     ///
     pub fn is_struct(&self, handle: Type) -> bool {
         let type_data = self.get(handle);
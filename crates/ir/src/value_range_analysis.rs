@@ -0,0 +1,754 @@
+use crate::{
+    function::FunctionData,
+    instruction::{BinaryOp, CastOp, Instruction, IntCompareOp, UnaryOp},
+    label::Label,
+    loop_analysis::LoopAnalysis,
+    ty::Types,
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Number of times a range may grow before it is widened to the whole type.
+const WIDENING_DELAY: usize = 3;
+
+/// Number of iterations recovering the precision lost by widening.
+const NARROWING_STEPS: usize = 2;
+
+/// What is known about an integer value: the range it lies in, and the bits known to
+/// be fixed. Every field only uses the low `num_bits` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IntegerRange {
+    /// Smallest possible value, compared as unsigned.
+    pub min: u64,
+    /// Largest possible value, compared as unsigned.
+    pub max: u64,
+    /// Bits known to be 0.
+    pub known_zero: u64,
+    /// Bits known to be 1.
+    pub known_one: u64,
+    pub num_bits: u32,
+}
+
+impl IntegerRange {
+    /// Any value of the type.
+    pub fn full(num_bits: u32) -> Self {
+        Self::from_bounds(0, mask(num_bits), num_bits)
+    }
+
+    pub fn constant(value: u64, num_bits: u32) -> Self {
+        let value = value & mask(num_bits);
+        Self::from_bounds(value, value, num_bits)
+    }
+
+    /// Values in `min..=max`.
+    pub fn from_bounds(min: u64, max: u64, num_bits: u32) -> Self {
+        Self {
+            min,
+            max,
+            known_zero: 0,
+            known_one: 0,
+            num_bits,
+        }
+        .normalize()
+    }
+
+    /// Values matching the known bits.
+    pub fn from_bits(known_zero: u64, known_one: u64, num_bits: u32) -> Self {
+        let mask = mask(num_bits);
+        Self {
+            min: known_one & mask,
+            max: !known_zero & mask,
+            known_zero: known_zero & mask,
+            known_one: known_one & mask,
+            num_bits,
+        }
+        .normalize()
+    }
+
+    /// The only possible value, if any.
+    pub fn as_constant(&self) -> Option<u64> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    /// Returns `true` if the sign bit is known to be 0.
+    pub fn is_non_negative(&self) -> bool {
+        self.max >> (self.num_bits - 1) & 1 == 0
+    }
+
+    /// Returns `true` if the sign bit is known to be 1.
+    pub fn is_negative(&self) -> bool {
+        self.min >> (self.num_bits - 1) & 1 == 1
+    }
+
+    /// Every value either range allows.
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            known_zero: self.known_zero & other.known_zero,
+            known_one: self.known_one & other.known_one,
+            num_bits: self.num_bits,
+        }
+        .normalize()
+    }
+
+    /// Values both ranges allow, or `None` if there are none.
+    pub fn intersect(self, other: Self) -> Option<Self> {
+        let range = Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+            known_zero: self.known_zero | other.known_zero,
+            known_one: self.known_one | other.known_one,
+            num_bits: self.num_bits,
+        };
+        if range.min > range.max || range.known_zero & range.known_one != 0 {
+            return None;
+        }
+
+        let range = range.normalize();
+        (range.min <= range.max).then_some(range)
+    }
+
+    /// Number of low bits known to be 0.
+    pub fn trailing_zeros(&self) -> u32 {
+        (!self.known_zero).trailing_zeros().min(self.num_bits)
+    }
+
+    /// Tightens the bounds with the known bits, and the known bits with the bounds.
+    fn normalize(mut self) -> Self {
+        let mask = mask(self.num_bits);
+
+        // Every value in `min..=max` shares the bits above the highest differing bit.
+        let differing = self.min ^ self.max;
+        let common = if differing == 0 {
+            mask
+        } else {
+            let highest = 63 - differing.leading_zeros();
+            mask & !low_mask(highest + 1)
+        };
+        self.known_zero |= !self.min & common;
+        self.known_one |= self.min & common;
+
+        self.min = self.min.max(self.known_one);
+        self.max = self.max.min(!self.known_zero & mask);
+        self
+    }
+
+    /// Keeps only the values matching the known bits.
+    fn with_bits(self, known_zero: u64, known_one: u64) -> Self {
+        self.intersect(Self::from_bits(known_zero, known_one, self.num_bits))
+            .unwrap_or(self)
+    }
+
+    /// Grows `self` to hold `other`, jumping straight to the type bounds in the
+    /// directions it grew, so a fixpoint is reached.
+    fn widen(self, other: Self) -> Self {
+        let joined = self.union(other);
+        let min = if joined.min < self.min { 0 } else { joined.min };
+        let max = if joined.max > self.max {
+            mask(self.num_bits)
+        } else {
+            joined.max
+        };
+
+        Self { min, max, ..joined }.normalize()
+    }
+
+    /// The range as seen by a signed comparison, with the sign bit flipped so the
+    /// signed order becomes the unsigned one. The known bits are dropped.
+    fn biased(&self) -> Self {
+        let sign = 1 << (self.num_bits - 1);
+        if self.is_non_negative() || self.is_negative() {
+            Self::from_bounds(self.min ^ sign, self.max ^ sign, self.num_bits)
+        } else {
+            Self::full(self.num_bits)
+        }
+    }
+}
+
+/// Evaluates `lhs pred rhs` for every value in the ranges, if the result is the same.
+pub(crate) fn compare(
+    pred: IntCompareOp,
+    lhs: IntegerRange,
+    rhs: IntegerRange,
+    is_signed: bool,
+) -> Option<bool> {
+    if matches!(pred, IntCompareOp::Equal | IntCompareOp::NotEqual) {
+        let different = lhs.max < rhs.min
+            || rhs.max < lhs.min
+            || lhs.known_zero & rhs.known_one != 0
+            || lhs.known_one & rhs.known_zero != 0;
+        let equal = matches!(
+            (lhs.as_constant(), rhs.as_constant()),
+            (Some(a), Some(b)) if a == b
+        );
+
+        let result = if equal {
+            Some(true)
+        } else if different {
+            Some(false)
+        } else {
+            None
+        };
+        return result.map(|result| result == (pred == IntCompareOp::Equal));
+    }
+
+    let (lhs, rhs) = if is_signed {
+        (lhs.biased(), rhs.biased())
+    } else {
+        (lhs, rhs)
+    };
+
+    match pred {
+        IntCompareOp::LessThan if lhs.max < rhs.min => Some(true),
+        IntCompareOp::LessThan if lhs.min >= rhs.max => Some(false),
+        IntCompareOp::LessThanOrEqual if lhs.max <= rhs.min => Some(true),
+        IntCompareOp::LessThanOrEqual if lhs.min > rhs.max => Some(false),
+        IntCompareOp::GreaterThan | IntCompareOp::GreaterThanOrEqual => {
+            compare(pred.swapped(), rhs, lhs, false)
+        }
+        _ => None,
+    }
+}
+
+/// Ranges and known bits of the integer values of a function.
+///
+/// Ranges are found by iterating over the instructions until nothing changes, with
+/// widening so loops reach a fixpoint, then refined again by a few narrowing steps.
+/// Loads from stack slots that don't escape get the union of the values stored to
+/// the slot, and branch conditions constrain the compared values in the blocks only
+/// reached through one side of the branch:
+/// ```text
+/// for i in 0..10 {    // `i` is in `0..=10` in the loop header
+///     a += i;         // and in `0..=9` in the body
+/// }
+/// ```
+#[derive(Debug)]
+pub(crate) struct ValueRangeAnalysis {
+    /// Ranges holding wherever the value is available. Integer values that are never
+    /// computed, e.g. in unreachable blocks, are missing.
+    pub ranges: HashMap<Value, IntegerRange>,
+    /// Branch conditions known to hold in every block, `(condition, result)`.
+    conditions: HashMap<Label, Vec<(Value, bool)>>,
+    /// Compares creating the branch conditions, `(pred, lhs, rhs, is_signed)`.
+    compares: HashMap<Value, (IntCompareOp, Value, Value, bool)>,
+    /// Stack slots whose content is tracked, mapped to the stores to them.
+    slots: HashMap<Value, Vec<(Label, Value)>>,
+}
+
+impl ValueRangeAnalysis {
+    /// Runs the analysis over the blocks reachable from the entry.
+    pub fn new(types: &Types, function: &FunctionData) -> Self {
+        let cfg = function.labels().cfg();
+        let mut order = Vec::new();
+        cfg.bfs(|label| order.push(label));
+
+        let mut analysis = ValueRangeAnalysis {
+            ranges: HashMap::new(),
+            conditions: Self::compute_conditions(function, &order),
+            compares: HashMap::new(),
+            slots: Self::compute_slots(types, function, &order),
+        };
+
+        for (value, data) in function.values().iter() {
+            let Some((num_bits, _)) = types.integer(data.ty()) else {
+                continue;
+            };
+            let range = match function.constant(value).and_then(|c| c.integer()) {
+                Some(constant) => IntegerRange::constant(constant, num_bits),
+                None if function.parameters().contains(&value) => {
+                    IntegerRange::full(num_bits)
+                }
+                None => continue,
+            };
+            analysis.ranges.insert(value, range);
+        }
+
+        for label in order.iter() {
+            for instr in function.labels().get(*label).instructions.iter() {
+                if let Instruction::IntCompare {
+                    pred,
+                    dst,
+                    lhs,
+                    rhs,
+                } = instr
+                {
+                    let ty = function.values().get(*lhs).ty();
+                    let is_signed = types.integer(ty).is_some_and(|(_, signed)| signed);
+                    analysis
+                        .compares
+                        .insert(*dst, (*pred, *lhs, *rhs, is_signed));
+                }
+            }
+        }
+
+        analysis.solve(types, function, &order);
+        analysis
+    }
+
+    /// The range of `value` wherever it is available.
+    pub fn range(&self, value: Value) -> Option<IntegerRange> {
+        self.ranges.get(&value).copied()
+    }
+
+    /// The range of `value` inside `label`, constrained by the branches leading there.
+    pub fn range_at(&self, value: Value, label: Label) -> Option<IntegerRange> {
+        let mut range = self.range(value)?;
+
+        for (condition, result) in self.conditions.get(&label).into_iter().flatten() {
+            if *condition == value {
+                let known = IntegerRange::constant(*result as u64, range.num_bits);
+                range = range.intersect(known).unwrap_or(range);
+                continue;
+            }
+
+            let Some((pred, lhs, rhs, is_signed)) = self.compares.get(condition) else {
+                continue;
+            };
+            let pred = if *result { *pred } else { pred.inverse() };
+            if *lhs == value {
+                if let Some(other) = self.range(*rhs) {
+                    range = constrain(range, pred, other, *is_signed);
+                }
+            }
+            if *rhs == value {
+                if let Some(other) = self.range(*lhs) {
+                    range = constrain(range, pred.swapped(), other, *is_signed);
+                }
+            }
+        }
+
+        Some(range)
+    }
+
+    /// Iterates until the ranges stop growing, then narrows them.
+    fn solve(&mut self, types: &Types, function: &FunctionData, order: &[Label]) {
+        let mut growth: HashMap<Value, usize> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for label in order.iter() {
+                for instr in function.labels().get(*label).instructions.iter() {
+                    let Some((dst, new)) = self.transfer(types, function, *label, instr)
+                    else {
+                        continue;
+                    };
+
+                    let range = match self.ranges.get(&dst) {
+                        None => new,
+                        Some(old) if old.union(new) == *old => continue,
+                        Some(old) => {
+                            let count = growth.entry(dst).or_default();
+                            *count += 1;
+                            if *count > WIDENING_DELAY {
+                                old.widen(new)
+                            } else {
+                                old.union(new)
+                            }
+                        }
+                    };
+                    self.ranges.insert(dst, range);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        for _ in 0..NARROWING_STEPS {
+            for label in order.iter() {
+                for instr in function.labels().get(*label).instructions.iter() {
+                    let Some((dst, new)) = self.transfer(types, function, *label, instr)
+                    else {
+                        continue;
+                    };
+
+                    if let Some(old) = self.ranges.get_mut(&dst) {
+                        *old = old.intersect(new).unwrap_or(*old);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The range of the integer created by `instr`, from the current operand ranges.
+    /// Returns `None` if it creates no integer, or the operands have no range yet.
+    fn transfer(
+        &self,
+        types: &Types,
+        function: &FunctionData,
+        label: Label,
+        instr: &Instruction,
+    ) -> Option<(Value, IntegerRange)> {
+        let dst = instr.creates()?;
+        let (num_bits, is_signed) = types.integer(function.values().get(dst).ty())?;
+        let operand = |value: Value| self.range_at(value, label);
+
+        let range = match instr {
+            Instruction::ArithmeticBinary { lhs, op, rhs, .. } => {
+                binary(*op, operand(*lhs)?, operand(*rhs)?, is_signed)
+            }
+            Instruction::ArithmeticUnary { op, value, .. } => {
+                unary(*op, operand(*value)?)
+            }
+            Instruction::Cast { cast_op, value, .. } => {
+                match types.integer(function.values().get(*value).ty()) {
+                    Some(_) => cast(*cast_op, operand(*value)?, num_bits),
+                    None => IntegerRange::full(num_bits),
+                }
+            }
+            Instruction::IntCompare { pred, lhs, rhs, .. } => {
+                let (_, _, _, is_signed) = self.compares[&dst];
+                match compare(*pred, operand(*lhs)?, operand(*rhs)?, is_signed) {
+                    Some(result) => IntegerRange::constant(result as u64, num_bits),
+                    None => IntegerRange::full(num_bits),
+                }
+            }
+            Instruction::Select {
+                condition,
+                on_true,
+                on_false,
+                ..
+            } => match operand(*condition).and_then(|c| c.as_constant()) {
+                Some(0) => operand(*on_false)?,
+                Some(_) => operand(*on_true)?,
+                None => match (operand(*on_true), operand(*on_false)) {
+                    (Some(a), Some(b)) => a.union(b),
+                    (a, b) => a.or(b)?,
+                },
+            },
+            Instruction::Load { ptr, .. } => match self.slots.get(ptr) {
+                Some(stores) => stores
+                    .iter()
+                    .filter_map(|(label, value)| self.range_at(*value, *label))
+                    .reduce(IntegerRange::union)?,
+                None => IntegerRange::full(num_bits),
+            },
+            _ => IntegerRange::full(num_bits),
+        };
+
+        Some((dst, range))
+    }
+
+    /// Maps every block to the branch results known to hold in it: a branch side
+    /// only reached from the branch, and the blocks it dominates, know the condition.
+    fn compute_conditions(
+        function: &FunctionData,
+        order: &[Label],
+    ) -> HashMap<Label, Vec<(Value, bool)>> {
+        let cfg = function.labels().cfg();
+        let dominators = LoopAnalysis::new(function.labels()).dominators;
+
+        let mut edges = Vec::new();
+        for label in order.iter() {
+            let Instruction::BranchConditional {
+                condition,
+                on_true,
+                on_false,
+            } = *function.last_instruction(*label)
+            else {
+                continue;
+            };
+            if on_true == on_false {
+                continue;
+            }
+
+            for (side, result) in [(on_true, true), (on_false, false)] {
+                let mut predecessors = cfg.incoming(side);
+                let only_from_branch =
+                    predecessors.next().is_some() && predecessors.next().is_none();
+                if only_from_branch {
+                    edges.push((side, condition, result));
+                }
+            }
+        }
+
+        order
+            .iter()
+            .map(|label| {
+                let known = edges
+                    .iter()
+                    .filter(|(side, _, _)| dominators[label].contains(side))
+                    .map(|(_, condition, result)| (*condition, *result))
+                    .collect();
+                (*label, known)
+            })
+            .collect()
+    }
+
+    /// Stack slots of a single element, only accessed directly, never escaping and never
+    /// passed to a call, with the stores to them.
+    fn compute_slots(
+        types: &Types,
+        function: &FunctionData,
+        order: &[Label],
+    ) -> HashMap<Value, Vec<(Label, Value)>> {
+        let pointer_analysis = function.pointer_analysis(types);
+        let untracked: HashSet<Value> = pointer_analysis
+            .escaped_pointers
+            .iter()
+            .map(|pointer| pointer_analysis.pointer_origins[pointer])
+            .chain(pointer_analysis.escaped_pointers.iter().copied())
+            .chain(
+                pointer_analysis
+                    .pointer_origins
+                    .iter()
+                    .filter(|(pointer, origin)| pointer != origin)
+                    .map(|(_, origin)| *origin),
+            )
+            .chain(order.iter().flat_map(|label| {
+                let instructions = &function.labels().get(*label).instructions;
+                instructions.iter().flat_map(|instr| match instr {
+                    Instruction::Call { arguments, .. } => arguments.clone(),
+                    _ => Vec::new(),
+                })
+            }))
+            .collect();
+
+        let mut slots: HashMap<Value, Vec<(Label, Value)>> = pointer_analysis
+            .creators
+            .iter()
+            .filter(|(value, _)| !untracked.contains(value))
+            .filter(|(_, location)| {
+                matches!(
+                    function.instruction(location),
+                    Instruction::StackAlloc { size: 1, .. }
+                )
+            })
+            .map(|(value, _)| (*value, Vec::new()))
+            .collect();
+
+        for label in order.iter() {
+            for instr in function.labels().get(*label).instructions.iter() {
+                if let Instruction::Store { ptr, value } = instr {
+                    if let Some(stores) = slots.get_mut(ptr) {
+                        stores.push((*label, *value));
+                    }
+                }
+            }
+        }
+
+        slots
+    }
+}
+
+/// Constrains `range` knowing that `range pred other` holds.
+fn constrain(
+    range: IntegerRange,
+    pred: IntCompareOp,
+    other: IntegerRange,
+    is_signed: bool,
+) -> IntegerRange {
+    if is_signed && !(range.is_non_negative() && other.is_non_negative()) {
+        return range;
+    }
+
+    let num_bits = range.num_bits;
+    let mask = mask(num_bits);
+    let bound = match pred {
+        IntCompareOp::LessThan if other.max > 0 => {
+            IntegerRange::from_bounds(0, other.max - 1, num_bits)
+        }
+        IntCompareOp::LessThanOrEqual => {
+            IntegerRange::from_bounds(0, other.max, num_bits)
+        }
+        IntCompareOp::GreaterThan if other.min < mask => {
+            IntegerRange::from_bounds(other.min + 1, mask, num_bits)
+        }
+        IntCompareOp::GreaterThanOrEqual => {
+            IntegerRange::from_bounds(other.min, mask, num_bits)
+        }
+        IntCompareOp::Equal => other,
+        IntCompareOp::NotEqual => match other.as_constant() {
+            Some(value) if value == range.min && value < range.max => {
+                IntegerRange::from_bounds(value + 1, mask, num_bits)
+            }
+            Some(value) if value == range.max && value > range.min => {
+                IntegerRange::from_bounds(0, value - 1, num_bits)
+            }
+            _ => return range,
+        },
+        _ => return range,
+    };
+
+    range.intersect(bound).unwrap_or(range)
+}
+
+/// The range of `a op b`.
+fn binary(
+    op: BinaryOp,
+    a: IntegerRange,
+    b: IntegerRange,
+    is_signed: bool,
+) -> IntegerRange {
+    let num_bits = a.num_bits;
+    let mask = mask(num_bits);
+    let full = IntegerRange::full(num_bits);
+    let non_negative = !is_signed || (a.is_non_negative() && b.is_non_negative());
+
+    match op {
+        BinaryOp::Add => {
+            let (known_zero, known_one) = add_bits(a, b, false);
+            let range = match a.max.checked_add(b.max) {
+                Some(max) if max <= mask => {
+                    IntegerRange::from_bounds(a.min + b.min, max, num_bits)
+                }
+                _ => full,
+            };
+            range.with_bits(known_zero, known_one)
+        }
+        BinaryOp::Sub => {
+            // `a - b == a + !b + 1`
+            let not_b = IntegerRange::from_bits(b.known_one, b.known_zero, num_bits);
+            let (known_zero, known_one) = add_bits(a, not_b, true);
+            let range = if a.min >= b.max {
+                IntegerRange::from_bounds(a.min - b.max, a.max - b.min, num_bits)
+            } else {
+                full
+            };
+            range.with_bits(known_zero, known_one)
+        }
+        BinaryOp::Mul => {
+            let trailing_zeros = (a.trailing_zeros() + b.trailing_zeros()).min(num_bits);
+            let range = match a.max.checked_mul(b.max) {
+                Some(max) if max <= mask => {
+                    IntegerRange::from_bounds(a.min * b.min, max, num_bits)
+                }
+                _ => full,
+            };
+            range.with_bits(low_mask(trailing_zeros), 0)
+        }
+        BinaryOp::Div if non_negative => match a.max.checked_div(b.min) {
+            Some(max) => IntegerRange::from_bounds(a.min / b.max, max, num_bits),
+            None => IntegerRange::from_bounds(0, a.max, num_bits),
+        },
+        BinaryOp::Mod if non_negative => {
+            if a.max < b.min {
+                a
+            } else if b.max > 0 {
+                IntegerRange::from_bounds(0, a.max.min(b.max - 1), num_bits)
+            } else {
+                full
+            }
+        }
+        BinaryOp::Shl => match b.as_constant() {
+            Some(shift) if shift < num_bits as u64 => {
+                let range = if a.max.leading_zeros() as u64
+                    >= 64 - num_bits as u64 + shift
+                {
+                    IntegerRange::from_bounds(a.min << shift, a.max << shift, num_bits)
+                } else {
+                    full
+                };
+                range.with_bits(
+                    a.known_zero << shift | low_mask(shift as u32),
+                    a.known_one << shift,
+                )
+            }
+            _ => full,
+        },
+        BinaryOp::Shr | BinaryOp::Sar if op == BinaryOp::Shr || a.is_non_negative() => {
+            match b.as_constant() {
+                Some(shift) if shift < num_bits as u64 => {
+                    let high = mask & !(mask >> shift);
+                    IntegerRange::from_bounds(a.min >> shift, a.max >> shift, num_bits)
+                        .with_bits(a.known_zero >> shift | high, a.known_one >> shift)
+                }
+                _ => IntegerRange::from_bounds(0, a.max, num_bits),
+            }
+        }
+        BinaryOp::And | BinaryOp::BitAnd => {
+            IntegerRange::from_bounds(0, a.max.min(b.max), num_bits)
+                .with_bits(a.known_zero | b.known_zero, a.known_one & b.known_one)
+        }
+        BinaryOp::Or | BinaryOp::BitOr => {
+            IntegerRange::from_bounds(a.min.max(b.min), mask, num_bits)
+                .with_bits(a.known_zero & b.known_zero, a.known_one | b.known_one)
+        }
+        BinaryOp::Xor => IntegerRange::from_bits(
+            (a.known_zero & b.known_zero) | (a.known_one & b.known_one),
+            (a.known_zero & b.known_one) | (a.known_one & b.known_zero),
+            num_bits,
+        ),
+        _ => full,
+    }
+}
+
+/// The range of `op a`.
+fn unary(op: UnaryOp, a: IntegerRange) -> IntegerRange {
+    let num_bits = a.num_bits;
+    let mask = mask(num_bits);
+
+    match op {
+        UnaryOp::Not => IntegerRange::from_bounds(mask - a.max, mask - a.min, num_bits)
+            .with_bits(a.known_one, a.known_zero),
+        // `-a == mask - a + 1`, which only wraps around for 0.
+        UnaryOp::Neg if a.min > 0 => {
+            IntegerRange::from_bounds(mask - a.max + 1, mask - a.min + 1, num_bits)
+        }
+        UnaryOp::Neg if a.max == 0 => a,
+        UnaryOp::Neg => IntegerRange::full(num_bits),
+    }
+}
+
+/// The range of `a` cast to an integer of `num_bits` bits.
+fn cast(op: CastOp, a: IntegerRange, num_bits: u32) -> IntegerRange {
+    let mask = mask(num_bits);
+    let extended = mask & !self::mask(a.num_bits);
+
+    match op {
+        CastOp::ZeroExtend => IntegerRange::from_bounds(a.min, a.max, num_bits)
+            .with_bits(a.known_zero | extended, a.known_one),
+        CastOp::SignExtend if a.is_non_negative() => {
+            cast(CastOp::ZeroExtend, a, num_bits)
+        }
+        CastOp::SignExtend if a.is_negative() => {
+            IntegerRange::from_bounds(a.min | extended, a.max | extended, num_bits)
+                .with_bits(a.known_zero, a.known_one | extended)
+        }
+        CastOp::Truncate if a.max <= mask => {
+            IntegerRange::from_bounds(a.min, a.max, num_bits)
+                .with_bits(a.known_zero, a.known_one)
+        }
+        CastOp::Truncate => IntegerRange::from_bits(a.known_zero, a.known_one, num_bits),
+        CastOp::BitCast if a.num_bits == num_bits => a,
+        _ => IntegerRange::full(num_bits),
+    }
+}
+
+/// Known bits of `a + b + carry`, as `(known_zero, known_one)`.
+fn add_bits(a: IntegerRange, b: IntegerRange, carry: bool) -> (u64, u64) {
+    let mask = mask(a.num_bits);
+
+    let possible_sum_zero = (!a.known_zero & mask)
+        .wrapping_add(!b.known_zero & mask)
+        .wrapping_add(carry as u64);
+    let possible_sum_one = a
+        .known_one
+        .wrapping_add(b.known_one)
+        .wrapping_add(carry as u64);
+
+    // Bits of the carry into every position, where known.
+    let carry_known_zero = !(possible_sum_zero ^ a.known_zero ^ b.known_zero);
+    let carry_known_one = possible_sum_one ^ a.known_one ^ b.known_one;
+
+    let known = (a.known_zero | a.known_one)
+        & (b.known_zero | b.known_one)
+        & (carry_known_zero | carry_known_one)
+        & mask;
+    (!possible_sum_zero & known, possible_sum_one & known)
+}
+
+fn mask(num_bits: u32) -> u64 {
+    low_mask(num_bits)
+}
+
+/// The `num_bits` low bits set.
+fn low_mask(num_bits: u32) -> u64 {
+    if num_bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << num_bits) - 1
+    }
+}
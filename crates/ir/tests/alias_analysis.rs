@@ -0,0 +1,110 @@
+//! Checks through store forwarding which pointers into a struct alias.
+
+mod common;
+
+use ir::{
+    constant::ConstantValue,
+    context::Context,
+    function_builder::FunctionBuilder,
+    ty::{Type, TypeKind},
+    value::Value,
+};
+
+/// Builds `f() -> field` over a `{u8, field}` struct on the stack. It stores 7 to
+/// field 1, then 9 through the pointer `overwrite` makes from the struct and the
+/// pointer to field 0, and returns field 1.
+fn store_to_struct(
+    field: TypeKind,
+    overwrite: fn(&mut FunctionBuilder, Value, Value, Value) -> Value,
+) -> Context {
+    let mut context = Context::new();
+    let u8 = integer(&mut context, 8);
+    let u32 = integer(&mut context, 32);
+    let field = context.create_type(field);
+    let pair = context.create_type(TypeKind::Struct {
+        types: vec![u8, field],
+    });
+    let function = context.create_function("f", Some(field), &[]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let mut constant =
+        |ty, value| builder.alloc_constant(ConstantValue::Integer { ty, value });
+    let (zero, one, seven, nine) = (
+        constant(u32, 0),
+        constant(u32, 1),
+        constant(field, 7),
+        constant(u8, 9),
+    );
+
+    let slot = builder.stack_alloc(pair, 1);
+    let second = builder.get_element_ptr(slot, one);
+    builder.store(second, seven);
+    let first = builder.get_element_ptr(slot, zero);
+    let ptr = overwrite(&mut builder, slot, first, one);
+    builder.store(ptr, nine);
+    let result = builder.load(second);
+    builder.ret(Some(result));
+
+    context
+}
+
+/// Optimizes the function built by [`store_to_struct`], checks that it still
+/// returns `result`, and returns its instructions.
+fn check(name: &str, context: &mut Context, result: u64) -> Vec<String> {
+    let before = common::dump(context, &format!("{name}_before"));
+    assert_eq!(before.run("f", &[]), Some(result));
+
+    context.optimize();
+    let after = common::dump(context, name);
+    assert_eq!(after.run("f", &[]), Some(result));
+    after
+        .instructions("f")
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+fn integer(context: &mut Context, num_bits: u32) -> Type {
+    context.create_type(TypeKind::Integer {
+        num_bits,
+        is_signed: false,
+    })
+}
+
+const U8: TypeKind = TypeKind::Integer {
+    num_bits: 8,
+    is_signed: false,
+};
+const U32: TypeKind = TypeKind::Integer {
+    num_bits: 32,
+    is_signed: false,
+};
+
+#[test]
+fn different_fields() {
+    // Field 0 doesn't alias field 1, so the 7 stored to it is forwarded to the load.
+    let mut context = store_to_struct(U32, |_, _, first, _| first);
+    let instructions = check("different_fields", &mut context, 7);
+    assert_eq!(instructions.last().unwrap(), "ret 7_u32");
+}
+
+#[test]
+fn element_after_a_field() {
+    // The element after a `u8` field 0 is in the padding before a `u32` field 1, so
+    // the 9 stored there must not be forwarded to the load of field 1.
+    let overwrite: fn(&mut FunctionBuilder, Value, Value, Value) -> Value =
+        |builder, _, first, one| builder.get_element_ptr(first, one);
+    let mut context = store_to_struct(U32, overwrite);
+    let instructions = check("element_after_a_field", &mut context, 7);
+    assert!(
+        instructions
+            .iter()
+            .any(|instruction| instruction.contains("= load.")),
+        "{instructions:?}"
+    );
+
+    // When field 1 is a `u8` too, the element after field 0 is field 1.
+    let mut context = store_to_struct(U8, overwrite);
+    check("element_after_a_field_u8", &mut context, 9);
+}
@@ -0,0 +1,52 @@
+//! Runs the bytecode in the virtual machine.
+
+mod common;
+
+use ir::{backend::bytecode::Program, ty::TypeKind};
+
+/// Runs every case on the program.
+fn check(program: &Program) {
+    for case in common::cases() {
+        let result = program.run(case.function, &case.arguments).unwrap();
+        assert_eq!(
+            result,
+            Some(case.result),
+            "{}{:?}",
+            case.function,
+            case.arguments
+        );
+    }
+}
+
+#[test]
+fn integers() {
+    check(&common::module().compile_bytecode().unwrap());
+}
+
+#[test]
+fn optimized_integers() {
+    let mut context = common::module();
+    context.optimize();
+    check(&context.compile_bytecode().unwrap());
+}
+
+#[test]
+fn encoded_integers() {
+    let program = common::module().compile_bytecode().unwrap();
+    check(&Program::decode(&program.encode()).unwrap());
+}
+
+#[test]
+fn unsupported_values() {
+    for ty in [
+        TypeKind::Float { num_bits: 64 },
+        TypeKind::Integer {
+            num_bits: 128,
+            is_signed: false,
+        },
+        TypeKind::Struct { types: Vec::new() },
+    ] {
+        let error = common::identity(ty).compile_bytecode().unwrap_err();
+        assert_eq!(error.function, "identity");
+    }
+}
@@ -0,0 +1,58 @@
+//! Builds the C output with the system C compiler and runs it.
+
+mod common;
+
+use ir::{context::Context, ty::TypeKind};
+use std::io::ErrorKind;
+
+/// Compiles the module with a driver checking the result of every case.
+fn check(context: &Context, name: &str, prototypes: &str, cases: &[common::Case]) {
+    let directory = common::directory(name);
+    let source = directory.join("module.c");
+    context.emit_c(&source).unwrap();
+    common::run_with_driver(&directory, prototypes, cases, &[source]);
+}
+
+#[test]
+fn integers() {
+    check(
+        &common::module(),
+        "c_integers",
+        common::PROTOTYPES,
+        &common::cases(),
+    );
+}
+
+#[test]
+fn optimized_integers() {
+    let mut context = common::module();
+    context.optimize();
+    let cases = common::cases();
+    check(&context, "c_optimized_integers", common::PROTOTYPES, &cases);
+}
+
+#[test]
+fn floats() {
+    let context = common::float_module();
+    let cases = common::float_cases();
+    check(&context, "c_floats", common::FLOAT_PROTOTYPES, &cases);
+}
+
+#[test]
+fn unsupported_values() {
+    let path = common::directory("c_unsupported_values").join("module.c");
+    for ty in [
+        TypeKind::Float { num_bits: 16 },
+        TypeKind::Integer {
+            num_bits: 128,
+            is_signed: false,
+        },
+        TypeKind::Integer {
+            num_bits: 24,
+            is_signed: true,
+        },
+    ] {
+        let error = common::identity(ty).emit_c(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+    }
+}
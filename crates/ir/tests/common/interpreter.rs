@@ -0,0 +1,408 @@
+//! Reads back the functions written by `Context::dump_ir` and runs them, so tests can
+//! check both the instructions a pass produced and what the function computes.
+
+use std::collections::HashMap;
+
+/// The functions of a dumped module.
+pub struct Module {
+    functions: Vec<Function>,
+}
+
+struct Function {
+    id: String,
+    name: String,
+    parameters: Vec<String>,
+    /// Reachable blocks, the entry block first.
+    blocks: Vec<(String, Vec<String>)>,
+}
+
+/// The state of a running function.
+struct Frame<'a> {
+    module: &'a Module,
+    values: HashMap<&'a str, u64>,
+    memory: &'a mut Memory,
+}
+
+#[derive(Default)]
+struct Memory {
+    cells: HashMap<u64, u64>,
+    next: u64,
+}
+
+impl Module {
+    /// Parses the graphviz output of `Context::dump_ir`.
+    pub fn parse(dump: &str) -> Self {
+        let mut functions = Vec::new();
+        let mut blocks = Vec::new();
+        let mut lines = dump.lines();
+
+        while let Some(line) = lines.next() {
+            if let Some(id) = line
+                .strip_prefix("subgraph function_")
+                .and_then(|line| line.strip_suffix("_graph {"))
+            {
+                let label = lines.next().unwrap();
+                let signature = label
+                    .strip_prefix("label = \"fn @")
+                    .and_then(|label| label.strip_suffix("\";"))
+                    .unwrap();
+                let (name, rest) = signature.split_once('(').unwrap();
+                let (parameters, _) = rest.split_once(')').unwrap();
+                let parameters = parameters
+                    .split(", ")
+                    .filter(|parameter| !parameter.is_empty())
+                    .map(|parameter| parameter.split_once(':').unwrap().0.to_string())
+                    .collect();
+
+                functions.push(Function {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    parameters,
+                    blocks: std::mem::take(&mut blocks),
+                });
+            } else if line.starts_with("block_") && line.contains("[label = <") {
+                let (label, _) = line.split_once(' ').unwrap();
+                let instructions = line
+                    .split("<td align=\"left\">")
+                    .skip(1)
+                    .map(|row| {
+                        let (instruction, _) = row.split_once("</td>").unwrap();
+                        match instruction.split_once(" <font") {
+                            Some((instruction, _)) => instruction.to_string(),
+                            None => instruction.to_string(),
+                        }
+                    })
+                    .collect();
+                blocks.push((label.to_string(), instructions));
+            }
+        }
+
+        Self { functions }
+    }
+
+    /// The instructions of the function `name`, block after block.
+    pub fn instructions(&self, name: &str) -> Vec<&str> {
+        self.function(name)
+            .blocks
+            .iter()
+            .flat_map(|(_, instructions)| instructions.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The number of reachable blocks of the function `name`.
+    pub fn block_count(&self, name: &str) -> usize {
+        self.function(name).blocks.len()
+    }
+
+    /// Calls the function `name`. Arguments and results are the bits of the values,
+    /// zero extended to 64 bits.
+    pub fn run(&self, name: &str, arguments: &[u64]) -> Option<u64> {
+        self.call(self.function(name), arguments, &mut Memory::default())
+    }
+
+    fn function(&self, name: &str) -> &Function {
+        self.functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap_or_else(|| panic!("no function `{name}` in the dump"))
+    }
+
+    fn call(
+        &self,
+        function: &Function,
+        arguments: &[u64],
+        memory: &mut Memory,
+    ) -> Option<u64> {
+        assert_eq!(function.parameters.len(), arguments.len());
+        let mut frame = Frame {
+            module: self,
+            values: HashMap::new(),
+            memory,
+        };
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            frame.values.insert(parameter, *argument);
+        }
+
+        let mut block = &function.blocks[0];
+        loop {
+            let mut next = None;
+            for instruction in block.1.iter() {
+                match frame.execute(instruction) {
+                    Step::Next => {}
+                    Step::Branch(target) => {
+                        next = Some(target);
+                        break;
+                    }
+                    Step::Return(value) => return value,
+                }
+            }
+
+            let target = next.unwrap_or_else(|| panic!("{} has no terminator", block.0));
+            block = function
+                .blocks
+                .iter()
+                .find(|(label, _)| label == target)
+                .unwrap();
+        }
+    }
+}
+
+enum Step<'a> {
+    Next,
+    Branch(&'a str),
+    Return(Option<u64>),
+}
+
+impl<'a> Frame<'a> {
+    fn execute(&mut self, instruction: &'a str) -> Step<'a> {
+        if let Some(rest) = instruction.strip_prefix("let ") {
+            let (dst, rest) = rest.split_once(": ").unwrap();
+            let (ty, rest) = rest.split_once(" = ").unwrap();
+            let value = self.evaluate(ty, rest);
+            self.values.insert(dst, mask(value, bits(ty)));
+            return Step::Next;
+        }
+
+        let (opcode, operands) = instruction.split_once(' ').unwrap_or((instruction, ""));
+        match opcode {
+            "nop" => Step::Next,
+            "ret" if operands.is_empty() => Step::Return(None),
+            "ret" => Step::Return(Some(self.operand(operands))),
+            "branch" => Step::Branch(operands),
+            "branch_if" => {
+                let (condition, targets) = operands.split_once(' ').unwrap();
+                let (on_true, on_false) = targets.split_once(", ").unwrap();
+                match self.operand(condition) {
+                    0 => Step::Branch(on_false),
+                    _ => Step::Branch(on_true),
+                }
+            }
+            "call" | "tail" => {
+                self.evaluate("", instruction);
+                Step::Next
+            }
+            _ => {
+                assert!(
+                    opcode.starts_with("store."),
+                    "unknown instruction {instruction}"
+                );
+                let (_, ty, operands) = split_instruction(instruction);
+                let (ptr, value) = operands.split_once(", ").unwrap();
+                let ptr = self.operand(ptr);
+                let value = mask(self.operand(value), bits(pointee(ty)));
+                self.memory.cells.insert(ptr, value);
+                Step::Next
+            }
+        }
+    }
+
+    /// Evaluates the right-hand side of a `let` of type `ty`.
+    fn evaluate(&mut self, ty: &str, expression: &'a str) -> u64 {
+        let expression = expression.strip_prefix("tail ").unwrap_or(expression);
+        if let Some(call) = expression.strip_prefix("call function_") {
+            let (id, arguments) =
+                call.strip_suffix(')').unwrap().split_once('(').unwrap();
+            let arguments: Vec<u64> = arguments
+                .split(", ")
+                .filter(|argument| !argument.is_empty())
+                .map(|argument| self.operand(argument))
+                .collect();
+            let module = self.module;
+            let function = module
+                .functions
+                .iter()
+                .find(|function| function.id == id)
+                .unwrap();
+            return module.call(function, &arguments, self.memory).unwrap_or(0);
+        }
+
+        let (opcode, operand_type, operands) = split_instruction(expression);
+        let operands: Vec<&str> = operands.split(", ").collect();
+        if opcode == "select" {
+            return match self.operand(operands[0]) {
+                0 => self.operand(operands[2]),
+                _ => self.operand(operands[1]),
+            };
+        }
+
+        match opcode {
+            "stack_alloc" => {
+                let address = self.memory.next;
+                let size = size(operand_type) * operands[0].parse::<u64>().unwrap();
+                self.memory.next += size.max(1);
+                return address;
+            }
+            "load" => {
+                let ptr = self.operand(operands[0]);
+                return *self.memory.cells.get(&ptr).unwrap_or(&0);
+            }
+            "get_element_ptr" => {
+                let ptr = self.operand(operands[0]);
+                let index = self.operand(operands[1]);
+                let pointee = pointee(operand_type);
+                return match fields(pointee) {
+                    Some(fields) => {
+                        ptr + offset(&fields[..index as usize], fields[index as usize])
+                    }
+                    None => ptr.wrapping_add(index.wrapping_mul(size(pointee))),
+                };
+            }
+            _ => {}
+        }
+
+        let num_bits = bits(operand_type);
+        let signed = operand_type.starts_with('i');
+
+        let a = self.operand(operands[0]);
+        let (sa, ua) = (sign_extend(a, num_bits), mask(a, num_bits));
+        if operands.len() == 1 {
+            return match opcode {
+                "neg" => ua.wrapping_neg(),
+                "not" => !ua,
+                "zero_extend" | "truncate" | "bit_cast" => ua,
+                "sign_extend" => sa as u64,
+                _ => panic!("unknown unary operation {opcode}"),
+            };
+        }
+
+        let b = self.operand(operands[1]);
+        let (sb, ub) = (sign_extend(b, num_bits), mask(b, num_bits));
+        let shift = |value: u64, f: fn(u64, u32) -> u64| {
+            assert!(
+                ub < num_bits as u64,
+                "shift by {ub} of a {num_bits} bit value"
+            );
+            f(value, ub as u32)
+        };
+        match opcode {
+            "add" => ua.wrapping_add(ub),
+            "sub" => ua.wrapping_sub(ub),
+            "mul" => ua.wrapping_mul(ub),
+            "div" if signed => sa.wrapping_div(sb) as u64,
+            "div" => ua / ub,
+            "mod" if signed => sa.wrapping_rem(sb) as u64,
+            "mod" => ua % ub,
+            "shl" => shift(ua, |value, amount| value << amount),
+            "shr" => shift(ua, |value, amount| value >> amount),
+            "sar" => shift(sa as u64, |value, amount| ((value as i64) >> amount) as u64),
+            "and" | "bit_and" => ua & ub,
+            "or" | "bit_or" => ua | ub,
+            "xor" => ua ^ ub,
+            "eq" => (ua == ub) as u64,
+            "neq" => (ua != ub) as u64,
+            "lt" if signed => (sa < sb) as u64,
+            "lt" => (ua < ub) as u64,
+            "lte" if signed => (sa <= sb) as u64,
+            "lte" => (ua <= ub) as u64,
+            "gt" if signed => (sa > sb) as u64,
+            "gt" => (ua > ub) as u64,
+            "gte" if signed => (sa >= sb) as u64,
+            "gte" => (ua >= ub) as u64,
+            _ => panic!("unknown binary operation {opcode} in {ty}"),
+        }
+    }
+
+    /// The value of `vN`, or of a constant written as `value_type`.
+    fn operand(&self, operand: &str) -> u64 {
+        match operand.split_once('_') {
+            Some((value, _)) => value.parse().unwrap(),
+            None => *self
+                .values
+                .get(operand)
+                .unwrap_or_else(|| panic!("{operand} is used before it is defined")),
+        }
+    }
+}
+
+/// The width of the values of type `ty`, as written in the dump.
+fn bits(ty: &str) -> u32 {
+    if ty.starts_with('*') {
+        64
+    } else {
+        ty[1..].parse().unwrap()
+    }
+}
+
+/// The size in memory of the values of type `ty`, with structs laid out like in C.
+fn size(ty: &str) -> u64 {
+    match fields(ty) {
+        Some(fields) => align_up(offset(&fields, ""), align(ty)),
+        None => bits(ty).max(8) as u64 / 8,
+    }
+}
+
+fn align(ty: &str) -> u64 {
+    match fields(ty) {
+        Some(fields) => fields.iter().map(|field| align(field)).max().unwrap_or(1),
+        None => size(ty),
+    }
+}
+
+/// The offset of a field of type `ty` following the fields `before`, or of the end
+/// of the fields if `ty` is empty.
+fn offset(before: &[&str], ty: &str) -> u64 {
+    let end = before.iter().fold(0, |offset, field| {
+        align_up(offset, align(field)) + size(field)
+    });
+    match ty {
+        "" => end,
+        ty => align_up(end, align(ty)),
+    }
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value + (align - value % align) % align
+}
+
+/// The types of the fields of the struct type `ty`, written as `{u8, u32}`.
+fn fields(ty: &str) -> Option<Vec<&str>> {
+    let mut inner = ty.strip_prefix('{')?.strip_suffix('}')?;
+    let mut fields = Vec::new();
+    while !inner.is_empty() {
+        let (field, rest) = split_type(inner, ',');
+        fields.push(field);
+        inner = rest.trim_start();
+    }
+    Some(fields)
+}
+
+/// Splits `opcode.type operands` into its parts. The type is empty for the
+/// instructions without one.
+fn split_instruction(instruction: &str) -> (&str, &str, &str) {
+    let end = instruction.find([' ', '.']).unwrap_or(instruction.len());
+    let (opcode, rest) = instruction.split_at(end);
+    match rest.strip_prefix('.') {
+        Some(rest) => {
+            let (ty, operands) = split_type(rest, ' ');
+            (opcode, ty, operands)
+        }
+        None => (opcode, "", rest.trim_start()),
+    }
+}
+
+/// Splits the type at the start of `text` from what follows the `separator` after it.
+fn split_type(text: &str, separator: char) -> (&str, &str) {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c == separator && depth == 0 => return (&text[..i], &text[i + 1..]),
+            _ => {}
+        }
+    }
+    (text, "")
+}
+
+/// The type a pointer of type `ty` points to.
+fn pointee(ty: &str) -> &str {
+    ty.strip_prefix('*').unwrap()
+}
+
+fn mask(value: u64, num_bits: u32) -> u64 {
+    value & (u64::MAX >> (64 - num_bits))
+}
+
+fn sign_extend(value: u64, num_bits: u32) -> i64 {
+    ((value << (64 - num_bits)) as i64) >> (64 - num_bits)
+}
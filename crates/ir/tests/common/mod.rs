@@ -0,0 +1,427 @@
+//! Helpers shared by the integration tests, and a module exercising the backends,
+//! built through the public API, with the results its functions must return.
+
+#![allow(dead_code)]
+
+pub mod interpreter;
+
+use interpreter::Module;
+use ir::{
+    constant::ConstantValue,
+    context::Context,
+    function_builder::FunctionBuilder,
+    ty::{Type, TypeKind},
+    value::Value,
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// C declarations of the functions of [`module`], for drivers linked with it.
+pub const PROTOTYPES: &str = "\
+#include <stdint.h>
+uint32_t sum_of_squares(uint32_t n);
+int32_t mix(int32_t a, int32_t b);
+uint16_t bits(uint16_t a, uint16_t b);
+int64_t widen(int8_t a);
+uint64_t weighted(uint64_t a, uint64_t b, uint64_t c, uint64_t d, uint64_t e,
+                  uint64_t f, uint64_t g, uint64_t h);
+uint64_t call_weighted(uint64_t x);
+uint32_t truncated(void);
+";
+
+/// A call to a function of [`module`] and what it returns. Arguments and results
+/// are extended to 64 bits according to the signedness of their type.
+pub struct Case {
+    pub function: &'static str,
+    pub arguments: Vec<u64>,
+    pub result: u64,
+}
+
+/// Calls covering every function of [`module`].
+pub fn cases() -> Vec<Case> {
+    let mut cases = Vec::new();
+    for n in [0u32, 1, 5, 100, 3000] {
+        let result = (0..n).fold(0u32, |sum, i| sum.wrapping_add(i.wrapping_mul(i)));
+        cases.push(Case {
+            function: "sum_of_squares",
+            arguments: vec![n as u64],
+            result: result as u64,
+        });
+    }
+    for (a, b) in [
+        (0i32, 0i32),
+        (10, 3),
+        (-10, 3),
+        (3, 100),
+        (-7, -50),
+        (1 << 30, 5),
+    ] {
+        let z = a.wrapping_mul(3).wrapping_sub(b) / 2 % 7;
+        let result = if a < b { -z } else { z };
+        cases.push(Case {
+            function: "mix",
+            arguments: vec![a as i64 as u64, b as i64 as u64],
+            result: result as i64 as u64,
+        });
+    }
+    for (a, b) in [(0u16, 0u16), (0x1234, 0xfedc), (0xffff, 3)] {
+        let result = (a << 3 | b >> 2) & 0x0ff0;
+        cases.push(Case {
+            function: "bits",
+            arguments: vec![a as u64, b as u64],
+            result: result as u64,
+        });
+    }
+    for a in [0i8, 5, -5, i8::MIN, i8::MAX] {
+        let result = a as i64 * 1000 + a as u8 as i64;
+        cases.push(Case {
+            function: "widen",
+            arguments: vec![a as i64 as u64],
+            result: result as u64,
+        });
+    }
+    for x in [0u64, 1, 1 << 40, u64::MAX - 3] {
+        let result = (0..8).fold(0u64, |sum, i: u64| {
+            sum.wrapping_add((i + 1).wrapping_mul(x.wrapping_add(i)))
+        });
+        cases.push(Case {
+            function: "call_weighted",
+            arguments: vec![x],
+            result,
+        });
+    }
+    cases.push(Case {
+        function: "truncated",
+        arguments: vec![],
+        result: 300 % 256,
+    });
+    cases
+}
+
+/// Builds the module the [`cases`] call, described by [`PROTOTYPES`].
+pub fn module() -> Context {
+    let mut context = Context::new();
+    let u16 = integer(&mut context, 16, false);
+    let u32 = integer(&mut context, 32, false);
+    let u64 = integer(&mut context, 64, false);
+    let i8 = integer(&mut context, 8, true);
+    let i32 = integer(&mut context, 32, true);
+    let i64 = integer(&mut context, 64, true);
+
+    // Sums `i * i` for `i` below `n`, keeping both variables on the stack.
+    let function = context.create_function("sum_of_squares", Some(u32), &[u32]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    let condition = builder.create_label("condition");
+    let body = builder.create_label("body");
+    let exit = builder.create_label("exit");
+    builder.set_insert_point(entry);
+    let n = builder.parameter(0);
+    let i = builder.stack_alloc(u32, 1);
+    let sum = builder.stack_alloc(u32, 1);
+    let zero = constant(&mut builder, u32, 0);
+    builder.store(i, zero);
+    builder.store(sum, zero);
+    builder.branch(condition);
+    builder.set_insert_point(condition);
+    let index = builder.load(i);
+    let in_range = builder.compare_lt(index, n);
+    builder.branch_conditional(in_range, body, exit);
+    builder.set_insert_point(body);
+    let index = builder.load(i);
+    let square = builder.mul(index, index);
+    let partial = builder.load(sum);
+    let partial = builder.add(partial, square);
+    builder.store(sum, partial);
+    let one = constant(&mut builder, u32, 1);
+    let next = builder.add(index, one);
+    builder.store(i, next);
+    builder.branch(condition);
+    builder.set_insert_point(exit);
+    let result = builder.load(sum);
+    builder.ret(Some(result));
+
+    // Signed arithmetic: `(a * 3 - b) / 2 % 7`, negated when `a < b`.
+    let function = context.create_function("mix", Some(i32), &[i32, i32]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let (a, b) = (builder.parameter(0), builder.parameter(1));
+    let three = constant(&mut builder, i32, 3);
+    let two = constant(&mut builder, i32, 2);
+    let seven = constant(&mut builder, i32, 7);
+    let x = builder.mul(a, three);
+    let x = builder.sub(x, b);
+    let x = builder.div(x, two);
+    let x = builder.mod_(x, seven);
+    let negated = builder.neg(x);
+    let less = builder.compare_lt(a, b);
+    let result = builder.select(less, negated, x);
+    builder.ret(Some(result));
+
+    // Shifts and masks wrapping at 16 bits: `(a << 3 | b >> 2) & 0x0ff0`.
+    let function = context.create_function("bits", Some(u16), &[u16, u16]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let (a, b) = (builder.parameter(0), builder.parameter(1));
+    let three = constant(&mut builder, u16, 3);
+    let two = constant(&mut builder, u16, 2);
+    let mask = constant(&mut builder, u16, 0x0ff0);
+    let a = builder.shl(a, three);
+    let b = builder.shr(b, two);
+    let result = builder.bit_or(a, b);
+    let result = builder.bit_and(result, mask);
+    builder.ret(Some(result));
+
+    // Casts: `a as i64 * 1000 + a as u8 as i64`.
+    let function = context.create_function("widen", Some(i64), &[i8]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let a = builder.parameter(0);
+    let u8 = builder.create_type(TypeKind::Integer {
+        num_bits: 8,
+        is_signed: false,
+    });
+    let extended = builder.sign_extend(i64, a);
+    let thousand = constant(&mut builder, i64, 1000);
+    let extended = builder.mul(extended, thousand);
+    let unsigned = builder.bit_cast(u8, a);
+    let unsigned = builder.zero_extend(i64, unsigned);
+    let result = builder.add(extended, unsigned);
+    builder.ret(Some(result));
+
+    // Eight arguments, the last two passed on the stack by native backends.
+    let weighted = context.create_function("weighted", Some(u64), &[u64; 8]);
+    let mut builder = context.builder(weighted);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let mut result = constant(&mut builder, u64, 0);
+    for i in 0..8 {
+        let weight = constant(&mut builder, u64, i as u64 + 1);
+        let argument = builder.parameter(i);
+        let term = builder.mul(weight, argument);
+        result = builder.add(result, term);
+    }
+    builder.ret(Some(result));
+
+    let function = context.create_function("call_weighted", Some(u64), &[u64]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let x = builder.parameter(0);
+    let arguments: Vec<Value> = (0..8)
+        .map(|i| {
+            let offset = constant(&mut builder, u64, i);
+            builder.add(x, offset)
+        })
+        .collect();
+    let result = builder.call(weighted, &arguments).unwrap();
+    builder.ret(Some(result));
+
+    // Casts of a constant, which has no register.
+    let function = context.create_function("truncated", Some(u32), &[]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let u8 = builder.create_type(TypeKind::Integer {
+        num_bits: 8,
+        is_signed: false,
+    });
+    let value = constant(&mut builder, u32, 300);
+    let value = builder.truncate(u8, value);
+    let result = builder.zero_extend(u32, value);
+    builder.ret(Some(result));
+
+    context
+}
+
+/// C declarations of the functions of [`float_module`], which take and return the
+/// bits of their floats.
+pub const FLOAT_PROTOTYPES: &str = "\
+#include <stdint.h>
+uint64_t f64_math(uint64_t a, uint64_t b);
+uint32_t f32_math(uint32_t a, uint32_t b);
+uint32_t narrow(uint64_t a);
+";
+
+/// Calls covering every function of [`float_module`].
+pub fn float_cases() -> Vec<Case> {
+    let mut cases = Vec::new();
+    for (x, y) in [
+        (2.0, 3.0),
+        (-1.25, 0.5),
+        (1e300, 1e-300),
+        (3.0, 3.0),
+        (0.1, 0.2),
+    ] {
+        let r = (x * y + 1.5) / (x - y);
+        let result = if x < y { -r } else { r };
+        cases.push(Case {
+            function: "f64_math",
+            arguments: vec![f64::to_bits(x), f64::to_bits(y)],
+            result: result.to_bits(),
+        });
+
+        let (x, y) = (x as f32, y as f32);
+        let r = (x * y + 0.25) / (x - y);
+        let result = if x < y { -r } else { r };
+        cases.push(Case {
+            function: "f32_math",
+            arguments: vec![x.to_bits() as u64, y.to_bits() as u64],
+            result: result.to_bits() as u64,
+        });
+    }
+    for x in [0.0, -2.5, 0.1, 1e300, f64::MIN_POSITIVE] {
+        cases.push(Case {
+            function: "narrow",
+            arguments: vec![f64::to_bits(x)],
+            result: (x as f32).to_bits() as u64,
+        });
+    }
+    cases
+}
+
+/// Builds the module the [`float_cases`] call, described by [`FLOAT_PROTOTYPES`].
+pub fn float_module() -> Context {
+    let mut context = Context::new();
+    let u32 = integer(&mut context, 32, false);
+    let u64 = integer(&mut context, 64, false);
+    let f32 = context.create_type(TypeKind::Float { num_bits: 32 });
+    let f64 = context.create_type(TypeKind::Float { num_bits: 64 });
+
+    // `(x * y + c) / (x - y)`, negated when `x < y`.
+    for (name, bits, float, c) in
+        [("f64_math", u64, f64, 1.5), ("f32_math", u32, f32, 0.25)]
+    {
+        let function = context.create_function(name, Some(bits), &[bits, bits]);
+        let mut builder = context.builder(function);
+        let entry = builder.create_label("entry");
+        builder.set_insert_point(entry);
+        let x = builder.parameter(0);
+        let x = builder.bit_cast(float, x);
+        let y = builder.parameter(1);
+        let y = builder.bit_cast(float, y);
+        let c = builder.alloc_constant(ConstantValue::Float {
+            ty: float,
+            value: c,
+        });
+        let product = builder.mul(x, y);
+        let sum = builder.add(product, c);
+        let difference = builder.sub(x, y);
+        let quotient = builder.div(sum, difference);
+        let negated = builder.neg(quotient);
+        let less = builder.compare_lt(x, y);
+        let result = builder.select(less, negated, quotient);
+        let result = builder.bit_cast(bits, result);
+        builder.ret(Some(result));
+    }
+
+    let function = context.create_function("narrow", Some(u32), &[u64]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let x = builder.parameter(0);
+    let x = builder.bit_cast(f64, x);
+    let x = builder.truncate(f32, x);
+    let result = builder.bit_cast(u32, x);
+    builder.ret(Some(result));
+
+    context
+}
+
+/// A directory of its own for the files of the test `name`.
+pub fn directory(name: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Dumps the IR of `context` for the test `name`, and reads it back.
+pub fn dump(context: &Context, name: &str) -> Module {
+    let path = directory(name).join("module.dot");
+    context.dump_ir(&path).unwrap();
+    Module::parse(&std::fs::read_to_string(path).unwrap())
+}
+
+/// Builds `sources` with the system C compiler, along with a `main` printing the
+/// result of every case, runs the program and checks what it prints.
+pub fn run_with_driver(
+    directory: &Path,
+    prototypes: &str,
+    cases: &[Case],
+    sources: &[PathBuf],
+) {
+    let mut driver = format!("{prototypes}#include <stdio.h>\nint main(void) {{\n");
+    for case in cases.iter() {
+        let arguments: Vec<String> = case
+            .arguments
+            .iter()
+            .map(|argument| format!("{}ll", *argument as i64))
+            .collect();
+        writeln!(
+            driver,
+            "    printf(\"%llu\\n\", (unsigned long long){}({}));",
+            case.function,
+            arguments.join(", ")
+        )
+        .unwrap();
+    }
+    driver.push_str("    return 0;\n}\n");
+    let source = directory.join("driver.c");
+    std::fs::write(&source, driver).unwrap();
+
+    let executable = directory.join("driver");
+    let status = Command::new("cc")
+        .arg(&source)
+        .args(sources)
+        .arg("-lm")
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&executable).output().unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+    for (case, line) in cases.iter().zip(output.lines()) {
+        assert_eq!(
+            line,
+            case.result.to_string(),
+            "{}{:?}",
+            case.function,
+            case.arguments
+        );
+    }
+    assert_eq!(output.lines().count(), cases.len());
+}
+
+/// Builds a module with a function taking and returning `ty`, which some backends
+/// cannot generate code for.
+pub fn identity(ty: TypeKind) -> Context {
+    let mut context = Context::new();
+    let ty = context.create_type(ty);
+    let function = context.create_function("identity", Some(ty), &[ty]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let value = builder.parameter(0);
+    builder.ret(Some(value));
+    context
+}
+
+fn integer(context: &mut Context, num_bits: u32, is_signed: bool) -> Type {
+    context.create_type(TypeKind::Integer {
+        num_bits,
+        is_signed,
+    })
+}
+
+fn constant(builder: &mut FunctionBuilder, ty: Type, value: u64) -> Value {
+    builder.alloc_constant(ConstantValue::Integer { ty, value })
+}
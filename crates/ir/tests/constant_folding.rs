@@ -0,0 +1,38 @@
+//! Checks that folded constants replace the values every instruction reads.
+
+mod common;
+
+use ir::{
+    constant::ConstantValue,
+    context::Context,
+    ty::{Type, TypeKind},
+};
+
+fn integer(context: &mut Context, num_bits: u32) -> Type {
+    context.create_type(TypeKind::Integer {
+        num_bits,
+        is_signed: false,
+    })
+}
+
+#[test]
+fn folded_operand_of_a_cast() {
+    let mut context = Context::new();
+    let u8 = integer(&mut context, 8);
+    let u32 = integer(&mut context, 32);
+    let function = context.create_function("f", Some(u8), &[]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let three = builder.alloc_constant(ConstantValue::Integer { ty: u8, value: 3 });
+    let four = builder.alloc_constant(ConstantValue::Integer { ty: u8, value: 4 });
+    let sum = builder.add(three, four);
+    let wide = builder.zero_extend(u32, sum);
+    let result = builder.truncate(u8, wide);
+    builder.ret(Some(result));
+
+    // The sum is folded into the extension, which instruction combining then removes.
+    context.optimize();
+    let module = common::dump(&context, "folded_operand_of_a_cast");
+    assert_eq!(module.instructions("f"), ["ret 7_u8"]);
+}
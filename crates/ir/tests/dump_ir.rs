@@ -0,0 +1,54 @@
+//! Checks the rows `Context::dump_ir` writes for instructions.
+
+mod common;
+
+use ir::{context::Context, debug_location::DebugLocation, ty::TypeKind};
+
+#[test]
+fn cast_with_a_location() {
+    let mut context = Context::new();
+    let file = context.add_source_file("main.c");
+    let u8 = context.create_type(TypeKind::Integer {
+        num_bits: 8,
+        is_signed: false,
+    });
+    let i32 = context.create_type(TypeKind::Integer {
+        num_bits: 32,
+        is_signed: true,
+    });
+    let function = context.create_function("f", Some(i32), &[u8]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let value = builder.parameter(0);
+    builder.set_location(Some(DebugLocation {
+        file,
+        line: 3,
+        column: 7,
+    }));
+    let result = builder.zero_extend(i32, value);
+    builder.set_location(Some(DebugLocation {
+        file,
+        line: 4,
+        column: 0,
+    }));
+    builder.ret(Some(result));
+
+    let module = common::dump(&context, "cast_with_a_location");
+    assert_eq!(
+        module.instructions("f"),
+        ["let v1: i32 = zero_extend.u8 v0", "ret v1"]
+    );
+    assert_eq!(module.run("f", &[200]), Some(200));
+
+    let dump = std::fs::read_to_string(
+        common::directory("cast_with_a_location").join("module.dot"),
+    )
+    .unwrap();
+    assert!(dump.contains(
+        "<td align=\"left\">let v1: i32 = zero_extend.u8 v0 \
+         <font color=\"gray\">main.c:3:7</font></td>"
+    ));
+    assert!(dump
+        .contains("<td align=\"left\">ret v1 <font color=\"gray\">main.c:4</font></td>"));
+}
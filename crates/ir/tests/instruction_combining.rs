@@ -0,0 +1,554 @@
+//! Checks each instruction combining rule on a function built through the public API:
+//! the instruction it rewrites, and that the function still computes the same results.
+
+mod common;
+
+use common::interpreter::Module;
+use ir::{
+    constant::ConstantValue,
+    context::Context,
+    function_builder::FunctionBuilder,
+    ty::{Type, TypeKind},
+    value::Value,
+};
+
+type Body = fn(&mut FunctionBuilder, Type, Value, Value) -> Value;
+
+const U1: TypeKind = TypeKind::Integer {
+    num_bits: 1,
+    is_signed: false,
+};
+const U8: TypeKind = TypeKind::Integer {
+    num_bits: 8,
+    is_signed: false,
+};
+const U16: TypeKind = TypeKind::Integer {
+    num_bits: 16,
+    is_signed: false,
+};
+const U32: TypeKind = TypeKind::Integer {
+    num_bits: 32,
+    is_signed: false,
+};
+const I8: TypeKind = TypeKind::Integer {
+    num_bits: 8,
+    is_signed: true,
+};
+const I32: TypeKind = TypeKind::Integer {
+    num_bits: 32,
+    is_signed: true,
+};
+
+/// Arguments with the interesting bits of 32-bit values.
+const ARGUMENTS: [[u64; 2]; 6] = [
+    [0, 0],
+    [1, 7],
+    [7, 1],
+    [100, 3],
+    [0xffff_fff9, 4],
+    [0x8000_0000, 0x7fff_ffff],
+];
+
+/// Builds `f(a: ty, b: ty) -> result` returning what `body` computes.
+fn build(ty: TypeKind, result: TypeKind, body: Body) -> Context {
+    let mut context = Context::new();
+    let ty = context.create_type(ty);
+    let result = context.create_type(result);
+    let function = context.create_function("f", Some(result), &[ty, ty]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let (a, b) = (builder.parameter(0), builder.parameter(1));
+    let value = body(&mut builder, ty, a, b);
+    builder.ret(Some(value));
+    context
+}
+
+/// Optimizes the function built by `body`, and checks that it returns the same results
+/// for `arguments` before and after. Returns the optimized instructions.
+fn check(
+    name: &str,
+    ty: TypeKind,
+    result: TypeKind,
+    arguments: &[[u64; 2]],
+    body: Body,
+) -> Vec<String> {
+    let mask = u64::MAX >> (64 - ty_bits(&ty));
+    let mut context = build(ty, result, body);
+    let before = common::dump(&context, &format!("{name}_before"));
+    context.optimize();
+    let after = common::dump(&context, name);
+
+    for [a, b] in arguments {
+        let arguments = [a & mask, b & mask];
+        assert_eq!(
+            before.run("f", &arguments),
+            after.run("f", &arguments),
+            "{name}{arguments:?}"
+        );
+    }
+    instructions(&after)
+}
+
+/// Optimizes the function built by `body`, and returns the optimized instructions.
+fn optimized(name: &str, ty: TypeKind, result: TypeKind, body: Body) -> Module {
+    let mut context = build(ty, result, body);
+    context.optimize();
+    common::dump(&context, name)
+}
+
+fn instructions(module: &Module) -> Vec<String> {
+    module
+        .instructions("f")
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+fn ty_bits(ty: &TypeKind) -> u32 {
+    match ty {
+        TypeKind::Integer { num_bits, .. } => *num_bits,
+        _ => unreachable!(),
+    }
+}
+
+fn constant(builder: &mut FunctionBuilder, ty: Type, value: u64) -> Value {
+    builder.alloc_constant(ConstantValue::Integer { ty, value })
+}
+
+#[test]
+fn identity() {
+    let instructions = check("identity", U32, U32, &ARGUMENTS, |builder, ty, a, _| {
+        let zero = constant(builder, ty, 0);
+        let one = constant(builder, ty, 1);
+        let all_ones = constant(builder, ty, u32::MAX as u64);
+        let x = builder.add(a, zero);
+        let x = builder.add(zero, x);
+        let x = builder.sub(x, zero);
+        let x = builder.mul(x, one);
+        let x = builder.mul(one, x);
+        let x = builder.div(x, one);
+        let x = builder.bit_or(x, zero);
+        let x = builder.xor(zero, x);
+        let x = builder.shl(x, zero);
+        let x = builder.shr(x, zero);
+        let x = builder.sar(x, zero);
+        let x = builder.bit_and(all_ones, x);
+        let x = builder.bit_and(x, x);
+        builder.bit_or(x, x)
+    });
+    assert_eq!(instructions, ["ret v0"]);
+}
+
+#[test]
+fn identity_on_the_wrong_side() {
+    // `0 - x` and `1 / x` depend on `x`.
+    let instructions = check(
+        "wrong_side",
+        U32,
+        U32,
+        &ARGUMENTS[1..],
+        |builder, ty, _, b| {
+            let zero = constant(builder, ty, 0);
+            let one = constant(builder, ty, 1);
+            let x = builder.sub(zero, b);
+            builder.div(one, x)
+        },
+    );
+    assert_eq!(instructions.len(), 3, "{instructions:?}");
+    assert!(instructions[0].ends_with("= sub.u32 0_u32, v1"));
+}
+
+#[test]
+fn annihilator() {
+    let cases: [(Body, &str); 7] = [
+        (
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.mul(a, zero)
+            },
+            "ret 0_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.bit_and(zero, a)
+            },
+            "ret 0_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let all_ones = constant(builder, ty, u32::MAX as u64);
+                builder.bit_or(a, all_ones)
+            },
+            "ret 4294967295_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let one = constant(builder, ty, 1);
+                builder.mod_(a, one)
+            },
+            "ret 0_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.shl(zero, a)
+            },
+            "ret 0_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.shr(zero, a)
+            },
+            "ret 0_u32",
+        ),
+        (
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.div(zero, a)
+            },
+            "ret 0_u32",
+        ),
+    ];
+
+    for (i, (body, expected)) in cases.into_iter().enumerate() {
+        let module = optimized(&format!("annihilator_{i}"), U32, U32, body);
+        assert_eq!(instructions(&module), [expected], "case {i}");
+    }
+}
+
+#[test]
+fn self_inverse() {
+    // `x - x` is 0 for signed values too, including the smallest one.
+    let instructions = check("self_inverse", I32, I32, &ARGUMENTS, |builder, _, a, _| {
+        builder.sub(a, a)
+    });
+    assert_eq!(instructions, ["ret 0_i32"]);
+
+    let instructions = check(
+        "self_inverse_xor",
+        U16,
+        U16,
+        &ARGUMENTS,
+        |builder, _, _, b| builder.xor(b, b),
+    );
+    assert_eq!(instructions, ["ret 0_u16"]);
+}
+
+#[test]
+fn strength_reduction_of_multiplications() {
+    let instructions =
+        check("mul_by_power", U32, U32, &ARGUMENTS, |builder, ty, a, b| {
+            let eight = constant(builder, ty, 8);
+            let sign = constant(builder, ty, 1 << 31);
+            let x = builder.mul(a, eight);
+            let y = builder.mul(sign, b);
+            builder.add(x, y)
+        });
+    assert!(
+        instructions[0].ends_with("= shl.u32 v0, 3_u32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= shl.u32 v1, 31_u32"),
+        "{instructions:?}"
+    );
+
+    // Shifting a negative value left multiplies it all the same.
+    let instructions = check("signed_mul", I32, I32, &ARGUMENTS, |builder, ty, a, _| {
+        let four = constant(builder, ty, 4);
+        builder.mul(a, four)
+    });
+    assert!(
+        instructions[0].ends_with("= shl.i32 v0, 2_i32"),
+        "{instructions:?}"
+    );
+
+    let instructions =
+        check("mul_by_other", U32, U32, &ARGUMENTS, |builder, ty, a, _| {
+            let six = constant(builder, ty, 6);
+            builder.mul(a, six)
+        });
+    assert!(
+        instructions[0].ends_with("= mul.u32 v0, 6_u32"),
+        "{instructions:?}"
+    );
+}
+
+#[test]
+fn strength_reduction_of_unsigned_divisions() {
+    let instructions =
+        check("unsigned_div", U32, U32, &ARGUMENTS, |builder, ty, a, b| {
+            let sixteen = constant(builder, ty, 16);
+            let x = builder.div(a, sixteen);
+            let y = builder.mod_(b, sixteen);
+            builder.add(x, y)
+        });
+    assert!(
+        instructions[0].ends_with("= shr.u32 v0, 4_u32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= bit_and.u32 v1, 15_u32"),
+        "{instructions:?}"
+    );
+}
+
+#[test]
+fn no_strength_reduction_of_signed_divisions() {
+    // A shift rounds `-7 / 4` down to -2 instead of towards zero, and a mask makes
+    // `-7 % 4` positive, so signed divisions are left alone.
+    let arguments = [
+        [-7i32 as u32 as u64, -8i32 as u32 as u64],
+        [-1i32 as u32 as u64, 5],
+    ];
+    let instructions = check("signed_div", I32, I32, &arguments, |builder, ty, a, b| {
+        let four = constant(builder, ty, 4);
+        let x = builder.div(a, four);
+        let y = builder.mod_(b, four);
+        let z = builder.mod_(a, four);
+        let x = builder.add(x, y);
+        builder.add(x, z)
+    });
+    assert!(
+        instructions[0].ends_with("= div.i32 v0, 4_i32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= mod.i32 v1, 4_i32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[2].ends_with("= mod.i32 v0, 4_i32"),
+        "{instructions:?}"
+    );
+
+    let after = optimized("signed_div_results", I32, I32, |builder, ty, a, _| {
+        let four = constant(builder, ty, 4);
+        builder.div(a, four)
+    });
+    assert_eq!(
+        after.run("f", &[-7i32 as u32 as u64, 0]),
+        Some(-1i32 as u32 as u64)
+    );
+}
+
+#[test]
+fn shifts_by_the_width_or_more() {
+    // Shifts by the width of the type or more are neither identities nor folded.
+    let instructions = optimized("wide_shifts", U32, U32, |builder, ty, a, b| {
+        let width = constant(builder, ty, 32);
+        let over = constant(builder, ty, 40);
+        let x = builder.shl(a, width);
+        let y = builder.shr(b, over);
+        builder.bit_or(x, y)
+    });
+    let instructions = instructions.instructions("f");
+    assert!(
+        instructions[0].ends_with("= shl.u32 v0, 32_u32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= shr.u32 v1, 40_u32"),
+        "{instructions:?}"
+    );
+}
+
+#[test]
+fn canonicalize_compare() {
+    let cases: [(TypeKind, Body, &str); 9] = [
+        (U32, |builder, _, a, _| builder.compare_lt(a, a), "ret 0_u1"),
+        (
+            U32,
+            |builder, _, a, _| builder.compare_lte(a, a),
+            "ret 1_u1",
+        ),
+        (
+            U32,
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.compare_lt(a, zero)
+            },
+            "ret 0_u1",
+        ),
+        (
+            U32,
+            |builder, ty, a, _| {
+                let zero = constant(builder, ty, 0);
+                builder.compare_gte(a, zero)
+            },
+            "ret 1_u1",
+        ),
+        (
+            U32,
+            |builder, ty, a, _| {
+                let max = constant(builder, ty, u32::MAX as u64);
+                builder.compare_gt(a, max)
+            },
+            "ret 0_u1",
+        ),
+        (
+            U32,
+            |builder, ty, a, _| {
+                let max = constant(builder, ty, u32::MAX as u64);
+                builder.compare_lte(a, max)
+            },
+            "ret 1_u1",
+        ),
+        (
+            I32,
+            |builder, ty, a, _| {
+                let min = constant(builder, ty, i32::MIN as u32 as u64);
+                builder.compare_lt(a, min)
+            },
+            "ret 0_u1",
+        ),
+        (
+            I32,
+            |builder, ty, a, _| {
+                let max = constant(builder, ty, i32::MAX as u64);
+                builder.compare_gt(a, max)
+            },
+            "ret 0_u1",
+        ),
+        (
+            I32,
+            |builder, ty, a, _| {
+                let max = constant(builder, ty, i32::MAX as u64);
+                builder.compare_lte(a, max)
+            },
+            "ret 1_u1",
+        ),
+    ];
+
+    for (i, (ty, body, expected)) in cases.into_iter().enumerate() {
+        let name = format!("always_{i}");
+        let instructions = check(&name, ty, U1, &ARGUMENTS, body);
+        assert_eq!(instructions, [expected], "case {i}");
+    }
+}
+
+#[test]
+fn canonicalize_compare_operands() {
+    let instructions = check(
+        "constant_on_the_left",
+        U32,
+        U1,
+        &ARGUMENTS,
+        |builder, ty, a, _| {
+            let five = constant(builder, ty, 5);
+            builder.compare_lt(five, a)
+        },
+    );
+    assert!(
+        instructions[0].ends_with("= gt.u32 v0, 5_u32"),
+        "{instructions:?}"
+    );
+
+    let instructions = check("non_strict", U32, U1, &ARGUMENTS, |builder, ty, a, b| {
+        let seven = constant(builder, ty, 7);
+        let x = builder.compare_lte(a, seven);
+        let y = builder.compare_gte(b, seven);
+        builder.bit_and(x, y)
+    });
+    assert!(
+        instructions[0].ends_with("= lt.u32 v0, 8_u32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= gt.u32 v1, 6_u32"),
+        "{instructions:?}"
+    );
+
+    let instructions = check(
+        "non_strict_signed",
+        I32,
+        U1,
+        &ARGUMENTS,
+        |builder, ty, a, b| {
+            let minus_one = constant(builder, ty, -1i32 as u32 as u64);
+            let x = builder.compare_lte(a, minus_one);
+            let y = builder.compare_gte(b, minus_one);
+            builder.bit_or(x, y)
+        },
+    );
+    assert!(
+        instructions[0].ends_with("= lt.i32 v0, 0_i32"),
+        "{instructions:?}"
+    );
+    assert!(
+        instructions[1].ends_with("= gt.i32 v1, 4294967294_i32"),
+        "{instructions:?}"
+    );
+}
+
+#[test]
+fn redundant_cast() {
+    let cases: [(TypeKind, Body); 3] = [
+        (U8, |builder, ty, a, _| {
+            let wide = builder.create_type(U32);
+            let x = builder.zero_extend(wide, a);
+            builder.truncate(ty, x)
+        }),
+        (I8, |builder, ty, a, _| {
+            let wide = builder.create_type(I32);
+            let x = builder.sign_extend(wide, a);
+            builder.truncate(ty, x)
+        }),
+        (U8, |builder, _, a, _| {
+            let same = builder.create_type(U8);
+            builder.bit_cast(same, a)
+        }),
+    ];
+
+    for (i, (ty, body)) in cases.into_iter().enumerate() {
+        let module = optimized(&format!("redundant_cast_{i}"), ty.clone(), ty, body);
+        assert_eq!(instructions(&module), ["ret v0"], "case {i}");
+    }
+}
+
+#[test]
+fn redundant_select() {
+    let cases: [(Body, &str); 3] = [
+        (
+            |builder, _, a, b| {
+                let less = builder.compare_lt(a, b);
+                builder.select(less, a, a)
+            },
+            "ret v0",
+        ),
+        (
+            |builder, _, a, b| {
+                let u1 = builder.create_type(U1);
+                let condition = constant(builder, u1, 1);
+                builder.select(condition, a, b)
+            },
+            "ret v0",
+        ),
+        (
+            |builder, _, a, b| {
+                let u1 = builder.create_type(U1);
+                let condition = constant(builder, u1, 0);
+                builder.select(condition, a, b)
+            },
+            "ret v1",
+        ),
+    ];
+
+    for (i, (body, expected)) in cases.into_iter().enumerate() {
+        let name = format!("redundant_select_{i}");
+        let instructions = check(&name, U32, U32, &ARGUMENTS, body);
+        assert_eq!(instructions, [expected], "case {i}");
+    }
+
+    let instructions = check("select", U32, U32, &ARGUMENTS, |builder, _, a, b| {
+        let less = builder.compare_lt(a, b);
+        builder.select(less, a, b)
+    });
+    assert!(
+        instructions[1].ends_with("= select v2, v0, v1"),
+        "{instructions:?}"
+    );
+}
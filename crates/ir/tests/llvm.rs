@@ -0,0 +1,63 @@
+//! Compiles the LLVM IR output with `llc` and runs it with a C driver.
+
+mod common;
+
+use ir::{context::Context, ty::TypeKind};
+use std::{io::ErrorKind, process::Command};
+
+/// Compiles the module to an object file linked with a driver checking the result
+/// of every case.
+fn check(context: &Context, name: &str, prototypes: &str, cases: &[common::Case]) {
+    let directory = common::directory(name);
+    let module = directory.join("module.ll");
+    context.emit_llvm(&module).unwrap();
+
+    let object = directory.join("module.o");
+    let status = Command::new("llc")
+        .args(["-filetype=obj", "-relocation-model=pic", "-o"])
+        .arg(&object)
+        .arg(&module)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    common::run_with_driver(&directory, prototypes, cases, &[object]);
+}
+
+#[test]
+fn integers() {
+    check(
+        &common::module(),
+        "llvm_integers",
+        common::PROTOTYPES,
+        &common::cases(),
+    );
+}
+
+#[test]
+fn optimized_integers() {
+    let mut context = common::module();
+    context.optimize();
+    let cases = common::cases();
+    check(
+        &context,
+        "llvm_optimized_integers",
+        common::PROTOTYPES,
+        &cases,
+    );
+}
+
+#[test]
+fn floats() {
+    let context = common::float_module();
+    let cases = common::float_cases();
+    check(&context, "llvm_floats", common::FLOAT_PROTOTYPES, &cases);
+}
+
+#[test]
+fn unsupported_values() {
+    let path = common::directory("llvm_unsupported_values").join("module.ll");
+    let error = common::identity(TypeKind::Float { num_bits: 16 })
+        .emit_llvm(&path)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Unsupported);
+}
@@ -0,0 +1,110 @@
+//! Checks that loops with a constant trip count are unrolled, and still run every
+//! iteration.
+
+mod common;
+
+use ir::{constant::ConstantValue, context::Context, ty::TypeKind};
+
+/// Builds `f() -> u32`, which counts the iterations of `for i in 0..bound` and sums
+/// `i`, keeping every variable on the stack like the frontend does. Returns
+/// `count * 1000 + sum`.
+fn counting_loop(bound: u64) -> Context {
+    let mut context = Context::new();
+    let u32 = context.create_type(TypeKind::Integer {
+        num_bits: 32,
+        is_signed: false,
+    });
+    let function = context.create_function("f", Some(u32), &[]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    let header = builder.create_label("header");
+    let body = builder.create_label("body");
+    let exit = builder.create_label("exit");
+    let mut constant =
+        |value| builder.alloc_constant(ConstantValue::Integer { ty: u32, value });
+    let (zero, one, bound, thousand) =
+        (constant(0), constant(1), constant(bound), constant(1000));
+
+    builder.set_insert_point(entry);
+    let i = builder.stack_alloc(u32, 1);
+    let count = builder.stack_alloc(u32, 1);
+    let sum = builder.stack_alloc(u32, 1);
+    builder.store(i, zero);
+    builder.store(count, zero);
+    builder.store(sum, zero);
+    builder.branch(header);
+
+    builder.set_insert_point(header);
+    let index = builder.load(i);
+    let in_range = builder.compare_lt(index, bound);
+    builder.branch_conditional(in_range, body, exit);
+
+    builder.set_insert_point(body);
+    let partial = builder.load(sum);
+    let partial = builder.add(partial, index);
+    builder.store(sum, partial);
+    let iterations = builder.load(count);
+    let iterations = builder.add(iterations, one);
+    builder.store(count, iterations);
+    let next = builder.add(index, one);
+    builder.store(i, next);
+    builder.branch(header);
+
+    builder.set_insert_point(exit);
+    let iterations = builder.load(count);
+    let iterations = builder.mul(iterations, thousand);
+    let partial = builder.load(sum);
+    let result = builder.add(iterations, partial);
+    builder.ret(Some(result));
+
+    context
+}
+
+fn expected(bound: u64) -> u64 {
+    bound * 1000 + (0..bound).sum::<u64>()
+}
+
+#[test]
+fn full_unroll() {
+    let mut context = counting_loop(5);
+    let before = common::dump(&context, "full_unroll_before");
+    assert_eq!(before.run("f", &[]), Some(expected(5)));
+
+    context.optimize();
+    let after = common::dump(&context, "full_unroll");
+    // The copies of the body fold into straight-line code, and then into a constant.
+    assert_eq!(
+        after.instructions("f"),
+        [format!("ret {}_u32", expected(5))]
+    );
+}
+
+#[test]
+fn partial_unroll() {
+    // 37 iterations are too many to unroll fully. Four copies of the body run 36 of
+    // them, and the remainder loop runs the last one.
+    for bound in [37, 38, 39, 40] {
+        let mut context = counting_loop(bound);
+        context.optimize();
+        let after = common::dump(&context, &format!("partial_unroll_{bound}"));
+        let instructions = after.instructions("f");
+
+        let end = format!(", {}_u32", bound - bound % 4);
+        assert!(
+            instructions
+                .iter()
+                .any(|instruction| instruction.contains("= neq.u32 ")
+                    && instruction.ends_with(&end)),
+            "{instructions:?}"
+        );
+        assert_eq!(
+            instructions
+                .iter()
+                .filter(|instruction| instruction.contains("= lt.u32 "))
+                .count(),
+            1,
+            "{instructions:?}"
+        );
+        assert_eq!(after.run("f", &[]), Some(expected(bound)), "0..{bound}");
+    }
+}
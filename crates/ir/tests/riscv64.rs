@@ -0,0 +1,72 @@
+//! Runs the RV64IM machine code in the bundled simulator, and assembles the assembly
+//! output with `llvm-mc`.
+
+mod common;
+
+use ir::{context::Context, ty::TypeKind};
+use std::{io::ErrorKind, process::Command};
+
+/// Runs every case on the module in the simulator.
+fn check(context: &Context) {
+    let program = context.compile_riscv64().unwrap();
+    for case in common::cases() {
+        let result = program.run(case.function, &case.arguments).unwrap();
+        assert_eq!(
+            result,
+            Some(case.result),
+            "{}{:?}",
+            case.function,
+            case.arguments
+        );
+    }
+}
+
+#[test]
+fn simulated() {
+    check(&common::module());
+}
+
+#[test]
+fn optimized_simulated() {
+    let mut context = common::module();
+    context.optimize();
+    check(&context);
+}
+
+#[test]
+fn assembly() {
+    let directory = common::directory("riscv64_assembly");
+    let assembly = directory.join("module.s");
+    common::module().emit_riscv64(&assembly).unwrap();
+
+    let status = Command::new("llvm-mc")
+        .args(["-triple=riscv64", "-mattr=+m", "-filetype=obj", "-o"])
+        .arg(directory.join("module.o"))
+        .arg(&assembly)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn unsupported_values() {
+    let path = common::directory("riscv64_unsupported_values").join("module.s");
+
+    for ty in [
+        TypeKind::Float { num_bits: 64 },
+        TypeKind::Integer {
+            num_bits: 128,
+            is_signed: false,
+        },
+        TypeKind::Integer {
+            num_bits: 24,
+            is_signed: true,
+        },
+        TypeKind::Struct { types: Vec::new() },
+    ] {
+        let context = common::identity(ty);
+        let error = context.emit_riscv64(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert_eq!(context.compile_riscv64().unwrap_err().function, "identity");
+    }
+}
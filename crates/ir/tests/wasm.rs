@@ -0,0 +1,105 @@
+//! Parses the WebAssembly text output, then validates and runs it in `wasmi`.
+
+mod common;
+
+use ir::{context::Context, ty::TypeKind};
+use std::io::ErrorKind;
+use wasmi::{core::ValueType, Engine, Linker, Module, Store, Value};
+
+/// Runs every case on the module, comparing the low bits of the results which fit
+/// in their wasm type.
+fn check(context: &Context, name: &str, cases: &[common::Case]) {
+    let path = common::directory(name).join("module.wat");
+    context.emit_wat(&path).unwrap();
+    let wasm = wat::parse_file(&path).unwrap();
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    let mut store = Store::new(&engine, ());
+    let instance = <Linker<()>>::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+
+    for case in cases.iter() {
+        let function = instance.get_func(&store, case.function).unwrap();
+        let ty = function.ty(&store);
+        let arguments: Vec<Value> = ty
+            .params()
+            .iter()
+            .zip(case.arguments.iter())
+            .map(|(ty, argument)| match ty {
+                ValueType::I32 => Value::I32(*argument as i32),
+                ValueType::I64 => Value::I64(*argument as i64),
+                ty => panic!("unexpected parameter type {ty:?}"),
+            })
+            .collect();
+        let mut results = [Value::I32(0)];
+        function.call(&mut store, &arguments, &mut results).unwrap();
+
+        let (result, expected) = match results[0] {
+            Value::I32(result) => (result as u32 as u64, case.result as u32 as u64),
+            Value::I64(result) => (result as u64, case.result),
+            ref result => panic!("unexpected result {result:?}"),
+        };
+        assert_eq!(result, expected, "{}{:?}", case.function, case.arguments);
+    }
+}
+
+#[test]
+fn integers() {
+    check(&common::module(), "wasm_integers", &common::cases());
+}
+
+#[test]
+fn optimized_integers() {
+    let mut context = common::module();
+    context.optimize();
+    check(&context, "wasm_optimized_integers", &common::cases());
+}
+
+#[test]
+fn floats() {
+    check(
+        &common::float_module(),
+        "wasm_floats",
+        &common::float_cases(),
+    );
+}
+
+#[test]
+fn unsupported_values() {
+    let path = common::directory("wasm_unsupported_values").join("module.wat");
+    for ty in [
+        TypeKind::Float { num_bits: 16 },
+        TypeKind::Integer {
+            num_bits: 128,
+            is_signed: false,
+        },
+        TypeKind::Integer {
+            num_bits: 48,
+            is_signed: true,
+        },
+    ] {
+        let error = common::identity(ty).emit_wat(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+fn float_remainders() {
+    let mut context = Context::new();
+    let f64 = context.create_type(TypeKind::Float { num_bits: 64 });
+    let function = context.create_function("remainder", Some(f64), &[f64, f64]);
+    let mut builder = context.builder(function);
+    let entry = builder.create_label("entry");
+    builder.set_insert_point(entry);
+    let (a, b) = (builder.parameter(0), builder.parameter(1));
+    let result = builder.mod_(a, b);
+    builder.ret(Some(result));
+
+    let path = common::directory("wasm_float_remainders").join("module.wat");
+    let error = context.emit_wat(&path).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Unsupported);
+}
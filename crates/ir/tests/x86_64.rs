@@ -0,0 +1,68 @@
+//! Runs the x86-64 assembly with a C driver built by the system compiler.
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+
+mod common;
+
+use ir::{context::Context, ty::TypeKind};
+use std::io::ErrorKind;
+
+/// Assembles and links the module with a driver checking the result of every case.
+fn check(context: &Context, name: &str) {
+    let directory = common::directory(name);
+    let assembly = directory.join("module.s");
+    context.emit_x86_64(&assembly).unwrap();
+    common::run_with_driver(
+        &directory,
+        common::PROTOTYPES,
+        &common::cases(),
+        &[assembly],
+    );
+}
+
+#[test]
+fn assembly() {
+    check(&common::module(), "assembly");
+}
+
+#[test]
+fn optimized_assembly() {
+    let mut context = common::module();
+    context.optimize();
+    check(&context, "optimized_assembly");
+}
+
+#[test]
+fn cast_of_constant() {
+    let context = common::module();
+    let directory = common::directory("cast_of_constant");
+    context
+        .emit_x86_64_object(&directory.join("module.o"))
+        .unwrap();
+
+    let module = context.jit().unwrap();
+    assert_eq!(unsafe { module.call("truncated", &[]) }, Some(44));
+}
+
+#[test]
+fn unsupported_values() {
+    let path = common::directory("unsupported_values").join("module.s");
+
+    for ty in [
+        TypeKind::Float { num_bits: 64 },
+        TypeKind::Integer {
+            num_bits: 128,
+            is_signed: false,
+        },
+        TypeKind::Integer {
+            num_bits: 24,
+            is_signed: true,
+        },
+    ] {
+        let context = common::identity(ty);
+        let error = context.emit_x86_64(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        let error = context.emit_x86_64_object(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert_eq!(context.jit().err().unwrap().kind(), ErrorKind::Unsupported);
+    }
+}